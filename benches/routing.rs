@@ -0,0 +1,131 @@
+#![feature(test)]
+
+extern crate test;
+
+use async_std::task;
+use http_types::{Method, Request, Response, Url};
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+use test::Bencher;
+use windmill::*;
+
+const ROUTES: usize = 500;
+
+async fn endpoint(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    Ok(Response::from("ok"))
+}
+
+type BoxedResponse = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send + Sync>>;
+
+/// How endpoints were run before: the params moved into a hash map, and the endpoint future
+/// boxed by the handler on top of the box it already came in.
+fn boxed_twice(_req: http_types::Request, params: Params) -> BoxedResponse {
+    let params: HashMap<&'static str, String> = params.into_iter().collect();
+    Box::pin(async move {
+        test::black_box(params);
+        Ok(Response::from("ok"))
+    })
+}
+
+/// `/resource{i}/{id}/items/{item}` for every `i`, along with a static route per resource.
+fn routes() -> Vec<Route> {
+    (0..ROUTES)
+        .flat_map(|i| {
+            let leak = |pattern: String| -> &'static str { Box::leak(pattern.into_boxed_str()) };
+            vec![
+                Route::from(leak(format!("/resource{}/{{id}}/items/{{item}}", i))),
+                Route::from(leak(format!("/resource{}/all", i))),
+            ]
+        })
+        .take(ROUTES)
+        .collect()
+}
+
+/// The last route added, the worst case of a linear scan.
+const PATH: &str = "/resource249/all";
+
+/// How routes were matched before the trie: every route compared with every segment.
+fn paths_match(route: &Route, segments: &[&str]) -> bool {
+    let len = route.static_segments.len() + route.dynamic_segments.len();
+    (segments.len() == len || (route.rest.is_some() && segments.len() > len))
+        && route
+            .static_segments
+            .iter()
+            .all(|segment| segments[segment.position] == segment.value)
+}
+
+#[bench]
+fn linear_scan(b: &mut Bencher) {
+    let routes = routes();
+    b.iter(|| {
+        let segments: Vec<&str> = test::black_box(PATH).split('/').skip(1).collect();
+        let route = routes.iter().find(|route| paths_match(route, &segments));
+        route.map(|route| route.to_string()).unwrap()
+    });
+}
+
+#[bench]
+fn trie(b: &mut Bencher) {
+    let mut router = Router::new();
+    for route in routes() {
+        router.add(Method::Get, route, endpoint);
+    }
+    b.iter(|| {
+        let limits = router.effective_limits(Method::Get, test::black_box(PATH));
+        limits.unwrap().route
+    });
+}
+
+/// A request routed and dispatched to `endpoint`, without a connection.  
+fn respond(b: &mut Bencher, path: &str, endpoint: impl Endpoint + Clone + Send + Sync) {
+    let mut router = Router::new();
+    for route in routes() {
+        router.add(Method::Get, route, endpoint.clone());
+    }
+    let router = Arc::new(router);
+    let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    b.iter(|| {
+        let req = Request::new(Method::Get, test::black_box(url.clone()));
+        task::block_on(router.clone().respond(req)).status()
+    });
+}
+
+#[bench]
+fn respond_static(b: &mut Bencher) {
+    respond(b, PATH, endpoint);
+}
+
+#[bench]
+fn respond_static_boxed_twice(b: &mut Bencher) {
+    respond(b, PATH, boxed_twice);
+}
+
+#[bench]
+fn respond_params(b: &mut Bencher) {
+    respond(b, "/resource249/7/items/3", endpoint);
+}
+
+#[bench]
+fn respond_params_boxed_twice(b: &mut Bencher) {
+    respond(b, "/resource249/7/items/3", boxed_twice);
+}
+
+/// How params were kept before: hashed, even for routes without dynamic segments.
+#[bench]
+fn params_hash_map(b: &mut Bencher) {
+    b.iter(|| {
+        let mut params = HashMap::new();
+        params.insert("id", test::black_box("7").to_string());
+        params.insert("item", test::black_box("3").to_string());
+        params.get("item").cloned()
+    });
+}
+
+#[bench]
+fn params(b: &mut Bencher) {
+    b.iter(|| {
+        let mut params = Params::default();
+        params.insert("id", test::black_box("7").to_string());
+        params.insert("item", test::black_box("3").to_string());
+        params.get("item").cloned()
+    });
+}
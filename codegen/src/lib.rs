@@ -5,8 +5,8 @@ use proc_macro2::Span;
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
 use syn::{
-    parenthesized, parse_macro_input, punctuated::Punctuated, FnArg, Generics, Ident, LitInt,
-    LitStr, Pat, PatIdent, PatType, Result, Token, Type, TypePath, Visibility,
+    parenthesized, parse_macro_input, punctuated::Punctuated, token::Paren, Expr, FnArg, Generics,
+    Ident, LitInt, LitStr, Pat, PatIdent, PatType, Path, Result, Token, Type, TypePath, Visibility,
 };
 
 trait LitIntExt {
@@ -32,6 +32,7 @@ struct Route {
     pub segments: Vec<Segment>,
     pub static_segment_positions: Vec<LitInt>,
     pub dynamic_segment_positions: Vec<LitInt>,
+    pub optional: LitInt,
 }
 
 #[derive(Debug)]
@@ -48,6 +49,8 @@ struct StaticSegment {
 #[derive(Debug)]
 struct DynamicSegment {
     pub field_name: Ident,
+    pub ty: Option<Box<Type>>,
+    pub constraint: Option<Box<Expr>>,
 }
 
 impl Parse for Route {
@@ -57,12 +60,24 @@ impl Parse for Route {
         let mut count = 0;
         let mut static_segment_positions = vec![];
         let mut dynamic_segment_positions = vec![];
+        let mut optional = 0;
 
         let segments = {
             let mut segments = vec![];
             while !input.is_empty() {
                 let lookahead = input.lookahead1();
-                if lookahead.peek(LitStr) {
+                // Only a suffix of the route can be optional, so a path leaving out one optional
+                // segment leaves out those after it too.
+                if optional > 0 && !lookahead.peek(Token![?]) && !lookahead.peek(Token![/]) {
+                    return Err(input.error("only the last segments of a route can be optional"));
+                }
+                if lookahead.peek(Token![?]) {
+                    let _: Token![?] = input.parse()?;
+                    segments.push(input.parse().map(Segment::Dynamic)?);
+                    dynamic_segment_positions.push(LitInt::from_usize(count));
+                    count += 1;
+                    optional += 1;
+                } else if lookahead.peek(LitStr) {
                     segments.push(input.parse().map(Segment::Static)?);
                     static_segment_positions.push(LitInt::from_usize(count));
                     count += 1;
@@ -83,6 +98,7 @@ impl Parse for Route {
             segments,
             static_segment_positions,
             dynamic_segment_positions,
+            optional: LitInt::from_usize(optional),
         })
     }
 }
@@ -98,8 +114,40 @@ impl Parse for StaticSegment {
 impl Parse for DynamicSegment {
     fn parse(input: ParseStream) -> Result<Self> {
         let field_name = input.parse()?;
+        let ty = match input.peek(Token![:]) {
+            true => {
+                let _: Token![:] = input.parse()?;
+                Some(input.parse()?)
+            }
+            false => None,
+        };
+        let constraint = match input.peek(Token![if]) {
+            true => {
+                let _: Token![if] = input.parse()?;
+                // A path to a function, or an expression such as a closure in parentheses, so
+                // the `/` of the next segment is not taken for a division.
+                let constraint = match input.peek(Paren) {
+                    true => {
+                        let content;
+                        let _ = parenthesized!(content in input);
+                        content.parse()?
+                    }
+                    false => Expr::Path(syn::ExprPath {
+                        attrs: vec![],
+                        qself: None,
+                        path: input.parse::<Path>()?,
+                    }),
+                };
+                Some(Box::new(constraint))
+            }
+            false => None,
+        };
 
-        Ok(Self { field_name })
+        Ok(Self {
+            field_name,
+            ty,
+            constraint,
+        })
     }
 }
 
@@ -137,11 +185,21 @@ impl Route {
 
     fn dynamic_segments(&self) -> proc_macro2::TokenStream {
         let mut dynamic_segment_names = vec![];
+        let mut dynamic_segment_types = vec![];
+        let mut dynamic_segment_constraints = vec![];
 
         self.segments.iter().for_each(|segment| match segment {
             Segment::Dynamic(dynamic_segment) => {
                 let name = &dynamic_segment.field_name.to_string();
                 dynamic_segment_names.push(quote!(#name));
+                dynamic_segment_types.push(match &dynamic_segment.ty {
+                    Some(ty) => quote!(Some(SegmentType::of::<#ty>())),
+                    None => quote!(None),
+                });
+                dynamic_segment_constraints.push(match &dynamic_segment.constraint {
+                    Some(constraint) => quote!(Some((#constraint) as fn(&str) -> bool)),
+                    None => quote!(None),
+                });
             }
             _ => {}
         });
@@ -153,6 +211,8 @@ impl Route {
                 dynamic_segments.push(DynamicSegment {
                     name: #dynamic_segment_names,
                     position: #dynamic_positions,
+                    ty: #dynamic_segment_types,
+                    constraint: #dynamic_segment_constraints,
                 });
             )*
         };
@@ -178,18 +238,50 @@ impl Route {
 /// ## Paths
 ///
 /// The `route!` macro takes a list of string literals and idents beginning with and separated by `/`.  
+///
+/// ## Typed parameters
+///
+/// A parameter can be given a type implementing `FromStr`, whose error implements `Display`.  
+/// ```ignore
+/// route!(/"users"/user_id: u64/"posts"/post_id: u64)
+/// ```
+/// The route only matches paths where the segment parses as the type, other paths fall through
+/// to the other routes.  The parsed value is available through
+/// [`Params::typed`](struct.Params.html#method.typed), alongside the raw one.  
+///
+/// ## Constraints
+///
+/// A parameter can be followed by `if` and a `fn(&str) -> bool`, either the path of a function
+/// or an expression in parentheses, such as a closure.  The route only matches paths where the
+/// function accepts the segment.  
+/// ```ignore
+/// route!(/"articles"/id if is_digits)
+/// route!(/"articles"/slug if (|slug: &str| slug.len() <= 64))
+/// ```
+///
+/// ## Optional parameters
+///
+/// The last parameters of a route can be marked optional with a leading `?`.  The route also
+/// matches paths leaving them out, and the params left out are absent from `Params`.  
+/// ```ignore
+/// route!(/"reports"/year/?month)
+/// ```
+/// Only a suffix of the route can be optional, a static segment or a parameter that is not
+/// optional cannot follow an optional one.  
 #[proc_macro]
 pub fn route(tokens: TokenStream) -> TokenStream {
     let input = parse_macro_input!(tokens as Route);
 
     let dynamic_segments = input.dynamic_segments();
     let static_segments = input.static_segments();
+    let optional = &input.optional;
 
     let output = quote! {
         Route {
             static_segments: #static_segments,
             dynamic_segments: #dynamic_segments,
-            handler: None,
+            optional: #optional,
+            ..Route::default()
         }
     };
 
@@ -239,7 +331,7 @@ impl Parse for Endpoint {
         let generated_props_calls = quote!(#(#props_calls)*);
 
         let generated_endpoint_call = quote! {
-            Ok(#fn_name(#(#fn_args),*).await?)
+            IntoResponse::into_response(#fn_name(#(#fn_args),*).await?)
         };
 
         let endpoint_fn = quote! {
@@ -274,9 +366,11 @@ fn parse_to_end(input: ParseStream) {
 /// # The macro used to generate the hidden endpoint functions.  
 ///
 /// The `#[endpoint]` macro generates a function that constructs the argument props for an endpoint in
-/// a short-circut fashion from left to right.  Finally the function invokes the endpoint, passing in
-/// the props.  The name of the function is the name of then endpoint preceeded by `___`.  
-/// 
+/// a short-circuit fashion from left to right, handing each props the request and params returned by
+/// the one before.  Finally the function invokes the endpoint, passing in
+/// the props, and turns what it returns into a response with `IntoResponse`.  The name of the
+/// function is the name of then endpoint preceeded by `___`.  
+///
 /// # Examples
 /// ```ignore
 /// #[endpoint]
@@ -291,7 +385,7 @@ fn parse_to_end(input: ParseStream) {
 /// async fn ___my_main_handler(req: http_types::Request, params: Params) -> Result<http_types::Response, Error> {
 ///     let (req, params, env) = <EnvVarsProps as Props>::call(req, params).await?;
 ///     let (req, params, body) = <Body<String> as Props>::call(req, params).await?;
-///     Ok(my_main_handler(env, body).await?)
+///     IntoResponse::into_response(my_main_handler(env, body).await?)
 /// }
 /// async fn my_main_handler(env: EnvVarsProps, body: Body<String>) -> Result<http_types::Response, Error> {
 ///     let response = http_types::Response::new(http_types::StatusCode::Ok);
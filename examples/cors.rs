@@ -0,0 +1,36 @@
+#![feature(proc_macro_hygiene)]
+
+use http_types::{Method, Response, StatusCode};
+use std::time::Duration;
+use windmill::*;
+
+fn main() {
+    let mut router = Router::new();
+    let config = Config::new("127.0.0.1:4000");
+
+    router.add(Method::Get, route!(/"orders"), ___list_orders);
+    router.add(Method::Post, route!(/"orders"), ___create_order);
+
+    // Let the frontend on port 8080 call the api, with its session cookie.
+    router.cors(
+        Cors::new()
+            .with_origin("http://localhost:8080")
+            .with_headers(&["content-type"])
+            .with_credentials()
+            .with_max_age(Duration::from_secs(600)),
+    );
+
+    if let Err(e) = Server::new(config).run(router) {
+        println!("{}", e);
+    }
+}
+
+#[endpoint]
+async fn list_orders() -> Result<Response, Error> {
+    Ok(Response::from("[]"))
+}
+
+#[endpoint]
+async fn create_order() -> Result<Response, Error> {
+    Ok(Response::new(StatusCode::Created))
+}
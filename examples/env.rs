@@ -3,7 +3,7 @@
 #[macro_use]
 extern crate lazy_static;
 
-use http_types::{Method, Request, StatusCode};
+use http_types::{Method, Request};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use windmill::*;
@@ -31,10 +31,8 @@ fn main() {
 async fn my_main_handler(env: EnvVarsProps) -> Result<http_types::Response, Error> {
     println!("env vars: {:?}", env);
     let mut response = http_types::Response::new(http_types::StatusCode::Ok);
-    let env_var_json = serde_json::to_string(env.env_vars).map_err(|e| Error {
-        code: StatusCode::InternalServerError,
-        msg: serde_json::json!(&format!("{}", e)),
-    })?;
+    let env_var_json =
+        serde_json::to_string(env.env_vars).map_err(|e| Error::internal(e.to_string()))?;
     let body = http_types::Body::from(env_var_json);
     response.set_body(body);
     Ok(response)
@@ -56,10 +54,9 @@ impl Props for EnvVarsProps {
     fn call(req: Request, params: Params) -> Self::Fut {
         Box::pin(async move {
             let props = Self {
-                env_vars: ENV_VARS.as_ref().map_err(|e| Error {
-                    code: StatusCode::InternalServerError,
-                    msg: serde_json::json!(&format!("{}", e)),
-                })?,
+                env_vars: ENV_VARS
+                    .as_ref()
+                    .map_err(|e| Error::internal(e.to_string()))?,
             };
             Ok((req, params, props))
         })
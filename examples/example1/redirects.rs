@@ -0,0 +1,21 @@
+use windmill::*;
+
+use http_types::Method;
+
+/// The routes that moved.
+pub fn router() -> Router {
+    let mut router = Router::new();
+    router.add(Method::Get, route!(/"old"), ___old);
+    router.add(Method::Get, route!(/"new"), ___new);
+    router
+}
+
+#[endpoint]
+async fn old() -> Result<Redirect, Error> {
+    Redirect::permanent("/new")
+}
+
+#[endpoint]
+async fn new() -> Result<Text, Error> {
+    Ok(Text::from("moved here from /old"))
+}
@@ -0,0 +1,95 @@
+use windmill::*;
+
+use http_types::{headers::HeaderName, Method, StatusCode};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct ExampleRequest {
+    foo: String,
+}
+
+/// The example routes.
+pub fn router() -> Router {
+    let mut router = Router::new();
+    #[rustfmt::skip]
+    router.add(Method::Get, route!(/"example"/id), ___example_route);
+    router.add(Method::Get, route!(/"hello"/name), ___hello);
+    router.add(Method::Delete, route!(/"example"/id), ___remove);
+    router
+}
+
+#[endpoint]
+async fn example_route(
+    _auth: Auth,
+    id: Id,
+    body: Body<ExampleRequest>,
+) -> Result<http_types::Response, Error> {
+    dbg!(body.into_inner());
+
+    dbg!(id.id);
+
+    Ok(http_types::Response::new(StatusCode::Ok))
+}
+
+#[endpoint]
+async fn hello() -> Result<http_types::Response, Error> {
+    Ok(http_types::Response::new(StatusCode::Ok))
+}
+
+#[endpoint]
+async fn remove(_auth: Auth, id: Id) -> Result<(), Error> {
+    println!("removed example {}", id.id);
+    Ok(())
+}
+
+struct Auth {
+    user_id: u64,
+    token: String,
+}
+
+fn parse_header(req: &http_types::Request) -> Result<String, Error> {
+    use std::str::FromStr;
+    let header_name = HeaderName::from_str("authorization")
+        .map_err(|_header_name| Error::internal("bad header name"))?;
+
+    let header = req
+        .header(&header_name)
+        .ok_or_else(|| Error::bad_request("authorization required"))?;
+
+    let header = header.first().as_ref().unwrap().to_string();
+
+    Ok(header)
+}
+
+impl Props for Auth {
+    type Fut = PropsFuture<Self>;
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let header = parse_header(&req)?;
+
+            Ok((
+                req,
+                params,
+                Self {
+                    user_id: 1,
+                    token: header,
+                },
+            ))
+        })
+    }
+}
+
+struct Id {
+    id: u64,
+}
+
+impl Props for Id {
+    type Fut = PropsFuture<Self>;
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let id = params.parse("id")?;
+
+            Ok((req, params, Self { id }))
+        })
+    }
+}
@@ -0,0 +1,30 @@
+#![feature(proc_macro_hygiene)]
+
+use async_std::{prelude::*, stream};
+use http_types::Method;
+use std::time::Duration;
+use windmill::*;
+
+fn main() {
+    let mut router = Router::new();
+    let config = Config::new("127.0.0.1:4000");
+
+    // Watch with `curl -N http://127.0.0.1:4000/counter`
+    router.add(Method::Get, route!(/"counter"), ___counter);
+
+    if let Err(e) = Server::new(config).run(router) {
+        println!("{}", e);
+    }
+}
+
+#[endpoint]
+async fn counter() -> Result<Sse, Error> {
+    let events = stream::interval(Duration::from_secs(1))
+        .enumerate()
+        .map(|(n, ())| {
+            Event::new(n.to_string())
+                .with_event("counter")
+                .with_id(n.to_string())
+        });
+    Ok(Sse::new(events))
+}
@@ -0,0 +1,29 @@
+#![feature(proc_macro_hygiene)]
+
+#[path = "../example1/routes.rs"]
+mod routes;
+
+use windmill::*;
+
+fn main() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into(), "127.0.0.1".into()])
+        .expect("unable to generate a self-signed certificate");
+    let cert_pem = cert.serialize_pem().unwrap();
+    let key_pem = cert.serialize_private_key_pem();
+
+    let cert_path = std::env::temp_dir().join("windmill-example-cert.pem");
+    std::fs::write(&cert_path, &cert_pem).expect("unable to write the certificate");
+    println!(
+        "trust the certificate with: curl --cacert {} ...",
+        cert_path.display()
+    );
+
+    let mut router = Router::new();
+    let config = Config::new("127.0.0.1:4443").with_tls_pem(cert_pem, key_pem);
+
+    routes::add(&mut router);
+
+    if let Err(e) = Server::new(config).run(router) {
+        println!("{}", e);
+    }
+}
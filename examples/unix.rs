@@ -0,0 +1,27 @@
+#![feature(proc_macro_hygiene)]
+
+use http_types::{Method, Response};
+use windmill::*;
+
+#[cfg(unix)]
+fn main() {
+    let mut router = Router::new();
+    let config = Config::unix("/tmp/windmill.sock").with_unix_permissions(0o660);
+
+    // Try with `curl --unix-socket /tmp/windmill.sock http://localhost/hello`
+    router.add(Method::Get, route!(/"hello"), ___hello);
+
+    if let Err(e) = Server::new(config).run(router) {
+        println!("{}", e);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("Unix sockets are only supported on unix");
+}
+
+#[endpoint]
+async fn hello() -> Result<Response, Error> {
+    Ok(Response::from("hello over a unix socket"))
+}
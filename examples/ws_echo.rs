@@ -0,0 +1,21 @@
+use async_std::io;
+use windmill::*;
+
+fn main() {
+    let mut router = Router::new();
+    let config = Config::new("127.0.0.1:4000");
+
+    // Connect with a WebSocket client to ws://127.0.0.1:4000/echo
+    router.add_ws(route!(/"echo"), echo);
+
+    if let Err(e) = Server::new(config).run(router) {
+        println!("{}", e);
+    }
+}
+
+async fn echo(mut ws: WebSocket) -> io::Result<()> {
+    while let Some(message) = ws.recv().await? {
+        ws.send(message).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::{
+    matched::MatchedRoute,
+    middleware::{MiddlewareFuture, Next},
+    params::Params,
+    redact::redacted_query,
+};
+use http_types::{Method, StatusCode};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// When the server finished reading the head of a request, attached to it by the server.
+#[derive(Clone, Copy)]
+pub(crate) struct ReceivedAt(pub(crate) Instant);
+
+impl ReceivedAt {
+    /// When `req` was received, or now if it did not come through the server.
+    pub(crate) fn of(req: &http_types::Request) -> Instant {
+        req.local()
+            .get::<Self>()
+            .map_or_else(Instant::now, |received| received.0)
+    }
+}
+
+/// One request as seen by [`access_log`](fn.access_log.html), once it has been answered.
+#[derive(Clone, Debug)]
+pub struct AccessRecord {
+    method: Method,
+    path: String,
+    query: Option<String>,
+    route: Option<String>,
+    status: StatusCode,
+    size: Option<usize>,
+    latency: Duration,
+}
+
+impl AccessRecord {
+    /// The method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The path of the request, as it was sent.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The query string of the request, if it has one, with the parameters the server's
+    /// [redaction policy](struct.Config.html#method.with_redaction) names redacted.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// The pattern of the route that matched the request, such as `/users/{id}`, if one did.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The length of the response body, if it is known before the body is sent.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// How long the request took, from when the server read its head until the response was
+    /// ready, so the time spent reading a slow upload is counted.  Requests dispatched with
+    /// [`Router::respond`](struct.Router.html#method.respond) are timed from when the middleware
+    /// ran.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+}
+
+/// A middleware that reports every request to `sink` as an [`AccessRecord`](struct.AccessRecord.html),
+/// with its method, path and redacted query, matched route, response status and size, and
+/// latency.
+///
+/// Requests that match no route are reported with no route, and endpoints that panic are
+/// reported with the `500 Internal Server Error` they were answered with.  The sink can format
+/// the record however it likes, or hand it to a logging library.  Added first with
+/// [`Router::middleware`](struct.Router.html#method.middleware), it also sees the responses of
+/// the middlewares after it.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use std::sync::{Arc, Mutex};
+/// async fn user(_req: http_types::Request, params: Params) -> Result<Response, Error> {
+///     Ok(Response::from(params.get("id").unwrap().to_owned()))
+/// }
+///
+/// async fn broken(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+///     panic!("broken");
+/// }
+///
+/// let records = Arc::new(Mutex::new(vec![]));
+/// let sink = records.clone();
+///
+/// let mut router = Router::new();
+/// router.middleware(access_log(move |record: &AccessRecord| {
+///     sink.lock().unwrap().push(record.clone())
+/// }));
+/// router.add(Method::Get, Route::from("/users/{id}"), user);
+/// router.add(Method::Get, Route::from("/broken"), broken);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     client.get("/users/7").send().await;
+///     client.get("/missing").send().await;
+///     client.get("/broken").send().await;
+/// });
+///
+/// let records = records.lock().unwrap();
+/// assert_eq!(records[0].method(), Method::Get);
+/// assert_eq!(records[0].path(), "/users/7");
+/// assert_eq!(records[0].route(), Some("/users/{id}"));
+/// assert_eq!(records[0].status(), StatusCode::Ok);
+/// assert_eq!(records[0].size(), Some(1));
+///
+/// assert_eq!(records[1].route(), None);
+/// assert_eq!(records[1].status(), StatusCode::NotFound);
+///
+/// assert_eq!(records[2].route(), Some("/broken"));
+/// assert_eq!(records[2].status(), StatusCode::InternalServerError);
+/// ```
+///
+/// Query parameters the server's [redaction policy](struct.Config.html#method.with_redaction)
+/// names are redacted before the record reaches the sink:
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use std::sync::{Arc, Mutex};
+/// let records = Arc::new(Mutex::new(vec![]));
+/// let sink = records.clone();
+///
+/// let mut router = Router::new();
+/// router.middleware(access_log(move |record: &AccessRecord| {
+///     sink.lock().unwrap().push(record.clone())
+/// }));
+/// let config = Config::new("127.0.0.1:0").with_redaction(Redaction::new().query_param("token"));
+/// let client = TestClient::new(router).with_config(config);
+///
+/// task::block_on(async {
+///     client.get("/search?q=rust&token=secret").send().await;
+///     client.get("/search?tok%65n=secret").send().await;
+///     client.get("/search").send().await;
+/// });
+///
+/// let records = records.lock().unwrap();
+/// assert_eq!(records[0].path(), "/search");
+/// assert_eq!(records[0].query(), Some("q=rust&token=[REDACTED]"));
+/// assert_eq!(records[1].query(), Some("tok%65n=[REDACTED]"));
+/// assert_eq!(records[2].query(), None);
+/// ```
+pub fn access_log<F>(
+    sink: F,
+) -> impl Fn(http_types::Request, Params, Next) -> MiddlewareFuture + Send + Sync + 'static
+where
+    F: Fn(&AccessRecord) + Send + Sync + 'static,
+{
+    let sink = Arc::new(sink);
+    move |req, params, next| {
+        let sink = sink.clone();
+        let start = ReceivedAt::of(&req);
+        let method = req.method();
+        let path = req.url().path().to_owned();
+        let query = redacted_query(&req);
+        let route = MatchedRoute::of(&req).map(|route| route.pattern().to_owned());
+        Box::pin(async move {
+            let res = next.run(req, params).await;
+            sink(&AccessRecord {
+                method,
+                path,
+                query,
+                route,
+                status: res.status(),
+                size: res.len(),
+                latency: start.elapsed(),
+            });
+            res
+        })
+    }
+}
@@ -0,0 +1,318 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+/// The class of routes that were not given one.
+pub(crate) const DEFAULT_CLASS: &str = "default";
+
+/// A snapshot of the load on a class of routes.
+///
+/// Produced by [`Router::class_metrics`](struct.Router.html#method.class_metrics).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClassMetrics {
+    /// The name of the class.
+    pub class: String,
+    /// The weight of the class.
+    pub weight: u32,
+    /// The number of requests of the class whose endpoint is running.
+    pub in_flight: usize,
+    /// The number of requests of the class waiting to be admitted.
+    pub queued: usize,
+}
+
+/// Admits requests to their endpoints in proportion to the weights of their route classes.
+///
+/// Every class is guaranteed a share of the capacity proportional to its weight, at least one
+/// request.  A class may borrow capacity beyond its share, but never the unused share of another
+/// class, so a saturated class cannot starve the others.  When capacity frees up it goes to the
+/// waiting class with the fewest in-flight requests relative to its weight.
+pub(crate) struct Admission {
+    state: Mutex<State>,
+}
+
+struct State {
+    capacity: Option<usize>,
+    in_flight: usize,
+    next_id: u64,
+    classes: HashMap<&'static str, Class>,
+}
+
+struct Class {
+    weight: u32,
+    in_flight: usize,
+    queue: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    id: u64,
+    waker: Option<Waker>,
+}
+
+impl Admission {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                capacity: None,
+                in_flight: 0,
+                next_id: 0,
+                classes: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.lock().capacity = Some(capacity.max(1));
+    }
+
+    pub(crate) fn set_weight(&self, class: &'static str, weight: u32) {
+        self.lock()
+            .classes
+            .entry(class)
+            .or_insert_with(|| Class::new(weight))
+            .weight = weight.max(1);
+    }
+
+    pub(crate) fn register(&self, class: &'static str) {
+        self.lock()
+            .classes
+            .entry(class)
+            .or_insert_with(|| Class::new(1));
+    }
+
+    pub(crate) fn is_limited(&self) -> bool {
+        self.lock().capacity.is_some()
+    }
+
+    /// Wait until a request of `class` may run, returning a permit that frees its slot on drop.
+    pub(crate) fn acquire(self: &Arc<Self>, class: &'static str) -> Acquire {
+        Acquire {
+            admission: self.clone(),
+            class,
+            id: None,
+            done: false,
+        }
+    }
+
+    /// The weight of `class`, the capacity, and the share of the capacity reserved for `class`.
+    pub(crate) fn limits(&self, class: &str) -> (u32, Option<usize>, Option<usize>) {
+        let state = self.lock();
+        match state.classes.get(class) {
+            Some(class) => (
+                class.weight,
+                state.capacity,
+                state.capacity.map(|capacity| state.share(class, capacity)),
+            ),
+            None => (1, state.capacity, None),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<ClassMetrics> {
+        let mut metrics: Vec<ClassMetrics> = self
+            .lock()
+            .classes
+            .iter()
+            .map(|(name, class)| ClassMetrics {
+                class: name.to_string(),
+                weight: class.weight,
+                in_flight: class.in_flight,
+                queued: class.queue.len(),
+            })
+            .collect();
+        metrics.sort_by(|a, b| a.class.cmp(&b.class));
+        metrics
+    }
+
+    fn release(&self, class: &'static str) {
+        let mut state = self.lock();
+        state.in_flight -= 1;
+        if let Some(class) = state.classes.get_mut(class) {
+            class.in_flight -= 1;
+        }
+        state.admit_waiting();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Class {
+    fn new(weight: u32) -> Self {
+        Self {
+            weight: weight.max(1),
+            in_flight: 0,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl State {
+    fn share(&self, class: &Class, capacity: usize) -> usize {
+        let total: u32 = self.classes.values().map(|class| class.weight).sum();
+        (capacity * class.weight as usize / total as usize).max(1)
+    }
+
+    fn can_admit(&self, name: &str) -> bool {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return true,
+        };
+        if self.in_flight >= capacity {
+            return false;
+        }
+
+        let class = &self.classes[name];
+        if class.in_flight < self.share(class, capacity) {
+            return true;
+        }
+
+        let reserved: usize = self
+            .classes
+            .iter()
+            .filter(|(other, _)| **other != name)
+            .map(|(_, other)| self.share(other, capacity).saturating_sub(other.in_flight))
+            .sum();
+        self.in_flight + reserved < capacity
+    }
+
+    fn admit(&mut self, name: &'static str) {
+        self.in_flight += 1;
+        self.classes.get_mut(name).unwrap().in_flight += 1;
+    }
+
+    /// Admit waiting requests, always picking the class that is furthest below its share.
+    fn admit_waiting(&mut self) {
+        loop {
+            let next = self
+                .classes
+                .iter()
+                .filter(|(name, class)| !class.queue.is_empty() && self.can_admit(name))
+                .min_by(|(_, a), (_, b)| {
+                    (a.in_flight * b.weight as usize).cmp(&(b.in_flight * a.weight as usize))
+                })
+                .map(|(name, _)| *name);
+
+            let name = match next {
+                Some(name) => name,
+                None => return,
+            };
+            self.admit(name);
+            let waiter = self
+                .classes
+                .get_mut(name)
+                .unwrap()
+                .queue
+                .pop_front()
+                .unwrap();
+            if let Some(waker) = waiter.waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// The future returned by [`Admission::acquire`](struct.Admission.html#method.acquire).
+pub(crate) struct Acquire {
+    admission: Arc<Admission>,
+    class: &'static str,
+    id: Option<u64>,
+    done: bool,
+}
+
+/// A slot held by a running request.
+pub(crate) struct Permit {
+    admission: Arc<Admission>,
+    class: &'static str,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let class = self.class;
+        let mut state = self.admission.lock();
+
+        let admitted = match self.id {
+            // Queued: admitted once the waiter has left the queue.
+            Some(id) => match state
+                .classes
+                .get_mut(class)
+                .unwrap()
+                .queue
+                .iter_mut()
+                .find(|w| w.id == id)
+            {
+                Some(waiter) => {
+                    waiter.waker = Some(cx.waker().clone());
+                    false
+                }
+                None => true,
+            },
+            None => {
+                if state.classes[class].queue.is_empty() && state.can_admit(class) {
+                    state.admit(class);
+                    true
+                } else {
+                    let id = state.next_id;
+                    state.next_id += 1;
+                    state
+                        .classes
+                        .get_mut(class)
+                        .unwrap()
+                        .queue
+                        .push_back(Waiter {
+                            id,
+                            waker: Some(cx.waker().clone()),
+                        });
+                    drop(state);
+                    self.id = Some(id);
+                    return Poll::Pending;
+                }
+            }
+        };
+        drop(state);
+
+        if admitted {
+            self.done = true;
+            Poll::Ready(Permit {
+                admission: self.admission.clone(),
+                class,
+            })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Acquire {
+    /// Give up a queued slot, or a slot that was granted but never picked up.
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Some(id) = self.id {
+            let mut state = self.admission.lock();
+            let queue = &mut state.classes.get_mut(self.class).unwrap().queue;
+            match queue.iter().position(|w| w.id == id) {
+                Some(position) => {
+                    queue.remove(position);
+                }
+                None => {
+                    drop(state);
+                    self.admission.release(self.class);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.admission.release(self.class);
+    }
+}
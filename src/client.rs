@@ -0,0 +1,483 @@
+use crate::{
+    config::Config,
+    error::{request_head, Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    timeout::Deadline,
+};
+use async_std::{
+    future,
+    io::{self, prelude::*},
+    net::TcpStream,
+};
+use http_types::{
+    headers::{HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// A pooled HTTP/1.1 client for making outbound requests from endpoints.
+///
+/// Connections are kept open per host and reused by later requests.  Register a client on the
+/// server with [`Config::with_http_client`](struct.Config.html#method.with_http_client) and take
+/// it as a props in an endpoint; the props copies the configured propagation headers from the
+/// incoming request onto every outbound request, and holds outbound requests to the
+/// [`Deadline`](struct.Deadline.html) of the incoming one when its route has a handler timeout.
+///
+/// ## Examples
+/// ```no_run
+/// # use windmill::*;
+/// # use http_types::{Method, Request, Response, Url};
+/// # use std::time::Duration;
+/// #[endpoint]
+/// async fn proxy(client: HttpClient) -> Result<Response, Error> {
+///     let url = Url::parse("http://127.0.0.1:5000/upstream").unwrap();
+///     client.send(Request::new(Method::Get, url)).await
+/// }
+///
+/// let client = HttpClient::new()
+///     .timeout(Duration::from_secs(5))
+///     .propagate("x-request-id");
+/// let config = Config::new("127.0.0.1:4000").with_http_client(client);
+/// ```
+#[derive(Clone)]
+pub struct HttpClient {
+    pool: Arc<Pool>,
+    timeout: Option<Duration>,
+    propagate: Vec<HeaderName>,
+    propagated: Vec<(HeaderName, Vec<HeaderValue>)>,
+    deadline: Option<Instant>,
+}
+
+struct Pool {
+    idle: Mutex<HashMap<String, Vec<TcpStream>>>,
+    max_idle_per_host: usize,
+    opened: AtomicUsize,
+}
+
+impl HttpClient {
+    /// Create a client that keeps up to 8 idle connections per host.
+    pub fn new() -> Self {
+        Self::with_max_idle_per_host(8)
+    }
+
+    /// Create a client that keeps up to `max` idle connections per host.
+    pub fn with_max_idle_per_host(max: usize) -> Self {
+        Self {
+            pool: Arc::new(Pool {
+                idle: Mutex::new(HashMap::new()),
+                max_idle_per_host: max,
+                opened: AtomicUsize::new(0),
+            }),
+            timeout: None,
+            propagate: vec![],
+            propagated: vec![],
+            deadline: None,
+        }
+    }
+
+    /// Fail requests that take longer than `timeout`, including connecting and reading the body.
+    /// A client taken as a props fails them sooner if the deadline of the incoming request comes
+    /// first.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Copy the header `name` from the incoming request onto outbound requests.
+    ///
+    /// ## Panics
+    /// Panics if `name` is not a valid header name.
+    pub fn propagate(mut self, name: &str) -> Self {
+        self.propagate
+            .push(HeaderName::from_str(name).expect("invalid header name"));
+        self
+    }
+
+    /// Send a request, returning the response with its body read into memory.
+    ///
+    /// Only `http` URLs are supported.  An idle connection to the host is reused when one is
+    /// available, and the connection is returned to the pool once the body has been read.  An
+    /// idempotent request that fails on a reused connection before any of the response arrives,
+    /// as when the upstream closed the connection while it sat idle, is sent once more on a new
+    /// connection.
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::{io::{BufRead, BufReader, Write}, sync::atomic::{AtomicU16, Ordering}, time::Duration};
+    /// // An upstream that answers `per_connection` requests on each connection after `delay`, with
+    /// // the `x-request-id` it was sent, then drops the connection without saying so.
+    /// fn upstream(per_connection: usize, delay: Duration) -> std::net::SocketAddr {
+    ///     let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    ///     let addr = listener.local_addr().unwrap();
+    ///     std::thread::spawn(move || {
+    ///         for stream in listener.incoming() {
+    ///             let mut stream = stream.unwrap();
+    ///             let mut reader = BufReader::new(stream.try_clone().unwrap());
+    ///             std::thread::spawn(move || {
+    ///                 for _ in 0..per_connection {
+    ///                     let mut id = String::new();
+    ///                     loop {
+    ///                         let mut line = String::new();
+    ///                         if reader.read_line(&mut line).unwrap_or(0) == 0 {
+    ///                             return;
+    ///                         }
+    ///                         if line == "\r\n" {
+    ///                             break;
+    ///                         }
+    ///                         if let Some(value) = line.to_lowercase().strip_prefix("x-request-id:") {
+    ///                             id = value.trim().to_owned();
+    ///                         }
+    ///                     }
+    ///                     std::thread::sleep(delay);
+    ///                     let res = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", id.len(), id);
+    ///                     let _ = stream.write_all(res.as_bytes());
+    ///                 }
+    ///             });
+    ///         }
+    ///     });
+    ///     addr
+    /// }
+    ///
+    /// static UPSTREAM: AtomicU16 = AtomicU16::new(0);
+    ///
+    /// #[endpoint]
+    /// async fn proxy(client: HttpClient) -> Result<Response, Error> {
+    ///     let url = format!("http://127.0.0.1:{}/", UPSTREAM.load(Ordering::SeqCst));
+    ///     match client.send(Request::new(Method::Get, Url::parse(&url).unwrap())).await {
+    ///         Ok(res) => Ok(res),
+    ///         // Answered before the handler timeout would have.
+    ///         Err(e) => Ok(Response::from(format!("fallback: {}", e.error_code().unwrap().as_str()))),
+    ///     }
+    /// }
+    ///
+    /// task::block_on(async {
+    ///     // Connections are reused.
+    ///     let client = HttpClient::new();
+    ///     let url = Url::parse(&format!("http://{}/", upstream(usize::MAX, Duration::from_millis(0)))).unwrap();
+    ///     for _ in 0..3 {
+    ///         let res = client.send(Request::new(Method::Get, url.clone())).await.unwrap();
+    ///         assert_eq!(res.status(), StatusCode::Ok);
+    ///     }
+    ///     assert_eq!(client.opened_connections(), 1);
+    ///
+    ///     // A request on a connection the upstream dropped is sent again on a new one.
+    ///     let client = HttpClient::new();
+    ///     let url = Url::parse(&format!("http://{}/", upstream(1, Duration::from_millis(0)))).unwrap();
+    ///     for _ in 0..2 {
+    ///         let res = client.send(Request::new(Method::Get, url.clone())).await.unwrap();
+    ///         assert_eq!(res.status(), StatusCode::Ok);
+    ///     }
+    ///     assert_eq!(client.opened_connections(), 2);
+    /// });
+    ///
+    /// UPSTREAM.store(upstream(usize::MAX, Duration::from_millis(300)).port(), Ordering::SeqCst);
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"proxy"), ___proxy);
+    /// router.add(Method::Get, route!(/"proxy"/"hurried").handler_timeout(Duration::from_millis(100)), ___proxy);
+    /// let client = HttpClient::new()
+    ///     .timeout(Duration::from_secs(5))
+    ///     .propagate("x-request-id");
+    /// let config = Config::new("127.0.0.1:0").with_http_client(client);
+    /// let client = TestClient::new(router).with_config(config);
+    ///
+    /// task::block_on(async {
+    ///     // The request id is passed on upstream.
+    ///     let mut res = client.get("/proxy").header("x-request-id", "abc123").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     assert_eq!(res.body_string().await.unwrap(), "abc123");
+    ///
+    ///     // The upstream request gives up at the deadline of the incoming one.
+    ///     let mut res = client.get("/proxy/hurried").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     assert_eq!(res.body_string().await.unwrap(), "fallback: upstream_timeout");
+    /// });
+    /// ```
+    pub async fn send(&self, mut req: http_types::Request) -> Result<http_types::Response, Error> {
+        for (name, values) in self.propagated.iter() {
+            if req.header(name).is_none() {
+                let _ = req.insert_header(name.clone(), &values[..]);
+            }
+        }
+
+        match self.budget() {
+            Some(timeout) => future::timeout(timeout, self.send_pooled(req))
+                .await
+                .map_err(|_| {
+                    Error::new(StatusCode::GatewayTimeout, "upstream request timed out")
+                        .with_code(WindmillErrorCode::UpstreamTimeout)
+                })?,
+            None => self.send_pooled(req).await,
+        }
+    }
+
+    /// The number of connections this client has opened so far.
+    pub fn opened_connections(&self) -> usize {
+        self.pool.opened.load(Ordering::SeqCst)
+    }
+
+    /// The number of idle connections currently held in the pool.
+    pub fn idle_connections(&self) -> usize {
+        self.pool.idle().values().map(Vec::len).sum()
+    }
+
+    /// Close every idle pooled connection.
+    pub fn close_idle(&self) {
+        for (_, streams) in self.pool.idle().drain() {
+            for stream in streams {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+
+    async fn send_pooled(
+        &self,
+        mut req: http_types::Request,
+    ) -> Result<http_types::Response, Error> {
+        let url = req.url();
+        if url.scheme() != "http" {
+            return Err(upstream_error(format!(
+                "unsupported scheme {}, only http is supported",
+                url.scheme()
+            )));
+        }
+        let host = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or(80)
+        );
+
+        let stream = match self.pool.take(&host) {
+            Some(stream) => stream,
+            None => {
+                let stream = self.connect(&host).await?;
+                return self.exchange(host, stream, req).await.map_err(|(e, _)| e);
+            }
+        };
+
+        // The upstream may have closed a reused connection while it sat idle.
+        let retry = match idempotent(req.method()) {
+            true => Some(replayable(&mut req).await?),
+            false => None,
+        };
+        match (self.exchange(host.clone(), stream, req).await, retry) {
+            (Err((_, false)), Some(retry)) => {
+                let stream = self.connect(&host).await?;
+                self.exchange(host, stream, retry).await.map_err(|(e, _)| e)
+            }
+            (res, _) => res.map_err(|(e, _)| e),
+        }
+    }
+
+    /// Send `req` over `stream` and read the response, pooling the connection unless the
+    /// upstream closes it.  A failure comes with whether any of the response had arrived.
+    async fn exchange(
+        &self,
+        host: String,
+        stream: TcpStream,
+        req: http_types::Request,
+    ) -> Result<http_types::Response, (Error, bool)> {
+        let conn = CountedStream {
+            stream: stream.clone(),
+            read: Arc::new(AtomicUsize::new(0)),
+        };
+        let mut res = match async_h1::client::connect(conn.clone(), req).await {
+            Ok(res) => res,
+            Err(e) => {
+                let answered = conn.read.load(Ordering::SeqCst) > 0;
+                return Err((upstream_error(e.to_string()), answered));
+            }
+        };
+
+        let mut body = vec![];
+        if let Err(e) = res.take_body().read_to_end(&mut body).await {
+            return Err((upstream_error(e.to_string()), true));
+        }
+        res.set_body(body);
+
+        let close = res
+            .header(&HeaderName::from_str("connection").unwrap())
+            .map(|values| {
+                values
+                    .iter()
+                    .any(|v| v.as_str().eq_ignore_ascii_case("close"))
+            })
+            .unwrap_or(false);
+        if !close {
+            self.pool.put(host, stream);
+        }
+
+        Ok(res)
+    }
+
+    async fn connect(&self, host: &str) -> Result<TcpStream, Error> {
+        let stream = TcpStream::connect(host)
+            .await
+            .map_err(|e| upstream_error(format!("unable to connect to {}: {}", host, e)))?;
+        self.pool.opened.fetch_add(1, Ordering::SeqCst);
+        Ok(stream)
+    }
+
+    /// How long a request may take: the timeout, cut short by the deadline of the incoming
+    /// request.
+    fn budget(&self) -> Option<Duration> {
+        let remaining = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match (self.timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        }
+    }
+
+    fn with_propagated(&self, req: &http_types::Request) -> Self {
+        let mut client = self.clone();
+        client.propagated = self
+            .propagate
+            .iter()
+            .filter_map(|name| {
+                req.header(name)
+                    .map(|values| (name.clone(), values.clone()))
+            })
+            .collect();
+        client.deadline = Deadline::of(req).instant();
+        client
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pool {
+    fn idle(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<TcpStream>>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take(&self, host: &str) -> Option<TcpStream> {
+        self.idle().get_mut(host).and_then(|streams| streams.pop())
+    }
+
+    fn put(&self, host: String, stream: TcpStream) {
+        let mut idle = self.idle();
+        let streams = idle.entry(host).or_default();
+        if streams.len() < self.max_idle_per_host {
+            streams.push(stream);
+        }
+    }
+}
+
+impl Props for HttpClient {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let client = req
+                .local()
+                .get::<Arc<Config>>()
+                .and_then(|config| config.http_client())
+                .map(|client| client.with_propagated(&req))
+                .ok_or_else(|| {
+                    Error::internal("no http client configured")
+                        .with_code(WindmillErrorCode::Internal)
+                })?;
+
+            Ok((req, params, client))
+        })
+    }
+}
+
+/// A connection that counts the bytes read from it, to tell whether a request that failed had
+/// started to be answered.
+#[derive(Clone)]
+struct CountedStream {
+    stream: TcpStream,
+    read: Arc<AtomicUsize>,
+}
+
+impl io::Read for CountedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut &self.stream).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.read.fetch_add(n, Ordering::SeqCst);
+        }
+        poll
+    }
+}
+
+impl io::Write for CountedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut &self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &self.stream).poll_close(cx)
+    }
+}
+
+/// Whether sending `method` twice has the same effect as sending it once.
+fn idempotent(method: Method) -> bool {
+    match method {
+        Method::Get
+        | Method::Head
+        | Method::Put
+        | Method::Delete
+        | Method::Options
+        | Method::Trace => true,
+        _ => false,
+    }
+}
+
+/// Read the body of `req` into memory so it can be sent twice, returning a copy to send again.
+async fn replayable(req: &mut http_types::Request) -> Result<http_types::Request, Error> {
+    let mut body = vec![];
+    req.take_body()
+        .read_to_end(&mut body)
+        .await
+        .map_err(|e| upstream_error(e.to_string()))?;
+    let mut copy = request_head(req);
+    set_body(&mut copy, body.clone());
+    set_body(req, body);
+    Ok(copy)
+}
+
+/// Set the body of `req` without adding a content type it was sent without.
+fn set_body(req: &mut http_types::Request, body: Vec<u8>) {
+    let typed = req.header(&CONTENT_TYPE).is_some();
+    req.set_body(body);
+    if !typed {
+        req.remove_header(&CONTENT_TYPE);
+    }
+}
+
+fn upstream_error(msg: String) -> Error {
+    Error::new(StatusCode::BadGateway, msg).with_code(WindmillErrorCode::UpstreamFailed)
+}
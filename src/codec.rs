@@ -0,0 +1,1115 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    query::{from_form, Values},
+    scratch::ScratchBuffer,
+    util::{max_body_size, read_body, read_body_into, ReadBody},
+};
+use http_types::{mime, Mime, StatusCode};
+use serde::{
+    de::{value::UnitDeserializer, DeserializeOwned, IntoDeserializer},
+    Deserialize, Serialize,
+};
+use std::{fmt, marker::PhantomData, ops::Deref, sync::Arc};
+
+/// A format for request and response bodies.
+///
+/// A codec is chosen per route with [`Router::add_with_codec`](struct.Router.html#method.add_with_codec),
+/// which encodes error bodies with it, and per argument with [`Body`](struct.Body.html), which
+/// decodes the request body with it.  [`JsonCodec`](struct.JsonCodec.html) is the default.
+///
+/// ## Examples
+/// A codec for comma separated lists of numbers:
+/// ```
+/// # use windmill::*;
+/// # use http_types::{mime, Mime, StatusCode};
+/// # use serde::{de::{DeserializeOwned, IntoDeserializer, value::SeqDeserializer}, Serialize};
+/// #[derive(Clone, Copy, Default)]
+/// struct CsvCodec;
+///
+/// impl Codec for CsvCodec {
+///     fn mime(&self) -> Mime {
+///         "text/csv".parse().unwrap()
+///     }
+///
+///     fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+///         let text = String::from_utf8_lossy(bytes);
+///         let numbers: Vec<u64> = text.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+///         let de: SeqDeserializer<_, serde::de::value::Error> = numbers.into_deserializer();
+///         T::deserialize(de).map_err(|e| {
+///             Error::bad_request(e.to_string()).with_code(WindmillErrorCode::DeserializeFailed)
+///         })
+///     }
+///
+///     fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+///         TextCodec.encode(value)
+///     }
+/// }
+///
+/// let numbers: Vec<u64> = CsvCodec.decode(b"1, 2,3").unwrap();
+/// assert_eq!(numbers, vec![1, 2, 3]);
+/// ```
+pub trait Codec: Send + Sync + 'static {
+    /// The content type of bodies in this format.
+    fn mime(&self) -> Mime;
+
+    /// Decode a body, failing with `400 Bad Request` if it is not a valid `T`.
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error>;
+
+    /// Encode a value as a body.
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error>;
+
+    /// Create a `200 OK` response with `value` encoded as its body.
+    fn respond<T: Serialize>(&self, value: &T) -> Result<http_types::Response, Error> {
+        let body = self.encode(value)?;
+        let mut res = http_types::Response::new(StatusCode::Ok);
+        let _ = res.set_content_type(self.mime());
+        res.set_body(body);
+        Ok(res)
+    }
+}
+
+/// The JSON codec, used unless another one is chosen.
+///
+/// A body that cannot be decoded is rejected with `400 Bad Request`, with a message telling the
+/// client what went wrong and where: the error of `serde_json`, the line, column and byte offset
+/// it was found at, and the path of the field it was found in, such as `items[1].price`.  The path
+/// is left out when the error is not in a field, and ends with the name of a missing field.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Item {
+///     name: String,
+///     price: u64,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Order {
+///     items: Vec<Item>,
+/// }
+///
+/// #[endpoint]
+/// async fn place(order: Body<Order>) -> Result<Response, Error> {
+///     Ok(Response::from(format!("{} items", order.items.len())))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"orders"), ___place);
+/// let client = &TestClient::new(router);
+///
+/// let post = |body: &'static str| async move {
+///     let mut res = client.post("/orders").body(body).send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///     assert_eq!(body["code"], "deserialize_failed");
+///     body["msg"].clone()
+/// };
+///
+/// task::block_on(async {
+///     let msg = post(r#"{"items": [}"#).await;
+///     assert_eq!(msg["message"], "expected value");
+///     assert_eq!((msg["line"].as_u64(), msg["column"].as_u64()), (Some(1), Some(12)));
+///     assert_eq!(msg["offset"], 11);
+///     assert_eq!(msg["path"], "items[0]");
+///
+///     let msg = post(r#"{"items": [{"name": "tea", "price": 3}, {"name": "cake", "price": "4"}]}"#).await;
+///     assert_eq!(msg["message"], r#"invalid type: string "4", expected u64"#);
+///     assert_eq!(msg["path"], "items[1].price");
+///
+///     let msg = post(r#"{"items": [{"name": "tea"}]}"#).await;
+///     assert_eq!(msg["message"], "missing field `price`");
+///     assert_eq!(msg["path"], "items[0].price");
+///
+///     let msg = post(r#"{"items": []"#).await;
+///     assert_eq!(msg["message"], "EOF while parsing an object");
+///     assert_eq!(msg["offset"], 11);
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn mime(&self) -> Mime {
+        mime::JSON
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        serde_json::from_slice(bytes).map_err(|e| {
+            Error::bad_request(json_error(&e, Some(bytes)))
+                .with_code(WindmillErrorCode::DeserializeFailed)
+        })
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(encode_error::<T>)
+    }
+}
+
+/// A codec for `text/plain` bodies.
+///
+/// Bodies decode into anything that can be parsed from a single string, such as a `String` or a
+/// number.  Strings, numbers, booleans and unit encode as their plain text, other values cannot be
+/// encoded.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[endpoint]
+/// async fn shout(body: Body<String, TextCodec>) -> Result<Response, Error> {
+///     TextCodec.respond(&body.to_uppercase())
+/// }
+///
+/// let mut router = Router::new();
+/// router.add_with_codec(Method::Post, route!(/"shout"), ___shout, TextCodec);
+/// let router = Arc::new(router);
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/shout").unwrap());
+/// req.insert_header("content-length", "5").unwrap();
+/// req.set_body("hello");
+/// let mut res = task::block_on(router.respond(req));
+///
+/// assert!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str().starts_with("text/plain"));
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "HELLO");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextCodec;
+
+impl Codec for TextCodec {
+    fn mime(&self) -> Mime {
+        mime::PLAIN
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        let text = String::from_utf8(bytes.to_vec()).map_err(|e| {
+            Error::bad_request(format!("invalid text body: {}", e))
+                .with_code(WindmillErrorCode::DeserializeFailed)
+        })?;
+
+        T::deserialize(Values::one(text)).map_err(|e| {
+            Error::bad_request(format!("invalid text body: {}", e))
+                .with_code(WindmillErrorCode::DeserializeFailed)
+        })
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match serde_json::to_value(value).map_err(encode_error::<T>)? {
+            serde_json::Value::String(text) => Ok(text.into_bytes()),
+            serde_json::Value::Null => Ok(vec![]),
+            value @ serde_json::Value::Number(_) | value @ serde_json::Value::Bool(_) => {
+                Ok(value.to_string().into_bytes())
+            }
+            _ => Err(
+                Error::internal("only strings, numbers and booleans can be encoded as text")
+                    .with_code(WindmillErrorCode::Internal),
+            ),
+        }
+    }
+}
+
+/// A codec for `application/x-www-form-urlencoded` bodies, as posted by HTML forms.
+///
+/// Fields that may be missing should be `Option`s, and fields that may repeat should be `Vec`s.
+/// Only maps and structs of strings, numbers and booleans can be encoded.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct Command {
+///     command: String,
+///     text: String,
+///     channel: Vec<String>,
+/// }
+///
+/// let command: Command = FormCodec
+///     .decode(b"command=%2Fweather&text=94070+tomorrow&channel=a&channel=b")
+///     .unwrap();
+/// assert_eq!(command, Command {
+///     command: "/weather".into(),
+///     text: "94070 tomorrow".into(),
+///     channel: vec!["a".into(), "b".into()],
+/// });
+///
+/// let err = FormCodec.decode::<Command>(b"command=%2Fweather").unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::BadRequest);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FormCodec;
+
+impl Codec for FormCodec {
+    fn mime(&self) -> Mime {
+        mime::FORM
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        from_form(&String::from_utf8_lossy(bytes))
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let fields = match serde_json::to_value(value).map_err(encode_error::<T>)? {
+            serde_json::Value::Object(fields) => fields,
+            _ => {
+                return Err(
+                    Error::internal("only maps and structs can be encoded as a form")
+                        .with_code(WindmillErrorCode::Internal),
+                )
+            }
+        };
+
+        let mut url = http_types::Url::parse("http://localhost/").unwrap();
+        {
+            let mut pairs = url.query_pairs_mut();
+            for (name, value) in fields.iter() {
+                let values = match value {
+                    serde_json::Value::Array(values) => values.iter().collect(),
+                    value => vec![value],
+                };
+                for value in values {
+                    match value {
+                        serde_json::Value::Null => {}
+                        serde_json::Value::String(value) => {
+                            pairs.append_pair(name, value);
+                        }
+                        value => {
+                            pairs.append_pair(name, &value.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(url.query().unwrap_or_default().as_bytes().to_vec())
+    }
+}
+
+/// A codec for `application/msgpack` bodies, a compact binary encoding of the same values as JSON.
+///
+/// Structs are encoded as maps keyed by their field names, so either side can add fields without
+/// breaking the other.  A body that cannot be decoded is rejected with `400 Bad Request` and the
+/// error of `rmp-serde` as its message.  Only available with the `msgpack` feature.
+///
+/// ## Examples
+/// A route that takes and answers MessagePack:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{prelude::*, task};
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Reading {
+///     sensor: String,
+///     values: Vec<f64>,
+/// }
+///
+/// #[endpoint]
+/// async fn calibrate(reading: Body<Reading, MsgPackCodec>) -> Result<Response, Error> {
+///     let mut reading = reading.into_inner();
+///     reading.values.iter_mut().for_each(|value| *value *= 2.0);
+///     MsgPackCodec.respond(&reading)
+/// }
+///
+/// let mut router = Router::new();
+/// router.add_with_codec(Method::Post, route!(/"calibrate"), ___calibrate, MsgPackCodec);
+/// let client = TestClient::new(router);
+/// let reading = Reading { sensor: "t1".into(), values: vec![1.5, 20.0] };
+///
+/// task::block_on(async {
+///     let body = MsgPackCodec.encode(&reading).unwrap();
+///     let mut res = client
+///         .post("/calibrate")
+///         .header("content-type", "application/msgpack")
+///         .body(body)
+///         .send()
+///         .await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     assert_eq!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str(), "application/msgpack");
+///     let mut bytes = vec![];
+///     res.read_to_end(&mut bytes).await.unwrap();
+///     let reading: Reading = MsgPackCodec.decode(&bytes).unwrap();
+///     assert_eq!(reading, Reading { sensor: "t1".into(), values: vec![3.0, 40.0] });
+///
+///     // Errors are encoded with the codec of the route too.
+///     let mut res = client.post("/calibrate").body(vec![0xc1]).send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     let mut bytes = vec![];
+///     res.read_to_end(&mut bytes).await.unwrap();
+///     let error: serde_json::Value = MsgPackCodec.decode(&bytes).unwrap();
+///     assert_eq!(error["code"], "deserialize_failed");
+/// });
+/// ```
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+    fn mime(&self) -> Mime {
+        "application/msgpack".parse().unwrap()
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        rmp_serde::from_slice(bytes).map_err(|e| {
+            Error::bad_request(e.to_string()).with_code(WindmillErrorCode::DeserializeFailed)
+        })
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        rmp_serde::to_vec_named(value).map_err(|e| {
+            let msg = format!("unable to encode {}: {}", std::any::type_name::<T>(), e);
+            Error::internal(msg).with_code(WindmillErrorCode::Internal)
+        })
+    }
+}
+
+/// A props choosing the codec of the response from the `Accept` header of the request: the
+/// [`MsgPackCodec`](struct.MsgPackCodec.html) when the client prefers `application/msgpack` to
+/// `application/json`, the [`JsonCodec`](struct.JsonCodec.html) otherwise.  Only available with
+/// the `msgpack` feature.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{prelude::*, task};
+/// # use http_types::{Method, StatusCode};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn rename(user: Body<User>, codec: Negotiated) -> Result<Respond<User, Negotiated>, Error> {
+///     let mut user = user.into_inner();
+///     user.name = user.name.to_uppercase();
+///     Ok(Respond::new(user).with_codec(codec))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"rename"), ___rename);
+/// let client = &TestClient::new(router);
+/// let user = User { id: 7, name: "ada".into() };
+///
+/// let send = |content_type: &'static str, accept: &'static str, body: Vec<u8>| async move {
+///     let mut res = client
+///         .post("/rename")
+///         .header("content-type", content_type)
+///         .header("accept", accept)
+///         .body(body)
+///         .send()
+///         .await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     let content_type = res.header(&"content-type".parse().unwrap()).unwrap()[0].to_string();
+///     let mut bytes = vec![];
+///     res.read_to_end(&mut bytes).await.unwrap();
+///     (content_type, bytes)
+/// };
+///
+/// task::block_on(async {
+///     let renamed = User { id: 7, name: "ADA".into() };
+///     let msgpack = MsgPackCodec.encode(&user).unwrap();
+///
+///     let (content_type, bytes) = send("application/msgpack", "application/msgpack", msgpack.clone()).await;
+///     assert_eq!(content_type, "application/msgpack");
+///     assert_eq!(MsgPackCodec.decode::<User>(&bytes).unwrap(), renamed);
+///
+///     let (content_type, bytes) = send("application/msgpack", "application/json", msgpack.clone()).await;
+///     assert_eq!(content_type, "application/json");
+///     assert_eq!(JsonCodec.decode::<User>(&bytes).unwrap(), renamed);
+///
+///     let json = JsonCodec.encode(&user).unwrap();
+///     let accept = "application/json;q=0.5, application/msgpack";
+///     let (content_type, bytes) = send("application/json", accept, json.clone()).await;
+///     assert_eq!(content_type, "application/msgpack");
+///     assert_eq!(MsgPackCodec.decode::<User>(&bytes).unwrap(), renamed);
+///
+///     let (content_type, _) = send("application/json", "*/*", json).await;
+///     assert_eq!(content_type, "application/json");
+/// });
+/// ```
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Negotiated {
+    msgpack: bool,
+}
+
+#[cfg(feature = "msgpack")]
+impl Negotiated {
+    /// The codec preferred by the client of `req`.
+    pub fn of(req: &http_types::Request) -> Self {
+        // The highest quality given to JSON and to MessagePack.
+        let (mut json, mut msgpack) = (0.0, 0.0);
+        let accept = req.header(&"accept".parse().unwrap());
+        for range in accept
+            .into_iter()
+            .flatten()
+            .flat_map(|v| v.as_str().split(','))
+        {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match media_type.as_str() {
+                "application/json" => json = quality,
+                "application/msgpack" | "application/x-msgpack" => msgpack = quality,
+                _ => {}
+            }
+        }
+        Self {
+            msgpack: msgpack > json,
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Codec for Negotiated {
+    fn mime(&self) -> Mime {
+        match self.msgpack {
+            true => MsgPackCodec.mime(),
+            false => JsonCodec.mime(),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        match self.msgpack {
+            true => MsgPackCodec.decode(bytes),
+            false => JsonCodec.decode(bytes),
+        }
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self.msgpack {
+            true => MsgPackCodec.encode(value),
+            false => JsonCodec.encode(value),
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Props for Negotiated {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let negotiated = Negotiated::of(&req);
+            Ok((req, params, negotiated))
+        })
+    }
+}
+
+/// A props that decodes the body of a request into `T` with the codec `C`.
+///
+/// Requests with a `Content-Type` of `application/x-www-form-urlencoded` are decoded with the
+/// [`FormCodec`](struct.FormCodec.html) whatever `C` is, so an endpoint accepts both JSON and form
+/// posts by default.  With the `msgpack` feature, requests with a `Content-Type` of
+/// `application/msgpack` are likewise decoded with the [`MsgPackCodec`](struct.MsgPackCodec.html).  A malformed body is rejected with `400 Bad Request` before the endpoint
+/// runs, and so is a missing one: a request without a body, or with an empty one, has no body to
+/// decode.  Take a `Body<Option<T>>` to make the body optional, `None` when there is none.
+///
+/// The server reads bodies into a buffer each connection keeps between its requests, of up to
+/// 64 KiB, so decoding the body of a request on a connection that already sent one that large
+/// allocates only for the decoded value.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::Response;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct NewUser {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn create_user(user: Body<NewUser>) -> Result<Response, Error> {
+///     Ok(Response::from(format!("created {}", user.name)))
+/// }
+///
+/// # use async_std::task;
+/// # use http_types::{Method, Request, StatusCode, Url};
+/// # use std::sync::Arc;
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"users"), ___create_user);
+/// let router = Arc::new(router);
+///
+/// let post = |content_type: Option<&str>, body: &str| {
+///     let mut req = Request::new(Method::Post, Url::parse("http://localhost/users").unwrap());
+///     req.insert_header("content-length", body.len().to_string()).unwrap();
+///     if let Some(content_type) = content_type {
+///         req.insert_header("content-type", content_type).unwrap();
+///     }
+///     req.set_body(body.to_owned());
+///     task::block_on(router.clone().respond(req))
+/// };
+///
+/// let mut res = post(None, r#"{"name":"ada"}"#);
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "created ada");
+///
+/// let mut res = post(Some("application/x-www-form-urlencoded"), "name=ada+lovelace");
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "created ada lovelace");
+///
+/// let res = post(Some("application/x-www-form-urlencoded"), "nom=ada");
+/// assert_eq!(res.status(), StatusCode::BadRequest);
+///
+/// let mut res = post(None, "");
+/// assert_eq!(res.status(), StatusCode::BadRequest);
+/// let body = task::block_on(res.body_string()).unwrap();
+/// assert_eq!(body, r#"{"code":"deserialize_failed","msg":"request body required"}"#);
+/// ```
+///
+/// An optional body:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Filter {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn search(filter: Body<Option<Filter>>) -> Result<Response, Error> {
+///     match filter.into_inner() {
+///         Some(filter) => Ok(Response::from(format!("users named {}", filter.name))),
+///         None => Ok(Response::from("all users")),
+///     }
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"search"), ___search);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.post("/search").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "all users");
+///
+///     let mut res = client.post("/search").body(r#"{"name":"ada"}"#).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "users named ada");
+///
+///     let res = client.post("/search").body("{").send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+/// });
+/// ```
+///
+/// Bodies sent on a connection after the first are read without allocating:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{net::TcpStream, prelude::*, task};
+/// # use http_types::{Method, Response};
+/// # use serde::Deserialize;
+/// # use std::{alloc::{GlobalAlloc, Layout, System}, sync::atomic::{AtomicUsize, Ordering::SeqCst}, time::Duration};
+/// struct Counting;
+/// static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+///
+/// unsafe impl GlobalAlloc for Counting {
+///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+///         ALLOCATED.fetch_add(layout.size(), SeqCst);
+///         System.alloc(layout)
+///     }
+///
+///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+///         System.dealloc(ptr, layout)
+///     }
+/// }
+///
+/// #[global_allocator]
+/// static GLOBAL: Counting = Counting;
+///
+/// #[derive(Deserialize)]
+/// struct Count {
+///     n: u64,
+/// }
+///
+/// #[endpoint]
+/// async fn count(count: Body<Count>) -> Result<Response, Error> {
+///     Ok(Response::from(count.n.to_string()))
+/// }
+///
+/// # fn main() {
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"count"), ___count);
+///
+/// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+/// std::thread::spawn(move || {
+///     Server::new(Config::new(addr.to_string())).run(router).map_err(|e| e.to_string())
+/// });
+///
+/// let requests: Vec<String> = (1..=3)
+///     .map(|n| {
+///         let body = format!(r#"{{"n":{},"padding":"{}"}}"#, n, "x".repeat(32 * 1024));
+///         format!(
+///             "POST /count HTTP/1.1\r\nhost: localhost\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+///             body.len(),
+///             body
+///         )
+///     })
+///     .collect();
+/// let len = requests[0].len();
+///
+/// task::block_on(async {
+///     let mut stream = loop {
+///         match TcpStream::connect(addr).await {
+///             Ok(stream) => break stream,
+///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+///         }
+///     };
+///     let mut buf = vec![0; 1024];
+///     for (i, req) in requests.iter().enumerate() {
+///         let before = ALLOCATED.load(SeqCst);
+///         stream.write_all(req.as_bytes()).await.unwrap();
+///         let (expected, mut res) = (format!("\r\n\r\n{}", i + 1), String::new());
+///         while !res.ends_with(&expected) {
+///             let n = stream.read(&mut buf).await.unwrap();
+///             assert!(n > 0, "{}", res);
+///             res.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+///         }
+///         let allocated = ALLOCATED.load(SeqCst) - before;
+///         if i > 0 {
+///             assert!(allocated < len / 2, "{} bytes allocated for a body of {}", allocated, len);
+///         }
+///     }
+/// });
+/// # }
+/// ```
+pub struct Body<T, C = JsonCodec> {
+    inner: T,
+    codec: PhantomData<fn() -> C>,
+}
+
+impl<T, C> Body<T, C> {
+    /// Take the decoded body.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, C> Deref for Body<T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, C> Props for Body<T, C>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    C: Codec + Default,
+{
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let scratch = ScratchBuffer::of(&req);
+            let mut bytes = scratch
+                .as_ref()
+                .map(ScratchBuffer::take)
+                .unwrap_or_default();
+            let inner = decode_body::<T, C>(&mut req, &mut bytes).await;
+            if let Some(scratch) = scratch {
+                scratch.put(bytes);
+            }
+            Ok((
+                req,
+                params,
+                Body {
+                    inner: inner?,
+                    codec: PhantomData,
+                },
+            ))
+        })
+    }
+}
+
+/// A props that reads a JSON body into memory for the endpoint to decode, into values that borrow
+/// from it.  
+///
+/// A [`Body<T>`](struct.Body.html) owns the value it decodes, so a `T` with fields such as `&str`
+/// or `&[u8]` cannot be taken with it.  Such a value can be decoded from a `JsonBuf` with
+/// [`decode`](#method.decode), and lives as long as the `JsonBuf` it borrows from: strings
+/// without escapes are not copied.  The body is read like [`read_body`](fn.read_body.html) reads it
+/// before the endpoint runs, and its content type is not checked.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct NewUser<'s> {
+///     name: &'s str,
+///     #[serde(borrow)]
+///     nickname: Option<&'s str>,
+/// }
+///
+/// #[endpoint]
+/// async fn create(body: JsonBuf) -> Result<Response, Error> {
+///     let user: NewUser = body.decode()?;
+///     let name = user.name;
+///     // The name points into the body rather than into a copy of it.
+///     assert!(body.as_bytes().as_ptr_range().contains(&name.as_ptr()));
+///     Ok(Response::from(format!("created {} ({})", name, user.nickname.unwrap_or("-"))))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"users"), ___create);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.post("/users").body(r#"{"name":"ada","nickname":"countess"}"#).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "created ada (countess)");
+///
+///     // A borrowed `&str` cannot hold a string with escapes, which has to be unescaped.
+///     let mut res = client.post("/users").body(r#"{"name":"\u0061da"}"#).send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///     assert_eq!(body["code"], "deserialize_failed");
+///
+///     let mut res = client.post("/users").send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     let body = res.body_string().await.unwrap();
+///     assert_eq!(body, r#"{"code":"deserialize_failed","msg":"request body required"}"#);
+/// });
+/// ```
+pub struct JsonBuf {
+    bytes: Vec<u8>,
+}
+
+impl JsonBuf {
+    /// Decode the body, failing with `400 Bad Request` like the
+    /// [`JsonCodec`](struct.JsonCodec.html) if it is not a valid `T`, or if there is none and `T`
+    /// is not an `Option`.  Fields of types such as `Cow<str>` borrow when they can and copy
+    /// otherwise.  
+    pub fn decode<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        if self.bytes.is_empty() {
+            return missing_body();
+        }
+        serde_json::from_slice(&self.bytes).map_err(|e| {
+            Error::bad_request(json_error(&e, Some(&self.bytes)))
+                .with_code(WindmillErrorCode::DeserializeFailed)
+        })
+    }
+
+    /// The body as it was sent.  
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Props for JsonBuf {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let bytes = read_body(&mut req).await?;
+            Ok((req, params, JsonBuf { bytes }))
+        })
+    }
+}
+
+/// A props that hands the endpoint the bytes of the body of a request, such as for checking a
+/// signature computed over them, and decodes it on demand.  
+///
+/// The body is read into memory after any `Content-Encoding` is removed, so a `gzip` body is
+/// handed over decompressed.  A signature the sender computed over the encoded bytes on the wire
+/// does not verify against them; senders signing the payload before encoding it are fine.  
+///
+/// Props after it read the body from the `BodyBytes` rather than from the request, whose headers
+/// are left as they were sent: a `Body<T>` taken along with it decodes the same bytes, without
+/// copying them.  Bodies are only kept for the routes whose endpoints take a `BodyBytes`.
+/// Decoding with [`decode`](#method.decode) is left to the endpoint, so the bytes are there even
+/// for a body that does not decode.  
+///
+/// ## Examples
+/// A webhook that checks the HMAC-SHA256 of its payload before decoding it:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use hmac::{Hmac, Mac, NewMac};
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::Deserialize;
+/// # use sha2::Sha256;
+/// const SECRET: &[u8] = b"It's a Secret to Everybody";
+///
+/// fn sign(payload: &[u8]) -> String {
+///     let mut mac = Hmac::<Sha256>::new_varkey(SECRET).unwrap();
+///     mac.update(payload);
+///     let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+///     format!("sha256={}", hex)
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Event {
+///     action: String,
+/// }
+///
+/// #[endpoint]
+/// async fn webhook(head: RequestHead, body: BodyBytes) -> Result<Response, Error> {
+///     let signature = head.header("x-hub-signature-256").and_then(|values| values.last());
+///     if signature.map(|value| value.as_str()) != Some(sign(body.as_bytes()).as_str()) {
+///         return Err(Error::unauthorized("invalid signature"));
+///     }
+///     let event: Event = body.decode()?;
+///     Ok(Response::from(event.action))
+/// }
+///
+/// #[endpoint]
+/// async fn audit(raw: BodyBytes, event: Body<Event>, head: RequestHead) -> Result<Response, Error> {
+///     // The request is left as it was sent.
+///     assert!(head.header("content-encoding").is_none());
+///     assert_eq!(head.header("content-length").unwrap()[0], "19");
+///     Ok(Response::from(format!("{} bytes: {}", raw.as_bytes().len(), event.action)))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"webhook"), ___webhook);
+/// router.add(Method::Post, route!(/"audit"), ___audit);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let payload = r#"{"action":"opened"}"#;
+///     let send = |payload: &'static str, signature: String| {
+///         client.post("/webhook").header("x-hub-signature-256", &signature).body(payload).send()
+///     };
+///
+///     let mut res = send(payload, sign(payload.as_bytes())).await;
+///     assert_eq!(res.body_string().await.unwrap(), "opened");
+///
+///     let res = send(payload, sign(b"something else")).await;
+///     assert_eq!(res.status(), StatusCode::Unauthorized);
+///
+///     // Signed, but not an event.
+///     let res = send("[]", sign(b"[]")).await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///
+///     let mut res = client.post("/audit").body(payload).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "19 bytes: opened");
+/// });
+/// ```
+pub struct BodyBytes<C = JsonCodec> {
+    bytes: Arc<Vec<u8>>,
+    content_type: String,
+    codec: PhantomData<fn() -> C>,
+}
+
+impl<C: Codec + Default> BodyBytes<C> {
+    /// Decode the body like [`Body<T, C>`](struct.Body.html) does, with the codec of its content
+    /// type.  
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        decode_as::<T, C>(&self.content_type, &self.bytes)
+    }
+}
+
+impl<C> BodyBytes<C> {
+    /// The body, decompressed if it was sent with a `Content-Encoding`.  
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the body.  
+    pub fn into_inner(self) -> Vec<u8> {
+        Arc::try_unwrap(self.bytes).unwrap_or_else(|bytes| bytes.to_vec())
+    }
+}
+
+impl<C: Codec + Default> Props for BodyBytes<C> {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let bytes = Arc::new(read_body(&mut req).await?);
+            req.local_mut().insert(ReadBody(Arc::downgrade(&bytes)));
+            let content_type = content_type(&req);
+            Ok((
+                req,
+                params,
+                BodyBytes {
+                    bytes,
+                    content_type,
+                    codec: PhantomData,
+                },
+            ))
+        })
+    }
+}
+
+/// Read the body of `req` into `bytes` and decode it with the codec of its content type.  The
+/// decoded value owns its data, so `bytes` can be reused once it is returned.  
+async fn decode_body<T, C>(req: &mut http_types::Request, bytes: &mut Vec<u8>) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    C: Codec + Default,
+{
+    // Decoded where a `BodyBytes` keeps it rather than copied.
+    if let Some(read) = ReadBody::of(req) {
+        return decode_as::<T, C>(&content_type(req), &read);
+    }
+    let limit = max_body_size(req);
+    read_body_into(req, bytes, limit).await?;
+    decode_as::<T, C>(&content_type(req), bytes)
+}
+
+/// Decode `bytes` with the codec for `content_type`, or with `C` for any content type without a
+/// codec of its own.  
+fn decode_as<T, C>(content_type: &str, bytes: &[u8]) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    C: Codec + Default,
+{
+    match content_type {
+        _ if bytes.is_empty() => missing_body(),
+        "application/x-www-form-urlencoded" => FormCodec.decode(bytes),
+        #[cfg(feature = "msgpack")]
+        "application/msgpack" => MsgPackCodec.decode(bytes),
+        _ => C::default().decode(bytes),
+    }
+}
+
+/// The media type of the body of `req`, lowercased and without parameters.  
+fn content_type(req: &http_types::Request) -> String {
+    req.header(&http_types::headers::CONTENT_TYPE)
+        .and_then(|values| values.last())
+        .map(|value| {
+            let media_type = value.as_str().split(';').next().unwrap_or_default();
+            media_type.trim().to_ascii_lowercase()
+        })
+        .unwrap_or_default()
+}
+
+/// The message of an error decoding JSON, with where in `bytes` it was found when they are known.
+pub(crate) fn json_error(e: &serde_json::Error, bytes: Option<&[u8]>) -> serde_json::Value {
+    let position = format!(" at line {} column {}", e.line(), e.column());
+    let msg = e.to_string();
+    let mut error = serde_json::json!({
+        "message": msg.strip_suffix(&position).unwrap_or(&msg),
+    });
+    if e.line() == 0 {
+        return error;
+    }
+    error["line"] = e.line().into();
+    error["column"] = e.column().into();
+
+    if let Some(bytes) = bytes {
+        let offset = bytes
+            .split(|b| *b == b'\n')
+            .take(e.line() - 1)
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            + e.column().saturating_sub(1);
+        let mut path = json_path(&bytes[..offset.min(bytes.len())]);
+        if let Some(field) = msg.strip_prefix("missing field `") {
+            // Found at the end of the object missing the field, which is still open.
+            let field = field.split('`').next().unwrap_or_default();
+            if let Some(segment @ PathSegment::Key(_)) = path.last_mut() {
+                *segment = PathSegment::Key(field.to_owned());
+            }
+        }
+        error["offset"] = offset.into();
+        let path: String = path.iter().map(PathSegment::to_string).collect();
+        if !path.is_empty() {
+            error["path"] = path.trim_start_matches('.').into();
+        }
+    }
+    error
+}
+
+/// A step into a JSON value, on the way to where an error was found.
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) if key.is_empty() => Ok(()),
+            PathSegment::Key(key) => write!(f, ".{}", key),
+            PathSegment::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+/// The path of the value still open at the end of `json`, a prefix of a JSON document.  An object
+/// whose next key has not been read yet is stepped into with an empty key.
+fn json_path(json: &[u8]) -> Vec<PathSegment> {
+    // Each open value, with the key or index of the field being read and whether a key is next.
+    let mut open: Vec<(PathSegment, bool)> = vec![];
+    let mut bytes = json.iter();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'{' => open.push((PathSegment::Key(String::new()), true)),
+            b'[' => open.push((PathSegment::Index(0), false)),
+            b'}' | b']' => {
+                open.pop();
+            }
+            b',' => match open.last_mut() {
+                Some((PathSegment::Index(index), _)) => *index += 1,
+                Some((segment, expect_key)) => {
+                    *segment = PathSegment::Key(String::new());
+                    *expect_key = true;
+                }
+                None => {}
+            },
+            b':' => {
+                if let Some((_, expect_key)) = open.last_mut() {
+                    *expect_key = false;
+                }
+            }
+            b'"' => {
+                let mut string = vec![];
+                let mut escaped = false;
+                for &b in &mut bytes {
+                    match (escaped, b) {
+                        (false, b'"') => break,
+                        (false, b'\\') => escaped = true,
+                        _ => escaped = false,
+                    }
+                    string.push(b);
+                }
+                if let Some((segment, true)) = open.last_mut() {
+                    let key = String::from_utf8_lossy(&string);
+                    let key = serde_json::from_str(&format!("\"{}\"", key))
+                        .unwrap_or_else(|_| key.into_owned());
+                    *segment = PathSegment::Key(key);
+                }
+            }
+            _ => {}
+        }
+    }
+    open.into_iter().map(|(segment, _)| segment).collect()
+}
+
+/// The value of a body that was not sent, which only an optional body has.
+fn missing_body<'de, T: Deserialize<'de>>() -> Result<T, Error> {
+    let none: UnitDeserializer<serde::de::value::Error> = ().into_deserializer();
+    T::deserialize(none).map_err(|_| {
+        Error::bad_request("request body required").with_code(WindmillErrorCode::DeserializeFailed)
+    })
+}
+
+fn encode_error<T: ?Sized>(e: serde_json::Error) -> Error {
+    let msg = format!("unable to encode {}: {}", std::any::type_name::<T>(), e);
+    Error::internal(msg).with_code(WindmillErrorCode::Internal)
+}
@@ -0,0 +1,396 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    util::body_too_large,
+};
+use async_std::{io::ReadExt, task};
+use http_types::{headers, StatusCode};
+use std::io::Write;
+
+/// How hard to compress response bodies, trading time for size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// The fastest compression, for bodies that are sent right after they are made.
+    Fastest,
+    /// A balance of speed and size, level 6 of `zlib`.
+    #[default]
+    Default,
+    /// The smallest bodies, at the cost of time.
+    Best,
+    /// A level from 0, no compression, to 9, the same as `Best`.
+    Precise(u32),
+}
+
+impl CompressionLevel {
+    fn flate2(self) -> flate2::Compression {
+        match self {
+            CompressionLevel::Fastest => flate2::Compression::fast(),
+            CompressionLevel::Default => flate2::Compression::default(),
+            CompressionLevel::Best => flate2::Compression::best(),
+            CompressionLevel::Precise(level) => flate2::Compression::new(level.min(9)),
+        }
+    }
+}
+
+/// The default size a response body must reach to be compressed.
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Compression of response bodies with `gzip` or `deflate`, set with
+/// [`Config::with_compression`](struct.Config.html#method.with_compression).
+///
+/// A response is compressed when the client accepts one of the encodings in its
+/// `Accept-Encoding`, preferring `gzip`, and its body is at least the minimum size, 1 KiB by
+/// default.  The compressed response gets a `Content-Encoding`, and a `Content-Length` of the
+/// compressed body when it had one.  Responses that could have been compressed also get
+/// `Vary: Accept-Encoding`, so caches keep the compressed and plain bodies apart.
+///
+/// Responses that already have a `Content-Encoding`, whose body is streamed without a known
+/// length, or whose `Content-Type` is already compressed, such as images, audio, video and
+/// archives, are sent as they are.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{prelude::*, task};
+/// # use http_types::{Method, Response};
+/// # use std::io::Read;
+/// #[endpoint]
+/// async fn users() -> Result<Respond<Vec<String>>, Error> {
+///     Ok(Respond::new((0..500).map(|i| format!("user {}", i)).collect()))
+/// }
+///
+/// #[endpoint]
+/// async fn health() -> Result<Response, Error> {
+///     Ok(Response::from("ok"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"), ___users);
+/// router.add(Method::Get, route!(/"health"), ___health);
+/// let config = Config::new("127.0.0.1:0").with_compression(
+///     Compression::new()
+///         .with_level(CompressionLevel::Best)
+///         .with_min_size(512),
+/// );
+/// let client = TestClient::new(router).with_config(config);
+/// let header = |res: &Response, name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+///
+/// task::block_on(async {
+///     let mut res = client.get("/users").header("accept-encoding", "deflate, gzip").send().await;
+///     assert_eq!(header(&res, "content-encoding").as_deref(), Some("gzip"));
+///     assert_eq!(header(&res, "vary").as_deref(), Some("accept-encoding"));
+///     let mut compressed = vec![];
+///     res.read_to_end(&mut compressed).await.unwrap();
+///     assert_eq!(header(&res, "content-length"), Some(compressed.len().to_string()));
+///
+///     let mut json = String::new();
+///     flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut json).unwrap();
+///     let users: Vec<String> = serde_json::from_str(&json).unwrap();
+///     assert_eq!(users.len(), 500);
+///     assert!(compressed.len() < json.len() / 4);
+///
+///     // The client does not accept a compressed body.
+///     let mut res = client.get("/users").send().await;
+///     assert_eq!(header(&res, "content-encoding"), None);
+///     assert_eq!(header(&res, "vary").as_deref(), Some("accept-encoding"));
+///     assert!(res.body_string().await.unwrap().starts_with(r#"["user 0""#));
+///
+///     // The body is too small to be worth it.
+///     let mut res = client.get("/health").header("accept-encoding", "gzip").send().await;
+///     assert_eq!(header(&res, "content-encoding"), None);
+///     assert_eq!(res.body_string().await.unwrap(), "ok");
+/// });
+/// ```
+///
+/// ## Request bodies
+///
+/// With the `compression` feature, request bodies sent with a `Content-Encoding` of `gzip` or
+/// `deflate` are decompressed before they are [read](fn.read_body.html), whether or not
+/// responses are compressed.  The maximum body size applies to the decompressed body, so a small
+/// body that inflates into a huge one is refused.
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::Deserialize;
+/// # use std::io::Write;
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn upload(body: Body<Upload>) -> Result<Response, Error> {
+///     Ok(Response::from(body.into_inner().name))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"uploads"), ___upload);
+/// let client = TestClient::new(router).with_config(Config::new("127.0.0.1:0").with_max_body_size(1024));
+///
+/// let gzip = |body: &[u8]| {
+///     let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+///     encoder.write_all(body).unwrap();
+///     encoder.finish().unwrap()
+/// };
+/// let deflate = |body: &[u8]| {
+///     let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+///     encoder.write_all(body).unwrap();
+///     encoder.finish().unwrap()
+/// };
+///
+/// task::block_on(async {
+///     let post = |encoding: &str, body: Vec<u8>| {
+///         client.post("/uploads").header("content-encoding", encoding).body(body).send()
+///     };
+///
+///     let mut res = post("gzip", gzip(br#"{"name":"report.csv"}"#)).await;
+///     assert_eq!(res.body_string().await.unwrap(), "report.csv");
+///
+///     let mut res = post("deflate", deflate(br#"{"name":"notes.txt"}"#)).await;
+///     assert_eq!(res.body_string().await.unwrap(), "notes.txt");
+///
+///     // Fewer bytes than the limit that inflate far past it.
+///     let bomb = gzip(&vec![b' '; 64 * 1024]);
+///     assert!(bomb.len() < 1024);
+///     let res = post("gzip", bomb).await;
+///     assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+///
+///     let mut res = post("gzip", b"not gzip".to_vec()).await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     assert!(res.body_string().await.unwrap().contains("unable to decompress gzip body"));
+///
+///     let res = post("br", b"whatever".to_vec()).await;
+///     assert_eq!(res.status(), StatusCode::UnsupportedMediaType);
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Compression {
+    level: CompressionLevel,
+    min_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            level: CompressionLevel::default(),
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
+impl Compression {
+    /// Compress at the default level bodies of at least 1 KiB.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how hard to compress.
+    pub fn with_level(mut self, level: CompressionLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set the size a body must reach to be compressed, below which compressing it gains little.
+    pub fn with_min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// How hard bodies are compressed.
+    pub fn level(&self) -> CompressionLevel {
+        self.level
+    }
+
+    /// The size a body must reach to be compressed.
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// Compress the body of `res` with an encoding in `accept_encoding`, the `Accept-Encoding` of
+    /// the request, when it is worth it.
+    pub(crate) async fn apply(
+        &self,
+        accept_encoding: Option<&str>,
+        res: &mut http_types::Response,
+    ) {
+        let status = res.status();
+        let bodiless = status.is_informational()
+            || matches!(status, StatusCode::NoContent | StatusCode::NotModified);
+        // The range is of the bytes as they are, compressing them would leave it meaningless.
+        let partial = status == StatusCode::PartialContent;
+        let is_encoded = res.header(&"content-encoding".parse().unwrap()).is_some();
+        let fits = matches!(res.len(), Some(len) if len >= self.min_size);
+        if bodiless || partial || is_encoded || !fits || !compressible(res) {
+            return;
+        }
+
+        add_vary(res);
+        let encoding = match accept_encoding.and_then(negotiate) {
+            Some(encoding) => encoding,
+            None => return,
+        };
+
+        let mut body = vec![];
+        if res.take_body().read_to_end(&mut body).await.is_err() {
+            return;
+        }
+        let level = self.level.flate2();
+        let compressed = task::spawn_blocking(move || {
+            let compressed = match encoding {
+                Encoding::Gzip => {
+                    let mut encoder = flate2::write::GzEncoder::new(vec![], level);
+                    encoder.write_all(&body).and_then(|_| encoder.finish())
+                }
+                Encoding::Deflate => {
+                    let mut encoder = flate2::write::ZlibEncoder::new(vec![], level);
+                    encoder.write_all(&body).and_then(|_| encoder.finish())
+                }
+            };
+            compressed.map_err(|_| body)
+        })
+        .await;
+
+        match compressed {
+            Ok(compressed) => {
+                let _ = res.insert_header("content-encoding", encoding.as_str());
+                if res.header(&headers::CONTENT_LENGTH).is_some() {
+                    let _ =
+                        res.insert_header(headers::CONTENT_LENGTH, compressed.len().to_string());
+                }
+                res.set_body(compressed);
+            }
+            Err(body) => res.set_body(body),
+        }
+    }
+}
+
+/// Decompress a request body compressed with `encoding`, one of `gzip`, `x-gzip` or `deflate`,
+/// stopping once it is larger than `limit`.
+pub(crate) fn decompress(encoding: &str, body: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
+
+    let decoder: Box<dyn Read + '_> = match encoding {
+        "deflate" if is_zlib(body) => Box::new(flate2::read::ZlibDecoder::new(body)),
+        // Some clients send a raw deflate stream without the zlib wrapper.
+        "deflate" => Box::new(flate2::read::DeflateDecoder::new(body)),
+        _ => Box::new(flate2::read::GzDecoder::new(body)),
+    };
+    let mut decompressed = vec![];
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| {
+            Error::bad_request(format!("unable to decompress {} body: {}", encoding, e))
+                .with_code(WindmillErrorCode::BadRequest)
+        })?;
+    if decompressed.len() > limit {
+        return Err(body_too_large(limit));
+    }
+    Ok(decompressed)
+}
+
+/// Whether `body` starts with a zlib header.
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// The encoding to compress with out of an `Accept-Encoding` header, the one with the highest
+/// quality, `gzip` when they are equal.
+fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let encoding = if name.eq_ignore_ascii_case("gzip") || name == "*" {
+            Encoding::Gzip
+        } else if name.eq_ignore_ascii_case("deflate") {
+            Encoding::Deflate
+        } else {
+            continue;
+        };
+        let better = match best {
+            Some((current, q)) => quality > q || (quality == q && current == Encoding::Deflate),
+            None => true,
+        };
+        if quality > 0.0 && better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether the body of `res` is worth compressing, judging by its `Content-Type`.
+fn compressible(res: &http_types::Response) -> bool {
+    let essence = match res
+        .header(&headers::CONTENT_TYPE)
+        .and_then(|values| values.last())
+    {
+        Some(value) => value
+            .as_str()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase(),
+        None => return true,
+    };
+    let compressed = match essence.split('/').next().unwrap_or_default() {
+        "image" => essence != "image/svg+xml",
+        "audio" | "video" => true,
+        _ => matches!(
+            essence.as_str(),
+            "application/gzip"
+                | "application/x-gzip"
+                | "application/zip"
+                | "application/zstd"
+                | "application/x-bzip2"
+                | "application/x-7z-compressed"
+                | "application/x-rar-compressed"
+                | "font/woff"
+                | "font/woff2"
+        ),
+    };
+    !compressed
+}
+
+/// Add `Accept-Encoding` to the `Vary` header of `res`.
+fn add_vary(res: &mut http_types::Response) {
+    let varies = res
+        .header(&"vary".parse().unwrap())
+        .map(|values| {
+            values.iter().any(|value| {
+                value.as_str().split(',').any(|name| {
+                    let name = name.trim();
+                    name == "*" || name.eq_ignore_ascii_case("accept-encoding")
+                })
+            })
+        })
+        .unwrap_or(false);
+    if !varies {
+        let _ = res.append_header("vary", "accept-encoding");
+    }
+}
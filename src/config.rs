@@ -1,6 +1,67 @@
+#[cfg(feature = "client")]
+use crate::client::HttpClient;
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+#[cfg(feature = "tls")]
+use crate::tls::TlsSource;
+use crate::{
+    connection_limit::ConnectionOverflow,
+    drain::{DEFAULT_DRAIN_BUDGET, DEFAULT_DRAIN_TIMEOUT},
+    forwarded::{self, ProxyRange},
+    multipart::DEFAULT_PART_LIMIT,
+    redact::Redaction,
+    request_id::DEFAULT_REQUEST_ID_HEADER,
+    serialize::SerializePolicy,
+    signature::SignatureGuard,
+    timeout::Timeouts,
+    util::DEFAULT_MAX_BODY_SIZE,
+};
+use http_types::{headers::HeaderName, Version};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::{net::IpAddr, str::FromStr, time::Duration};
+
+/// The default read, write and header read timeouts of a connection.
+const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default time a connection waits for its next request.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Configuration for the server.  
 pub struct Config {
     addr: String,
+    #[cfg(unix)]
+    unix_path: Option<PathBuf>,
+    #[cfg(unix)]
+    unix_permissions: Option<u32>,
+    redaction: Redaction,
+    large_response_threshold: usize,
+    large_response_policy: SerializePolicy,
+    drain_budget: u64,
+    drain_timeout: Duration,
+    multipart_part_limit: u64,
+    max_body_size: usize,
+    signature_guard: Option<SignatureGuard>,
+    shutdown_timeout: Duration,
+    header_read_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    max_connections: Option<usize>,
+    connection_overflow: ConnectionOverflow,
+    min_http_version: Version,
+    timeouts: Timeouts,
+    handler_timeout: Option<Duration>,
+    request_id_header: HeaderName,
+    trusted_proxies: Vec<ProxyRange>,
+    print_routes: bool,
+    #[cfg(feature = "client")]
+    http_client: Option<HttpClient>,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsSource>,
 }
 
 impl Config {
@@ -10,7 +71,135 @@ impl Config {
     /// let config = Config::new("127.0.0.1:4000");
     /// ```
     pub fn new(addr: impl Into<String>) -> Self {
-        Self { addr: addr.into() }
+        Self {
+            addr: addr.into(),
+            #[cfg(unix)]
+            unix_path: None,
+            #[cfg(unix)]
+            unix_permissions: None,
+            redaction: Redaction::default(),
+            large_response_threshold: 1024 * 1024,
+            large_response_policy: SerializePolicy::default(),
+            drain_budget: DEFAULT_DRAIN_BUDGET,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            multipart_part_limit: DEFAULT_PART_LIMIT,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            signature_guard: None,
+            shutdown_timeout: Duration::from_secs(30),
+            header_read_timeout: Some(DEFAULT_CONNECTION_TIMEOUT),
+            read_timeout: Some(DEFAULT_CONNECTION_TIMEOUT),
+            write_timeout: Some(DEFAULT_CONNECTION_TIMEOUT),
+            keep_alive_timeout: Some(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            max_requests_per_connection: None,
+            max_connections: None,
+            connection_overflow: ConnectionOverflow::default(),
+            min_http_version: Version::Http1_0,
+            timeouts: Timeouts::default(),
+            handler_timeout: None,
+            request_id_header: HeaderName::from_str(DEFAULT_REQUEST_ID_HEADER).unwrap(),
+            trusted_proxies: Vec::new(),
+            print_routes: false,
+            #[cfg(feature = "client")]
+            http_client: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Create a new instance of `Config` for a server that listens on the Unix socket at `path`,
+    /// instead of a tcp address.  
+    ///
+    /// Requests are routed the same as over tcp, but they have no
+    /// [peer address](struct.RequestHead.html#method.peer_addr) and tls does not apply.  A socket
+    /// file left at `path` by a server that is no longer running is replaced, and the file is
+    /// removed once the server stops.  Binding fails if a running server still listens on `path`,
+    /// or if `path` is some other kind of file.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{os::unix::net::UnixStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::os::unix::fs::PermissionsExt;
+    /// #[endpoint]
+    /// async fn peer(head: RequestHead) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("{} from {:?}", head.url().path(), head.peer_addr())))
+    /// }
+    ///
+    /// let dir = std::env::temp_dir().join(format!("windmill-unix-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("app.sock");
+    ///
+    /// // A socket left behind by a server that crashed.
+    /// drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"peer"), ___peer);
+    /// let config = Config::unix(&path).with_unix_permissions(0o660);
+    /// assert_eq!(config.unix_path(), Some(path.as_path()));
+    /// let server = Server::bind(config).unwrap();
+    /// assert_eq!(server.local_addr(), None);
+    /// let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    /// assert_eq!(mode & 0o777, 0o660);
+    ///
+    /// // Another server cannot take over the socket while it is in use.
+    /// assert!(Server::bind(Config::unix(&path)).is_err());
+    ///
+    /// let (stop, stopped) = channel(1);
+    /// let running = std::thread::spawn(move || {
+    ///     server
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = UnixStream::connect(&path).await.unwrap();
+    ///     stream
+    ///         .write_all(b"GET /peer HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    ///     assert!(res.ends_with("/peer from None"), "{}", res);
+    ///     stop.send(()).await;
+    /// });
+    ///
+    /// running.join().unwrap().unwrap();
+    /// assert!(!path.exists());
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            unix_path: Some(path.clone()),
+            ..Self::new(path.display().to_string())
+        }
+    }
+
+    /// Get the path of the Unix socket the server listens on, if it does not listen on tcp.  
+    #[cfg(unix)]
+    pub fn unix_path(&self) -> Option<&Path> {
+        self.unix_path.as_deref()
+    }
+
+    /// Set the permissions of the [Unix socket](#method.unix) file, such as `0o660` to let only the
+    /// owner and group of the server connect.  Without them the file gets the permissions the
+    /// process creates files with.  
+    #[cfg(unix)]
+    pub fn with_unix_permissions(mut self, mode: u32) -> Self {
+        self.unix_permissions = Some(mode);
+        self
+    }
+
+    /// Get the permissions of the Unix socket file, if they were set.  
+    #[cfg(unix)]
+    pub fn unix_permissions(&self) -> Option<u32> {
+        self.unix_permissions
     }
 
     /// Get the address that the server is running on.  
@@ -22,4 +211,896 @@ impl Config {
     pub fn addr(&self) -> &str {
         &self.addr
     }
+
+    /// Set the policy for redacting sensitive request data from everything the server logs or
+    /// records.  
+    /// ```
+    /// # use windmill::{Config, Redaction};
+    /// let config = Config::new("127.0.0.1:4000")
+    ///     .with_redaction(Redaction::new().header("authorization").field("*.password"));
+    /// ```
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Get the redaction policy.  
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// Set the estimated size in bytes above which the [`Serializer`](struct.Serializer.html)
+    /// props stops serializing response bodies in one go.  Defaults to 1 MiB.  
+    pub fn with_large_response_threshold(mut self, bytes: usize) -> Self {
+        self.large_response_threshold = bytes;
+        self
+    }
+
+    /// Get the large response threshold.  
+    pub fn large_response_threshold(&self) -> usize {
+        self.large_response_threshold
+    }
+
+    /// Set how response bodies above the large response threshold are serialized.  
+    /// ```
+    /// # use windmill::{Config, SerializePolicy};
+    /// let config = Config::new("127.0.0.1:4000")
+    ///     .with_large_response_threshold(512 * 1024)
+    ///     .with_large_response_policy(SerializePolicy::Blocking);
+    /// ```
+    pub fn with_large_response_policy(mut self, policy: SerializePolicy) -> Self {
+        self.large_response_policy = policy;
+        self
+    }
+
+    /// Get the large response policy.  
+    pub fn large_response_policy(&self) -> SerializePolicy {
+        self.large_response_policy
+    }
+
+    /// Set how many bytes of a request body the endpoint left unread are drained after it
+    /// responds, so the connection can be reused.  Bodies with more left over, or that take longer
+    /// than the drain timeout, are not drained and the connection is closed after the response
+    /// instead.  Defaults to 64 KiB.  
+    /// ```
+    /// # use windmill::Config;
+    /// # use std::time::Duration;
+    /// let config = Config::new("127.0.0.1:4000")
+    ///     .with_drain_budget(16 * 1024)
+    ///     .with_drain_timeout(Duration::from_millis(200));
+    /// ```
+    pub fn with_drain_budget(mut self, bytes: u64) -> Self {
+        self.drain_budget = bytes;
+        self
+    }
+
+    /// Get the drain budget.  
+    pub fn drain_budget(&self) -> u64 {
+        self.drain_budget
+    }
+
+    /// Set how long to spend draining an unread request body.  Defaults to 1 second.  
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Get the drain timeout.  
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    /// Set the [time budgets](struct.Timeouts.html) of every route that does not set its own.
+    /// Every budget is unset by default.  
+    /// ```
+    /// # use windmill::{Config, Timeouts};
+    /// # use std::time::Duration;
+    /// let config = Config::new("127.0.0.1:4000").with_timeouts(
+    ///     Timeouts::new()
+    ///         .with_headers(Duration::from_secs(5))
+    ///         .with_idle(Duration::from_secs(30)),
+    /// );
+    /// assert_eq!(config.timeouts().total(), None);
+    /// ```
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Get the time budgets of routes that do not set their own.  
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Set how long the endpoint of a route that does not set its own
+    /// [handler timeout](struct.Route.html#method.handler_timeout) may take, props and body
+    /// reading included, from when the request is routed.  Unset by default.  
+    /// ```
+    /// # use windmill::Config;
+    /// # use std::time::Duration;
+    /// let config = Config::new("127.0.0.1:4000").with_handler_timeout(Duration::from_secs(10));
+    /// assert_eq!(config.handler_timeout(), Some(Duration::from_secs(10)));
+    /// ```
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = Some(timeout);
+        self
+    }
+
+    /// Get the handler timeout of routes that do not set their own.  
+    pub fn handler_timeout(&self) -> Option<Duration> {
+        self.handler_timeout
+    }
+
+    /// Set the header the [`RequestId`](struct.RequestId.html) of a request is read from and
+    /// echoed in.  Defaults to `x-request-id`.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4000").with_request_id_header("x-correlation-id");
+    /// assert_eq!(config.request_id_header(), "x-correlation-id");
+    /// ```
+    ///
+    /// ## Panics
+    /// Panics if `name` is not a valid header name.
+    pub fn with_request_id_header(mut self, name: &str) -> Self {
+        self.request_id_header = HeaderName::from_str(name).expect("invalid header name");
+        self
+    }
+
+    /// Get the header request IDs are read from and echoed in.  
+    pub fn request_id_header(&self) -> &str {
+        self.request_id_header.as_str()
+    }
+
+    /// Trust the proxies connecting from `proxies`, addresses such as `127.0.0.1` or ranges such
+    /// as `10.0.0.0/8`, to report the [client address](struct.RequestHead.html#method.client_addr)
+    /// and [scheme](struct.RequestHead.html#method.scheme) of the requests they forward.  No
+    /// proxy is trusted by default, and the forwarding headers of requests from peers that are
+    /// not trusted are ignored.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4000").with_trusted_proxies(&["127.0.0.1", "10.0.0.0/8", "fd00::/8"]);
+    /// assert!(config.is_trusted_proxy("10.1.2.3".parse().unwrap()));
+    /// assert!(config.is_trusted_proxy("::ffff:127.0.0.1".parse().unwrap()));
+    /// assert!(!config.is_trusted_proxy("11.0.0.1".parse().unwrap()));
+    /// ```
+    ///
+    /// ## Panics
+    /// Panics if one of `proxies` is not an address or a range of addresses.
+    pub fn with_trusted_proxies(mut self, proxies: &[&str]) -> Self {
+        self.trusted_proxies = proxies
+            .iter()
+            .map(|proxy| proxy.parse().unwrap_or_else(|e: String| panic!("{}", e)))
+            .collect();
+        self
+    }
+
+    /// Whether requests from `ip` come from a trusted proxy.  
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        forwarded::is_trusted(&self.trusted_proxies, ip)
+    }
+
+    /// Set how long [`Server::run_until`](struct.Server.html#method.run_until) waits for
+    /// requests that are being handled to finish once it is shutting down, before closing their
+    /// connections.  Defaults to 30 seconds.  
+    /// ```
+    /// # use windmill::Config;
+    /// # use std::time::Duration;
+    /// let config = Config::new("127.0.0.1:4000").with_shutdown_timeout(Duration::from_secs(5));
+    /// assert_eq!(config.shutdown_timeout(), Duration::from_secs(5));
+    /// ```
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Get the shutdown timeout.  
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// Set how long a connection may take to send the head of a request, its request line and
+    /// headers, counted from the first byte of the request.  `None` disables it.  Defaults to 30
+    /// seconds.  
+    ///
+    /// A client that sends its head a byte at a time cannot hold a connection open past it, and
+    /// its connection is closed without a response.  How long the connection may wait for that
+    /// first byte is the [keep-alive timeout](#method.with_keep_alive_timeout).  
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use std::time::{Duration, Instant};
+    /// let config = Config::new("127.0.0.1:4000").with_header_read_timeout(None);
+    /// assert_eq!(config.header_read_timeout(), None);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string()).with_header_read_timeout(Duration::from_millis(200));
+    /// assert_eq!(config.header_read_timeout(), Some(Duration::from_millis(200)));
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     Server::new(config)
+    ///         .run_until(Router::new(), async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     // A byte every 50ms never finishes the head in time.
+    ///     let start = Instant::now();
+    ///     for byte in b"GET / HTTP/1.1\r\nhost: localhost\r\n".iter() {
+    ///         if stream.write_all(&[*byte]).await.is_err() {
+    ///             break;
+    ///         }
+    ///         task::sleep(Duration::from_millis(50)).await;
+    ///     }
+    ///     let mut res = vec![];
+    ///     let _ = stream.read_to_end(&mut res).await;
+    ///     assert!(res.is_empty());
+    ///     assert!(start.elapsed() < Duration::from_secs(1));
+    ///     stop.send(()).await;
+    /// });
+    ///
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn with_header_read_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.header_read_timeout = timeout.into();
+        self
+    }
+
+    /// Get the header read timeout.  
+    pub fn header_read_timeout(&self) -> Option<Duration> {
+        self.header_read_timeout
+    }
+
+    /// Set how long a read from a connection may wait for the client to send anything.  `None`
+    /// disables it.  Defaults to 30 seconds.  
+    ///
+    /// A client that stops sending the body of a request is answered with
+    /// `408 Request Timeout` by the endpoint reading it, and its connection is closed after the
+    /// response.  
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::Duration;
+    /// async fn upload(mut req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    ///     let body = read_body(&mut req).await?;
+    ///     Ok(Response::from(format!("{} bytes", body.len())))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, Route::from("/upload"), upload);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string()).with_read_timeout(Duration::from_millis(200));
+    /// assert_eq!(config.read_timeout(), Some(Duration::from_millis(200)));
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     // Half of the declared body, then nothing.
+    ///     stream
+    ///         .write_all(b"POST /upload HTTP/1.1\r\nhost: localhost\r\ncontent-length: 10\r\n\r\n12345")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.starts_with("HTTP/1.1 408"), "{}", res);
+    ///     assert!(res.contains("connection: close"), "{}", res);
+    ///     assert!(res.contains("request_timeout"), "{}", res);
+    ///     stop.send(()).await;
+    /// });
+    ///
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn with_read_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.read_timeout = timeout.into();
+        self
+    }
+
+    /// Get the read timeout.  
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Set how long a write to a connection may wait for the client to read what was sent
+    /// before.  `None` disables it.  Defaults to 30 seconds.  
+    ///
+    /// A client that stops reading its response has its connection closed.  
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::{Duration, Instant};
+    /// async fn large(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from(vec![0u8; 64 * 1024 * 1024]))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, Route::from("/large"), large);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string())
+    ///     .with_write_timeout(Duration::from_millis(200))
+    ///     .with_shutdown_timeout(Duration::from_secs(30));
+    /// assert_eq!(config.write_timeout(), Some(Duration::from_millis(200)));
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     // Ask for a large response and never read it.
+    ///     stream.write_all(b"GET /large HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    ///     task::sleep(Duration::from_millis(1000)).await;
+    ///
+    ///     // The connection was closed, so shutting down does not wait for it.
+    ///     let start = Instant::now();
+    ///     stop.send(()).await;
+    ///     task::spawn_blocking(move || server.join().unwrap().unwrap()).await;
+    ///     assert!(start.elapsed() < Duration::from_secs(5));
+    ///     drop(stream);
+    /// });
+    ///
+    /// assert_eq!(Config::new("127.0.0.1:4000").with_write_timeout(None).write_timeout(), None);
+    /// ```
+    pub fn with_write_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.write_timeout = timeout.into();
+        self
+    }
+
+    /// Get the write timeout.  
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    /// Set how long a connection waits for the first byte of its next request, before it is
+    /// closed.  `None` disables it.  Defaults to 5 seconds.  
+    ///
+    /// HTTP/1.1 connections are kept alive for more requests after each response, unless the
+    /// request or the response has `Connection: close`.  Requests can also be pipelined, sent
+    /// before the response to the previous one.  Responses say where they end with a
+    /// `Content-Length`, or are chunked when their length is not known in advance.  A new
+    /// connection waits this long for its first request too.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::{Duration, Instant};
+    /// async fn user(_req: http_types::Request, params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("user {}", params.get("id").unwrap())))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, Route::from("/users/{id}"), user);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string()).with_keep_alive_timeout(Duration::from_millis(300));
+    /// assert_eq!(config.keep_alive_timeout(), Some(Duration::from_millis(300)));
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     // Read until `expected` responses have arrived.
+    ///     async fn responses(stream: &mut TcpStream, expected: usize) -> String {
+    ///         let mut res = String::new();
+    ///         while res.matches("user ").count() < expected {
+    ///             let mut buf = [0; 1024];
+    ///             let n = stream.read(&mut buf).await.unwrap();
+    ///             assert!(n > 0, "connection closed early: {}", res);
+    ///             res.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+    ///         }
+    ///         res
+    ///     }
+    ///
+    ///     // Two requests one after the other over one connection.
+    ///     stream.write_all(b"GET /users/1 HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    ///     let res = responses(&mut stream, 1).await;
+    ///     assert!(res.starts_with("HTTP/1.1 200") && res.contains("content-length: 6"), "{}", res);
+    ///     assert!(res.ends_with("user 1"), "{}", res);
+    ///     stream.write_all(b"GET /users/2 HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    ///     assert!(responses(&mut stream, 1).await.ends_with("user 2"));
+    ///
+    ///     // Two requests pipelined, answered in order.
+    ///     stream
+    ///         .write_all(b"GET /users/3 HTTP/1.1\r\nhost: localhost\r\n\r\nGET /users/4 HTTP/1.1\r\nhost: localhost\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let res = responses(&mut stream, 2).await;
+    ///     assert!(res.find("user 3").unwrap() < res.find("user 4").unwrap(), "{}", res);
+    ///
+    ///     // An idle connection is closed once the keep-alive timeout passes.
+    ///     let start = Instant::now();
+    ///     let mut rest = vec![];
+    ///     stream.read_to_end(&mut rest).await.unwrap();
+    ///     assert!(rest.is_empty());
+    ///     assert!(start.elapsed() < Duration::from_secs(2));
+    ///
+    ///     // `Connection: close` from the client closes the connection after the response.
+    ///     let mut stream = TcpStream::connect(addr).await.unwrap();
+    ///     stream
+    ///         .write_all(b"GET /users/5 HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.ends_with("user 5"), "{}", res);
+    ///     stop.send(()).await;
+    /// });
+    ///
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn with_keep_alive_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.keep_alive_timeout = timeout.into();
+        self
+    }
+
+    /// Get the keep-alive timeout.  
+    pub fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.keep_alive_timeout
+    }
+
+    /// Set how many requests a connection may send, the response to the last one has
+    /// `Connection: close` and the connection is closed after it.  `None`, the default, allows
+    /// any number.  
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::Duration;
+    /// async fn hello(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from("hello"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, Route::from("/hello"), hello);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string()).with_max_requests_per_connection(2);
+    /// assert_eq!(config.max_requests_per_connection(), Some(2));
+    /// std::thread::spawn(move || Server::new(config).run(router).map_err(|e| e.to_string()));
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     let req = "GET /hello HTTP/1.1\r\nhost: localhost\r\n\r\n";
+    ///     stream.write_all(req.repeat(3).as_bytes()).await.unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert_eq!(res.matches("HTTP/1.1 200").count(), 2, "{}", res);
+    ///     assert_eq!(res.matches("connection: close").count(), 1, "{}", res);
+    /// });
+    /// ```
+    pub fn with_max_requests_per_connection(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.max_requests_per_connection = max.into();
+        self
+    }
+
+    /// Get the most requests a connection may send.  
+    pub fn max_requests_per_connection(&self) -> Option<usize> {
+        self.max_requests_per_connection
+    }
+
+    /// Set how many connections the server may have open at once.  `None`, the default, allows
+    /// any number.  Once the limit is reached, new connections wait or are rejected depending on
+    /// the [connection overflow](#method.with_connection_overflow) policy.  
+    ///
+    /// A connection stops counting once it is closed, whether its client went away, a timeout
+    /// closed it or its endpoint panicked.  Kept-alive connections count while they wait for their
+    /// next request, so a low limit goes with a short
+    /// [keep-alive timeout](#method.with_keep_alive_timeout).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::{Duration, Instant};
+    /// #[endpoint]
+    /// async fn slow() -> Result<Response, Error> {
+    ///     task::sleep(Duration::from_millis(300)).await;
+    ///     Ok(Response::from("done"))
+    /// }
+    ///
+    /// let serve = |overflow| {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"slow"), ___slow);
+    ///     let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    ///     let config = Config::new(addr.to_string())
+    ///         .with_max_connections(1)
+    ///         .with_connection_overflow(overflow);
+    ///     std::thread::spawn(move || Server::new(config).run(router).map_err(|e| e.to_string()));
+    ///     addr
+    /// };
+    /// // Send a request for `/slow` on a new connection, once the server is up.
+    /// let start = |addr| async move {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     stream
+    ///         .write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     stream
+    /// };
+    /// let read = |mut stream: TcpStream| async move {
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     res
+    /// };
+    ///
+    /// // The second connection waits for the first to close.
+    /// let addr = serve(ConnectionOverflow::Wait);
+    /// task::block_on(async {
+    ///     let first = start(addr).await;
+    ///     task::sleep(Duration::from_millis(50)).await;
+    ///     let begin = Instant::now();
+    ///     let second = start(addr).await;
+    ///     assert!(read(first).await.ends_with("done"));
+    ///     assert!(read(second).await.ends_with("done"));
+    ///     assert!(begin.elapsed() >= Duration::from_millis(450), "{:?}", begin.elapsed());
+    /// });
+    ///
+    /// // Or is answered right away.
+    /// let addr = serve(ConnectionOverflow::Reject);
+    /// task::block_on(async {
+    ///     let first = start(addr).await;
+    ///     task::sleep(Duration::from_millis(50)).await;
+    ///     let second = start(addr).await;
+    ///     assert!(read(second).await.starts_with("HTTP/1.1 503"));
+    ///     assert!(read(first).await.ends_with("done"));
+    ///
+    ///     // There is room again once the first connection has closed.
+    ///     task::sleep(Duration::from_millis(50)).await;
+    ///     assert!(read(start(addr).await).await.ends_with("done"));
+    /// });
+    /// ```
+    pub fn with_max_connections(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.max_connections = max.into();
+        self
+    }
+
+    /// Get the most connections the server may have open at once.  
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// Set what happens to new connections while the server has as many as it may.  Defaults to
+    /// [`ConnectionOverflow::Wait`](enum.ConnectionOverflow.html#variant.Wait).  
+    pub fn with_connection_overflow(mut self, overflow: ConnectionOverflow) -> Self {
+        self.connection_overflow = overflow;
+        self
+    }
+
+    /// Get the connection overflow policy.  
+    pub fn connection_overflow(&self) -> ConnectionOverflow {
+        self.connection_overflow
+    }
+
+    /// Set the oldest HTTP version the server answers, requests for older versions get
+    /// `505 HTTP Version Not Supported`.  Defaults to HTTP/1.0.  
+    ///
+    /// HTTP/1.0 and HTTP/1.1 are served, and responses are always HTTP/1.1 as the version a server
+    /// supports.  HTTP/1.0 connections are closed after each response unless the request asked for
+    /// `Connection: keep-alive`, and their responses are never chunked.  Requests for other
+    /// versions get a `505`, except HTTP/0.9 and HTTP/2 with prior knowledge whose connections are
+    /// closed without a response, since those clients could not read one.  HTTP/1.1 requests
+    /// without a `Host` header get `400 Bad Request`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, task};
+    /// # use http_types::{Method, Response, Version};
+    /// # use std::time::Duration;
+    /// #[endpoint]
+    /// async fn hello() -> Result<Response, Error> {
+    ///     Ok(Response::from("hello"))
+    /// }
+    ///
+    /// let serve = |min_version| {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"hello"), ___hello);
+    ///     let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    ///     let config = Config::new(addr.to_string()).with_min_http_version(min_version);
+    ///     std::thread::spawn(move || Server::new(config).run(router).map_err(|e| e.to_string()));
+    ///     addr
+    /// };
+    /// let connect = |addr| task::block_on(async move {
+    ///     loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     }
+    /// });
+    /// let send = |stream: &mut TcpStream, req: &str| task::block_on(async {
+    ///     stream.write_all(req.as_bytes()).await.unwrap();
+    /// });
+    /// let read_to_end = |mut stream: TcpStream| task::block_on(async move {
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     res
+    /// });
+    ///
+    /// let addr = serve(Version::Http1_0);
+    ///
+    /// // HTTP/1.0 closes the connection after the response by default.
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "GET /hello HTTP/1.0\r\n\r\n");
+    /// let res = read_to_end(stream);
+    /// assert!(res.starts_with("HTTP/1.1 200"));
+    /// assert!(res.contains("connection: close"));
+    /// assert!(res.ends_with("hello"));
+    ///
+    /// // Unless the client asks to keep it alive.
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "GET /hello HTTP/1.0\r\nconnection: keep-alive\r\n\r\n");
+    /// task::block_on(async {
+    ///     let mut res = vec![];
+    ///     while !res.ends_with(b"hello") {
+    ///         let mut buf = [0; 1024];
+    ///         let n = stream.read(&mut buf).await.unwrap();
+    ///         assert!(n > 0, "connection closed early");
+    ///         res.extend_from_slice(&buf[..n]);
+    ///     }
+    ///     assert!(String::from_utf8(res).unwrap().contains("connection: keep-alive"));
+    /// });
+    /// send(&mut stream, "GET /hello HTTP/1.0\r\n\r\n");
+    /// assert!(read_to_end(stream).starts_with("HTTP/1.1 200"));
+    ///
+    /// // Versions that do not exist are refused.
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "GET /hello HTTP/9.9\r\nhost: localhost\r\n\r\n");
+    /// assert!(read_to_end(stream).starts_with("HTTP/1.1 505"));
+    ///
+    /// // HTTP/1.1 requests must name their host.
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "GET /hello HTTP/1.1\r\n\r\n");
+    /// assert!(read_to_end(stream).starts_with("HTTP/1.1 400"));
+    ///
+    /// // HTTP/2 prior knowledge is closed without a response.
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+    /// assert_eq!(read_to_end(stream), "");
+    ///
+    /// // HTTP/1.0 can be refused too.
+    /// let addr = serve(Version::Http1_1);
+    /// let mut stream = connect(addr);
+    /// send(&mut stream, "GET /hello HTTP/1.0\r\n\r\n");
+    /// assert!(read_to_end(stream).starts_with("HTTP/1.1 505"));
+    /// ```
+    pub fn with_min_http_version(mut self, version: Version) -> Self {
+        self.min_http_version = version;
+        self
+    }
+
+    /// Get the oldest HTTP version the server answers.  
+    pub fn min_http_version(&self) -> Version {
+        self.min_http_version
+    }
+
+    /// Set the largest single part a [`Multipart`](struct.Multipart.html) body may have before the
+    /// request is rejected with `413 Payload Too Large`.  Defaults to 10 MiB.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4000").with_multipart_part_limit(1024 * 1024);
+    /// assert_eq!(config.multipart_part_limit(), 1024 * 1024);
+    /// ```
+    pub fn with_multipart_part_limit(mut self, bytes: u64) -> Self {
+        self.multipart_part_limit = bytes;
+        self
+    }
+
+    /// Print the routes of the router in a table once the server is listening, see
+    /// [`Router::print_routes`](struct.Router.html#method.print_routes).  Defaults to false.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4000").with_print_routes(true);
+    /// assert!(config.print_routes());
+    /// ```
+    pub fn with_print_routes(mut self, enabled: bool) -> Self {
+        self.print_routes = enabled;
+        self
+    }
+
+    /// Get whether the routes are printed at startup.  
+    pub fn print_routes(&self) -> bool {
+        self.print_routes
+    }
+
+    /// Get the multipart part limit.  
+    pub fn multipart_part_limit(&self) -> u64 {
+        self.multipart_part_limit
+    }
+
+    /// Set the largest request body that is read into memory.  Requests with a larger body are
+    /// rejected with `413 Payload Too Large` before their endpoint is called.  Defaults to 1 MiB.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4000").with_max_body_size(64 * 1024);
+    /// assert_eq!(config.max_body_size(), 64 * 1024);
+    /// ```
+    pub fn with_max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = bytes;
+        self
+    }
+
+    /// Get the maximum request body size.  
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// Register the [`SignatureGuard`](struct.SignatureGuard.html) that verifies requests for the
+    /// [`Signed`](struct.Signed.html) props.  
+    pub fn with_signature_guard(mut self, guard: SignatureGuard) -> Self {
+        self.signature_guard = Some(guard);
+        self
+    }
+
+    /// Get the signature guard, if one is registered.  
+    pub fn signature_guard(&self) -> Option<&SignatureGuard> {
+        self.signature_guard.as_ref()
+    }
+
+    /// Register the [`HttpClient`](struct.HttpClient.html) that endpoints receive as a props.  
+    #[cfg(feature = "client")]
+    pub fn with_http_client(mut self, client: HttpClient) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Get the registered http client.  
+    #[cfg(feature = "client")]
+    pub fn http_client(&self) -> Option<&HttpClient> {
+        self.http_client.as_ref()
+    }
+
+    /// Compress response bodies for clients that accept it, as set out by
+    /// [`Compression`](struct.Compression.html).  Responses are sent as they are by default.  
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: impl Into<Option<Compression>>) -> Self {
+        self.compression = compression.into();
+        self
+    }
+
+    /// Get how response bodies are compressed, if they are.  
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref()
+    }
+
+    /// Serve HTTPS, with the PEM encoded certificate chain and private key read from the given
+    /// files when the server starts.  
+    /// ```
+    /// # use windmill::Config;
+    /// let config = Config::new("127.0.0.1:4443").with_tls("cert.pem", "key.pem");
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        cert_chain: impl Into<std::path::PathBuf>,
+        key: impl Into<std::path::PathBuf>,
+    ) -> Self {
+        self.tls = Some(TlsSource::Files {
+            cert_chain: cert_chain.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// Serve HTTPS with a PEM encoded certificate chain and private key held in memory.  
+    ///
+    /// Connections that fail the TLS handshake are logged and dropped, the server keeps accepting
+    /// others.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, task};
+    /// # use futures_rustls::{rustls, TlsConnector};
+    /// # use http_types::{Method, Response};
+    /// # use std::{convert::TryFrom, sync::Arc, time::Duration};
+    /// #[endpoint]
+    /// async fn hello() -> Result<Response, Error> {
+    ///     Ok(Response::from("hello over tls"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"hello"), ___hello);
+    ///
+    /// let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let config = Config::new(addr.to_string())
+    ///     .with_tls_pem(cert.serialize_pem().unwrap(), cert.serialize_private_key_pem());
+    /// std::thread::spawn(move || Server::new(config).run(router).map_err(|e| e.to_string()));
+    ///
+    /// let mut roots = rustls::RootCertStore::empty();
+    /// roots.add(&rustls::Certificate(cert.serialize_der().unwrap())).unwrap();
+    /// let client = rustls::ClientConfig::builder()
+    ///     .with_safe_defaults()
+    ///     .with_root_certificates(roots)
+    ///     .with_no_client_auth();
+    /// let connector = TlsConnector::from(Arc::new(client));
+    ///
+    /// task::block_on(async {
+    ///     let mut plain = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     plain.write_all(b"GET /hello HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    ///     let mut res = vec![];
+    ///     let _ = plain.read_to_end(&mut res).await;
+    ///     assert!(!res.starts_with(b"HTTP/1.1 200"));
+    ///
+    ///     let stream = TcpStream::connect(addr).await.unwrap();
+    ///     let name = rustls::ServerName::try_from("localhost").unwrap();
+    ///     let mut stream = connector.connect(name, stream).await.unwrap();
+    ///     stream
+    ///         .write_all(b"GET /hello HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.starts_with("HTTP/1.1 200"));
+    ///     assert!(res.ends_with("hello over tls"));
+    /// });
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn with_tls_pem(mut self, cert_chain: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        self.tls = Some(TlsSource::Pem {
+            cert_chain: cert_chain.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls(&self) -> Option<&TlsSource> {
+        self.tls.as_ref()
+    }
 }
@@ -0,0 +1,152 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+/// What the server does with new connections while it has
+/// [as many as it may](struct.Config.html#method.with_max_connections).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionOverflow {
+    /// Stop accepting connections until one closes, leaving new ones in the backlog of the
+    /// listening socket.
+    #[default]
+    Wait,
+    /// Accept new connections, answer them with `503 Service Unavailable` and close them.
+    /// Connections over tls are closed without a response.
+    Reject,
+}
+
+/// The number of connections a server has open, taken with
+/// [`Server::connections`](struct.Server.html#method.connections) before it runs.
+///
+/// Connections are counted from when they are accepted until they are closed, whatever closed
+/// them.  Connections rejected for being over the limit are not counted.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{net::TcpStream, prelude::*, task};
+/// # use http_types::{Method, Response};
+/// # use std::time::Duration;
+/// #[endpoint]
+/// async fn health(connections: State<ConnectionCount>) -> Result<Response, Error> {
+///     Ok(Response::from(format!("{} connections", connections.get())))
+/// }
+///
+/// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+/// let server = Server::new(Config::new(addr.to_string()));
+/// let connections = server.connections();
+/// assert_eq!(connections.get(), 0);
+///
+/// let mut router = Router::new();
+/// router.state(connections.clone());
+/// router.add(Method::Get, route!(/"health"), ___health);
+/// std::thread::spawn(move || server.run(router).map_err(|e| e.to_string()));
+///
+/// task::block_on(async {
+///     let connect = || async {
+///         loop {
+///             match TcpStream::connect(addr).await {
+///                 Ok(stream) => break stream,
+///                 Err(_) => task::sleep(Duration::from_millis(10)).await,
+///             }
+///         }
+///     };
+///     let idle = connect().await;
+///     let mut stream = connect().await;
+///     stream
+///         .write_all(b"GET /health HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+///         .await
+///         .unwrap();
+///     let mut res = String::new();
+///     stream.read_to_string(&mut res).await.unwrap();
+///     assert!(res.ends_with("2 connections"), "{}", res);
+///
+///     drop(idle);
+///     while connections.get() > 0 {
+///         task::sleep(Duration::from_millis(10)).await;
+///     }
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct ConnectionCount(Arc<Slots>);
+
+impl ConnectionCount {
+    /// The number of connections open right now.
+    pub fn get(&self) -> usize {
+        self.0.lock().open
+    }
+
+    /// Take a slot for a new connection right away, unless `max` connections are open.
+    pub(crate) fn try_acquire(&self, max: Option<usize>) -> Option<Slot> {
+        let mut slots = self.0.lock();
+        match max {
+            Some(max) if slots.open >= max => None,
+            _ => {
+                slots.open += 1;
+                Some(Slot(self.0.clone()))
+            }
+        }
+    }
+
+    /// Wait until fewer than `max` connections are open.
+    pub(crate) fn room(&self, max: Option<usize>) -> Room {
+        Room {
+            count: self.clone(),
+            max,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    state: Mutex<SlotState>,
+}
+
+#[derive(Default)]
+struct SlotState {
+    open: usize,
+    waiters: Vec<Waker>,
+}
+
+impl Slots {
+    fn lock(&self) -> MutexGuard<'_, SlotState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A connection counted as open, until it is dropped.
+pub(crate) struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut slots = self.0.lock();
+        slots.open -= 1;
+        for waker in slots.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+pub(crate) struct Room {
+    count: ConnectionCount,
+    max: Option<usize>,
+}
+
+impl Future for Room {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut slots = self.count.0.lock();
+        match self.max {
+            Some(max) if slots.open >= max => {
+                slots.waiters.push(cx.waker().clone());
+                Poll::Pending
+            }
+            _ => Poll::Ready(()),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    params::Params,
+    props::{Props, PropsFuture},
+    util::percent_decode,
+};
+use std::{fmt, time::Duration};
+
+/// A props with the cookies sent with a request in its `Cookie` headers.
+///
+/// Quoted values are unquoted and percent-encoded values are decoded.  When a name is sent more
+/// than once the first value is kept, since browsers send the cookie with the most specific path
+/// first.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::{sync::Arc, time::Duration};
+/// #[endpoint]
+/// async fn login(cookies: Cookies) -> Result<Respond<String>, Error> {
+///     let greeting = format!(
+///         "{} {} {}",
+///         cookies.get("theme").unwrap(),
+///         cookies.get("lang").unwrap(),
+///         cookies.get("note").unwrap(),
+///     );
+///     let session = Cookie::new("session", "s3cr3t")
+///         .with_path("/")
+///         .with_max_age(Duration::from_secs(3600))
+///         .with_http_only()
+///         .with_secure()
+///         .with_same_site(SameSite::Lax);
+///     let note = Cookie::new("note", "fish & chips; 100%");
+///     Ok(Respond::new(greeting).with_cookie(session).with_cookie(note))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"login"), ___login);
+/// let router = Arc::new(router);
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/login").unwrap());
+/// req.insert_header("cookie", r#"theme="dark"; lang=en; note=caf%C3%A9; lang=fr"#).unwrap();
+/// let res = task::block_on(router.respond(req));
+///
+/// let set_cookie: Vec<String> = res
+///     .header(&"set-cookie".parse().unwrap())
+///     .unwrap()
+///     .iter()
+///     .map(|value| value.to_string())
+///     .collect();
+/// assert_eq!(
+///     set_cookie,
+///     vec![
+///         "session=s3cr3t; Max-Age=3600; Path=/; Secure; HttpOnly; SameSite=Lax",
+///         "note=fish%20&%20chips%3B%20100%25",
+///     ]
+/// );
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), r#""dark en café""#);
+///
+/// // Values round-trip through their encoding.
+/// let cookies = Cookies::parse(&set_cookie[1]);
+/// assert_eq!(cookies.get("note"), Some("fish & chips; 100%"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cookies(Vec<(String, String)>);
+
+impl Cookies {
+    /// Parse the value of a `Cookie` header, such as `theme=dark; lang=en`.
+    pub fn parse(header: &str) -> Self {
+        let mut cookies = Self::default();
+        cookies.extend(header);
+        cookies
+    }
+
+    /// Get the cookies of a request.
+    pub fn of(req: &http_types::Request) -> Self {
+        let mut cookies = Self::default();
+        if let Some(values) = req.header(&http_types::headers::COOKIE) {
+            for value in values.iter() {
+                cookies.extend(value.as_str());
+            }
+        }
+        cookies
+    }
+
+    /// The value of the cookie called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(cookie, _)| cookie == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The names and values of every cookie, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The number of cookies.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no cookies were sent.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn extend(&mut self, header: &str) {
+        for pair in header.split(';') {
+            let (name, value) = match pair.find('=') {
+                Some(eq) => (pair[..eq].trim(), pair[eq + 1..].trim()),
+                None => continue,
+            };
+            if name.is_empty() || self.get(name).is_some() {
+                continue;
+            }
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            self.0.push((name.to_owned(), percent_decode(value)));
+        }
+    }
+}
+
+impl Props for Cookies {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let cookies = Self::of(&req);
+            Ok((req, params, cookies))
+        })
+    }
+}
+
+/// The `SameSite` attribute of a [`Cookie`](struct.Cookie.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// A cookie to set on the client with a `Set-Cookie` header.
+///
+/// The value is percent-encoded where it has characters a cookie value cannot hold, and
+/// [`Cookies`](struct.Cookies.html) decodes it again.  Add it to a response with
+/// [`Respond::with_cookie`](struct.Respond.html#method.with_cookie) or
+/// [`append_to`](#method.append_to).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    max_age: Option<Duration>,
+    path: Option<String>,
+    domain: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Create a session cookie without attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            max_age: None,
+            path: None,
+            domain: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Keep the cookie for `max_age`, or remove it from the client if zero.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Only send the cookie with requests under `path`.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Send the cookie with requests to `domain` and its subdomains.
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Only send the cookie over https.
+    pub fn with_secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Hide the cookie from scripts.
+    pub fn with_http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    /// Set when the cookie is sent with cross-site requests.
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of the cookie, not encoded.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Add a `Set-Cookie` header for the cookie to `res`, keeping the ones already there.
+    pub fn append_to(&self, res: &mut http_types::Response) {
+        let _ = res.append_header("set-cookie", self.to_string().as_str());
+    }
+}
+
+impl fmt::Display for Cookie {
+    /// Render the cookie as the value of a `Set-Cookie` header.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, percent_encode(&self.value))?;
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(path) = self.path.as_ref() {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = self.domain.as_ref() {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => f.write_str("; SameSite=Strict"),
+            Some(SameSite::Lax) => f.write_str("; SameSite=Lax"),
+            Some(SameSite::None) => f.write_str("; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Encode the bytes a cookie value cannot hold, and `%` itself.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'!' | b'#'..=b'+' | b'-'..=b':' | b'<'..=b'[' | b']'..=b'~' if b != b'%' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
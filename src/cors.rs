@@ -0,0 +1,198 @@
+use http_types::{Method, StatusCode};
+use std::time::Duration;
+
+/// Cross-origin resource sharing for browser frontends served from other origins, set with
+/// [`Router::cors`](struct.Router.html#method.cors).
+///
+/// The router answers `OPTIONS` preflight requests for every path that has a route, without
+/// running middlewares or endpoints, and adds `Access-Control-Allow-Origin` to every other
+/// response of an allowed origin, errors included.  Preflights from origins that are not allowed
+/// are answered without any `Access-Control-Allow-*` headers, so the browser blocks the request.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::{sync::Arc, time::Duration};
+/// #[endpoint]
+/// async fn orders() -> Result<Response, Error> {
+///     Ok(Response::from("[]"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"orders"), ___orders);
+/// router.add(Method::Post, route!(/"orders"), ___orders);
+/// router.cors(
+///     Cors::new()
+///         .with_origin("https://app.example.com")
+///         .with_headers(&["content-type", "authorization"])
+///         .with_credentials()
+///         .with_max_age(Duration::from_secs(600)),
+/// );
+/// let router = Arc::new(router);
+///
+/// let send = |method: Method, path: &str, origin: &str| {
+///     let url = Url::parse("http://api.example.com").unwrap().join(path).unwrap();
+///     let mut req = Request::new(method, url);
+///     req.insert_header("origin", origin).unwrap();
+///     req.insert_header("access-control-request-method", "POST").unwrap();
+///     task::block_on(router.clone().respond(req))
+/// };
+/// let header = |res: &Response, name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+///
+/// // Preflight from the frontend.
+/// let res = send(Method::Options, "/orders", "https://app.example.com");
+/// assert_eq!(res.status(), StatusCode::NoContent);
+/// assert_eq!(header(&res, "access-control-allow-origin").unwrap(), "https://app.example.com");
+/// assert_eq!(header(&res, "access-control-allow-methods").unwrap(), "GET, HEAD, POST");
+/// assert_eq!(header(&res, "access-control-allow-headers").unwrap(), "content-type, authorization");
+/// assert_eq!(header(&res, "access-control-allow-credentials").unwrap(), "true");
+/// assert_eq!(header(&res, "access-control-max-age").unwrap(), "600");
+///
+/// // Preflight from anywhere else.
+/// let res = send(Method::Options, "/orders", "https://evil.example.com");
+/// assert_eq!(res.status(), StatusCode::NoContent);
+/// assert!(header(&res, "access-control-allow-origin").is_none());
+/// assert!(header(&res, "access-control-allow-methods").is_none());
+///
+/// // Preflight for a path without routes.
+/// assert_eq!(send(Method::Options, "/nothing", "https://app.example.com").status(), StatusCode::NotFound);
+///
+/// // Simple requests, errors included.
+/// let res = send(Method::Get, "/orders", "https://app.example.com");
+/// assert_eq!(header(&res, "access-control-allow-origin").unwrap(), "https://app.example.com");
+/// assert_eq!(header(&res, "vary").unwrap(), "origin");
+/// let res = send(Method::Delete, "/orders", "https://app.example.com");
+/// assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+/// assert_eq!(header(&res, "access-control-allow-origin").unwrap(), "https://app.example.com");
+/// assert!(header(&send(Method::Get, "/orders", "https://evil.example.com"), "access-control-allow-origin").is_none());
+/// ```
+///
+/// Any origin is allowed with `*`, unless credentials are, which browsers only accept for an
+/// echoed origin:
+/// ```
+/// # use windmill::Cors;
+/// let public = Cors::new().with_any_origin();
+/// assert_eq!(public.allow_origin("https://anyone.example"), Some("*"));
+/// let private = Cors::new().with_any_origin().with_credentials();
+/// assert_eq!(private.allow_origin("https://anyone.example"), Some("https://anyone.example"));
+/// assert_eq!(Cors::new().allow_origin("https://anyone.example"), None);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Cors {
+    origins: Vec<String>,
+    any_origin: bool,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Create a new instance of `Cors` that allows no origin.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow requests from `origin`, such as `https://app.example.com`.
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.push(origin.into());
+        self
+    }
+
+    /// Allow requests from any origin.
+    pub fn with_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Allow only `methods` in preflights, rather than every method with a route for the path.
+    pub fn with_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Allow requests to send `headers` beyond the ones browsers always allow.
+    pub fn with_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|header| (*header).to_owned()).collect();
+        self
+    }
+
+    /// Allow requests with credentials, such as cookies.
+    pub fn with_credentials(mut self) -> Self {
+        self.credentials = true;
+        self
+    }
+
+    /// Let browsers cache preflight responses for `max_age`.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The `Access-Control-Allow-Origin` for requests from `origin`, or `None` if it is not
+    /// allowed.
+    pub fn allow_origin<'o>(&self, origin: &'o str) -> Option<&'o str> {
+        match self.any_origin {
+            true if !self.credentials => Some("*"),
+            true => Some(origin),
+            false => self
+                .origins
+                .iter()
+                .find(|allowed| allowed.eq_ignore_ascii_case(origin))
+                .map(|_| origin),
+        }
+    }
+
+    /// Whether `req` is a preflight request.
+    pub(crate) fn is_preflight(req: &http_types::Request) -> bool {
+        req.method() == Method::Options
+            && req.header(&"origin".parse().unwrap()).is_some()
+            && req
+                .header(&"access-control-request-method".parse().unwrap())
+                .is_some()
+    }
+
+    /// The response to a preflight request from `origin` for a path with routes for `methods`.
+    pub(crate) fn preflight(
+        &self,
+        origin: Option<&str>,
+        methods: Vec<String>,
+    ) -> http_types::Response {
+        let mut res = http_types::Response::new(StatusCode::NoContent);
+        if !self.apply(origin, &mut res) {
+            return res;
+        }
+
+        let methods = match self.methods.is_empty() {
+            true => methods,
+            false => self.methods.iter().map(Method::to_string).collect(),
+        };
+        let _ = res.insert_header("access-control-allow-methods", methods.join(", "));
+        if !self.headers.is_empty() {
+            let _ = res.insert_header("access-control-allow-headers", self.headers.join(", "));
+        }
+        if let Some(max_age) = self.max_age {
+            let _ = res.insert_header("access-control-max-age", max_age.as_secs().to_string());
+        }
+        res
+    }
+
+    /// Add the headers allowing `origin` to read `res`, returning whether it is allowed.
+    pub(crate) fn apply(&self, origin: Option<&str>, res: &mut http_types::Response) -> bool {
+        let allowed = match origin.and_then(|origin| self.allow_origin(origin)) {
+            Some(allowed) => allowed,
+            None => return false,
+        };
+        let _ = res.insert_header("access-control-allow-origin", allowed);
+        if allowed != "*" {
+            let _ = res.append_header("vary", "origin");
+        }
+        if self.credentials {
+            let _ = res.insert_header("access-control-allow-credentials", "true");
+        }
+        true
+    }
+}
@@ -0,0 +1,34 @@
+use crate::extensions::Extensions;
+use std::{future::Future, pin::Pin, time::Duration};
+
+pub(crate) type DecorationFuture =
+    Pin<Box<dyn Future<Output = Vec<(String, String)>> + Send + Sync>>;
+
+/// A hook that attaches headers to a response after the endpoint has run.  
+pub(crate) struct Decorator {
+    budget: Duration,
+    hook: Box<dyn Fn(Extensions) -> DecorationFuture + Send + Sync>,
+}
+
+impl Decorator {
+    pub(crate) fn new<F, Fut>(budget: Duration, hook: F) -> Self
+    where
+        F: Fn(Extensions) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Vec<(String, String)>> + Send + Sync + 'static,
+    {
+        Self {
+            budget,
+            hook: Box::new(move |ext| Box::pin(hook(ext))),
+        }
+    }
+
+    /// Run the hook, appending its headers to `res` unless it overruns its budget.  
+    pub(crate) async fn apply(&self, ext: &Extensions, res: &mut http_types::Response) {
+        if let Ok(headers) = async_std::future::timeout(self.budget, (self.hook)(ext.clone())).await
+        {
+            for (name, value) in headers {
+                let _ = res.append_header(name.as_str(), value.as_str());
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{listener::Stream, matched::RequestLabel, route::ResponseFuture};
+use async_std::prelude::*;
+use http_types::StatusCode;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// The connection a request came in on, attached to it by the server so the router can tell
+/// when the client goes away.
+#[derive(Clone)]
+pub(crate) struct ClientConnection(pub(crate) Stream);
+
+impl ClientConnection {
+    pub(crate) fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+}
+
+/// Marks the response of a request whose client went away before it was answered.
+#[derive(Clone, Copy)]
+pub(crate) struct ClientAborted;
+
+impl ClientAborted {
+    pub(crate) fn is_set(res: &http_types::Response) -> bool {
+        res.local().get::<Self>().is_some()
+    }
+}
+
+/// Drop `res` if the client closes `conn` before it has produced the response, answering with a
+/// `400 Bad Request` nobody reads.  The abort is counted in `aborts` and logged, `request`, such
+/// as `GET /users/{id}`, identifying it.
+pub(crate) fn cancel_on_disconnect(
+    conn: ClientConnection,
+    aborts: Arc<AtomicU64>,
+    request: RequestLabel,
+    res: ResponseFuture,
+) -> ResponseFuture {
+    Box::pin(res.race(async move {
+        conn.0.closed().await;
+        aborts.fetch_add(1, Ordering::Relaxed);
+        eprintln!(
+            "{}: client closed the connection, handler cancelled",
+            request
+        );
+        let mut res = http_types::Response::new(StatusCode::BadRequest);
+        res.local_mut().insert(ClientAborted);
+        res
+    }))
+}
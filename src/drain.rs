@@ -0,0 +1,117 @@
+use crate::{config::Config, expect::ExpectContinue};
+use async_std::io::{self, prelude::*, BufReader};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// The default number of unread body bytes drained after an early response.
+pub(crate) const DEFAULT_DRAIN_BUDGET: u64 = 64 * 1024;
+
+/// The default time spent draining unread body bytes after an early response.
+pub(crate) const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Tracks how much of a request body the endpoint read, so whatever it left behind can be dealt
+/// with before the connection is reused.
+///
+/// Without this an endpoint that responds before reading the body, like a props rejecting an
+/// unauthenticated upload, leaves the rest of the upload on the connection to be parsed as the
+/// next request.
+pub(crate) struct BodyTracker {
+    inner: Arc<Mutex<Tracked>>,
+    len: Option<u64>,
+    expect: Option<ExpectContinue>,
+}
+
+struct Tracked {
+    body: http_types::Body,
+    read: u64,
+    eof: bool,
+}
+
+struct TrackedReader(Arc<Mutex<Tracked>>);
+
+impl BodyTracker {
+    /// Replace the body of `req` with one that tracks how much of it is read.
+    pub(crate) fn wrap(req: &mut http_types::Request) -> Self {
+        let len = req
+            .header(&http_types::headers::CONTENT_LENGTH)
+            .and_then(|values| values.last())
+            .and_then(|value| value.as_str().trim().parse::<u64>().ok());
+
+        let inner = Arc::new(Mutex::new(Tracked {
+            body: req.take_body(),
+            read: 0,
+            eof: len == Some(0),
+        }));
+        let reader = BufReader::new(TrackedReader(inner.clone()));
+        req.set_body(http_types::Body::from_reader(
+            reader,
+            len.map(|len| len as usize),
+        ));
+
+        Self {
+            inner,
+            len,
+            expect: ExpectContinue::of(req),
+        }
+    }
+
+    fn finished(&self) -> bool {
+        let tracked = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        tracked.eof || matches!(self.len, Some(len) if tracked.read >= len)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        let tracked = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.len.map(|len| len.saturating_sub(tracked.read))
+    }
+
+    /// Drain whatever the endpoint did not read if it fits in the configured budget, otherwise
+    /// mark the response `Connection: close` so the server drops the connection after sending it.
+    /// A body the client is waiting for `100 Continue` to send is never asked for.
+    pub(crate) async fn settle(self, config: Option<&Config>, res: &mut http_types::Response) {
+        if self.finished() {
+            return;
+        }
+        if self.expect.as_ref().map_or(false, ExpectContinue::decline) {
+            let _ = res.insert_header("connection", "close");
+            return;
+        }
+
+        let (budget, timeout) = match config {
+            Some(config) => (config.drain_budget(), config.drain_timeout()),
+            None => (DEFAULT_DRAIN_BUDGET, DEFAULT_DRAIN_TIMEOUT),
+        };
+
+        if !matches!(self.remaining(), Some(remaining) if remaining > budget) {
+            let mut reader = TrackedReader(self.inner.clone()).take(budget);
+            let _ = io::timeout(timeout, io::copy(&mut reader, &mut io::sink())).await;
+            if self.finished() {
+                return;
+            }
+        }
+
+        let _ = res.insert_header("connection", "close");
+    }
+}
+
+impl io::Read for TrackedReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut tracked = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let poll = Pin::new(&mut tracked.body).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            if n == 0 && !buf.is_empty() {
+                tracked.eof = true;
+            }
+            tracked.read += n as u64;
+        }
+        poll
+    }
+}
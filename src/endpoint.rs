@@ -1,22 +1,129 @@
-use crate::{error::Error, Params};
+use crate::{error::Error, responder::IntoResponse, util::set_content_length, Params};
 use std::{future::Future, pin::Pin};
 
 /// A trait for things that can be used as routes.  
-pub trait Endpoint: 'static + Copy {
-    type Fut: Future<Output = Result<http_types::Response, Error>> + Send + Sync + 'static;
+///
+/// An endpoint, or one of its props, that panics answers the request with
+/// `500 Internal Server Error` and logs the panic with the method and path of the request, while
+/// the server keeps serving other requests.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+/// # use http_types::{Method, Response};
+/// # use std::time::Duration;
+/// #[endpoint]
+/// async fn user(route: MatchedRoute) -> Result<Response, Error> {
+///     // the route was never named
+///     let name = route.name().unwrap();
+///     Ok(Response::from(name))
+/// }
+///
+/// #[endpoint]
+/// async fn health() -> Result<Response, Error> {
+///     Ok(Response::from("ok"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"/user_id), ___user);
+/// router.add(Method::Get, route!(/"health"), ___health);
+///
+/// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+/// let (stop, stopped) = channel(1);
+/// let server = std::thread::spawn(move || {
+///     let config = Config::new(addr.to_string());
+///     Server::new(config)
+///         .run_until(router, async move { let _ = stopped.recv().await; })
+///         .map_err(|e| e.to_string())
+/// });
+///
+/// let get = |path: &'static str| async move {
+///     let mut stream = loop {
+///         match TcpStream::connect(addr).await {
+///             Ok(stream) => break stream,
+///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+///         }
+///     };
+///     let req = format!("GET {} HTTP/1.0\r\nhost: localhost\r\n\r\n", path);
+///     stream.write_all(req.as_bytes()).await.unwrap();
+///     let mut res = String::new();
+///     stream.read_to_string(&mut res).await.unwrap();
+///     res
+/// };
+///
+/// task::block_on(async {
+///     let res = get("/users/7").await;
+///     assert!(res.contains(" 500 Internal Server Error"), "{}", res);
+///     assert!(res.ends_with(r#"{"code":"internal","msg":"internal server error"}"#));
+///
+///     assert!(get("/health").await.ends_with("ok"));
+///     stop.send(()).await;
+/// });
+/// server.join().unwrap().unwrap();
+/// ```
+pub trait Endpoint: 'static {
+    /// The future of a response.  The router polls it where it is, so a future that has to be
+    /// pinned is boxed by the endpoint, once.  
+    type Fut: Future<Output = Result<http_types::Response, Error>> + Unpin + Send + Sync + 'static;
     fn call(&self, req: http_types::Request, params: Params) -> Self::Fut;
 }
 
-/// A blanket impl over the generated hidden functions for endpoints.  
-impl<F, G> Endpoint for F
+/// A blanket impl over the generated hidden functions for endpoints, and any other function
+/// returning something that [turns into a response](trait.IntoResponse.html).  
+///
+/// Closures are endpoints too, and may capture state of their own, such as a connection pool,
+/// which the router keeps for as long as the route and shares between the requests it answers.  
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response};
+/// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// struct Pool {
+///     checkouts: AtomicUsize,
+/// }
+///
+/// let pool = Arc::new(Pool { checkouts: AtomicUsize::new(0) });
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, Route::from("/visits"), {
+///     let pool = pool.clone();
+///     move |_req: Request, _params: Params| {
+///         let pool = pool.clone();
+///         async move {
+///             let n = pool.checkouts.fetch_add(1, Ordering::SeqCst) + 1;
+///             Ok::<_, Error>(Response::from(n.to_string()))
+///         }
+///     }
+/// });
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/visits").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "1");
+///     let mut res = client.get("/visits").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "2");
+/// });
+/// assert_eq!(pool.checkouts.load(Ordering::SeqCst), 2);
+/// ```
+impl<F, G, R> Endpoint for F
 where
-    F: Fn(http_types::Request, Params) -> G + Copy + 'static,
-    G: Future<Output = Result<http_types::Response, Error>> + Send + Sync + 'static,
+    F: Fn(http_types::Request, Params) -> G + 'static,
+    G: Future<Output = Result<R, Error>> + Send + Sync + 'static,
+    R: IntoResponse,
 {
     type Fut = Pin<Box<dyn Future<Output = Result<http_types::Response, Error>> + Send + Sync>>;
 
     fn call(&self, req: http_types::Request, params: Params) -> Self::Fut {
         let fut = (self)(req, params);
-        Box::pin(async move { fut.await })
+        // The only allocation for the endpoint: `fut` is kept inline in the boxed future.
+        Box::pin(async move {
+            let mut res = fut.await?.into_response()?;
+            set_content_length(&mut res);
+            Ok(res)
+        })
     }
 }
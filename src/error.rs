@@ -1,11 +1,111 @@
+use serde::Serialize;
+use std::fmt;
+
 /// A HTTP error.  
+///
+/// An error with an [error code](enum.WindmillErrorCode.html) is sent as a JSON object holding the
+/// code and the message, `{"code": "body_too_large", "msg": "..."}`.  Every error the framework
+/// generates has one.  An error without a code is sent as just its message.  
+///
+/// Errors are built with [`Error::new`](#method.new) or one of the constructors named after a
+/// status, and the `From` impls let `?` turn the errors of parsing and io into one.  
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// async fn user(_req: http_types::Request, params: Params) -> Result<Response, Error> {
+///     let id: u64 = params.get("id").unwrap().parse()?;
+///     match id {
+///         0 => Err(Error::not_found("no such user")),
+///         _ => Ok(Response::from(id.to_string())),
+///     }
+/// }
+///
+/// async fn admin(req: http_types::Request, _params: Params) -> Result<Response, Error> {
+///     match req.header(&"authorization".parse().unwrap()) {
+///         Some(_) => Ok(Response::from("welcome")),
+///         None => Err(Error::unauthorized("log in first")
+///             .with_header("www-authenticate", r#"Basic realm="admin""#)),
+///     }
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, Route::from("/users/{id}"), user);
+/// router.add(Method::Get, Route::from("/admin"), admin);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/users/7").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "7");
+///
+///     let mut res = client.get("/users/seven").send().await;
+///     assert_eq!(res.status(), StatusCode::BadRequest);
+///     assert_eq!(res.body_string().await.unwrap(), r#""invalid digit found in string""#);
+///
+///     let res = client.get("/users/0").send().await;
+///     assert_eq!(res.status(), StatusCode::NotFound);
+///
+///     let res = client.get("/admin").send().await;
+///     assert_eq!(res.status(), StatusCode::Unauthorized);
+///     let challenge = res.header(&"www-authenticate".parse().unwrap()).unwrap();
+///     assert_eq!(challenge[0].as_str(), r#"Basic realm="admin""#);
+/// });
+///
+/// let e = Error::not_found("no such user");
+/// assert_eq!(e.to_string(), "404 Not Found: no such user");
+/// ```
 #[derive(Debug)]
 pub struct Error {
     pub code: http_types::StatusCode,
     pub msg: serde_json::Value,
+    pub error_code: Option<WindmillErrorCode>,
+    headers: Vec<(String, String)>,
 }
 
 impl Error {
+    /// An error with the status `code`, sent with `msg` serialized as JSON.  
+    pub fn new(code: http_types::StatusCode, msg: impl Serialize) -> Self {
+        Self {
+            code,
+            msg: serde_json::to_value(msg).unwrap_or_else(|e| serde_json::json!(e.to_string())),
+            error_code: None,
+            headers: vec![],
+        }
+    }
+
+    /// A `400 Bad Request` error.  
+    pub fn bad_request(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::BadRequest, msg)
+    }
+
+    /// A `401 Unauthorized` error.  Clients expect it to come with a `WWW-Authenticate` header,
+    /// added with [`with_header`](#method.with_header).  
+    pub fn unauthorized(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::Unauthorized, msg)
+    }
+
+    /// A `403 Forbidden` error.  
+    pub fn forbidden(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::Forbidden, msg)
+    }
+
+    /// A `404 Not Found` error.  
+    pub fn not_found(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::NotFound, msg)
+    }
+
+    /// A `409 Conflict` error.  
+    pub fn conflict(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::Conflict, msg)
+    }
+
+    /// A `500 Internal Server Error` error.  
+    pub fn internal(msg: impl Serialize) -> Self {
+        Self::new(http_types::StatusCode::InternalServerError, msg)
+    }
+
     /// The HTTP error code.  
     pub fn code(&self) -> http_types::StatusCode {
         self.code
@@ -15,4 +115,363 @@ impl Error {
     pub fn msg(&self) -> &serde_json::Value {
         &self.msg
     }
+
+    /// The machine-readable kind of the error, if it has one.  
+    pub fn error_code(&self) -> Option<WindmillErrorCode> {
+        self.error_code
+    }
+
+    /// Give the error a machine-readable kind, sent in the `code` field of its body.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::{Error, WindmillErrorCode};
+    /// # use http_types::StatusCode;
+    /// let e = Error::new(StatusCode::TooManyRequests, "slow down")
+    ///     .with_code(WindmillErrorCode::RateLimited);
+    /// assert_eq!(e.error_code(), Some(WindmillErrorCode::RateLimited));
+    /// ```
+    pub fn with_code(mut self, error_code: WindmillErrorCode) -> Self {
+        self.error_code = Some(error_code);
+        self
+    }
+
+    /// Add a header to the response the error is sent as, such as `WWW-Authenticate` or
+    /// `Retry-After`.  Headers with the same name are all sent.  They are added after the
+    /// [error formatter](struct.Router.html#method.set_error_formatter) runs, so it does not need
+    /// to copy them.  
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The headers added with [`with_header`](#method.with_header).  
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The body the error is sent with.  
+    pub(crate) fn body(&self) -> serde_json::Value {
+        match self.error_code {
+            Some(error_code) => serde_json::json!({ "code": error_code, "msg": self.msg }),
+            None => self.msg.clone(),
+        }
+    }
+
+    /// Add the headers of the error to `res`, the response it is sent as.  
+    pub(crate) fn apply_headers(&self, res: &mut http_types::Response) {
+        for (name, value) in self.headers.iter() {
+            let _ = res.append_header(name.as_str(), value.as_str());
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.code, self.code.canonical_reason())?;
+        match &self.msg {
+            serde_json::Value::String(msg) => f.write_str(msg),
+            msg => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A body, query string or value that could not be parsed, sent as `400 Bad Request`.  
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::bad_request(crate::codec::json_error(&e, None))
+            .with_code(WindmillErrorCode::DeserializeFailed)
+    }
+}
+
+/// A number that could not be parsed, sent as `400 Bad Request`.  
+impl From<std::num::ParseIntError> for Error {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Self::bad_request(e.to_string())
+    }
+}
+
+/// An io error, sent as `404 Not Found` for a missing file, `408 Request Timeout` for a timed out
+/// read and `500 Internal Server Error` otherwise.  
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        let code = match e.kind() {
+            std::io::ErrorKind::NotFound => http_types::StatusCode::NotFound,
+            std::io::ErrorKind::TimedOut => http_types::StatusCode::RequestTimeout,
+            _ => http_types::StatusCode::InternalServerError,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+/// An error adding a route to a [`Router`](struct.Router.html).  
+#[derive(Debug)]
+pub enum RouterError {
+    /// The route matches some of the same paths as a route already registered under the same
+    /// method, which would always be chosen in its place.  
+    Conflict {
+        method: http_types::Method,
+        route: String,
+        existing: String,
+    },
+    /// The route added for any method matches some of the same paths as a route already added
+    /// for any method.  
+    AnyConflict { route: String, existing: String },
+    /// The route has the name of a route already registered with another path, so
+    /// [`Router::url_for`](struct.Router.html#method.url_for) could not tell them apart.  
+    DuplicateName {
+        name: &'static str,
+        route: String,
+        existing: String,
+    },
+}
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterError::Conflict {
+                method,
+                route,
+                existing,
+            } => write!(
+                f,
+                "route {} {} conflicts with {} {}",
+                method, route, method, existing
+            ),
+            RouterError::AnyConflict { route, existing } => write!(
+                f,
+                "route {} for any method conflicts with {}",
+                route, existing
+            ),
+            RouterError::DuplicateName {
+                name,
+                route,
+                existing,
+            } => write!(f, "route {} is named `{}` like {}", route, name, existing),
+        }
+    }
+}
+
+impl std::error::Error for RouterError {}
+
+/// The kinds of errors, sent as a stable snake_case string in the `code` field of error bodies.  
+///
+/// Codes are only ever added, never renamed or removed.  Clients should read the code as a string
+/// and treat codes they do not know like any other error with the same status, which is also why
+/// matching on this enum needs a wildcard arm.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use serde::Deserialize;
+/// # use std::{sync::Arc, time::Duration};
+/// #[derive(Deserialize)]
+/// struct Payload {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn create(body: Body<Payload>) -> Result<Response, Error> {
+///     Ok(Response::from(body.into_inner().name))
+/// }
+///
+/// #[endpoint]
+/// async fn slow() -> Result<Response, Error> {
+///     task::sleep(Duration::from_millis(200)).await;
+///     Ok(Response::from("late"))
+/// }
+///
+/// #[endpoint]
+/// async fn limited() -> Result<Response, Error> {
+///     Err(Error::new(StatusCode::TooManyRequests, "slow down")
+///         .with_code(WindmillErrorCode::RateLimited))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"items"), ___create);
+/// let timeouts = Timeouts::new().with_headers(Duration::from_millis(50));
+/// router.add(Method::Get, route!(/"slow").timeouts(timeouts), ___slow);
+/// router.add(Method::Get, route!(/"limited"), ___limited);
+/// router.add(Method::Get, route!(/"down").name("down"), ___limited);
+/// router.handle().disable_route("down", None);
+/// let router = Arc::new(router);
+///
+/// let send = |method: Method, path: &str, body: &str| {
+///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///     let mut req = Request::new(method, url);
+///     req.insert_header("content-length", body.len().to_string()).unwrap();
+///     req.set_body(body.to_owned());
+///     let router = router.clone();
+///     async move {
+///         let res = router.respond(req).await;
+///         let status = res.status();
+///         let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///         (status, body["code"].as_str().unwrap().to_owned())
+///     }
+/// };
+///
+/// task::block_on(async {
+///     let too_large = "x".repeat(2 * 1024 * 1024);
+///     for (method, path, body, expected) in vec![
+///         (Method::Post, "/items", "{", (StatusCode::BadRequest, "deserialize_failed")),
+///         (Method::Post, "/items", too_large.as_str(), (StatusCode::PayloadTooLarge, "body_too_large")),
+///         (Method::Get, "/missing", "", (StatusCode::NotFound, "route_not_found")),
+///         (Method::Delete, "/items", "", (StatusCode::MethodNotAllowed, "method_not_allowed")),
+///         (Method::Get, "/slow", "", (StatusCode::ServiceUnavailable, "timeout")),
+///         (Method::Get, "/down", "", (StatusCode::ServiceUnavailable, "route_disabled")),
+///         (Method::Get, "/limited", "", (StatusCode::TooManyRequests, "rate_limited")),
+///     ] {
+///         let (status, code) = send(method, path, body).await;
+///         assert_eq!((status, code.as_str()), expected);
+///     }
+/// });
+///
+/// // A client built against today's codes keeps working when new ones appear.
+/// #[derive(Deserialize)]
+/// struct ClientError {
+///     code: String,
+/// }
+/// let e: ClientError = serde_json::from_str(r#"{"code":"added_next_year","msg":"?"}"#).unwrap();
+/// assert_eq!(e.code, "added_next_year");
+///
+/// let kind = |code: WindmillErrorCode| match code {
+///     WindmillErrorCode::RouteNotFound => "missing",
+///     _ => "other",
+/// };
+/// assert_eq!(kind(WindmillErrorCode::Timeout), "other");
+/// assert_eq!(WindmillErrorCode::BodyTooLarge.to_string(), "body_too_large");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WindmillErrorCode {
+    /// The request is malformed, such as an invalid `Content-Length` or an unreadable body.
+    BadRequest,
+    /// The request body is larger than the maximum body size.
+    BodyTooLarge,
+    /// The client stopped sending the request body for longer than the
+    /// [read timeout](struct.Config.html#method.with_read_timeout).
+    RequestTimeout,
+    /// The request body has a media type the endpoint does not accept.
+    UnsupportedMediaType,
+    /// The request body, query string or params could not be parsed.
+    DeserializeFailed,
+    /// The request signature is missing or invalid.
+    SignatureInvalid,
+    /// No route matches the path of the request.
+    RouteNotFound,
+    /// Routes match the path of the request, but not its method.
+    MethodNotAllowed,
+    /// The route is disabled through a [`RouterHandle`](struct.RouterHandle.html).
+    RouteDisabled,
+    /// The file to send does not exist.
+    FileNotFound,
+    /// The endpoint found nothing to answer with, returning `None`.
+    NotFound,
+    /// The endpoint exceeded one of its [time budgets](struct.Timeouts.html).
+    Timeout,
+    /// An upstream request made through the http client timed out.
+    UpstreamTimeout,
+    /// An upstream request made through the http client failed.
+    UpstreamFailed,
+    /// The caller sent too many requests.
+    RateLimited,
+    /// The route only accepts WebSocket upgrade requests.
+    UpgradeRequired,
+    /// The `If-Match` or `If-None-Match` header of the request does not hold for the resource.
+    PreconditionFailed,
+    /// The `Range` header of the request asks for bytes past the end of the file.
+    RangeNotSatisfiable,
+    /// Something went wrong on the server, such as a panicking endpoint.
+    Internal,
+}
+
+impl WindmillErrorCode {
+    /// The code as it is sent, e.g. `"body_too_large"`.  
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindmillErrorCode::BadRequest => "bad_request",
+            WindmillErrorCode::BodyTooLarge => "body_too_large",
+            WindmillErrorCode::RequestTimeout => "request_timeout",
+            WindmillErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            WindmillErrorCode::DeserializeFailed => "deserialize_failed",
+            WindmillErrorCode::SignatureInvalid => "signature_invalid",
+            WindmillErrorCode::RouteNotFound => "route_not_found",
+            WindmillErrorCode::MethodNotAllowed => "method_not_allowed",
+            WindmillErrorCode::RouteDisabled => "route_disabled",
+            WindmillErrorCode::FileNotFound => "file_not_found",
+            WindmillErrorCode::NotFound => "not_found",
+            WindmillErrorCode::Timeout => "timeout",
+            WindmillErrorCode::UpstreamTimeout => "upstream_timeout",
+            WindmillErrorCode::UpstreamFailed => "upstream_failed",
+            WindmillErrorCode::RateLimited => "rate_limited",
+            WindmillErrorCode::UpgradeRequired => "upgrade_required",
+            WindmillErrorCode::PreconditionFailed => "precondition_failed",
+            WindmillErrorCode::RangeNotSatisfiable => "range_not_satisfiable",
+            WindmillErrorCode::Internal => "internal",
+        }
+    }
+}
+
+impl Serialize for WindmillErrorCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl fmt::Display for WindmillErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Renders every error the router turns into a response, set with
+/// [`Router::set_error_formatter`](struct.Router.html#method.set_error_formatter).  
+#[derive(Clone)]
+pub(crate) struct ErrorFormatter(std::sync::Arc<FormatFn>);
+
+type FormatFn = dyn Fn(&Error, &http_types::Request) -> http_types::Response + Send + Sync;
+
+impl ErrorFormatter {
+    pub(crate) fn new<F>(format: F) -> Self
+    where
+        F: Fn(&Error, &http_types::Request) -> http_types::Response + Send + Sync + 'static,
+    {
+        Self(std::sync::Arc::new(format))
+    }
+
+    /// Get the formatter attached to a request by the router.  
+    pub(crate) fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+
+    pub(crate) fn format(&self, e: &Error, req: &http_types::Request) -> http_types::Response {
+        let mut res = (self.0)(e, req);
+        e.apply_headers(&mut res);
+        res
+    }
+}
+
+/// A copy of the method, url, headers and extensions of `req` without its body, for formatting
+/// errors after the request has been handed to an endpoint.  
+pub(crate) fn request_head(req: &http_types::Request) -> http_types::Request {
+    let mut head = http_types::Request::new(req.method(), req.url().clone());
+    for (name, values) in req.iter() {
+        for value in values.iter() {
+            let _ = head.append_header(name.clone(), value.as_str());
+        }
+    }
+    if let Some(ext) = req.local().get::<crate::extensions::Extensions>() {
+        head.local_mut().insert(ext.clone());
+    }
+    if let Some(route) = req.local().get::<crate::matched::MatchedRoute>() {
+        head.local_mut().insert(route.clone());
+    }
+    head
 }
@@ -0,0 +1,375 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    route::ResponseFuture,
+    util::{body_read_error, hex, set_content_length},
+};
+use async_std::prelude::*;
+use http_types::{
+    headers::{self, HeaderValue},
+    Method, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+/// An entity tag, the validator of one version of a resource.
+///
+/// Strong tags change whenever the bytes of the representation do, weak ones (`W/"..."`) only
+/// when its meaning does.  Routes marked with [`Route::with_etag`](struct.Route.html#method.with_etag)
+/// tag their responses with [`of_body`](#method.of_body), and endpoints that know the version of
+/// what they serve, like the version of a database row, can set their own `ETag` header instead.
+///
+/// ## Examples
+/// ```
+/// # use windmill::ETag;
+/// let etag: ETag = r#"W/"v7""#.parse().unwrap();
+/// assert!(etag.is_weak());
+/// assert_eq!(etag.tag(), "v7");
+/// assert!(etag.weak_eq(&ETag::strong("v7")));
+/// assert!(!etag.strong_eq(&ETag::strong("v7")));
+/// assert_eq!(ETag::strong("v7").to_string(), r#""v7""#);
+/// assert!("v7".parse::<ETag>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// A strong tag.
+    ///
+    /// ## Panics
+    /// Panics if `tag` has a character an entity tag cannot have, like `"` or a space.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self::new(tag.into(), false)
+    }
+
+    /// A weak tag.
+    ///
+    /// ## Panics
+    /// Panics if `tag` has a character an entity tag cannot have, like `"` or a space.
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self::new(tag.into(), true)
+    }
+
+    fn new(tag: String, weak: bool) -> Self {
+        assert!(valid_tag(&tag), "invalid entity tag {:?}", tag);
+        Self { tag, weak }
+    }
+
+    /// The strong tag of a response body, from the SHA-256 hash of its bytes.
+    pub fn of_body(body: &[u8]) -> Self {
+        Self::strong(&hex(&Sha256::digest(body))[..32])
+    }
+
+    /// The tag, without its quotes or weak prefix.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether the tag is weak.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Whether both tags are strong and the same, the comparison `If-Match` uses.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Whether both tags are the same, weak or not, the comparison `If-None-Match` uses.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// The tag at the start of `value`, and what follows it.
+    fn parse_prefix(value: &str) -> Option<(Self, &str)> {
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let value = value.strip_prefix('"')?;
+        let end = value.find('"')?;
+        let tag = &value[..end];
+        match valid_tag(tag) {
+            true => Some((Self::new(tag.to_owned(), weak), &value[end + 1..])),
+            false => None,
+        }
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag),
+        }
+    }
+}
+
+impl FromStr for ETag {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match ETag::parse_prefix(value.trim()) {
+            Some((etag, rest)) if rest.is_empty() => Ok(etag),
+            _ => Err(Error::bad_request(format!("invalid entity tag: {}", value))
+                .with_code(WindmillErrorCode::BadRequest)),
+        }
+    }
+}
+
+/// Whether `tag` only has the characters allowed between the quotes of an entity tag.
+fn valid_tag(tag: &str) -> bool {
+    tag.bytes()
+        .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80)
+}
+
+/// The entity tags of an `If-Match` or `If-None-Match` header.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Validators {
+    /// `*`, any current version of the resource.
+    Any,
+    /// The listed tags.
+    Tags(Vec<ETag>),
+}
+
+impl Validators {
+    /// Parse the values of a header, skipping tags that are malformed.
+    fn parse(values: &[HeaderValue]) -> Self {
+        if values.iter().any(|value| value.as_str().trim() == "*") {
+            return Validators::Any;
+        }
+        let mut tags = vec![];
+        for value in values.iter() {
+            let mut rest = value.as_str();
+            loop {
+                rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+                if rest.is_empty() {
+                    break;
+                }
+                match ETag::parse_prefix(rest) {
+                    Some((etag, after)) => {
+                        tags.push(etag);
+                        rest = after;
+                    }
+                    None => rest = rest.find(',').map_or("", |comma| &rest[comma..]),
+                }
+            }
+        }
+        Validators::Tags(tags)
+    }
+
+    /// Whether `current`, the tag of the resource as it is now, strongly matches one of the tags.
+    pub fn strong_match(&self, current: &ETag) -> bool {
+        match self {
+            Validators::Any => true,
+            Validators::Tags(tags) => tags.iter().any(|tag| tag.strong_eq(current)),
+        }
+    }
+
+    /// Whether `current`, the tag of the resource as it is now, weakly matches one of the tags.
+    pub fn weak_match(&self, current: &ETag) -> bool {
+        match self {
+            Validators::Any => true,
+            Validators::Tags(tags) => tags.iter().any(|tag| tag.weak_eq(current)),
+        }
+    }
+}
+
+/// The `If-Match` and `If-None-Match` headers of a request, for endpoints that tag their
+/// resources themselves.
+///
+/// An endpoint that knows the current version of a resource, like the version of a database row,
+/// can [`check`](#method.check) a request against it before changing anything, and answer a
+/// `GET` with `304 Not Modified` when the client has it already.  Routes marked with
+/// [`Route::with_etag`](struct.Route.html#method.with_etag) have both done for them from the body
+/// of the response.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use std::sync::atomic::{AtomicU64, Ordering};
+/// static VERSION: AtomicU64 = AtomicU64::new(1);
+///
+/// fn current() -> ETag {
+///     ETag::strong(format!("v{}", VERSION.load(Ordering::SeqCst)))
+/// }
+///
+/// #[endpoint]
+/// async fn show(preconditions: Preconditions) -> Result<Response, Error> {
+///     let etag = current();
+///     let mut res = match preconditions.not_modified(&etag) {
+///         true => Response::new(StatusCode::NotModified),
+///         false => Response::from("the document"),
+///     };
+///     res.insert_header("etag", etag.to_string()).unwrap();
+///     Ok(res)
+/// }
+///
+/// #[endpoint]
+/// async fn update(preconditions: Preconditions) -> Result<Response, Error> {
+///     preconditions.check(Some(&current()))?;
+///     VERSION.fetch_add(1, Ordering::SeqCst);
+///     let mut res = Response::new(StatusCode::NoContent);
+///     res.insert_header("etag", current().to_string()).unwrap();
+///     Ok(res)
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"document"), ___show);
+/// router.add(Method::Put, route!(/"document"), ___update);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let res = client.get("/document").header("if-none-match", r#"W/"v1""#).send().await;
+///     assert_eq!(res.status(), StatusCode::NotModified);
+///
+///     let res = client.put("/document").header("if-match", r#""v1""#).send().await;
+///     assert_eq!(res.status(), StatusCode::NoContent);
+///     assert_eq!(res.header(&"etag".parse().unwrap()).unwrap()[0], r#""v2""#);
+///
+///     // Someone else's update came first.
+///     let mut res = client.put("/document").header("if-match", r#""v1""#).send().await;
+///     assert_eq!(res.status(), StatusCode::PreconditionFailed);
+///     let body = res.body_string().await.unwrap();
+///     assert!(body.contains("precondition_failed"), "{}", body);
+///
+///     // If-Match compares strongly.
+///     let res = client.put("/document").header("if-match", r#"W/"v2""#).send().await;
+///     assert_eq!(res.status(), StatusCode::PreconditionFailed);
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preconditions {
+    safe: bool,
+    if_match: Option<Validators>,
+    if_none_match: Option<Validators>,
+}
+
+impl Preconditions {
+    /// Get the preconditions of a request.
+    pub fn of(req: &http_types::Request) -> Self {
+        let validators = |name: &str| {
+            req.header(&name.parse().unwrap())
+                .map(|values| Validators::parse(values))
+        };
+        Self {
+            safe: matches!(req.method(), Method::Get | Method::Head),
+            if_match: validators("if-match"),
+            if_none_match: validators("if-none-match"),
+        }
+    }
+
+    /// The tags of the `If-Match` header, if the request has one.
+    pub fn if_match(&self) -> Option<&Validators> {
+        self.if_match.as_ref()
+    }
+
+    /// The tags of the `If-None-Match` header, if the request has one.
+    pub fn if_none_match(&self) -> Option<&Validators> {
+        self.if_none_match.as_ref()
+    }
+
+    /// Check the request against `current`, the tag of the resource as it is now, or `None` if
+    /// it does not exist.  The error is a `412 Precondition Failed` when `If-Match` has no strong
+    /// match, or when `If-None-Match` matches a request other than a `GET` or `HEAD`.
+    pub fn check(&self, current: Option<&ETag>) -> Result<(), Error> {
+        let if_match_fails = match (self.if_match.as_ref(), current) {
+            (Some(validators), Some(current)) => !validators.strong_match(current),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        let if_none_match_fails = match (self.if_none_match.as_ref(), current) {
+            (Some(validators), Some(current)) => !self.safe && validators.weak_match(current),
+            _ => false,
+        };
+        match if_match_fails || if_none_match_fails {
+            true => Err(
+                Error::new(StatusCode::PreconditionFailed, "precondition failed")
+                    .with_code(WindmillErrorCode::PreconditionFailed),
+            ),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether a `GET` or `HEAD` request can be answered with `304 Not Modified`, because
+    /// `If-None-Match` matches `current`, the tag of the resource as it is now.
+    pub fn not_modified(&self, current: &ETag) -> bool {
+        let matches = self
+            .if_none_match
+            .as_ref()
+            .map_or(false, |validators| validators.weak_match(current));
+        self.safe && matches
+    }
+}
+
+impl Props for Preconditions {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let preconditions = Self::of(&req);
+            Ok((req, params, preconditions))
+        })
+    }
+}
+
+/// Tag the `200 OK` responses of `res` and hold them to `preconditions`, answering with `fail`
+/// when they do not hold and with `304 Not Modified` when the client has the response already.
+/// A response with its own `ETag` keeps it, others are tagged from their body.
+pub(crate) fn conditional<F>(
+    preconditions: Preconditions,
+    res: ResponseFuture,
+    fail: F,
+) -> ResponseFuture
+where
+    F: FnOnce(Error) -> http_types::Response + Send + Sync + 'static,
+{
+    Box::pin(async move {
+        let mut res = res.await;
+        if res.status() != StatusCode::Ok {
+            return res;
+        }
+
+        let tagged = res
+            .header(&"etag".parse().unwrap())
+            .and_then(|values| values.last())
+            .and_then(|value| value.as_str().parse::<ETag>().ok());
+        let etag = match tagged {
+            Some(etag) => etag,
+            None => {
+                let mut body = vec![];
+                if let Err(e) = res.take_body().read_to_end(&mut body).await {
+                    return fail(body_read_error(e));
+                }
+                let etag = ETag::of_body(&body);
+                res.set_body(body);
+                set_content_length(&mut res);
+                let _ = res.insert_header("etag", etag.to_string());
+                etag
+            }
+        };
+
+        if let Err(e) = preconditions.check(Some(&etag)) {
+            return fail(e);
+        }
+        if preconditions.not_modified(&etag) {
+            let mut not_modified = http_types::Response::new(StatusCode::NotModified);
+            for (name, values) in res.iter() {
+                if *name != headers::CONTENT_LENGTH {
+                    for value in values.iter() {
+                        let _ = not_modified.append_header(name.clone(), value.as_str());
+                    }
+                }
+            }
+            return not_modified;
+        }
+        res
+    })
+}
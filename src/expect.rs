@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+
+/// The interim response telling a client to go ahead and send the body.
+const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// Whether the client of a request waits for `100 Continue` before sending its body, shared by
+/// the connection and the request.
+///
+/// The interim response is only sent once the endpoint starts reading the body, so a request that
+/// is answered without it, like one matching no route or with a `Content-Length` over the limit,
+/// gets its final response right away and the client never uploads the body.
+#[derive(Clone, Default)]
+pub(crate) struct ExpectContinue(Arc<Mutex<Stage>>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    /// The request does not wait for `100 Continue`, or its client was sent it.
+    Done,
+    /// The client waits, and this much of `100 Continue` was written.
+    Waiting(usize),
+    /// `100 Continue` was written and is being flushed.
+    Flushing,
+    /// The request was answered without reading the body, which the client never sent.
+    Declined,
+}
+
+impl Default for Stage {
+    fn default() -> Self {
+        Stage::Done
+    }
+}
+
+impl ExpectContinue {
+    /// Whether the client of `req` waits for `100 Continue` before sending a body.
+    pub(crate) fn wanted(req: &http_types::Request) -> bool {
+        let expects = req
+            .header(&"expect".parse().unwrap())
+            .map(|values| {
+                values
+                    .iter()
+                    .any(|value| value.as_str().trim().eq_ignore_ascii_case("100-continue"))
+            })
+            .unwrap_or(false);
+        let has_body = match req.len() {
+            Some(len) => len > 0,
+            None => req
+                .header(&http_types::headers::TRANSFER_ENCODING)
+                .is_some(),
+        };
+        expects && has_body
+    }
+
+    pub(crate) fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+
+    fn stage(&self) -> std::sync::MutexGuard<'_, Stage> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start a request, waiting for `100 Continue` if `wanted`.
+    pub(crate) fn reset(&self, wanted: bool) {
+        *self.stage() = match wanted {
+            true => Stage::Waiting(0),
+            false => Stage::Done,
+        };
+    }
+
+    /// What is left to write of `100 Continue`, if the client is waiting for it.
+    pub(crate) fn unsent(&self) -> Option<&'static [u8]> {
+        match *self.stage() {
+            Stage::Waiting(written) => Some(&CONTINUE[written..]),
+            _ => None,
+        }
+    }
+
+    /// `n` more bytes of `100 Continue` were written.
+    pub(crate) fn wrote(&self, n: usize) {
+        let mut stage = self.stage();
+        if let Stage::Waiting(written) = *stage {
+            *stage = match written + n >= CONTINUE.len() {
+                true => Stage::Flushing,
+                false => Stage::Waiting(written + n),
+            };
+        }
+    }
+
+    pub(crate) fn flushing(&self) -> bool {
+        *self.stage() == Stage::Flushing
+    }
+
+    pub(crate) fn flushed(&self) {
+        *self.stage() = Stage::Done;
+    }
+
+    /// Give up on the body if the client is still waiting to send it, returning whether it was.
+    pub(crate) fn decline(&self) -> bool {
+        let mut stage = self.stage();
+        match *stage {
+            Stage::Waiting(0) | Stage::Declined => {
+                *stage = Stage::Declined;
+                true
+            }
+            _ => false,
+        }
+    }
+}
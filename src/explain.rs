@@ -0,0 +1,163 @@
+use crate::route::{RawRoute, Route};
+use serde::Serialize;
+use std::fmt;
+
+/// Why a request did or did not match the routes in a [`Router`](struct.Router.html).  
+///
+/// Produced by [`Router::explain`](struct.Router.html#method.explain).
+#[derive(Debug, Serialize)]
+pub struct MatchExplanation {
+    /// The method that was looked up.  
+    pub method: String,
+    /// The path that was looked up.  
+    pub path: String,
+    /// Every route registered for the method, in lookup order.  
+    pub candidates: Vec<Candidate>,
+    /// Routes registered for other methods that match the path.  
+    pub near_misses: Vec<Candidate>,
+}
+
+/// A single route considered while explaining a lookup.  
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    /// The method the route is registered for.  
+    pub method: String,
+    /// The route pattern, e.g. `/users/{id}`.  
+    pub route: String,
+    /// How the route compared against the path.  
+    pub outcome: Outcome,
+    /// Whether the route is disabled through a [`RouterHandle`](struct.RouterHandle.html).  
+    pub disabled: bool,
+}
+
+/// The result of comparing a route against a path.  
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The route matches the path.  
+    Matched,
+    /// The path has a different number of segments than the route.  
+    SegmentCount { expected: usize, actual: usize },
+    /// A static segment of the route differs from the path.  
+    StaticMismatch {
+        position: usize,
+        expected: String,
+        actual: String,
+    },
+    /// A constrained segment of the route rejects its value.  
+    ConstraintMismatch {
+        position: usize,
+        name: String,
+        actual: String,
+    },
+    /// A typed segment of the route does not parse.  
+    ParamMismatch {
+        position: usize,
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl MatchExplanation {
+    /// The route that matched, if any.  
+    pub fn matched(&self) -> Option<&Candidate> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.outcome == Outcome::Matched)
+    }
+
+    /// One line per candidate, suitable for a development 404 response.  
+    pub fn compact(&self) -> Vec<String> {
+        self.candidates
+            .iter()
+            .chain(self.near_misses.iter())
+            .map(|candidate| candidate.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.method, self.route)?;
+        match &self.outcome {
+            Outcome::Matched if self.disabled => write!(f, "matched, disabled"),
+            Outcome::Matched => write!(f, "matched"),
+            Outcome::SegmentCount { expected, actual } => {
+                write!(f, "expected {} segments, got {}", expected, actual)
+            }
+            Outcome::StaticMismatch {
+                position,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "segment {} expected {:?}, got {:?}",
+                position, expected, actual
+            ),
+            Outcome::ConstraintMismatch {
+                position,
+                name,
+                actual,
+            } => write!(f, "segment {} ({}) rejected {:?}", position, name, actual),
+            Outcome::ParamMismatch {
+                position,
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "segment {} ({}) expected {}, got {:?}",
+                position, name, expected, actual
+            ),
+        }
+    }
+}
+
+pub(crate) fn explain_route(
+    route: &Route,
+    raw_route: &RawRoute,
+    case_insensitive: bool,
+) -> Outcome {
+    let expected = route.len();
+    let actual = raw_route.raw_segments.len();
+    let fits = (route.min_len()..=expected).contains(&actual);
+    if !fits && !(route.rest.is_some() && actual > expected) {
+        return Outcome::SegmentCount { expected, actual };
+    }
+
+    let mut static_segments: Vec<_> = route.static_segments.iter().collect();
+    static_segments.sort_by_key(|segment| segment.position);
+
+    static_segments
+        .into_iter()
+        .find(|segment| {
+            !segment.matches(
+                &raw_route.raw_segments[segment.position].value,
+                case_insensitive,
+            )
+        })
+        .map(|segment| Outcome::StaticMismatch {
+            position: segment.position,
+            expected: segment.value.into(),
+            actual: raw_route.raw_segments[segment.position].value.to_string(),
+        })
+        .or_else(|| {
+            let segment = route.rejected_segment(&raw_route.raw_segments)?;
+            Some(Outcome::ConstraintMismatch {
+                position: segment.position,
+                name: segment.name.into(),
+                actual: raw_route.raw_segments[segment.position].value.to_string(),
+            })
+        })
+        .or_else(|| {
+            let (segment, _) = route.unparsed_segment(&raw_route.raw_segments)?;
+            Some(Outcome::ParamMismatch {
+                position: segment.position,
+                name: segment.name.into(),
+                expected: segment.ty?.name.into(),
+                actual: raw_route.raw_segments[segment.position].value.to_string(),
+            })
+        })
+        .unwrap_or(Outcome::Matched)
+}
@@ -0,0 +1,149 @@
+use crate::{
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Typed values scoped to a single request.
+///
+/// The router attaches a fresh `Extensions` to every request before any props run.  Props can
+/// store values in it, endpoints can take it as an argument, and response decorators receive it
+/// after the endpoint has finished.  Clones share the same underlying map.
+///
+/// [Middlewares](struct.Router.html#method.middleware) reach it with [`of`](#method.of), which
+/// is how one middleware hands typed data to the next and to the endpoint: every stage of a
+/// request sees the same map, and the next request starts with an empty one.  Values are keyed
+/// by their type, so a crate can keep its values private by using its own types.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[derive(Clone)]
+/// struct CurrentUser {
+///     id: u64,
+///     admin: bool,
+/// }
+///
+/// #[endpoint]
+/// async fn report(ext: Extensions, head: RequestHead) -> Result<Response, Error> {
+///     let user = ext.get::<CurrentUser>().unwrap();
+///     let same = head.extensions().with(|user: &CurrentUser| user.id);
+///     Ok(Response::from(format!("report for {} {:?}", user.id, same)))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"report"), ___report);
+/// // Authenticate the caller.
+/// router.middleware(|req: Request, params: Params, next: Next| {
+///     let token = req.header(&"authorization".parse().unwrap()).map(|v| v[0].to_string());
+///     let user = match token.as_deref() {
+///         Some("Bearer admin") => Some(CurrentUser { id: 1, admin: true }),
+///         Some("Bearer guest") => Some(CurrentUser { id: 2, admin: false }),
+///         _ => None,
+///     };
+///     if let Some(user) = user {
+///         Extensions::of(&req).insert(user);
+///     }
+///     next.run(req, params)
+/// });
+/// // Only let admins through.
+/// router.middleware(|req: Request, params: Params, next: Next| {
+///     let admin = Extensions::of(&req).with(|user: &CurrentUser| user.admin);
+///     async move {
+///         match admin {
+///             Some(true) => next.run(req, params).await,
+///             Some(false) => Response::new(StatusCode::Forbidden),
+///             None => Response::new(StatusCode::Unauthorized),
+///         }
+///     }
+/// });
+/// let router = Arc::new(router);
+///
+/// let get = |token: Option<&str>| {
+///     let mut req = Request::new(Method::Get, Url::parse("http://localhost/report").unwrap());
+///     if let Some(token) = token {
+///         req.insert_header("authorization", token).unwrap();
+///     }
+///     let res = task::block_on(router.clone().respond(req));
+///     (res.status(), task::block_on(res.body_string()).unwrap())
+/// };
+///
+/// assert_eq!(get(Some("Bearer admin")), (StatusCode::Ok, "report for 1 Some(1)".into()));
+/// assert_eq!(get(Some("Bearer guest")).0, StatusCode::Forbidden);
+/// // Nothing is left over from earlier requests.
+/// assert_eq!(get(None).0, StatusCode::Unauthorized);
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+}
+
+impl Extensions {
+    /// Get the extensions attached to a request, or an empty set if there are none.
+    pub fn of(req: &http_types::Request) -> Self {
+        req.local().get::<Self>().cloned().unwrap_or_default()
+    }
+
+    /// Insert a value, returning the previous value of the same type.
+    /// ```
+    /// # use windmill::Extensions;
+    /// struct CurrentUser(u64);
+    ///
+    /// let ext = Extensions::default();
+    /// ext.insert(CurrentUser(7));
+    /// assert_eq!(ext.with(|user: &CurrentUser| user.0), Some(7));
+    /// ```
+    pub fn insert<T: Send + Sync + 'static>(&self, val: T) -> Option<T> {
+        self.lock()
+            .insert(TypeId::of::<T>(), Box::new(val))
+            .and_then(|boxed| (boxed as Box<dyn Any>).downcast().ok().map(|boxed| *boxed))
+    }
+
+    /// Get a clone of the value of type `T`.
+    pub fn get<T: Clone + 'static>(&self) -> Option<T> {
+        self.with(|val: &T| val.clone())
+    }
+
+    /// Run `f` against a reference to the value of type `T`.
+    pub fn with<T: 'static, R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.lock()
+            .get(&TypeId::of::<T>())
+            .and_then(|boxed| boxed.downcast_ref())
+            .map(f)
+    }
+
+    /// Whether a value of type `T` is present.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.lock().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove the value of type `T`.
+    pub fn remove<T: 'static>(&self) -> Option<T> {
+        self.lock()
+            .remove(&TypeId::of::<T>())
+            .and_then(|boxed| (boxed as Box<dyn Any>).downcast().ok().map(|boxed| *boxed))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<TypeId, Box<dyn Any + Send + Sync>>> {
+        self.map.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Props for Extensions {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let ext = Self::of(&req);
+            Ok((req, params, ext))
+        })
+    }
+}
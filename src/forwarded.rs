@@ -0,0 +1,170 @@
+use crate::{config::Config, request_head::PeerAddr};
+use std::{net::IpAddr, str::FromStr, sync::Arc};
+
+/// A range of addresses that proxies in front of the server connect from, such as `10.0.0.0/8`
+/// or `::1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ProxyRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl ProxyRange {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ProxyRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid proxy address `{}`", s);
+        let (addr, prefix) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// Whether `ip` is in one of `ranges`.  IPv4 addresses mapped into IPv6 are matched as IPv4.
+pub(crate) fn is_trusted(ranges: &[ProxyRange], ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => v6.to_ipv4().map(IpAddr::V4).unwrap_or(ip),
+            _ => ip,
+        },
+        ip => ip,
+    };
+    ranges.iter().any(|range| range.contains(ip))
+}
+
+/// The address of the client that sent `req`.
+///
+/// When the peer is a trusted proxy, the hops it forwarded the request for, listed by `Forwarded`
+/// or else by `X-Forwarded-For`, are walked from the closest, and the first one that is not a
+/// trusted proxy itself is the client.  A hop that is not an address, such as `unknown` or an
+/// obfuscated identifier, stops the walk at the proxy that reported it, since nothing it
+/// reports further away can be checked.
+pub(crate) fn client_addr(req: &http_types::Request) -> Option<IpAddr> {
+    let peer = req.local().get::<PeerAddr>()?.0.ip();
+    let config = match req.local().get::<Arc<Config>>() {
+        Some(config) => config,
+        None => return Some(peer),
+    };
+    if !config.is_trusted_proxy(peer) {
+        return Some(peer);
+    }
+
+    let hops = match forwarded(req, "for") {
+        Some(hops) => hops,
+        None => header_list(req, "x-forwarded-for"),
+    };
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        if !config.is_trusted_proxy(client) {
+            break;
+        }
+        match parse_node(hop) {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    Some(client)
+}
+
+/// The scheme the client sent `req` with, `http` or `https`.
+///
+/// When the peer is a trusted proxy, the `proto` of its `Forwarded` header, or else its
+/// `X-Forwarded-Proto`, is the scheme.  A chain of proxies appends to these headers, so the last
+/// value, set by the closest proxy, is used.
+pub(crate) fn scheme(req: &http_types::Request) -> String {
+    let scheme = req.url().scheme().to_owned();
+    let peer = match req.local().get::<PeerAddr>() {
+        Some(peer) => peer.0.ip(),
+        None => return scheme,
+    };
+    match req.local().get::<Arc<Config>>() {
+        Some(config) if config.is_trusted_proxy(peer) => {}
+        _ => return scheme,
+    }
+
+    let forwarded = match forwarded(req, "proto") {
+        Some(protos) => protos,
+        None => header_list(req, "x-forwarded-proto"),
+    };
+    forwarded
+        .last()
+        .map(|proto| proto.to_ascii_lowercase())
+        .filter(|proto| proto == "http" || proto == "https")
+        .unwrap_or(scheme)
+}
+
+/// The comma separated values of the header `name`, across all of its lines.
+fn header_list(req: &http_types::Request, name: &str) -> Vec<String> {
+    req.header(&name.parse().unwrap())
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.as_str().split(','))
+        .map(|value| value.trim().to_owned())
+        .collect()
+}
+
+/// The values of the parameter `name` in the elements of the `Forwarded` headers of `req`, one
+/// for each element that has it, or `None` without a `Forwarded` header.
+fn forwarded(req: &http_types::Request, name: &str) -> Option<Vec<String>> {
+    let elements = header_list(req, "forwarded");
+    if elements.is_empty() {
+        return None;
+    }
+    let values = elements
+        .iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                let key = pair.next()?.trim();
+                let value = pair.next()?.trim();
+                match key.eq_ignore_ascii_case(name) {
+                    true => Some(value.trim_matches('"').to_owned()),
+                    false => None,
+                }
+            })
+        })
+        .collect();
+    Some(values)
+}
+
+/// The address of a hop, written as `192.0.2.43`, `192.0.2.43:4711`, `2001:db8::1` or
+/// `[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        let end = rest.find(']')?;
+        return rest[..end].parse().ok();
+    }
+    let (host, port) = node.split_at(node.rfind(':')?);
+    port[1..].parse::<u16>().ok()?;
+    host.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+}
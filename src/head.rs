@@ -0,0 +1,164 @@
+use http_types::Version;
+
+/// The most of a request head that is buffered while its version is checked.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// What to do with a request, decided from the version in its request line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Verdict {
+    /// Serve an HTTP/1.1 request.
+    Serve,
+    /// Serve an HTTP/1.0 request, keeping the connection open after it only when asked to.
+    Serve1_0 { keep_alive: bool },
+    /// Answer with `505 HTTP Version Not Supported` and close the connection.
+    Refuse,
+    /// Answer an HTTP/1.1 request without a `Host` header with `400 Bad Request` and close the
+    /// connection.
+    MissingHost,
+    /// Close the connection without answering, for clients that could not read an HTTP/1.1
+    /// response anyway: HTTP/0.9 and HTTP/2 with prior knowledge.
+    Close,
+}
+
+/// The start of a request, buffered until the version in its request line is known.
+///
+/// The HTTP/1.1 parser only speaks HTTP/1.1, so the request line of an HTTP/1.0 request is
+/// rewritten to HTTP/1.1 before the parser sees it, with a `Host` header added if it has none.
+pub(crate) struct Head {
+    buf: Vec<u8>,
+    verdict: Option<Verdict>,
+    min_version: Version,
+    host: String,
+}
+
+impl Head {
+    pub(crate) fn new(min_version: Version, host: String) -> Self {
+        Self {
+            buf: vec![],
+            verdict: None,
+            min_version,
+            host,
+        }
+    }
+
+    /// Start looking for the head of the next request.
+    pub(crate) fn reset(&mut self) {
+        self.verdict = None;
+    }
+
+    /// The verdict on the current request, once enough of it has been read.
+    pub(crate) fn verdict(&self) -> Option<Verdict> {
+        self.verdict
+    }
+
+    /// Buffer bytes read from the connection.
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        self.verdict = self.decide();
+    }
+
+    /// The connection ended, hand whatever was read to the parser.
+    pub(crate) fn finish(&mut self) {
+        self.verdict = self.verdict.or(Some(Verdict::Serve));
+    }
+
+    /// Move buffered bytes into `buf`, returning how many were moved.
+    pub(crate) fn take(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.buf.len());
+        buf[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        n
+    }
+
+    fn decide(&mut self) -> Option<Verdict> {
+        let line_end = match self.buf.iter().position(|&byte| byte == b'\n') {
+            Some(line_end) => line_end,
+            None if self.buf.len() > MAX_HEAD => return Some(Verdict::Serve),
+            None => return None,
+        };
+
+        let line = String::from_utf8_lossy(&self.buf[..line_end]).into_owned();
+        let mut tokens = line.split_whitespace();
+        let (method, target) = match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some(_), Some(_), None) => return Some(Verdict::Close),
+            (Some(method), Some(target), Some(version)) => match version {
+                "HTTP/1.0" => (method, target),
+                version if version.starts_with("HTTP/2") => return Some(Verdict::Close),
+                version if version.starts_with("HTTP/1.") => {
+                    if Version::Http1_1 < self.min_version {
+                        return Some(Verdict::Refuse);
+                    }
+                    let headers = self.headers(line_end)?;
+                    if header(&headers, "host").is_none() {
+                        return Some(Verdict::MissingHost);
+                    }
+                    // Later HTTP/1.x minor versions are served as HTTP/1.1.
+                    if version != "HTTP/1.1" {
+                        self.rewrite(method, target, line_end, false);
+                    }
+                    return Some(Verdict::Serve);
+                }
+                version if version.starts_with("HTTP/") => return Some(Verdict::Refuse),
+                _ => return Some(Verdict::Serve),
+            },
+            _ => return Some(Verdict::Serve),
+        };
+        if Version::Http1_0 < self.min_version {
+            return Some(Verdict::Refuse);
+        }
+
+        let headers = self.headers(line_end)?;
+        let keep_alive = header(&headers, "connection")
+            .map(|value| {
+                value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("keep-alive"))
+            })
+            .unwrap_or(false);
+        let has_host = header(&headers, "host").is_some();
+
+        self.rewrite(method, target, line_end, !has_host);
+        Some(Verdict::Serve1_0 { keep_alive })
+    }
+
+    /// The header lines following the request line ending at `line_end`, once they have all
+    /// been read.
+    fn headers(&self, line_end: usize) -> Option<String> {
+        // The headers run from after the request line up to the blank line that ends them.
+        let rest = &self.buf[line_end..];
+        let head_end = match find(rest, b"\n\r\n").or_else(|| find(rest, b"\n\n")) {
+            Some(blank) => line_end + blank + 1,
+            None if self.buf.len() > MAX_HEAD => self.buf.len(),
+            None => return None,
+        };
+        Some(String::from_utf8_lossy(&self.buf[line_end + 1..head_end]).into_owned())
+    }
+
+    /// Replace the request line with an HTTP/1.1 one.
+    fn rewrite(&mut self, method: &str, target: &str, line_end: usize, add_host: bool) {
+        let mut head = format!("{} {} HTTP/1.1\r\n", method, target);
+        if add_host {
+            head.push_str(&format!("host: {}\r\n", self.host));
+        }
+        self.buf.splice(..=line_end, head.into_bytes());
+    }
+}
+
+/// The value of the header `name` among the header lines `headers`.
+fn header(headers: &str, name: &str) -> Option<String> {
+    headers.lines().find_map(|line| {
+        let mut line = line.splitn(2, ':');
+        match (line.next(), line.next()) {
+            (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case(name) => {
+                Some(value.trim().to_owned())
+            }
+            _ => None,
+        }
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
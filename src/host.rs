@@ -0,0 +1,195 @@
+use crate::{
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use std::fmt;
+
+/// A pattern the host of a request must match, such as `{tenant}.example.com`, set with
+/// [`Router::host`](struct.Router.html#method.host).
+#[doc(hidden)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostPattern {
+    labels: Vec<Label>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Label {
+    /// A label that must be exactly this, lowercase.
+    Exact(String),
+    /// `*`, any single label.
+    Any,
+    /// `{name}`, any single label, captured as `name`.
+    Capture(&'static str),
+    /// `{name*}`, one or more labels, captured as `name`.  Only allowed first.
+    Rest(&'static str),
+}
+
+impl HostPattern {
+    /// Parse `pattern`, panicking if it is not a valid host pattern.
+    pub(crate) fn parse(pattern: &'static str) -> Self {
+        let labels: Vec<Label> = pattern
+            .trim_end_matches('.')
+            .split('.')
+            .map(
+                |label| match label.strip_prefix('{').and_then(|l| l.strip_suffix('}')) {
+                    Some(name) => match name.strip_suffix('*') {
+                        Some(name) => Label::Rest(name),
+                        None => Label::Capture(name),
+                    },
+                    None if label == "*" => Label::Any,
+                    None => {
+                        let label = label.to_ascii_lowercase();
+                        assert!(valid_label(&label), "invalid host pattern `{}`", pattern);
+                        Label::Exact(label)
+                    }
+                },
+            )
+            .collect();
+        assert!(
+            labels
+                .iter()
+                .skip(1)
+                .all(|label| !matches!(label, Label::Rest(_))),
+            "only the first label of host pattern `{}` can capture several labels",
+            pattern
+        );
+        Self { labels }
+    }
+
+    /// How specific the pattern is: whether it matches a single host, then how many of its labels
+    /// are exact.  Routes with more specific host rules are tried first.
+    pub(crate) fn specificity(&self) -> (bool, usize) {
+        let exact = self
+            .labels
+            .iter()
+            .filter(|label| matches!(label, Label::Exact(_)))
+            .count();
+        (exact == self.labels.len(), exact)
+    }
+
+    /// The labels of `host` captured by the pattern, or `None` if it does not match.
+    pub(crate) fn captures(&self, host: &str) -> Option<HostCaptures> {
+        let host = normalize(host)?;
+        let labels: Vec<&str> = host.split('.').collect();
+
+        let (rest, fixed) = match self.labels.split_first() {
+            Some((Label::Rest(name), fixed)) => (Some(*name), fixed),
+            _ => (None, &self.labels[..]),
+        };
+        let enough = match rest {
+            Some(_) => labels.len() > fixed.len(),
+            None => labels.len() == fixed.len(),
+        };
+        if !enough {
+            return None;
+        }
+
+        let mut captures = HostCaptures::default();
+        let (head, tail) = labels.split_at(labels.len() - fixed.len());
+        for (label, pattern) in tail.iter().zip(fixed) {
+            match pattern {
+                Label::Exact(exact) if label == exact => {}
+                Label::Any if valid_label(label) => {}
+                Label::Capture(name) if valid_label(label) => {
+                    captures.0.push((name, (*label).to_owned()))
+                }
+                _ => return None,
+            }
+        }
+        if let Some(name) = rest {
+            if !head.iter().all(|label| valid_label(label)) {
+                return None;
+            }
+            captures.0.push((name, head.join(".")));
+        }
+        Some(captures)
+    }
+}
+
+/// The host without its port or trailing dot, lowercase.  `None` for IP literals in brackets.
+fn normalize(host: &str) -> Option<String> {
+    if host.starts_with('[') {
+        return None;
+    }
+    let host = host.split(':').next()?.trim_end_matches('.');
+    Some(host.to_ascii_lowercase())
+}
+
+/// Whether `label` is a single lowercase DNS label: letters, digits and hyphens, not starting or
+/// ending with a hyphen.  Internationalized names are only accepted in their `xn--` form.
+fn valid_label(label: &str) -> bool {
+    (1..=63).contains(&label.len())
+        && label
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+/// The labels captured from the host of a request by the [host rule](struct.Router.html#method.host)
+/// of the route it matched.
+///
+/// The captures are also passed to the endpoint in its [`Params`](struct.Params.html), and
+/// written by [`request_log`](fn.request_log.html).  Requests for routes without a host rule have
+/// none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostCaptures(Vec<(&'static str, String)>);
+
+impl HostCaptures {
+    /// Get the captures of a request.
+    pub fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+
+    /// The label captured as `name`, lowercase.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(capture, _)| *capture == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The names and labels of every capture.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.0.iter().map(|(name, value)| (*name, value.as_str()))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for HostCaptures {
+    /// Render the captures as `tenant=acme region=eu`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Props for HostCaptures {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let captures = Self::of(&req).unwrap_or_default();
+            Ok((req, params, captures))
+        })
+    }
+}
+
+/// The host a request was sent to, from its `Host` header or else its url.
+pub(crate) fn request_host(req: &http_types::Request) -> Option<String> {
+    match req
+        .header(&http_types::headers::HOST)
+        .and_then(|values| values.last())
+    {
+        Some(value) => Some(value.as_str().to_owned()),
+        None => req.url().host_str().map(str::to_owned),
+    }
+}
@@ -0,0 +1,194 @@
+use crate::forwarded;
+use http_types::StatusCode;
+
+/// How a [`Router`](struct.Router.html) moves plaintext traffic over to https.
+///
+/// Set with [`Router::https_redirect`](struct.Router.html#method.https_redirect).  Plaintext
+/// requests for routes marked [`https_only`](struct.Route.html#method.https_only), or for every
+/// route after [`all_routes`](#method.all_routes), are redirected to the same path and query over
+/// https.  Routes marked [`health_check`](struct.Route.html#method.health_check) are never
+/// redirected.  The `Strict-Transport-Security` header is only ever sent on responses to requests
+/// that arrived over https, since sending it over plaintext violates the preload requirements.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use std::time::Duration;
+/// let mut router = Router::new();
+/// router.https_redirect(
+///     HttpsRedirect::new()
+///         .all_routes()
+///         .canonical_host("example.com")
+///         .trust_forwarded_proto()
+///         .hsts(Duration::from_secs(63_072_000)),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct HttpsRedirect {
+    all_routes: bool,
+    canonical_host: Option<String>,
+    status: StatusCode,
+    trust_forwarded_proto: bool,
+    hsts: Option<String>,
+}
+
+impl Default for HttpsRedirect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpsRedirect {
+    /// Redirect routes marked `https_only` with `308 Permanent Redirect`, which keeps the method
+    /// and body of the request.
+    pub fn new() -> Self {
+        Self {
+            all_routes: false,
+            canonical_host: None,
+            status: StatusCode::PermanentRedirect,
+            trust_forwarded_proto: false,
+            hsts: None,
+        }
+    }
+
+    /// Redirect every route except health checks, not only those marked `https_only`.
+    pub fn all_routes(mut self) -> Self {
+        self.all_routes = true;
+        self
+    }
+
+    /// Redirect to `host` rather than to the host the request was sent to.
+    pub fn canonical_host(mut self, host: impl Into<String>) -> Self {
+        self.canonical_host = Some(host.into());
+        self
+    }
+
+    /// Redirect with `301 Moved Permanently` instead of `308 Permanent Redirect`.
+    pub fn moved_permanently(mut self) -> Self {
+        self.status = StatusCode::MovedPermanently;
+        self
+    }
+
+    /// Treat requests carrying `X-Forwarded-Proto: https` as having arrived over https.  Only
+    /// enable this when every request passes through a proxy that sets the header itself.
+    /// Requests from [trusted proxies](struct.Config.html#method.with_trusted_proxies) are
+    /// checked for the header either way.
+    pub fn trust_forwarded_proto(mut self) -> Self {
+        self.trust_forwarded_proto = true;
+        self
+    }
+
+    /// Send `Strict-Transport-Security` with `max_age`, including subdomains and opting into
+    /// preloading, on every https response.
+    pub fn hsts(mut self, max_age: std::time::Duration) -> Self {
+        self.hsts = Some(format!(
+            "max-age={}; includeSubDomains; preload",
+            max_age.as_secs()
+        ));
+        self
+    }
+
+    /// Whether the request arrived over https.
+    /// ```
+    /// # use windmill::HttpsRedirect;
+    /// # use http_types::{Method, Request, Url};
+    /// let mut req = Request::new(Method::Get, Url::parse("http://example.com/").unwrap());
+    /// req.insert_header("x-forwarded-proto", "https").unwrap();
+    ///
+    /// assert!(!HttpsRedirect::new().is_secure(&req));
+    /// assert!(HttpsRedirect::new().trust_forwarded_proto().is_secure(&req));
+    ///
+    /// let req = Request::new(Method::Get, Url::parse("https://example.com/").unwrap());
+    /// assert!(HttpsRedirect::new().is_secure(&req));
+    /// ```
+    pub fn is_secure(&self, req: &http_types::Request) -> bool {
+        if req.url().scheme() == "https" || forwarded::scheme(req) == "https" {
+            return true;
+        }
+
+        self.trust_forwarded_proto
+            && req
+                .header(&"x-forwarded-proto".parse().unwrap())
+                .and_then(|values| values.last())
+                .map(|value| {
+                    // A proxy chain appends its own protocol, so the last one is the closest.
+                    value
+                        .as_str()
+                        .rsplit(',')
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .eq_ignore_ascii_case("https")
+                })
+                .unwrap_or(false)
+    }
+
+    /// The https URL a plaintext request is redirected to, or `None` if it is already secure.
+    ///
+    /// The path and query are kept exactly as they were sent.  The host is the canonical host if
+    /// one is configured, otherwise the `Host` header without its port.
+    /// ```
+    /// # use windmill::HttpsRedirect;
+    /// # use http_types::{Method, Request, Url};
+    /// let url = Url::parse("http://localhost:8080/a%20b/c?q=1&tag=x%2By&empty=").unwrap();
+    /// let mut req = Request::new(Method::Get, url);
+    /// req.insert_header("host", "example.com:8080").unwrap();
+    ///
+    /// assert_eq!(
+    ///     HttpsRedirect::new().location(&req).unwrap(),
+    ///     "https://example.com/a%20b/c?q=1&tag=x%2By&empty=",
+    /// );
+    /// assert_eq!(
+    ///     HttpsRedirect::new().canonical_host("www.example.com").location(&req).unwrap(),
+    ///     "https://www.example.com/a%20b/c?q=1&tag=x%2By&empty=",
+    /// );
+    /// ```
+    pub fn location(&self, req: &http_types::Request) -> Option<String> {
+        if self.is_secure(req) {
+            return None;
+        }
+
+        let host = match &self.canonical_host {
+            Some(host) => host.clone(),
+            None => req
+                .header(&"host".parse().unwrap())
+                .and_then(|values| values.last())
+                .map(|value| value.as_str().to_owned())
+                .or_else(|| req.url().host_str().map(String::from))
+                .map(|host| strip_port(&host).to_owned())?,
+        };
+
+        let mut location = format!("https://{}{}", host, req.url().path());
+        if let Some(query) = req.url().query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        Some(location)
+    }
+
+    /// Whether a route with the given flags must be served over https.
+    pub(crate) fn applies(&self, https_only: bool, health_check: bool) -> bool {
+        !health_check && (https_only || self.all_routes)
+    }
+
+    pub(crate) fn redirect(&self, location: &str) -> http_types::Response {
+        let mut res = http_types::Response::new(self.status);
+        let _ = res.insert_header("location", location);
+        res
+    }
+
+    /// Add `Strict-Transport-Security` to the response of a request that arrived over https.
+    pub(crate) fn apply_hsts(&self, secure: bool, res: &mut http_types::Response) {
+        if let (true, Some(hsts)) = (secure, &self.hsts) {
+            let _ = res.insert_header("strict-transport-security", hsts.as_str());
+        }
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    // Leave bracketed IPv6 addresses without a port alone.
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
@@ -0,0 +1,422 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    util::{body_too_large, max_body_size},
+};
+use async_std::io::{prelude::*, Read};
+use http_types::StatusCode;
+use serde::de::DeserializeOwned;
+use std::{marker::PhantomData, pin::Pin};
+
+/// How much of the body is read at a time.
+const CHUNK: usize = 8 * 1024;
+
+/// A props that reads a JSON array body one element at a time.
+///
+/// Only the element being parsed is held in memory, so arrays far larger than would be sensible
+/// to buffer can be processed as they arrive.  Each element is deserialized on its own, and one
+/// that does not parse as a `T` yields an [`ElementError`](struct.ElementError.html) carrying its
+/// index without ending the stream, leaving the endpoint to decide whether to stop at the first
+/// bad element or collect them all.  A body that is not a well formed array, or that grows past
+/// the [maximum body size](struct.Config.html#method.with_max_body_size), ends the stream with a
+/// fatal error.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use serde::Deserialize;
+/// # use std::sync::Arc;
+/// #[derive(Deserialize)]
+/// struct Item {
+///     id: u64,
+/// }
+///
+/// // Import what can be imported, reporting the elements that could not.
+/// #[endpoint]
+/// async fn import(mut items: JsonArrayStream<Item>) -> Result<Response, Error> {
+///     let (mut imported, mut failed) = (0, vec![]);
+///     while let Some(item) = items.next_element().await {
+///         match item {
+///             Ok(item) => imported += item.id,
+///             Err(e) if e.is_fatal() => return Err(e.into()),
+///             Err(e) => failed.push(e.index()),
+///         }
+///     }
+///     Ok(Response::from(format!("{} {:?}", imported, failed)))
+/// }
+///
+/// // Stop at the first bad element.
+/// #[endpoint]
+/// async fn strict(mut items: JsonArrayStream<Item>) -> Result<Response, Error> {
+///     let mut imported = 0;
+///     while let Some(item) = items.next_element().await {
+///         imported += item?.id;
+///     }
+///     Ok(Response::from(imported.to_string()))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"import"), ___import);
+/// router.add(Method::Post, route!(/"strict"), ___strict);
+/// let router = Arc::new(router);
+///
+/// let post = |path: &str, body: &str| {
+///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///     let mut req = Request::new(Method::Post, url);
+///     req.set_body(body.to_owned());
+///     let mut res = task::block_on(router.clone().respond(req));
+///     (res.status(), task::block_on(res.body_string()).unwrap())
+/// };
+///
+/// let body = r#" [ {"id": 1}, {"id": "two"}, {"id": 3, "tags": ["a,]"]}, 4 ] "#;
+/// assert_eq!(post("/import", body), (StatusCode::Ok, "4 [1, 3]".to_owned()));
+///
+/// let (status, msg) = post("/strict", body);
+/// assert_eq!(status, StatusCode::BadRequest);
+/// assert!(msg.contains("element 1"), "{}", msg);
+///
+/// // A syntax error between elements ends the stream.
+/// let (status, msg) = post("/import", r#"[{"id": 1} {"id": 2}]"#);
+/// assert_eq!(status, StatusCode::BadRequest);
+/// assert!(msg.contains("element 1"), "{}", msg);
+///
+/// assert_eq!(post("/import", r#"{"id": 1}"#).0, StatusCode::BadRequest);
+/// assert_eq!(post("/import", r#"[{"id": 1},"#).0, StatusCode::BadRequest);
+/// assert_eq!(post("/import", "[]"), (StatusCode::Ok, "0 []".to_owned()));
+/// ```
+pub struct JsonArrayStream<T> {
+    reader: Pin<Box<dyn Read + Send + Sync>>,
+    buf: Vec<u8>,
+    pos: usize,
+    scan: Scan,
+    index: usize,
+    read: usize,
+    body_limit: usize,
+    eof: bool,
+    state: State,
+    element: PhantomData<fn() -> T>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Start,
+    First,
+    Value,
+    AfterValue,
+    Done,
+}
+
+/// How far the end of the current element has been searched for.
+#[derive(Default)]
+struct Scan {
+    offset: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+/// An element of a [`JsonArrayStream`](struct.JsonArrayStream.html) that could not be read.
+#[derive(Debug)]
+pub struct ElementError {
+    index: usize,
+    fatal: bool,
+    error: Error,
+}
+
+impl ElementError {
+    /// The index of the element in the array.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Whether the stream ended with this error.  Elements that were well formed JSON but did
+    /// not deserialize are not fatal, and the stream continues with the next element.
+    pub fn is_fatal(&self) -> bool {
+        self.fatal
+    }
+
+    /// The error, `400 Bad Request` or `413 Payload Too Large`, naming the element.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+impl From<ElementError> for Error {
+    fn from(e: ElementError) -> Self {
+        e.error
+    }
+}
+
+impl<T: DeserializeOwned> JsonArrayStream<T> {
+    /// Read the next element, or `None` once the array has ended.
+    ///
+    /// ## Examples
+    /// Memory use stays at the size of a chunk of the body however large the array is.
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, Url};
+    /// # use serde::Deserialize;
+    /// # use std::{alloc::{GlobalAlloc, Layout, System}, sync::{atomic::{AtomicUsize, Ordering::SeqCst}, Arc}};
+    /// struct Counting;
+    /// static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+    /// static PEAK: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         let allocated = ALLOCATED.fetch_add(layout.size(), SeqCst) + layout.size();
+    ///         PEAK.fetch_max(allocated, SeqCst);
+    ///         System.alloc(layout)
+    ///     }
+    ///
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         ALLOCATED.fetch_sub(layout.size(), SeqCst);
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// #[global_allocator]
+    /// static GLOBAL: Counting = Counting;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Item {
+    ///     id: u64,
+    ///     name: String,
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn import(mut items: JsonArrayStream<Item>) -> Result<Response, Error> {
+    ///     let (mut count, mut sum) = (0u64, 0u64);
+    ///     while let Some(item) = items.next_element().await {
+    ///         let item = item?;
+    ///         assert_eq!(item.name, format!("item {}", item.id));
+    ///         count += 1;
+    ///         sum += item.id;
+    ///     }
+    ///     Ok(Response::from(format!("{} {}", count, sum)))
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"import"), ___import);
+    /// let router = Arc::new(router);
+    ///
+    /// let items: Vec<String> = (0..200_000)
+    ///     .map(|id| format!(r#"{{"id":{},"name":"item {}"}}"#, id, id))
+    ///     .collect();
+    /// let body = format!("[{}]", items.join(",\n"));
+    /// drop(items);
+    /// let len = body.len();
+    ///
+    /// let mut req = Request::new(Method::Post, Url::parse("http://localhost/import").unwrap());
+    /// req.local_mut().insert(Arc::new(Config::new("127.0.0.1:4000").with_max_body_size(len)));
+    /// req.set_body(body);
+    ///
+    /// let before = ALLOCATED.load(SeqCst);
+    /// PEAK.store(before, SeqCst);
+    /// let mut res = task::block_on(router.respond(req));
+    /// assert_eq!(task::block_on(res.body_string()).unwrap(), "200000 19999900000");
+    /// assert!(PEAK.load(SeqCst) - before < len / 20, "{} of {}", PEAK.load(SeqCst) - before, len);
+    /// # }
+    /// ```
+    pub async fn next_element(&mut self) -> Option<Result<T, ElementError>> {
+        loop {
+            if self.state == State::Done {
+                return None;
+            }
+
+            if self.state != State::Value {
+                let byte = match self.next_token().await {
+                    Ok(byte) => byte,
+                    Err(e) => return Some(Err(e)),
+                };
+                match (self.state, byte) {
+                    (State::Start, Some(b'[')) => self.advance(State::First),
+                    (State::Start, _) => return Some(Err(self.fatal("expected a JSON array"))),
+                    (State::First, Some(b']')) | (State::AfterValue, Some(b']')) => {
+                        self.advance(State::Done);
+                        return match self.next_token().await {
+                            Ok(None) => None,
+                            Ok(Some(_)) => Some(Err(self.fatal("unexpected data after array"))),
+                            Err(e) => Some(Err(e)),
+                        };
+                    }
+                    (State::First, Some(_)) => self.state = State::Value,
+                    (State::AfterValue, Some(b',')) => self.advance(State::Value),
+                    (State::AfterValue, Some(_)) => {
+                        return Some(Err(self.fatal("expected `,` or `]`")))
+                    }
+                    (_, None) => return Some(Err(self.fatal("unexpected end of body"))),
+                    _ => unreachable!(),
+                }
+                continue;
+            }
+
+            match self.next_token().await {
+                Ok(Some(_)) => {}
+                Ok(None) => return Some(Err(self.fatal("unexpected end of body"))),
+                Err(e) => return Some(Err(e)),
+            }
+            let end = match self.element_end().await {
+                Ok(end) => end,
+                Err(e) => return Some(Err(e)),
+            };
+            let element = serde_json::from_slice(&self.buf[self.pos..end])
+                .map_err(|e| self.error(StatusCode::BadRequest, &e.to_string(), false));
+            self.pos = end;
+            self.scan = Scan::default();
+            self.index += 1;
+            self.state = State::AfterValue;
+            return Some(element);
+        }
+    }
+
+    /// Skip whitespace, returning the next byte without consuming it, or `None` at the end of
+    /// the body.
+    async fn next_token(&mut self) -> Result<Option<u8>, ElementError> {
+        loop {
+            while let Some(&byte) = self.buf.get(self.pos) {
+                if !byte.is_ascii_whitespace() {
+                    return Ok(Some(byte));
+                }
+                self.pos += 1;
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn advance(&mut self, state: State) {
+        self.pos += 1;
+        self.state = state;
+    }
+
+    /// Find where the element starting at `pos` ends, reading more of the body as needed.
+    /// Strings are skipped over so brackets and commas inside them are not mistaken for the end.
+    async fn element_end(&mut self) -> Result<usize, ElementError> {
+        let nested = matches!(self.buf[self.pos], b'{' | b'[' | b'"');
+        loop {
+            let scan = &mut self.scan;
+            for (i, &byte) in self.buf[self.pos + scan.offset..].iter().enumerate() {
+                let at = self.pos + scan.offset + i;
+                if scan.in_string {
+                    match byte {
+                        _ if scan.escaped => scan.escaped = false,
+                        b'\\' => scan.escaped = true,
+                        b'"' => {
+                            scan.in_string = false;
+                            if scan.depth == 0 {
+                                return Ok(at + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                match byte {
+                    b'"' => scan.in_string = true,
+                    b'{' | b'[' => scan.depth += 1,
+                    b'}' | b']' if scan.depth > 0 => {
+                        scan.depth -= 1;
+                        if scan.depth == 0 {
+                            return Ok(at + 1);
+                        }
+                    }
+                    b',' | b']' | b'}' if !nested => return Ok(at),
+                    byte if byte.is_ascii_whitespace() && !nested => return Ok(at),
+                    _ => {}
+                }
+            }
+            scan.offset = self.buf.len() - self.pos;
+
+            if !self.fill().await? {
+                return match nested {
+                    true => Err(self.fatal("unexpected end of body")),
+                    false => Ok(self.buf.len()),
+                };
+            }
+        }
+    }
+
+    /// Read the next chunk of the body into the buffer, dropping what has been consumed.  Returns
+    /// false at the end of the body.
+    async fn fill(&mut self) -> Result<bool, ElementError> {
+        if self.eof {
+            return Ok(false);
+        }
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+
+        let len = self.buf.len();
+        self.buf.resize(len + CHUNK, 0);
+        let read = self.reader.read(&mut self.buf[len..]).await;
+        let read = match read {
+            Ok(read) => read,
+            Err(e) => {
+                self.buf.truncate(len);
+                let msg = format!("unable to read body: {}", e);
+                return Err(self.fatal(&msg));
+            }
+        };
+        self.buf.truncate(len + read);
+
+        self.read += read;
+        if self.read > self.body_limit {
+            self.state = State::Done;
+            return Err(ElementError {
+                index: self.index,
+                fatal: true,
+                error: body_too_large(self.body_limit),
+            });
+        }
+        self.eof = read == 0;
+        Ok(!self.eof)
+    }
+
+    fn fatal(&mut self, msg: &str) -> ElementError {
+        self.state = State::Done;
+        self.error(StatusCode::BadRequest, msg, true)
+    }
+
+    fn error(&self, code: StatusCode, msg: &str, fatal: bool) -> ElementError {
+        ElementError {
+            index: self.index,
+            fatal,
+            error: Error::new(code, format!("element {}: {}", self.index, msg))
+                .with_code(WindmillErrorCode::DeserializeFailed),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> Props for JsonArrayStream<T> {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let body_limit = max_body_size(&req);
+            if matches!(req.len(), Some(len) if len > body_limit) {
+                return Err(body_too_large(body_limit));
+            }
+
+            let stream = JsonArrayStream {
+                reader: Box::pin(req.take_body()),
+                buf: Vec::with_capacity(CHUNK),
+                pos: 0,
+                scan: Scan::default(),
+                index: 0,
+                read: 0,
+                body_limit,
+                eof: false,
+                state: State::Start,
+                element: PhantomData,
+            };
+            Ok((req, params, stream))
+        })
+    }
+}
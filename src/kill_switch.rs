@@ -0,0 +1,265 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    route::Route,
+};
+use http_types::{mime, Method, StatusCode};
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// The message sent with `503 Service Unavailable` when a route is disabled without one.
+const DEFAULT_MESSAGE: &str = "this route is temporarily disabled";
+
+/// Whether a route is disabled, shared between the route and every
+/// [`RouterHandle`](struct.RouterHandle.html).
+#[doc(hidden)]
+#[derive(Default)]
+pub struct RouteSwitch {
+    disabled: AtomicBool,
+    message: Mutex<Option<String>>,
+    rejected: AtomicU64,
+}
+
+impl RouteSwitch {
+    /// Whether the route is disabled, one relaxed load on the request path.
+    pub(crate) fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    /// The error to answer a request for the route with, unless it was enabled again since
+    /// [`is_disabled`](#method.is_disabled) said otherwise.
+    pub(crate) fn reject(&self) -> Option<Error> {
+        // The message is the source of truth, so a request never sees a disabled route without
+        // its message.
+        let message = self.lock().clone()?;
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        Some(
+            Error::new(StatusCode::ServiceUnavailable, message)
+                .with_code(WindmillErrorCode::RouteDisabled),
+        )
+    }
+
+    fn set(&self, message: Option<String>) {
+        let mut current = self.lock();
+        self.disabled.store(message.is_some(), Ordering::Relaxed);
+        *current = message;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        self.message.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A named route registered with a router.
+struct NamedRoute {
+    name: &'static str,
+    method: String,
+    pattern: String,
+    switch: Arc<RouteSwitch>,
+}
+
+/// The state of a named route, as reported by
+/// [`RouterHandle::routes`](struct.RouterHandle.html#method.routes).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RouteStatus {
+    /// The name of the route.
+    pub name: &'static str,
+    /// The method the route is registered for.
+    pub method: String,
+    /// The route pattern, e.g. `/users/{id}`.
+    pub route: String,
+    /// The message sent to requests while the route is disabled, or `None` when it is enabled.
+    pub disabled: Option<String>,
+    /// The number of requests turned away while the route was disabled.
+    pub rejected: u64,
+}
+
+/// Disables and enables named routes of a running router.
+///
+/// Taken from [`Router::handle`](struct.Router.html#method.handle) before the router is handed to
+/// the server.  Requests for a disabled route get `503 Service Unavailable` with an incident
+/// message and the `route_disabled` [code](enum.WindmillErrorCode.html) as their body, while
+/// every other route keeps being served.  A name given to routes under several methods disables
+/// all of them.  Enabled routes cost one relaxed atomic load per request.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[endpoint]
+/// async fn ok() -> Result<Response, Error> {
+///     Ok(Response::from("ok"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"search").name("search"), ___ok);
+/// router.add(Method::Get, route!(/"orders").name("orders"), ___ok);
+/// let handle = router.handle();
+/// let router = Arc::new(router);
+///
+/// let get = move |path: &'static str| {
+///     let router = router.clone();
+///     async move {
+///         let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///         let mut res = router.respond(Request::new(Method::Get, url)).await;
+///         (res.status(), res.body_string().await.unwrap())
+///     }
+/// };
+///
+/// assert!(handle.disable_route("search", Some("search is down, see status page")));
+/// assert!(!handle.disable_route("missing", None));
+/// task::block_on(async {
+///     assert_eq!(
+///         get("/search").await,
+///         (StatusCode::ServiceUnavailable, r#"{"code":"route_disabled","msg":"search is down, see status page"}"#.to_owned())
+///     );
+///     assert_eq!(get("/orders").await.0, StatusCode::Ok);
+/// });
+/// assert_eq!(handle.route("search").unwrap().rejected, 1);
+///
+/// // Toggling under traffic never serves a disabled route without its message.
+/// handle.enable_route("search");
+/// task::block_on(async {
+///     let traffic: Vec<_> = (0..8)
+///         .map(|_| {
+///             let get = get.clone();
+///             task::spawn(async move {
+///                 for _ in 0..200 {
+///                     match get("/search").await {
+///                         (StatusCode::Ok, body) => assert_eq!(body, "ok"),
+///                         (StatusCode::ServiceUnavailable, body) => {
+///                             assert_eq!(body, r#"{"code":"route_disabled","msg":"incident 42"}"#)
+///                         }
+///                         other => panic!("{:?}", other),
+///                     }
+///                 }
+///             })
+///         })
+///         .collect();
+///     for _ in 0..100 {
+///         handle.disable_route("search", Some("incident 42"));
+///         assert_eq!(get("/search").await.0, StatusCode::ServiceUnavailable);
+///         handle.enable_route("search");
+///         assert_eq!(get("/search").await.0, StatusCode::Ok);
+///     }
+///     for traffic in traffic {
+///         traffic.await;
+///     }
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct RouterHandle {
+    routes: Arc<Mutex<Vec<NamedRoute>>>,
+}
+
+impl RouterHandle {
+    /// Disable every route named `name`, answering requests with `message` or a default one.
+    /// Returns false if no route has that name.
+    pub fn disable_route(&self, name: &str, message: Option<&str>) -> bool {
+        let message = message.unwrap_or(DEFAULT_MESSAGE);
+        self.set(name, Some(message.to_owned()))
+    }
+
+    /// Enable every route named `name` again.  Returns false if no route has that name.
+    pub fn enable_route(&self, name: &str) -> bool {
+        self.set(name, None)
+    }
+
+    /// Whether the routes named `name` are disabled, or `None` if no route has that name.
+    pub fn is_disabled(&self, name: &str) -> Option<bool> {
+        self.route(name).map(|route| route.disabled.is_some())
+    }
+
+    /// The state of the first route named `name`.
+    pub fn route(&self, name: &str) -> Option<RouteStatus> {
+        self.routes().into_iter().find(|route| route.name == name)
+    }
+
+    /// The state of every named route, in the order they were added.
+    pub fn routes(&self) -> Vec<RouteStatus> {
+        self.lock()
+            .iter()
+            .map(|route| RouteStatus {
+                name: route.name,
+                method: route.method.clone(),
+                route: route.pattern.clone(),
+                disabled: route.switch.lock().clone(),
+                rejected: route.switch.rejected.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Make `route` controllable through the handle, if it has a name.
+    pub(crate) fn register_route(&self, method: &str, route: &Route) {
+        if let Some(name) = route.name {
+            self.lock().push(NamedRoute {
+                name,
+                method: method.into(),
+                pattern: route.to_string(),
+                switch: route.switch.clone(),
+            });
+        }
+    }
+
+    fn set(&self, name: &str, message: Option<String>) -> bool {
+        // Holding the lock applies the change to every route of the name before any other.
+        let routes = self.lock();
+        let mut found = false;
+        for route in routes.iter().filter(|route| route.name == name) {
+            route.switch.set(message.clone());
+            found = true;
+        }
+        found
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<NamedRoute>> {
+        self.routes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Answer a request to the route admin endpoints under `/__routes`.
+pub(crate) async fn admin_endpoint(
+    handle: RouterHandle,
+    authorized: bool,
+    req: http_types::Request,
+) -> http_types::Response {
+    if !authorized {
+        return http_types::Response::new(StatusCode::Unauthorized);
+    }
+
+    let json = |code: StatusCode, body: serde_json::Value| {
+        let mut res = http_types::Response::new(code);
+        let _ = res.set_content_type(mime::JSON);
+        res.set_body(body.to_string());
+        res
+    };
+
+    let path = req.url().path().to_owned();
+    let segments: Vec<&str> = path.trim_start_matches("/__routes").split('/').collect();
+    match (req.method(), segments.as_slice()) {
+        (Method::Get, [""]) => json(StatusCode::Ok, serde_json::json!(handle.routes())),
+        (Method::Post, ["", name, action]) if *action == "disable" || *action == "enable" => {
+            let message = match req.body_string().await {
+                Ok(message) if !message.trim().is_empty() => Some(message),
+                _ => None,
+            };
+            let found = match *action {
+                "disable" => handle.disable_route(name, message.as_deref()),
+                _ => handle.enable_route(name),
+            };
+            match found {
+                true => json(StatusCode::Ok, serde_json::json!(handle.route(name))),
+                false => json(
+                    StatusCode::NotFound,
+                    serde_json::json!(format!("no route named `{}`", name)),
+                ),
+            }
+        }
+        _ => http_types::Response::new(StatusCode::NotFound),
+    }
+}
@@ -28,15 +28,14 @@
 //! # pub use http_types::{Response};
 //! # use serde::Deserialize;
 //! # struct Body<T> {
-//! #     inner: Option<T>,
+//! #     inner: T,
 //! # }
 //!
 //! # impl<T: for<'de> Deserialize<'de>> Props for Body<T> {
 //! #     type Fut = PropsFuture<Self>;
 //! #     fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
 //! #         Box::pin(async move {
-//! #             let body: Option<T> =
-//! #                 serde_json::from_slice(&read_body(&mut req).await).unwrap_or_else(|_| None);
+//! #             let body = serde_json::from_slice(&read_body(&mut req).await?)?;
 //! #             Ok((req, params, Body { inner: body }))
 //! #         })
 //! #     }
@@ -55,7 +54,7 @@
 //! # pub use windmill::*;
 //! # use serde::Deserialize;
 //! struct Body<T> {
-//!     inner: Option<T>,
+//!     inner: T,
 //! }
 //!
 //! impl<T: for<'de> Deserialize<'de>> Props for Body<T> {
@@ -63,8 +62,7 @@
 //!
 //!     fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
 //!         Box::pin(async move {
-//!             let body: Option<T> =
-//!                 serde_json::from_slice(&read_body(&mut req).await).unwrap_or_else(|_| None);
+//!             let body = serde_json::from_slice(&read_body(&mut req).await?)?;
 //!
 //!             Ok((req, params, Body { inner: body }))
 //!         })
@@ -77,9 +75,13 @@
 //! Before `example_route` is invoked, an instance of the `Body` props is constructed using the
 //! `call` method above.  Constructing an instance of the `Body` props parses the body from the
 //! request and returns `Self`, this instance is then passed in as an argument to `example_route`.
+//! A body that does not parse is turned into a `400 Bad Request` by `?`, telling the client what
+//! went wrong, and `example_route` is not invoked.  
 //!
 //! In this example we have made the parsed JSON body available to the endpoint through the `body`
-//! argument.
+//! argument.  A props is owned by the endpoint, so it cannot hold a value borrowing from the
+//! request, such as a struct with `&str` fields: take a [`JsonBuf`](struct.JsonBuf.html) to
+//! decode one from the body without copying.
 //!
 //! # Examples
 //!
@@ -100,33 +102,143 @@
 //! }
 //! ```
 
+mod access_log;
+mod admission;
+#[cfg(feature = "client")]
+mod client;
+mod codec;
+#[cfg(feature = "compression")]
+mod compression;
 mod config;
+mod connection_limit;
+mod cookie;
+mod cors;
+mod decorator;
+mod disconnect;
+mod drain;
 mod endpoint;
 mod error;
+mod etag;
+mod expect;
+mod explain;
+mod extensions;
+mod forwarded;
+mod head;
+mod host;
+mod https;
+mod json_stream;
+mod kill_switch;
+mod lifecycle;
+mod limits;
+mod listener;
+mod live_reload;
+mod matched;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod middleware;
+mod multipart;
+mod named_file;
+mod observer;
+#[cfg(feature = "openapi")]
+mod openapi;
+mod panic;
+mod params;
 mod props;
+mod query;
+mod raw_body;
+mod redact;
+mod replay;
+mod request_head;
+mod request_id;
+mod responder;
 mod route;
+mod route_info;
 mod router;
+mod scratch;
+mod serialize;
+mod serve_dir;
 mod server;
+mod session;
+mod signature;
+mod sse;
+mod state;
+mod test_client;
+mod timeout;
+#[cfg(feature = "tls")]
+mod tls;
+mod trailing_slash;
+mod trie;
+mod upload;
+mod urls;
 mod util;
+mod ws;
 
 mod codegen {
     pub use codegen::endpoint;
     pub use codegen::route;
 }
 
-mod params {
-    pub type Params = std::collections::HashMap<&'static str, String>;
-}
+#[cfg(feature = "client")]
+pub use crate::client::HttpClient;
+#[cfg(feature = "msgpack")]
+pub use crate::codec::{MsgPackCodec, Negotiated};
+#[cfg(feature = "compression")]
+pub use crate::compression::{Compression, CompressionLevel};
+#[cfg(feature = "metrics")]
+pub use crate::metrics::Metrics;
+#[cfg(feature = "openapi")]
+pub use crate::openapi::ApiInfo;
 
 pub use crate::{
+    access_log::{access_log, AccessRecord},
+    admission::ClassMetrics,
+    codec::{Body, BodyBytes, Codec, FormCodec, JsonBuf, JsonCodec, TextCodec},
     codegen::{endpoint, route},
     config::Config,
+    connection_limit::{ConnectionCount, ConnectionOverflow},
+    cookie::{Cookie, Cookies, SameSite},
+    cors::Cors,
     endpoint::Endpoint,
-    error::Error,
+    error::{Error, RouterError, WindmillErrorCode},
+    etag::{ETag, Preconditions, Validators},
+    explain::{Candidate, MatchExplanation, Outcome},
+    extensions::Extensions,
+    host::HostCaptures,
+    https::HttpsRedirect,
+    json_stream::{ElementError, JsonArrayStream},
+    kill_switch::{RouteStatus, RouterHandle},
+    lifecycle::Startup,
+    limits::EffectiveLimits,
+    live_reload::LiveReload,
+    matched::MatchedRoute,
+    middleware::{request_log, MiddlewareFuture, Next},
+    multipart::{Multipart, Part},
+    named_file::{FileConditions, NamedFile},
+    observer::{RequestInfo, RequestObserver, ResponseInfo},
     params::Params,
-    props::{Props, PropsFuture},
-    route::{DynamicSegment, Route, StaticSegment},
+    props::{Props, PropsFuture, StatefulProps, With},
+    query::{Query, RawQuery},
+    raw_body::RawBody,
+    redact::{Redaction, REDACTED},
+    replay::{replay, Recorder, ReplayDiff, ReplayReport},
+    request_head::RequestHead,
+    request_id::RequestId,
+    responder::{Bytes, IntoResponse, Redirect, Respond, Text},
+    route::{DynamicSegment, Route, SegmentType, StaticSegment},
+    route_info::RouteInfo,
     router::Router,
-    server::Server,
-    util::read_body,
+    serialize::{SerializePolicy, Serializer},
+    serve_dir::ServeDir,
+    server::{BoundServer, Server},
+    session::{MemoryStore, Session, SessionData, SessionFuture, SessionStore, Sessions},
+    signature::{CanonicalRequest, SignatureGuard, Signed},
+    sse::{Event, Sse},
+    state::State,
+    test_client::{TestClient, TestRequest},
+    timeout::{Deadline, TimeoutMetrics, Timeouts},
+    trailing_slash::TrailingSlash,
+    upload::{UploadEnd, UploadProgress},
+    urls::Urls,
+    util::{read_body, read_body_with_limit},
+    ws::{Message, WebSocket},
 };
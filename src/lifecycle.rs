@@ -0,0 +1,24 @@
+use crate::state::AppState;
+use std::{error::Error, future::Future, pin::Pin};
+
+pub(crate) type StartHook =
+    Box<dyn FnOnce(Startup) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error>>>>> + Send>;
+
+pub(crate) type StopHook = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// What a start hook, added with [`Server::on_start`](struct.Server.html#method.on_start), is
+/// given to set the server up with.
+///
+/// State the hook creates, such as a connection pool, is registered on the router as if with
+/// [`Router::state`](struct.Router.html#method.state), for endpoints to take as
+/// [`State<S>`](struct.State.html).
+pub struct Startup {
+    pub(crate) state: AppState,
+}
+
+impl Startup {
+    /// Share `state` with every endpoint, replacing state of the same type registered before.
+    pub fn state<S: Clone + Send + Sync + 'static>(&self, state: S) {
+        self.state.insert(state);
+    }
+}
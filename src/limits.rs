@@ -0,0 +1,21 @@
+use serde::Serialize;
+
+/// The limits that apply to requests for a route.
+///
+/// Produced by [`Router::effective_limits`](struct.Router.html#method.effective_limits) without
+/// sending a request, so support tooling can answer what a caller of a route is subject to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EffectiveLimits {
+    /// The method of the route.
+    pub method: String,
+    /// The route pattern, e.g. `/users/{id}`.
+    pub route: String,
+    /// The class the route is admitted under.
+    pub class: String,
+    /// The weight of the class.
+    pub class_weight: u32,
+    /// The most endpoints the router runs at once, if it is limited.
+    pub max_in_flight: Option<usize>,
+    /// The number of those slots reserved for the class, if the router is limited.
+    pub class_share: Option<usize>,
+}
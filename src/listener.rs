@@ -0,0 +1,257 @@
+use crate::config::Config;
+#[cfg(unix)]
+use async_std::os::unix::net::{UnixListener, UnixStream};
+use async_std::{
+    io,
+    net::{TcpListener, TcpStream},
+    task,
+};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    net::{Shutdown, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// How often a connection with unread bytes is checked for the client having closed it.
+const CLOSED_RECHECK: Duration = Duration::from_millis(50);
+
+/// A socket bound to the address of a server, before it accepts connections.
+pub(crate) enum BoundSocket {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, SocketFile),
+}
+
+impl BoundSocket {
+    /// Bind to the address in `config`.
+    pub(crate) fn bind(config: &Config) -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            if let Some(path) = config.unix_path() {
+                return bind_unix(path, config.unix_permissions());
+            }
+        }
+        bind_tcp(config.addr()).map(BoundSocket::Tcp)
+    }
+
+    /// The address of a tcp socket.
+    pub(crate) fn local_addr(&self) -> io::Result<Option<SocketAddr>> {
+        match self {
+            BoundSocket::Tcp(listener) => listener.local_addr().map(Some),
+            #[cfg(unix)]
+            BoundSocket::Unix(..) => Ok(None),
+        }
+    }
+
+    /// Start listening for connections.
+    pub(crate) fn listen(self) -> Listener {
+        match self {
+            BoundSocket::Tcp(listener) => Listener::Tcp(TcpListener::from(listener)),
+            #[cfg(unix)]
+            BoundSocket::Unix(listener, file) => Listener::Unix(UnixListener::from(listener), file),
+        }
+    }
+}
+
+/// Bind to the first address `addr` resolves to that can be bound.
+fn bind_tcp(addr: &str) -> io::Result<std::net::TcpListener> {
+    let mut attempts = vec![];
+    let mut kind = io::ErrorKind::AddrNotAvailable;
+    for socket_addr in addr.to_socket_addrs()? {
+        match std::net::TcpListener::bind(socket_addr) {
+            Ok(listener) => {
+                listener.set_nonblocking(true)?;
+                return Ok(listener);
+            }
+            Err(e) => {
+                kind = e.kind();
+                attempts.push(format!("{}: {}", socket_addr, e));
+            }
+        }
+    }
+    let msg = match attempts.is_empty() {
+        true => format!("{} resolves to no address", addr),
+        false => format!("unable to bind to {} ({})", addr, attempts.join(", ")),
+    };
+    Err(io::Error::new(kind, msg))
+}
+
+/// Bind to the unix socket at `path`, replacing a socket file left behind by a server that is no
+/// longer running.
+#[cfg(unix)]
+fn bind_unix(path: &Path, permissions: Option<u32>) -> io::Result<BoundSocket> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            let msg = format!("{} exists and is not a socket", path.display());
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, msg));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            let msg = format!("{} is in use by a running server", path.display());
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, msg));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    let file = SocketFile(path.to_owned());
+    if let Some(mode) = permissions {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    listener.set_nonblocking(true)?;
+    Ok(BoundSocket::Unix(listener, file))
+}
+
+/// The file of a unix socket, removed once the server no longer listens on it.
+#[cfg(unix)]
+pub(crate) struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A socket accepting connections.
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, SocketFile),
+}
+
+impl Listener {
+    pub(crate) async fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().await.map(|(s, _)| Stream::Tcp(s)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.accept().await.map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+
+    /// The url the paths of requests are relative to.
+    pub(crate) fn url(&self, scheme: &str) -> io::Result<String> {
+        match self {
+            Listener::Tcp(listener) => Ok(format!("{}://{}", scheme, listener.local_addr()?)),
+            #[cfg(unix)]
+            Listener::Unix(..) => Ok(format!("{}://localhost", scheme)),
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp socket"),
+            },
+            #[cfg(unix)]
+            Listener::Unix(_, file) => write!(f, "unix:{}", file.0.display()),
+        }
+    }
+}
+
+/// A client connection to a server.
+#[derive(Clone)]
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// The address of the client, if it connected over tcp.
+    pub(crate) fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Stream::Tcp(stream) => stream.peer_addr().ok(),
+            #[cfg(unix)]
+            Stream::Unix(_) => None,
+        }
+    }
+
+    /// The host the client connected to, for requests that do not name one.
+    pub(crate) fn host(&self) -> String {
+        match self {
+            Stream::Tcp(stream) => stream.local_addr().map(|addr| addr.to_string()).ok(),
+            #[cfg(unix)]
+            Stream::Unix(_) => None,
+        }
+        .unwrap_or_else(|| "localhost".to_owned())
+    }
+
+    /// Resolve once the client has closed its side of a tcp connection, without reading from it.
+    /// Bytes the client sent that were not read yet hide the close until they are, and a unix
+    /// socket is never seen closing.
+    pub(crate) async fn closed(&self) {
+        let stream = match self {
+            Stream::Tcp(stream) => stream,
+            #[cfg(unix)]
+            Stream::Unix(_) => return async_std::future::pending().await,
+        };
+        let mut byte = [0; 1];
+        loop {
+            match stream.peek(&mut byte).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => task::sleep(CLOSED_RECHECK).await,
+            }
+        }
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+
+impl io::Read for &Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match *self {
+            Stream::Tcp(stream) => Pin::new(&mut &*stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(&mut &*stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl io::Write for &Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match *self {
+            Stream::Tcp(stream) => Pin::new(&mut &*stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(&mut &*stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match *self {
+            Stream::Tcp(stream) => Pin::new(&mut &*stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(&mut &*stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match *self {
+            Stream::Tcp(stream) => Pin::new(&mut &*stream).poll_close(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(&mut &*stream).poll_close(cx),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use async_std::{io::prelude::*, task};
+use http_types::{mime, StatusCode};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The script injected into HTML responses unless another snippet is set.  It long-polls
+/// `/__reload` and reloads the page once the generation it reports changes.
+const DEFAULT_SNIPPET: &str = "<script>(function poll(since) {\
+    fetch('/__reload?since=' + since)\
+    .then(function (res) { return res.json(); })\
+    .then(function (body) {\
+    if (since >= 0 && body.generation !== since) { location.reload(); } else { poll(body.generation); }\
+    }, function () { setTimeout(function () { poll(since); }, 1000); });\
+    })(-1);</script>";
+
+/// Reloads pages open in a browser when the files behind them change, for development.
+///
+/// Once registered with [`Router::live_reload`](struct.Router.html#method.live_reload), HTML
+/// responses get a script injected before their closing `</body>` tag, or at the end when there
+/// is none, and `/__reload` is served for the script to long-poll.  The poll answers with the
+/// current generation as soon as it differs from the `since` the script sent, or after the poll
+/// timeout, and the script reloads the page when it sees a new generation.
+///
+/// The generation moves on with [`trigger`](#method.trigger), or on any change under a
+/// directory passed to [`watch`](#method.watch) with the `live-reload` feature enabled.
+/// Responses that are compressed, empty, of unknown length or larger than the
+/// [size cap](#method.max_size) are left alone.
+///
+/// Like [`Router::debug_explain`](struct.Router.html#method.debug_explain) this only takes effect
+/// in debug builds, release builds ignore it and never start a watcher.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, Url};
+/// # use std::{sync::Arc, time::Duration};
+/// #[endpoint]
+/// async fn page() -> Result<Response, Error> {
+///     Ok(Text::from("<html><BODY><h1>hi</h1></BODY></html>").with_type("text/html".parse().unwrap()).into())
+/// }
+///
+/// #[endpoint]
+/// async fn fragment() -> Result<Response, Error> {
+///     Ok(Text::from("<p>no body tag</p>").with_type("text/html".parse().unwrap()).into())
+/// }
+///
+/// #[endpoint]
+/// async fn json() -> Result<Response, Error> {
+///     JsonCodec.respond(&"</body>")
+/// }
+///
+/// let live_reload = LiveReload::new().snippet("<script>reload()</script>");
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"page"), ___page);
+/// router.add(Method::Get, route!(/"fragment"), ___fragment);
+/// router.add(Method::Get, route!(/"json"), ___json);
+/// router.live_reload(live_reload.clone());
+/// let router = Arc::new(router);
+///
+/// let get = |url: &str| {
+///     let mut res = task::block_on(router.clone().respond(Request::new(Method::Get, Url::parse(url).unwrap())));
+///     let len = res.len();
+///     let body = task::block_on(res.body_string()).unwrap();
+///     assert_eq!(len, Some(body.len()));
+///     body
+/// };
+///
+/// assert_eq!(get("http://localhost/page"), "<html><BODY><h1>hi</h1><script>reload()</script></BODY></html>");
+/// assert_eq!(get("http://localhost/fragment"), "<p>no body tag</p><script>reload()</script>");
+/// assert_eq!(get("http://localhost/json"), r#""</body>""#);
+///
+/// // The first poll answers straight away, the next waits for a change.
+/// assert_eq!(get("http://localhost/__reload?since=-1"), r#"{"generation":0}"#);
+/// let trigger = live_reload.clone();
+/// task::spawn(async move {
+///     task::sleep(Duration::from_millis(50)).await;
+///     trigger.trigger();
+/// });
+/// assert_eq!(get("http://localhost/__reload?since=0"), r#"{"generation":1}"#);
+/// ```
+#[derive(Clone)]
+pub struct LiveReload {
+    generation: Arc<AtomicU64>,
+    snippet: Arc<str>,
+    max_size: usize,
+    poll_timeout: Duration,
+    #[cfg(feature = "live-reload")]
+    watchers: Arc<std::sync::Mutex<Vec<notify::RecommendedWatcher>>>,
+}
+
+impl LiveReload {
+    /// Create a live reloader that injects a polling script into HTML responses up to 1 MiB, and
+    /// holds polls open for up to 25 seconds.
+    pub fn new() -> Self {
+        Self {
+            generation: Arc::new(AtomicU64::new(0)),
+            snippet: DEFAULT_SNIPPET.into(),
+            max_size: 1024 * 1024,
+            poll_timeout: Duration::from_secs(25),
+            #[cfg(feature = "live-reload")]
+            watchers: Default::default(),
+        }
+    }
+
+    /// Inject `snippet` instead of the built-in polling script.
+    pub fn snippet(mut self, snippet: &str) -> Self {
+        self.snippet = snippet.into();
+        self
+    }
+
+    /// Leave HTML responses larger than `bytes` alone.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Answer polls that see no change after `timeout`.
+    pub fn poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// Move on to the next generation, reloading every page that is polling.
+    pub fn trigger(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The current generation.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Trigger a reload whenever anything under `dir` changes.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::LiveReload;
+    /// # use std::time::{Duration, Instant};
+    /// let dir = std::env::temp_dir().join(format!("windmill-live-reload-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let live_reload = LiveReload::new().watch(&dir).unwrap();
+    /// std::fs::write(dir.join("index.html"), "<h1>hi</h1>").unwrap();
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// while live_reload.generation() == 0 && Instant::now() < deadline {
+    ///     std::thread::sleep(Duration::from_millis(10));
+    /// }
+    /// assert!(live_reload.generation() > 0);
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(feature = "live-reload")]
+    pub fn watch(self, dir: impl AsRef<std::path::Path>) -> notify::Result<Self> {
+        use notify::Watcher;
+
+        if cfg!(debug_assertions) {
+            let generation = self.generation.clone();
+            let mut watcher =
+                notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    if event.is_ok() {
+                        generation.fetch_add(1, Ordering::SeqCst);
+                    }
+                })?;
+            watcher.watch(dir.as_ref(), notify::RecursiveMode::Recursive)?;
+            self.watchers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(watcher);
+        }
+        Ok(self)
+    }
+
+    /// Inject the snippet into `res` if it is an HTML response within the size cap.
+    pub(crate) async fn inject(&self, res: &mut http_types::Response) {
+        let is_html = res
+            .header(&http_types::headers::CONTENT_TYPE)
+            .and_then(|values| values.last())
+            .map(|value| {
+                let essence = value.as_str().split(';').next().unwrap_or_default();
+                essence.trim().eq_ignore_ascii_case("text/html")
+            })
+            .unwrap_or(false);
+        let is_encoded = res.header(&"content-encoding".parse().unwrap()).is_some();
+        let fits = matches!(res.len(), Some(len) if len > 0 && len <= self.max_size);
+        if !is_html || is_encoded || !fits {
+            return;
+        }
+
+        let mut body = vec![];
+        if res.take_body().read_to_end(&mut body).await.is_err() {
+            return;
+        }
+
+        let at = body
+            .windows(b"</body".len())
+            .rposition(|window| window.eq_ignore_ascii_case(b"</body"))
+            .unwrap_or(body.len());
+        body.splice(at..at, self.snippet.bytes());
+
+        if res.header(&http_types::headers::CONTENT_LENGTH).is_some() {
+            let _ = res.insert_header("content-length", body.len().to_string());
+        }
+        res.set_body(body);
+    }
+
+    /// Answer a poll from the injected script.
+    pub(crate) async fn poll(&self, req: &http_types::Request) -> http_types::Response {
+        let since = req
+            .url()
+            .query_pairs()
+            .find(|(key, _)| key == "since")
+            .and_then(|(_, value)| value.parse::<i64>().ok())
+            .unwrap_or(-1);
+
+        let deadline = Instant::now() + self.poll_timeout;
+        while since == self.generation() as i64 && Instant::now() < deadline {
+            task::sleep(Duration::from_millis(20)).await;
+        }
+
+        let mut res = http_types::Response::new(StatusCode::Ok);
+        let _ = res.set_content_type(mime::JSON);
+        let _ = res.insert_header("cache-control", "no-store");
+        res.set_body(serde_json::json!({ "generation": self.generation() }).to_string());
+        res
+    }
+}
+
+impl Default for LiveReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
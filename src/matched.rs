@@ -0,0 +1,166 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    extensions::Extensions,
+    params::Params,
+    props::{Props, PropsFuture},
+    route::Route,
+};
+use http_types::Method;
+use std::{fmt, sync::Arc};
+
+/// The route that matched a request, as declared when it was registered.
+///
+/// The router attaches it to the request before running the middlewares added with
+/// [`Router::middleware`](struct.Router.html#method.middleware), which can read it with
+/// [`of`](#method.of) to decide whether to let the request through.  Endpoints can take it as an
+/// argument.  Requests that match no route have none, and neither do requests seen by
+/// [pre-routing middlewares](struct.Router.html#method.pre_routing).
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[derive(Clone)]
+/// struct RequiresScope(&'static str);
+///
+/// #[endpoint]
+/// async fn orders(route: MatchedRoute) -> Result<Response, Error> {
+///     Ok(Response::from(route.name().unwrap_or_default()))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(
+///     Method::Get,
+///     route!(/"orders").name("orders.list").tag("orders").metadata(RequiresScope("orders:read")),
+///     ___orders,
+/// );
+/// router.add(Method::Get, route!(/"status"), ___orders);
+/// router.middleware(|req: Request, params: Params, next: Next| {
+///     let scope = MatchedRoute::of(&req).and_then(|route| route.metadata::<RequiresScope>());
+///     let granted = req
+///         .header(&"x-scopes".parse().unwrap())
+///         .map(|scopes| scopes[0].as_str().split(' ').any(|s| Some(s) == scope.as_ref().map(|s| s.0)))
+///         .unwrap_or(false);
+///     async move {
+///         match scope {
+///             Some(_) if !granted => Response::new(StatusCode::Forbidden),
+///             _ => next.run(req, params).await,
+///         }
+///     }
+/// });
+/// let router = Arc::new(router);
+///
+/// let get = |path: &str, scopes: Option<&str>| {
+///     let mut req = Request::new(Method::Get, Url::parse("http://localhost").unwrap().join(path).unwrap());
+///     if let Some(scopes) = scopes {
+///         req.insert_header("x-scopes", scopes).unwrap();
+///     }
+///     task::block_on(router.clone().respond(req))
+/// };
+///
+/// assert_eq!(get("/orders", None).status(), StatusCode::Forbidden);
+/// assert_eq!(get("/orders", Some("users:read")).status(), StatusCode::Forbidden);
+/// let mut res = get("/orders", Some("users:read orders:read"));
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "orders.list");
+///
+/// // Routes without the marker are let through.
+/// assert_eq!(get("/status", None).status(), StatusCode::Ok);
+/// ```
+#[derive(Clone)]
+pub struct MatchedRoute {
+    method: Method,
+    pattern: Arc<str>,
+    name: Option<&'static str>,
+    tags: Arc<[&'static str]>,
+    metadata: Extensions,
+}
+
+impl MatchedRoute {
+    pub(crate) fn new(method: Method, route: &Route) -> Self {
+        Self {
+            method,
+            pattern: route.to_string().into(),
+            name: route.name,
+            tags: route.tags.clone().into(),
+            metadata: route.metadata.clone(),
+        }
+    }
+
+    /// Get the route that matched a request, if the request has been routed and matched one.
+    pub fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+
+    /// The method the route was registered under.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The path pattern of the route, such as `/orders/{id}`.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The name of the route, if it was given one.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// The tags of the route.
+    pub fn tags(&self) -> &[&'static str] {
+        &self.tags
+    }
+
+    /// Whether the route is tagged `tag`.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    /// Get a clone of the metadata of type `T` attached to the route.
+    pub fn metadata<T: Clone + 'static>(&self) -> Option<T> {
+        self.metadata.get()
+    }
+}
+
+impl Props for MatchedRoute {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let route = Self::of(&req).ok_or_else(|| {
+                Error::internal("request was not routed").with_code(WindmillErrorCode::Internal)
+            })?;
+            Ok((req, params, route))
+        })
+    }
+}
+
+/// Names a request in the log, as in `GET /users/{id}`, by the route it matched or else its path.
+/// Taken when the request is routed and only formatted when something is logged.  
+#[derive(Clone)]
+pub(crate) struct RequestLabel {
+    method: Method,
+    pattern: Arc<str>,
+}
+
+impl RequestLabel {
+    pub(crate) fn of(req: &http_types::Request) -> Self {
+        let pattern = match req.local().get::<MatchedRoute>() {
+            Some(route) => route.pattern.clone(),
+            None => req.url().path().into(),
+        };
+        Self {
+            method: req.method(),
+            pattern,
+        }
+    }
+}
+
+impl fmt::Display for RequestLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.pattern)
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    matched::MatchedRoute,
+    observer::{RequestInfo, RequestObserver, ResponseInfo},
+    params::Params,
+};
+use http_types::StatusCode;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The upper bounds, in seconds, of the latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The route label of requests that match no route.
+const UNMATCHED: &str = "unmatched";
+
+/// A [`RequestObserver`](trait.RequestObserver.html) keeping Prometheus metrics of the requests a
+/// router handles, behind the `metrics` feature.
+///
+/// It counts requests by method, route pattern and status class in `windmill_requests_total`,
+/// times them in the `windmill_request_duration_seconds` histogram, and tracks the requests being
+/// handled in `windmill_requests_in_flight`.  Requests that match no route are labelled with the
+/// route `unmatched`, and requests whose client went away before they were answered with the
+/// status `aborted`.  Clones share their metrics, so one clone can be given to
+/// [`Router::observe`](struct.Router.html#method.observe) and another to
+/// [`Router::serve_metrics`](struct.Router.html#method.serve_metrics), or kept to
+/// [`render`](#method.render) the metrics elsewhere.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response};
+/// async fn user(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+///     Ok(Response::from("user"))
+/// }
+///
+/// let metrics = Metrics::new();
+/// let mut router = Router::new();
+/// router.observe(metrics.clone());
+/// router.serve_metrics("/metrics", metrics.clone());
+/// router.add(Method::Get, Route::from("/users/{id}"), user);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     client.get("/users/7").send().await;
+///     client.get("/users/8").send().await;
+///     client.get("/missing").send().await;
+///
+///     let mut res = client.get("/metrics").send().await;
+///     let content_type = res.header(&"content-type".parse().unwrap()).unwrap()[0].to_string();
+///     assert_eq!(content_type, "text/plain; version=0.0.4");
+///     let body = res.body_string().await.unwrap();
+///     assert!(body.contains(r#"windmill_requests_total{method="GET",route="/users/{id}",status="2xx"} 2"#));
+///     assert!(body.contains(r#"windmill_requests_total{method="GET",route="unmatched",status="4xx"} 1"#));
+///     assert!(body.contains(r#"windmill_request_duration_seconds_bucket{method="GET",route="/users/{id}",le="+Inf"} 2"#));
+///     assert!(body.contains(r#"windmill_request_duration_seconds_count{method="GET",route="/users/{id}"} 2"#));
+///     // The request for the metrics is still being handled.
+///     assert!(body.contains("windmill_requests_in_flight 1"));
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Series>>,
+}
+
+/// The metrics, keyed by method and route.
+#[derive(Default)]
+struct Series {
+    in_flight: i64,
+    requests: BTreeMap<(String, String, &'static str), u64>,
+    latencies: BTreeMap<(String, String), Histogram>,
+}
+
+/// The latencies of the requests of one method and route.
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    /// Create a new instance of `Metrics` with no requests counted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let series = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP windmill_requests_total Requests handled, by method, route and status class.\n",
+        );
+        out.push_str("# TYPE windmill_requests_total counter\n");
+        for ((method, route, status), count) in series.requests.iter() {
+            let _ = writeln!(
+                out,
+                "windmill_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP windmill_request_duration_seconds How long requests took, by method and route.\n");
+        out.push_str("# TYPE windmill_request_duration_seconds histogram\n");
+        for ((method, route), histogram) in series.latencies.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "windmill_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "windmill_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "windmill_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "windmill_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        out.push_str("# HELP windmill_requests_in_flight Requests being handled.\n");
+        out.push_str("# TYPE windmill_requests_in_flight gauge\n");
+        let _ = writeln!(out, "windmill_requests_in_flight {}", series.in_flight);
+        out
+    }
+}
+
+impl RequestObserver for Metrics {
+    fn on_request_start(&self, _req: &RequestInfo) {
+        self.inner.lock().unwrap().in_flight += 1;
+    }
+
+    fn on_request_end(&self, req: &RequestInfo, res: &ResponseInfo, latency: Duration) {
+        let method = req.method().to_string();
+        let route = req.route().unwrap_or(UNMATCHED).to_owned();
+        let seconds = latency.as_secs_f64();
+        let status = match res.client_aborted() {
+            true => "aborted",
+            false => res.status_class(),
+        };
+
+        let mut series = self.inner.lock().unwrap();
+        series.in_flight -= 1;
+        *series
+            .requests
+            .entry((method.clone(), route.clone(), status))
+            .or_default() += 1;
+        let histogram = series.latencies.entry((method, route)).or_default();
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.buckets[i] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+}
+
+/// Escape a label value for the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The endpoint of [`Router::serve_metrics`](struct.Router.html#method.serve_metrics), rendering
+/// the metrics the route was given.
+pub(crate) async fn serve_metrics(
+    req: http_types::Request,
+    _params: Params,
+) -> Result<http_types::Response, Error> {
+    let metrics = MatchedRoute::of(&req)
+        .and_then(|route| route.metadata::<Metrics>())
+        .ok_or_else(|| {
+            Error::internal("request was not routed to metrics")
+                .with_code(WindmillErrorCode::Internal)
+        })?;
+    let mut res = http_types::Response::new(StatusCode::Ok);
+    let _ = res.insert_header("content-type", "text/plain; version=0.0.4");
+    res.set_body(metrics.render());
+    Ok(res)
+}
@@ -0,0 +1,146 @@
+use crate::{
+    access_log::ReceivedAt, host::HostCaptures, params::Params, redact::redacted_query,
+    router::Router,
+};
+use std::{future::Future, pin::Pin, sync::Arc};
+
+pub type MiddlewareFuture = Pin<Box<dyn Future<Output = http_types::Response> + Send + Sync>>;
+
+pub(crate) type MiddlewareFn =
+    Box<dyn Fn(http_types::Request, Params, Next) -> MiddlewareFuture + Send + Sync>;
+
+/// The rest of the middleware chain, ending in the endpoint of the matched route.
+///
+/// Passed to every middleware registered with
+/// [`Router::middleware`](struct.Router.html#method.middleware) or
+/// [`Router::pre_routing`](struct.Router.html#method.pre_routing).  A middleware that responds
+/// without calling [`run`](#method.run) short-circuits the chain, and neither later middlewares
+/// nor the endpoint see the request.
+pub struct Next {
+    router: Arc<Router>,
+    routed: bool,
+    index: usize,
+}
+
+impl Next {
+    pub(crate) fn new(router: Arc<Router>) -> Self {
+        Self {
+            router,
+            routed: false,
+            index: 0,
+        }
+    }
+
+    /// Pass the request on to the next middleware, or to the endpoint after the last one.
+    pub fn run(self, mut req: http_types::Request, params: Params) -> MiddlewareFuture {
+        let middlewares = match self.routed {
+            false => self.router.pre_routing_middlewares(),
+            true => self.router.middlewares(),
+        };
+        match (middlewares.get(self.index), self.routed) {
+            (Some(middleware), routed) => {
+                let next = Next {
+                    router: self.router.clone(),
+                    routed,
+                    index: self.index + 1,
+                };
+                middleware(req, params, next)
+            }
+            (None, false) => {
+                let params = self.router.route(&mut req);
+                let next = Next {
+                    router: self.router,
+                    routed: true,
+                    index: 0,
+                };
+                next.run(req, params)
+            }
+            (None, true) => self.router.dispatch(req, params),
+        }
+    }
+}
+
+/// A middleware that reports every request to `sink` as a line with its method, path and query,
+/// response status and how long it took, such as `GET /users/7 200 1.204ms`, followed by the
+/// labels captured by a [host rule](struct.Router.html#method.host) if any, such as
+/// `tenant=acme`.  Requests that match no route are reported too.  The query is redacted with the
+/// server's [redaction policy](struct.Config.html#method.with_redaction).
+/// [`access_log`](fn.access_log.html) reports the same requests as structured records.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Url};
+/// # use std::sync::{Arc, Mutex};
+/// let lines = Arc::new(Mutex::new(vec![]));
+/// let sink = lines.clone();
+///
+/// let mut router = Router::new();
+/// router.middleware(request_log(move |line| sink.lock().unwrap().push(line)));
+/// let router = Arc::new(router);
+///
+/// let req = Request::new(Method::Get, Url::parse("http://localhost/missing").unwrap());
+/// task::block_on(router.respond(req));
+///
+/// let lines = lines.lock().unwrap();
+/// assert!(lines[0].starts_with("GET /missing 404 "), "{}", lines[0]);
+/// ```
+///
+/// Query parameters the redaction policy names are not logged:
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use std::sync::{Arc, Mutex};
+/// let lines = Arc::new(Mutex::new(vec![]));
+/// let sink = lines.clone();
+///
+/// let mut router = Router::new();
+/// router.middleware(request_log(move |line| sink.lock().unwrap().push(line)));
+/// let config = Config::new("127.0.0.1:0").with_redaction(Redaction::new().query_param("token"));
+/// let client = TestClient::new(router).with_config(config);
+///
+/// task::block_on(async {
+///     client.get("/login?token=secret&next=%2F").send().await;
+///     client.get("/login?tok%65n=secret").send().await;
+/// });
+///
+/// let lines = lines.lock().unwrap();
+/// assert!(lines[0].starts_with("GET /login?token=[REDACTED]&next=%2F 404 "), "{}", lines[0]);
+/// assert!(lines[1].starts_with("GET /login?tok%65n=[REDACTED] 404 "), "{}", lines[1]);
+/// assert!(lines.iter().all(|line| !line.contains("secret")));
+/// ```
+pub fn request_log<F>(
+    sink: F,
+) -> impl Fn(http_types::Request, Params, Next) -> MiddlewareFuture + Send + Sync + 'static
+where
+    F: Fn(String) + Send + Sync + 'static,
+{
+    let sink = Arc::new(sink);
+    move |req, params, next| {
+        let sink = sink.clone();
+        let method = req.method();
+        let mut path = req.url().path().to_owned();
+        if let Some(query) = redacted_query(&req) {
+            path.push('?');
+            path.push_str(&query);
+        }
+        let captures = HostCaptures::of(&req);
+        let start = ReceivedAt::of(&req);
+        Box::pin(async move {
+            let res = next.run(req, params).await;
+            let mut line = format!(
+                "{} {} {} {:.3}ms",
+                method,
+                path,
+                res.status() as u16,
+                start.elapsed().as_secs_f64() * 1000.0
+            );
+            if let Some(captures) = captures {
+                line = format!("{} {}", line, captures);
+            }
+            sink(line);
+            res
+        })
+    }
+}
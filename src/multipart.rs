@@ -0,0 +1,326 @@
+use crate::{
+    config::Config,
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    util::{body_too_large, max_body_size},
+};
+use async_std::io::{prelude::*, BufRead};
+use http_types::StatusCode;
+use std::{pin::Pin, sync::Arc};
+
+/// The default size limit of a single part of a multipart body.
+pub(crate) const DEFAULT_PART_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// A props that reads a `multipart/form-data` body one part at a time.
+///
+/// Each part is read into memory when it is reached, up to the
+/// [part limit](struct.Config.html#method.with_multipart_part_limit); a larger part fails with
+/// `413 Payload Too Large`, as does a whole body larger than the
+/// [maximum body size](struct.Config.html#method.with_max_body_size).  A request that is not `multipart/form-data`, has no boundary, or
+/// whose body does not follow its boundary fails with `400 Bad Request`.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[endpoint]
+/// async fn upload(mut form: Multipart) -> Result<Response, Error> {
+///     let mut summary = vec![];
+///     while let Some(part) = form.next_part().await? {
+///         summary.push(format!(
+///             "{} {:?} {:?} {:?}",
+///             part.name(),
+///             part.filename(),
+///             part.content_type(),
+///             part.bytes(),
+///         ));
+///     }
+///     Ok(Text::from(summary.join("\n")).into())
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"upload"), ___upload);
+/// let router = Arc::new(router);
+///
+/// let mut body = b"--XyZ\r\n\
+///     Content-Disposition: form-data; name=\"title\"\r\n\
+///     \r\n\
+///     a cat\r\n\
+///     --XyZ\r\n\
+///     Content-Disposition: form-data; name=\"image\"; filename=\"cat.png\"\r\n\
+///     Content-Type: image/png\r\n\
+///     \r\n".to_vec();
+/// body.extend_from_slice(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x00, b'\n']);
+/// body.extend_from_slice(b"\r\n--XyZ--\r\n");
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+/// req.insert_header("content-type", "multipart/form-data; boundary=XyZ").unwrap();
+/// req.insert_header("content-length", body.len().to_string()).unwrap();
+/// req.set_body(body.clone());
+///
+/// let mut res = task::block_on(router.clone().respond(req));
+/// assert_eq!(res.status(), StatusCode::Ok);
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "\
+///     title None None [97, 32, 99, 97, 116]\n\
+///     image Some(\"cat.png\") Some(\"image/png\") [137, 80, 78, 71, 13, 10, 0, 10]");
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+/// req.insert_header("content-type", "multipart/form-data; boundary=XyZ").unwrap();
+/// req.local_mut().insert(Arc::new(Config::new("127.0.0.1:4000").with_multipart_part_limit(4)));
+/// req.set_body(body);
+/// let res = task::block_on(router.clone().respond(req));
+/// assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+/// req.insert_header("content-type", "multipart/form-data").unwrap();
+/// let res = task::block_on(router.respond(req));
+/// assert_eq!(res.status(), StatusCode::BadRequest);
+/// ```
+pub struct Multipart {
+    reader: Pin<Box<dyn BufRead + Send + Sync>>,
+    delimiter: Vec<u8>,
+    part_limit: u64,
+    body_limit: usize,
+    read: usize,
+    state: State,
+}
+
+#[derive(PartialEq)]
+enum State {
+    Preamble,
+    Parts,
+    Done,
+}
+
+/// A single part of a multipart body.
+#[derive(Debug)]
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    bytes: Vec<u8>,
+}
+
+impl Multipart {
+    /// Read the next part, or `None` once every part has been read.
+    pub async fn next_part(&mut self) -> Result<Option<Part>, Error> {
+        if self.state == State::Preamble {
+            self.skip_preamble().await?;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        let mut name = None;
+        let mut filename = None;
+        let mut content_type = None;
+        loop {
+            let line = self.read_line(8 * 1024).await?;
+            if !line.ends_with(b"\n") {
+                return Err(bad_request("multipart part header is too long"));
+            }
+            let line = String::from_utf8_lossy(trim_newline(&line)).into_owned();
+            if line.is_empty() {
+                break;
+            }
+
+            let (header, value) = match line.find(':') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => return Err(bad_request("malformed part header")),
+            };
+            if header.eq_ignore_ascii_case("content-disposition") {
+                for param in value.split(';').skip(1) {
+                    let mut param = param.splitn(2, '=');
+                    let key = param.next().unwrap_or_default().trim();
+                    let value = param.next().unwrap_or_default().trim().trim_matches('"');
+                    match key {
+                        "name" => name = Some(value.to_owned()),
+                        "filename" => filename = Some(value.to_owned()),
+                        _ => {}
+                    }
+                }
+            } else if header.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.to_owned());
+            }
+        }
+
+        let mut bytes = vec![];
+        loop {
+            let remaining = (self.part_limit + 2).saturating_sub(bytes.len() as u64);
+            let line = self
+                .read_line(remaining + self.delimiter.len() as u64 + 4)
+                .await?;
+
+            let trimmed = trim_newline(&line);
+            if trimmed == &self.delimiter[..] {
+                break;
+            }
+            if trimmed.len() == self.delimiter.len() + 2
+                && trimmed.starts_with(&self.delimiter)
+                && trimmed.ends_with(b"--")
+            {
+                self.state = State::Done;
+                break;
+            }
+
+            bytes.extend_from_slice(&line);
+            if bytes.len() as u64 > self.part_limit + 2 {
+                return Err(Error::new(
+                    StatusCode::PayloadTooLarge,
+                    format!("multipart part is larger than {} bytes", self.part_limit),
+                )
+                .with_code(WindmillErrorCode::BodyTooLarge));
+            }
+        }
+
+        // The line break before the delimiter belongs to the delimiter.
+        if bytes.ends_with(b"\r\n") {
+            bytes.truncate(bytes.len() - 2);
+        } else if bytes.ends_with(b"\n") {
+            bytes.truncate(bytes.len() - 1);
+        }
+
+        Ok(Some(Part {
+            name: name.ok_or_else(|| bad_request("multipart part has no name"))?,
+            filename,
+            content_type,
+            bytes,
+        }))
+    }
+
+    async fn skip_preamble(&mut self) -> Result<(), Error> {
+        loop {
+            let line = self.read_line(8 * 1024).await?;
+            let line = trim_newline(&line);
+            if line == &self.delimiter[..] {
+                self.state = State::Parts;
+                return Ok(());
+            }
+            if line.starts_with(&self.delimiter) && line.ends_with(b"--") {
+                self.state = State::Done;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read up to and including the next `\n`, or at most `limit` bytes, failing if the body ends
+    /// first.
+    async fn read_line(&mut self, limit: u64) -> Result<Vec<u8>, Error> {
+        let mut line = vec![];
+        let read = (&mut self.reader)
+            .take(limit)
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(|e| bad_request(&format!("unable to read multipart body: {}", e)))?;
+
+        self.read += read;
+        if self.read > self.body_limit {
+            return Err(body_too_large(self.body_limit));
+        }
+
+        // A line cut short by `limit` is handed back for the caller to reject as too large.
+        if read == 0 || (!line.ends_with(b"\n") && (read as u64) < limit) {
+            return Err(bad_request(
+                "multipart body ended before its closing boundary",
+            ));
+        }
+        Ok(line)
+    }
+}
+
+impl Part {
+    /// The name of the form field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the uploaded file, if the part is a file.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The content type of the part, if it declared one.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The contents of the part.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the contents of the part.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Props for Multipart {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let boundary = req
+                .header(&http_types::headers::CONTENT_TYPE)
+                .and_then(|values| values.last())
+                .and_then(|value| boundary(value.as_str()))
+                .ok_or_else(|| bad_request("expected multipart/form-data with a boundary"))?;
+
+            let part_limit = req
+                .local()
+                .get::<Arc<Config>>()
+                .map(|config| config.multipart_part_limit())
+                .unwrap_or(DEFAULT_PART_LIMIT);
+
+            let body_limit = max_body_size(&req);
+            if matches!(req.len(), Some(len) if len > body_limit) {
+                return Err(body_too_large(body_limit));
+            }
+
+            let multipart = Multipart {
+                reader: Box::pin(req.take_body()),
+                delimiter: format!("--{}", boundary).into_bytes(),
+                part_limit,
+                body_limit,
+                read: 0,
+                state: State::Preamble,
+            };
+            Ok((req, params, multipart))
+        })
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type.
+fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+
+    params
+        .filter_map(|param| {
+            let mut param = param.splitn(2, '=');
+            match param.next()?.trim() {
+                "boundary" => Some(param.next()?.trim().trim_matches('"').to_owned()),
+                _ => None,
+            }
+        })
+        .find(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn bad_request(msg: &str) -> Error {
+    Error::bad_request(msg).with_code(WindmillErrorCode::DeserializeFailed)
+}
@@ -0,0 +1,497 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    responder::IntoResponse,
+    util::{fmt_http_date, parse_http_date},
+};
+use async_std::{
+    fs::File,
+    io::{self, BufReader, Read, SeekFrom},
+    prelude::*,
+};
+use http_types::{mime, Method, Mime, StatusCode};
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A file on disk that can be returned from an endpoint.
+///
+/// The file is streamed to the client rather than read into memory, and the response carries the
+/// `Content-Length`, `Last-Modified` and `Content-Type` of the file.  Sent with
+/// [`respond`](#method.respond) it also answers `If-Modified-Since` and `Range` requests.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use http_types::Response;
+/// #[endpoint]
+/// async fn report() -> Result<Response, Error> {
+///     let file = NamedFile::open("/tmp/report.csv").await?;
+///     Ok(file.into())
+/// }
+/// ```
+pub struct NamedFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    modified: Option<SystemTime>,
+    content_type: Mime,
+    delete_after_send: bool,
+}
+
+impl NamedFile {
+    /// Open a file whose path was supplied by the client.
+    ///
+    /// A file that does not exist results in a `404 Not Found` error.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(path.as_ref(), StatusCode::NotFound).await
+    }
+
+    /// Open a file whose path was produced by the server itself, such as a file just generated in
+    /// a temp directory.
+    ///
+    /// A file that does not exist is a bug on the server, so it results in a
+    /// `500 Internal Server Error` rather than a `404 Not Found`.
+    pub async fn open_internal(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(path.as_ref(), StatusCode::InternalServerError).await
+    }
+
+    async fn open_with(path: &Path, missing: StatusCode) -> Result<Self, Error> {
+        let file = File::open(path).await.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                Error::new(missing, "file not found").with_code(WindmillErrorCode::FileNotFound)
+            }
+            _ => Error::internal(format!("{}", e)).with_code(WindmillErrorCode::Internal),
+        })?;
+
+        let metadata = file.metadata().await.map_err(|e| {
+            Error::internal(format!("{}", e)).with_code(WindmillErrorCode::Internal)
+        })?;
+
+        Ok(Self {
+            path: path.into(),
+            file,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            content_type: guess_mime(path),
+            delete_after_send: false,
+        })
+    }
+
+    /// Override the `Content-Type` guessed from the file extension.
+    pub fn content_type(mut self, mime: Mime) -> Self {
+        self.content_type = mime;
+        self
+    }
+
+    /// Delete the file from disk once the response body has been sent, or dropped because the
+    /// client went away mid-transfer.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # async_std::task::block_on(async {
+    /// let path = std::env::temp_dir().join("windmill-named-file-doc.txt");
+    /// std::fs::write(&path, "generated").unwrap();
+    ///
+    /// let file = NamedFile::open_internal(&path).await.unwrap().delete_after_send();
+    /// let res: http_types::Response = file.into();
+    /// assert!(path.exists());
+    ///
+    /// assert_eq!(res.body_string().await.unwrap(), "generated");
+    /// assert!(!path.exists());
+    /// # });
+    /// ```
+    ///
+    /// A response that is dropped before the body is fully read still removes the file:
+    /// ```
+    /// # use windmill::*;
+    /// # async_std::task::block_on(async {
+    /// let path = std::env::temp_dir().join("windmill-named-file-doc-abort.txt");
+    /// std::fs::write(&path, "generated").unwrap();
+    ///
+    /// let file = NamedFile::open_internal(&path).await.unwrap().delete_after_send();
+    /// let res: http_types::Response = file.into();
+    ///
+    /// drop(res);
+    /// assert!(!path.exists());
+    /// # });
+    /// ```
+    pub fn delete_after_send(mut self) -> Self {
+        self.delete_after_send = true;
+        self
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The length of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The time the file was last modified, if the platform reports it.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Answer a request with the file, as told by its [`FileConditions`](struct.FileConditions.html).
+    ///
+    /// A `GET` or `HEAD` request whose `If-Modified-Since` is no earlier than the modification
+    /// time of the file is answered with `304 Not Modified`.  A `GET` request with a `Range` of
+    /// one byte range gets those bytes in a `206 Partial Content`, or a
+    /// `416 Range Not Satisfiable` if the range starts past the end of the file.  Other requests,
+    /// and requests for several ranges, get the whole file.  The response advertises
+    /// `Accept-Ranges: bytes`.  A file to [delete after it is sent](#method.delete_after_send) is
+    /// deleted once the response is sent, whichever it is.
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// #[endpoint]
+    /// async fn download(conditions: FileConditions) -> Result<Response, Error> {
+    ///     let path = std::env::temp_dir().join("windmill-named-file-respond.txt");
+    ///     NamedFile::open(path).await?.respond(&conditions).await
+    /// }
+    ///
+    /// let path = std::env::temp_dir().join("windmill-named-file-respond.txt");
+    /// std::fs::write(&path, "0123456789abcdef").unwrap();
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"download"), ___download);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     let header = |res: &Response, name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+    ///
+    ///     let mut res = client.get("/download").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     assert_eq!(header(&res, "accept-ranges").unwrap(), "bytes");
+    ///     let modified = header(&res, "last-modified").unwrap();
+    ///     assert_eq!(res.body_string().await.unwrap(), "0123456789abcdef");
+    ///
+    ///     let res = client.get("/download").header("if-modified-since", &modified).send().await;
+    ///     assert_eq!(res.status(), StatusCode::NotModified);
+    ///     let res = client
+    ///         .get("/download")
+    ///         .header("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")
+    ///         .send()
+    ///         .await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///
+    ///     let mut res = client.get("/download").header("range", "bytes=4-7").send().await;
+    ///     assert_eq!(res.status(), StatusCode::PartialContent);
+    ///     assert_eq!(header(&res, "content-range").unwrap(), "bytes 4-7/16");
+    ///     assert_eq!(header(&res, "content-length").unwrap(), "4");
+    ///     assert_eq!(res.body_string().await.unwrap(), "4567");
+    ///
+    ///     let mut res = client.get("/download").header("range", "bytes=-3").send().await;
+    ///     assert_eq!(header(&res, "content-range").unwrap(), "bytes 13-15/16");
+    ///     assert_eq!(res.body_string().await.unwrap(), "def");
+    ///     let mut res = client.get("/download").header("range", "bytes=10-99").send().await;
+    ///     assert_eq!(res.body_string().await.unwrap(), "abcdef");
+    ///
+    ///     let res = client.get("/download").header("range", "bytes=16-").send().await;
+    ///     assert_eq!(res.status(), StatusCode::RequestedRangeNotSatisfiable);
+    ///     assert_eq!(header(&res, "content-range").unwrap(), "bytes */16");
+    ///
+    ///     // Several ranges, or a malformed one, get the whole file.
+    ///     let res = client.get("/download").header("range", "bytes=0-1,4-5").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     let res = client.get("/download").header("range", "lines=1-2").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    /// });
+    /// ```
+    ///
+    /// A range of a file deleted after it is sent:
+    /// ```
+    /// # use windmill::*;
+    /// # use http_types::{Method, Request, StatusCode, Url};
+    /// # async_std::task::block_on(async {
+    /// let path = std::env::temp_dir().join("windmill-named-file-range-delete.txt");
+    /// std::fs::write(&path, "generated report").unwrap();
+    ///
+    /// let mut req = Request::new(Method::Get, Url::parse("http://localhost/report").unwrap());
+    /// req.insert_header("range", "bytes=10-").unwrap();
+    /// let conditions = FileConditions::of(&req);
+    ///
+    /// let file = NamedFile::open_internal(&path).await.unwrap().delete_after_send();
+    /// let res = file.respond(&conditions).await.unwrap();
+    /// assert_eq!(res.status(), StatusCode::PartialContent);
+    /// assert!(path.exists());
+    ///
+    /// assert_eq!(res.body_string().await.unwrap(), "report");
+    /// assert!(!path.exists());
+    ///
+    /// // Out of range, the file is deleted all the same.
+    /// std::fs::write(&path, "generated report").unwrap();
+    /// let mut req = Request::new(Method::Get, Url::parse("http://localhost/report").unwrap());
+    /// req.insert_header("range", "bytes=100-").unwrap();
+    /// let file = NamedFile::open_internal(&path).await.unwrap().delete_after_send();
+    /// let e = file.respond(&FileConditions::of(&req)).await.unwrap_err();
+    /// assert_eq!(e.code(), StatusCode::RequestedRangeNotSatisfiable);
+    /// assert!(!path.exists());
+    /// # });
+    /// ```
+    pub async fn respond(self, conditions: &FileConditions) -> Result<http_types::Response, Error> {
+        let (len, modified) = (self.len, self.modified);
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let not_modified = match (conditions.if_modified_since, modified) {
+            (Some(since), Some(modified)) => conditions.safe && secs(modified) <= secs(since),
+            _ => false,
+        };
+        let range = match conditions.range.as_deref() {
+            // A range is only for the version of the file the client has, if it names one.
+            Some(_) if !conditions.if_range_holds(modified) => None,
+            Some(range) => byte_range(range, len),
+            None => None,
+        };
+
+        let mut res = match (not_modified, range) {
+            (true, _) => {
+                let mut res = http_types::Response::new(StatusCode::NotModified);
+                if let Some(modified) = modified {
+                    let _ = res.insert_header("last-modified", fmt_http_date(modified));
+                }
+                // Dropped unsent, which deletes the file if asked to.
+                drop(self.into_reader());
+                return Ok(res);
+            }
+            (false, Some(None)) => {
+                return Err(Error::new(
+                    StatusCode::RequestedRangeNotSatisfiable,
+                    "range not satisfiable",
+                )
+                .with_code(WindmillErrorCode::RangeNotSatisfiable)
+                .with_header("content-range", format!("bytes */{}", len)));
+            }
+            (false, Some(Some((first, last)))) => {
+                let content_type = self.content_type.clone();
+                let mut reader = self.into_reader();
+                reader
+                    .file
+                    .seek(SeekFrom::Start(first))
+                    .await
+                    .map_err(|e| {
+                        Error::internal(format!("{}", e)).with_code(WindmillErrorCode::Internal)
+                    })?;
+                let mut res = http_types::Response::new(StatusCode::PartialContent);
+                let _ =
+                    res.insert_header("content-range", format!("bytes {}-{}/{}", first, last, len));
+                let _ = res.set_content_type(content_type);
+                if let Some(modified) = modified {
+                    let _ = res.insert_header("last-modified", fmt_http_date(modified));
+                }
+                let n = last - first + 1;
+                res.set_body(http_types::Body::from_reader(
+                    BufReader::new(reader.take(n)),
+                    Some(n as usize),
+                ));
+                res
+            }
+            (false, None) => self.into(),
+        };
+        let _ = res.insert_header("accept-ranges", "bytes");
+        Ok(res)
+    }
+
+    fn into_reader(self) -> FileReader {
+        FileReader {
+            file: self.file,
+            path: self.path,
+            delete_after_send: self.delete_after_send,
+        }
+    }
+}
+
+/// The `If-Modified-Since`, `Range` and `If-Range` headers of a request, which decide how much
+/// of a file [`NamedFile::respond`](struct.NamedFile.html#method.respond) sends.
+///
+/// Endpoints take it as an argument.  A malformed `If-Modified-Since` is ignored, as is one sent
+/// alongside `If-None-Match`, which takes precedence.  
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileConditions {
+    safe: bool,
+    if_modified_since: Option<SystemTime>,
+    range: Option<String>,
+    if_range: Option<String>,
+}
+
+impl FileConditions {
+    /// Get the conditions of a request.
+    pub fn of(req: &http_types::Request) -> Self {
+        let header = |name: &str| {
+            req.header(&name.parse().unwrap())
+                .map(|values| values[0].as_str().to_owned())
+        };
+        let if_modified_since = match header("if-none-match") {
+            Some(_) => None,
+            None => header("if-modified-since").and_then(|value| parse_http_date(&value)),
+        };
+        Self {
+            safe: matches!(req.method(), Method::Get | Method::Head),
+            if_modified_since,
+            range: header("range").filter(|_| req.method() == Method::Get),
+            if_range: header("if-range"),
+        }
+    }
+
+    /// The time of the `If-Modified-Since` header, if the request has a valid one.
+    pub fn if_modified_since(&self) -> Option<SystemTime> {
+        self.if_modified_since
+    }
+
+    /// The `Range` header of a `GET` request, if it has one.
+    pub fn range(&self) -> Option<&str> {
+        self.range.as_deref()
+    }
+
+    /// Whether the `If-Range` header, if any, names the version of the file last modified at
+    /// `modified`.  Only dates are compared, a file has no entity tag.
+    fn if_range_holds(&self, modified: Option<SystemTime>) -> bool {
+        match (self.if_range.as_deref(), modified) {
+            (None, _) => true,
+            (Some(date), Some(modified)) => date.trim() == fmt_http_date(modified),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl Props for FileConditions {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let conditions = Self::of(&req);
+            Ok((req, params, conditions))
+        })
+    }
+}
+
+/// The byte range of a `Range` header with one range, as its first and last byte in a file of
+/// `len` bytes, `Some(None)` if it starts past the end of the file, or `None` for a header the
+/// whole file is sent for: one that is malformed or has several ranges.
+fn byte_range(value: &str, len: u64) -> Option<Option<(u64, u64)>> {
+    let range = value.trim().strip_prefix("bytes=")?.trim();
+    if range.contains(',') {
+        return None;
+    }
+    let dash = range.find('-')?;
+    let (first, last) = (range[..dash].trim(), range[dash + 1..].trim());
+    if first.is_empty() {
+        let suffix: u64 = last.parse().ok()?;
+        return match suffix == 0 || len == 0 {
+            true => Some(None),
+            false => Some(Some((len.saturating_sub(suffix), len - 1))),
+        };
+    }
+    let first: u64 = first.parse().ok()?;
+    let last = match last.is_empty() {
+        true => u64::MAX,
+        false => last.parse().ok()?,
+    };
+    if last < first {
+        return None;
+    }
+    match first < len {
+        true => Some(Some((first, last.min(len - 1)))),
+        false => Some(None),
+    }
+}
+
+impl IntoResponse for NamedFile {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(self.into())
+    }
+}
+
+impl From<NamedFile> for http_types::Response {
+    fn from(file: NamedFile) -> Self {
+        let mut res = http_types::Response::new(StatusCode::Ok);
+        let len = file.len as usize;
+
+        if let Some(modified) = file.modified {
+            let _ = res.insert_header("last-modified", fmt_http_date(modified));
+        }
+        let _ = res.set_content_type(file.content_type.clone());
+
+        let reader = file.into_reader();
+        res.set_body(http_types::Body::from_reader(
+            BufReader::new(reader),
+            Some(len),
+        ));
+        res
+    }
+}
+
+/// Reads the file for the response body, removing it from disk when dropped if requested.
+struct FileReader {
+    file: File,
+    path: PathBuf,
+    delete_after_send: bool,
+}
+
+impl Read for FileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.file).poll_read(cx, buf)
+    }
+}
+
+impl Drop for FileReader {
+    fn drop(&mut self) {
+        if self.delete_after_send {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn guess_mime(path: &Path) -> Mime {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let essence = match ext.as_deref() {
+        Some("html") | Some("htm") => return mime::HTML,
+        Some("css") => return mime::CSS,
+        Some("js") | Some("mjs") => return mime::JAVASCRIPT,
+        Some("json") => return mime::JSON,
+        Some("txt") => return mime::PLAIN,
+        Some("csv") => "text/csv",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("wasm") => "application/wasm",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => return mime::BYTE_STREAM,
+    };
+
+    Mime::from_str(essence).unwrap_or(mime::BYTE_STREAM)
+}
@@ -0,0 +1,138 @@
+use http_types::{Method, StatusCode};
+use std::time::Duration;
+
+/// A hook told about every request a router handles, for exporting metrics.
+///
+/// Register one with [`Router::observe`](struct.Router.html#method.observe).
+/// `on_request_start` runs before the middlewares when a request arrives. `on_request_end` runs
+/// once the response is ready, with how long the request took. Each runs exactly once per
+/// request. That includes requests that match no route, requests answered by the router itself
+/// such as redirects and CORS preflights, and endpoints that panic, which are reported with the
+/// `500 Internal Server Error` they were answered with.  Observers run in the order they were
+/// added, and should return quickly, as the response waits for them.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use std::{sync::{Arc, Mutex}, time::Duration};
+/// async fn user(_req: http_types::Request, params: Params) -> Result<Response, Error> {
+///     Ok(Response::from(params.get("id").unwrap().to_owned()))
+/// }
+///
+/// async fn broken(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+///     panic!("broken");
+/// }
+///
+/// #[derive(Default)]
+/// struct Seen {
+///     started: Mutex<Vec<Option<String>>>,
+///     ended: Mutex<Vec<(Option<String>, StatusCode, &'static str)>>,
+/// }
+///
+/// impl RequestObserver for Arc<Seen> {
+///     fn on_request_start(&self, req: &RequestInfo) {
+///         self.started.lock().unwrap().push(req.route().map(str::to_owned));
+///     }
+///
+///     fn on_request_end(&self, req: &RequestInfo, res: &ResponseInfo, _latency: Duration) {
+///         let route = req.route().map(str::to_owned);
+///         self.ended.lock().unwrap().push((route, res.status(), res.status_class()));
+///     }
+/// }
+///
+/// let seen = Arc::new(Seen::default());
+/// let mut router = Router::new();
+/// router.observe(seen.clone());
+/// router.add(Method::Get, Route::from("/users/{id}"), user);
+/// router.add(Method::Get, Route::from("/broken"), broken);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     client.get("/users/7").send().await;
+///     client.get("/users/8").send().await;
+///     client.get("/missing").send().await;
+///     client.get("/broken").send().await;
+/// });
+///
+/// let route = |pattern: &str| Some(pattern.to_owned());
+/// assert_eq!(
+///     *seen.started.lock().unwrap(),
+///     vec![route("/users/{id}"), route("/users/{id}"), None, route("/broken")],
+/// );
+/// assert_eq!(
+///     *seen.ended.lock().unwrap(),
+///     vec![
+///         (route("/users/{id}"), StatusCode::Ok, "2xx"),
+///         (route("/users/{id}"), StatusCode::Ok, "2xx"),
+///         (None, StatusCode::NotFound, "4xx"),
+///         (route("/broken"), StatusCode::InternalServerError, "5xx"),
+///     ],
+/// );
+/// ```
+pub trait RequestObserver: Send + Sync + 'static {
+    /// Called when a request arrives, before any middleware has run.
+    fn on_request_start(&self, _req: &RequestInfo) {}
+
+    /// Called once the response to a request is ready, with how long the request took.
+    fn on_request_end(&self, req: &RequestInfo, res: &ResponseInfo, latency: Duration);
+}
+
+/// A request as seen by a [`RequestObserver`](trait.RequestObserver.html).
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    pub(crate) method: Method,
+    pub(crate) route: Option<String>,
+}
+
+impl RequestInfo {
+    /// The method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The pattern of the route the request matches, such as `/users/{id}`, if it matches one.
+    /// Unlike the path, it has a bounded number of values, so it can label metrics.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+}
+
+/// A response as seen by a [`RequestObserver`](trait.RequestObserver.html).
+#[derive(Clone, Debug)]
+pub struct ResponseInfo {
+    pub(crate) status: StatusCode,
+    pub(crate) size: Option<usize>,
+    pub(crate) client_aborted: bool,
+}
+
+impl ResponseInfo {
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The class of the status, from `1xx` to `5xx`.
+    pub fn status_class(&self) -> &'static str {
+        match self.status as u16 {
+            100..=199 => "1xx",
+            200..=299 => "2xx",
+            300..=399 => "3xx",
+            400..=499 => "4xx",
+            _ => "5xx",
+        }
+    }
+
+    /// The length of the response body, if it is known before the body is sent.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Whether the client closed the connection before the endpoint returned, which
+    /// [cancelled the endpoint](struct.Router.html#method.client_aborts).  The response is never
+    /// sent.
+    pub fn client_aborted(&self) -> bool {
+        self.client_aborted
+    }
+}
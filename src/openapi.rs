@@ -0,0 +1,228 @@
+use crate::route::{Route, SegmentType};
+use http_types::Method;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// The title, version and description of an API, at the top of its
+/// [OpenAPI document](struct.Router.html#method.openapi).
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiInfo {
+    title: String,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl ApiInfo {
+    /// Describe the API by its title and its version, such as `"1.2.0"`.
+    pub fn new(title: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            version: version.into(),
+            description: None,
+        }
+    }
+
+    /// Set a longer description of the API.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Get the title.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Get the version.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Get the description.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+}
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// What a route tells the OpenAPI document about itself.
+#[doc(hidden)]
+#[derive(Clone, Copy, Default)]
+pub struct RouteDoc {
+    pub(crate) summary: Option<&'static str>,
+    pub(crate) request: Option<SchemaFn>,
+    pub(crate) response: Option<SchemaFn>,
+}
+
+pub(crate) fn schema_of<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+/// The OpenAPI 3 document of `routes`.
+pub(crate) fn document<'r>(
+    info: &ApiInfo,
+    routes: impl Iterator<Item = (&'r Method, &'r Route)>,
+) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    for (method, route) in routes {
+        let method = match operation_method(*method) {
+            Some(method) => method,
+            None => continue,
+        };
+
+        let mut operation = Map::new();
+        if let Some(name) = route.name {
+            operation.insert("operationId".into(), json!(name));
+        }
+        if let Some(summary) = route.doc.summary {
+            operation.insert("summary".into(), json!(summary));
+        }
+
+        let request = match (route.doc.request, method) {
+            (Some(schema), _) => Some(json!(schema(&mut gen))),
+            (None, "post") | (None, "put") | (None, "patch") => Some(json!({ "type": "object" })),
+            (None, _) => None,
+        };
+        if let Some(schema) = request {
+            operation.insert(
+                "requestBody".into(),
+                json!({
+                    "required": true,
+                    "content": { "application/json": { "schema": schema } },
+                }),
+            );
+        }
+        let response = match route.doc.response {
+            Some(schema) => json!(schema(&mut gen)),
+            None => json!({ "type": "object" }),
+        };
+        operation.insert(
+            "responses".into(),
+            json!({
+                "200": {
+                    "description": "OK",
+                    "content": { "application/json": { "schema": response } },
+                },
+            }),
+        );
+
+        let mut dynamic_segments = route.dynamic_segments.clone();
+        dynamic_segments.sort_by_key(|segment| segment.position);
+        // Path params are always required, so a route with optional segments is one path per
+        // number of segments it matches.
+        for len in route.min_len()..=route.len() {
+            let mut operation = operation.clone();
+            // Operation ids are unique in a document, so only the full path has it.
+            if len < route.len() {
+                operation.remove("operationId");
+            }
+            let parameters: Vec<Value> = dynamic_segments
+                .iter()
+                .filter(|segment| segment.position < len)
+                .map(|segment| (segment.name, segment.ty))
+                .chain(
+                    route
+                        .rest
+                        .filter(|_| len == route.len())
+                        .map(|rest| (rest, None)),
+                )
+                .map(|(name, ty)| {
+                    json!({
+                        "name": name,
+                        "in": "path",
+                        "required": true,
+                        "schema": param_schema(ty),
+                    })
+                })
+                .collect();
+            if !parameters.is_empty() {
+                operation.insert("parameters".into(), json!(parameters));
+            }
+
+            let path = route.path_template(len);
+            if let Value::Object(operations) = paths.entry(path).or_insert_with(|| json!({})) {
+                operations.insert(method.into(), Value::Object(operation));
+            }
+        }
+    }
+
+    let mut document = json!({
+        "openapi": "3.0.3",
+        "info": info,
+        "paths": paths,
+    });
+    if !gen.definitions().is_empty() {
+        document["components"] = json!({ "schemas": gen.definitions() });
+    }
+    document
+}
+
+/// The key of the operations of `method` in a path item, for the methods OpenAPI describes.
+fn operation_method(method: Method) -> Option<&'static str> {
+    match method {
+        Method::Get => Some("get"),
+        Method::Put => Some("put"),
+        Method::Post => Some("post"),
+        Method::Delete => Some("delete"),
+        Method::Options => Some("options"),
+        Method::Head => Some("head"),
+        Method::Patch => Some("patch"),
+        Method::Trace => Some("trace"),
+        _ => None,
+    }
+}
+
+/// The schema of a path parameter, from the type of its segment for the primitive types and a
+/// string for everything else.
+fn param_schema(ty: Option<SegmentType>) -> Value {
+    match ty.map(|ty| ty.name) {
+        Some("u8") | Some("u16") | Some("u32") | Some("u64") | Some("u128") | Some("usize") => {
+            json!({ "type": "integer", "minimum": 0 })
+        }
+        Some("i8") | Some("i16") | Some("i32") | Some("i64") | Some("i128") | Some("isize") => {
+            json!({ "type": "integer" })
+        }
+        Some("f32") | Some("f64") => json!({ "type": "number" }),
+        Some("bool") => json!({ "type": "boolean" }),
+        _ => json!({ "type": "string" }),
+    }
+}
+
+/// A page showing the document at `url` with Swagger UI, loaded from a CDN.
+pub(crate) fn swagger_ui(title: &str, url: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>
+window.ui = SwaggerUIBundle({{ url: {url}, dom_id: "#swagger-ui" }});
+</script>
+</body>
+</html>
+"##,
+        title = escape_html(title),
+        url = json!(url),
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,38 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Resolves to the output of a future, or to the payload of a panic while polling it.
+///
+/// The future is asserted unwind safe: once it panics it is never polled again, and whatever it
+/// shared with other requests is expected to recover on its own, like the poisoned locks the
+/// router already works through.
+pub(crate) struct CatchUnwind<F>(pub(crate) F);
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &mut self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| Pin::new(inner).poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+/// The message a panic was raised with.
+pub(crate) fn message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&'static str>() {
+        Some(msg) => msg,
+        None => payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or("Box<dyn Any>"),
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    props::{Props, PropsFuture},
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{self, Display},
+    slice,
+    str::FromStr,
+    sync::Arc,
+    vec,
+};
+
+/// The values of the dynamic segments of the route that matched a request, keyed by name.  
+///
+/// The segments of a route that are given a type, such as `route!(/"users"/id: u64)`, are also
+/// kept in their parsed form, see [`typed`](#method.typed).  Endpoints can take the params as an
+/// argument.  
+///
+/// Routes have a handful of params at most, so they are kept in the order of the segments rather
+/// than hashed, and the params of a route without dynamic segments allocate nothing.  
+#[derive(Clone, Default)]
+pub struct Params {
+    values: Vec<(&'static str, String)>,
+    typed: Vec<(&'static str, Arc<dyn Any + Send + Sync>)>,
+}
+
+impl Params {
+    /// Get the raw value of a parameter.  
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Get the parsed value of a typed parameter.  
+    ///
+    /// `None` when the parameter is missing, was not typed in the route, or was typed as
+    /// something other than `T`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response};
+    /// #[endpoint]
+    /// async fn post(path: Params) -> Result<Response, Error> {
+    ///     let user_id: u64 = *path.typed("user_id").unwrap();
+    ///     let post_id: &u64 = path.typed("post_id").unwrap();
+    ///     assert!(path.typed::<String>("user_id").is_none());
+    ///     assert!(path.typed::<String>("slug").is_none());
+    ///     Ok(Response::from(format!("{} {} {}", user_id + 1, post_id, path.get("slug").unwrap())))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/user_id: u64/"posts"/post_id: u64/slug), ___post);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     let mut res = client.get("/users/7/posts/3/hello").send().await;
+    ///     assert_eq!(res.body_string().await.unwrap(), "8 3 hello");
+    /// });
+    /// ```
+    pub fn typed<T: Any>(&self, name: &str) -> Option<&T> {
+        let (_, value) = self.typed.iter().find(|(key, _)| *key == name)?;
+        value.downcast_ref()
+    }
+
+    /// Parse the value of a parameter.  
+    ///
+    /// A missing parameter means the route has no such segment, which is a bug in the server and
+    /// results in a `500 Internal Server Error`.  A value that does not parse results in a
+    /// `400 Bad Request` naming the parameter.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::Params;
+    /// # use http_types::StatusCode;
+    /// let mut params = Params::default();
+    /// params.insert("id", "5".into());
+    /// params.insert("name", "five".into());
+    ///
+    /// let id: u64 = params.parse("id").unwrap();
+    /// assert_eq!(id, 5);
+    ///
+    /// assert_eq!(params.parse::<u64>("name").unwrap_err().code(), StatusCode::BadRequest);
+    /// assert_eq!(params.parse::<u64>("missing").unwrap_err().code(), StatusCode::InternalServerError);
+    /// ```
+    pub fn parse<T>(&self, name: &str) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(name).ok_or_else(|| {
+            Error::internal(format!("param `{}` does not exist", name))
+                .with_code(WindmillErrorCode::Internal)
+        })?;
+
+        value.parse().map_err(|e| {
+            Error::bad_request(format!("invalid param `{}`: {}", name, e))
+                .with_code(WindmillErrorCode::DeserializeFailed)
+        })
+    }
+
+    /// Set the value of a parameter, returning the previous value.  Any parsed value of the
+    /// parameter is dropped.  
+    pub fn insert(&mut self, name: &'static str, value: String) -> Option<String> {
+        self.typed.retain(|(key, _)| *key != name);
+        match self.values.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            None => {
+                self.values.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// Set the parsed value of a parameter whose raw value is already present.  
+    pub(crate) fn insert_typed(&mut self, name: &'static str, value: Arc<dyn Any + Send + Sync>) {
+        self.typed.retain(|(key, _)| *key != name);
+        self.typed.push((name, value));
+    }
+
+    /// Whether a parameter is present.  
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Iterate over the names and values of the parameters, in the order they were inserted.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::Params;
+    /// let mut params = Params::default();
+    /// params.insert("user_id", "7".into());
+    /// params.insert("post_id", "3".into());
+    /// params.insert("user_id", "8".into());
+    ///
+    /// let names: Vec<_> = params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    /// assert_eq!(names, ["user_id=8", "post_id=3"]);
+    /// ```
+    pub fn iter(&self) -> slice::Iter<'_, (&'static str, String)> {
+        self.values.iter()
+    }
+
+    /// The number of parameters.  
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether there are no parameters.  
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Params").field(&self.values).finish()
+    }
+}
+
+impl PartialEq for Params {
+    /// Params are equal when their raw values are, parsed values being derived from them.  
+    /// The order they were inserted in does not matter.  
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(name, value)| other.get(name) == Some(value))
+    }
+}
+
+impl From<HashMap<&'static str, String>> for Params {
+    fn from(values: HashMap<&'static str, String>) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+            typed: Vec::new(),
+        }
+    }
+}
+
+impl Props for Params {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let copy = params.clone();
+            Ok((req, params, copy))
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a Params {
+    type Item = &'a (&'static str, String);
+    type IntoIter = slice::Iter<'a, (&'static str, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.iter()
+    }
+}
+
+impl IntoIterator for Params {
+    type Item = (&'static str, String);
+    type IntoIter = vec::IntoIter<(&'static str, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
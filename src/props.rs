@@ -1,5 +1,10 @@
-use crate::{error::Error, params::Params};
-use std::{future::Future, pin::Pin};
+use crate::{
+    error::{Error, WindmillErrorCode},
+    matched::MatchedRoute,
+    params::Params,
+    state::State,
+};
+use std::{future::Future, ops::Deref, pin::Pin, sync::Arc};
 
 pub type PropsFuture<T> = Pin<
     Box<
@@ -11,6 +16,72 @@ pub type PropsFuture<T> = Pin<
 >;
 
 /// A trait implemented by functions that can be used as props.  
+///
+/// The props of an endpoint are built one after the other, in the order of its arguments.  Each
+/// receives the request and params returned by the one before, so changes it makes to them are
+/// seen by the next ones, and the first error is the response: the props after it and the
+/// endpoint never run.  Put the props that reject requests, like authentication, before the ones
+/// that read the body.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// struct Auth;
+///
+/// impl Props for Auth {
+///     type Fut = PropsFuture<Self>;
+///     fn call(req: Request, mut params: Params) -> Self::Fut {
+///         Box::pin(async move {
+///             match req.header(&"authorization".parse().unwrap()) {
+///                 Some(values) => {
+///                     params.insert("user", values[0].as_str().to_owned());
+///                     Ok((req, params, Auth))
+///                 }
+///                 None => Err(Error::unauthorized("who are you")),
+///             }
+///         })
+///     }
+/// }
+///
+/// /// Sees the params `Auth` left behind, and must never run for rejected requests.
+/// struct Greeting(String);
+///
+/// impl Props for Greeting {
+///     type Fut = PropsFuture<Self>;
+///     fn call(req: Request, params: Params) -> Self::Fut {
+///         Box::pin(async move {
+///             let user = params.get("user").cloned().expect("runs after Auth accepted");
+///             Ok((req, params, Greeting(format!("hello {}", user))))
+///         })
+///     }
+/// }
+///
+/// #[endpoint]
+/// async fn greet(_auth: Auth, greeting: Greeting) -> Result<Response, Error> {
+///     Ok(Response::from(greeting.0))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"greet"), ___greet);
+/// let router = Arc::new(router);
+///
+/// let get = |user: Option<&str>| {
+///     let mut req = Request::new(Method::Get, Url::parse("http://localhost/greet").unwrap());
+///     if let Some(user) = user {
+///         req.insert_header("authorization", user).unwrap();
+///     }
+///     let res = task::block_on(router.clone().respond(req));
+///     (res.status(), task::block_on(res.body_string()).unwrap())
+/// };
+///
+/// assert_eq!(get(Some("ada")), (StatusCode::Ok, "hello ada".into()));
+/// // `Greeting` would panic, and the router would answer 500, had it run.
+/// assert_eq!(get(None), (StatusCode::Unauthorized, "\"who are you\"".into()));
+/// ```
 pub trait Props: Sized {
     type Fut: Future<Output = Result<(http_types::Request, Params, Self), Error>>
         + Unpin
@@ -19,3 +90,137 @@ pub trait Props: Sized {
 
     fn call(_: http_types::Request, _: Params) -> Self::Fut;
 }
+
+/// Props that hold configuration, such as a key to verify tokens with or a size limit.  
+///
+/// An instance is attached to a route with [`Route::props`](struct.Route.html#method.props), or
+/// to every route by registering it as `Arc<P>` [state](struct.Router.html#method.state), and
+/// endpoints receive its output by taking [`With<P>`](struct.With.html) as an argument.  
+pub trait StatefulProps: Send + Sync + 'static {
+    type Output;
+
+    fn call(&self, _: http_types::Request, _: Params) -> PropsFuture<Self::Output>;
+}
+
+/// The output of the [`StatefulProps`](trait.StatefulProps.html) instance `P` of the route.  
+///
+/// The instance attached to the matched route is used, or the one registered as state.  A route
+/// with neither fails with `500 Internal Server Error`.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// /// Checks the bearer token against a key.
+/// struct Auth {
+///     key: String,
+/// }
+///
+/// struct User(String);
+///
+/// impl StatefulProps for Auth {
+///     type Output = User;
+///
+///     fn call(&self, req: Request, params: Params) -> PropsFuture<User> {
+///         let expected = format!("Bearer {}", self.key);
+///         Box::pin(async move {
+///             match req.header(&"authorization".parse().unwrap()) {
+///                 Some(values) if values[0].as_str() == expected => Ok((req, params, User("ada".into()))),
+///                 _ => Err(Error::unauthorized("bad token")),
+///             }
+///         })
+///     }
+/// }
+///
+/// /// Reads the body as text, up to a limit.
+/// struct Text {
+///     max: usize,
+/// }
+///
+/// impl StatefulProps for Text {
+///     type Output = String;
+///
+///     fn call(&self, mut req: Request, params: Params) -> PropsFuture<String> {
+///         let max = self.max;
+///         Box::pin(async move {
+///             let body = String::from_utf8_lossy(&read_body(&mut req).await?).into_owned();
+///             match body.len() <= max {
+///                 true => Ok((req, params, body)),
+///                 false => Err(Error::new(StatusCode::PayloadTooLarge, "too long")),
+///             }
+///         })
+///     }
+/// }
+///
+/// #[endpoint]
+/// async fn post(user: With<Auth>, text: With<Text>) -> Result<Response, Error> {
+///     Ok(Response::from(format!("{}: {}", user.0 .0, *text)))
+/// }
+///
+/// let mut router = Router::new();
+/// router.state(Arc::new(Auth { key: "secret".into() }));
+/// router.add(Method::Post, route!(/"short").props(Text { max: 5 }), ___post);
+/// router.add(Method::Post, route!(/"long").props(Text { max: 50 }), ___post);
+/// router.add(Method::Post, route!(/"unlimited"), ___post);
+/// let router = Arc::new(router);
+///
+/// let post = |path: &str, token: &str, body: &str| {
+///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///     let mut req = Request::new(Method::Post, url);
+///     req.insert_header("authorization", token).unwrap();
+///     req.insert_header("content-length", body.len().to_string()).unwrap();
+///     req.set_body(body);
+///     let res = task::block_on(router.clone().respond(req));
+///     (res.status(), task::block_on(res.body_string()).unwrap())
+/// };
+///
+/// assert_eq!(post("/short", "Bearer secret", "hello"), (StatusCode::Ok, "ada: hello".into()));
+/// assert_eq!(post("/short", "Bearer secret", "hello world").0, StatusCode::PayloadTooLarge);
+/// assert_eq!(post("/long", "Bearer secret", "hello world").1, "ada: hello world");
+/// assert_eq!(post("/long", "Bearer guess", "hello").0, StatusCode::Unauthorized);
+/// // No `Text` for this route.
+/// assert_eq!(post("/unlimited", "Bearer secret", "hello").0, StatusCode::InternalServerError);
+/// ```
+pub struct With<P: StatefulProps>(pub P::Output);
+
+impl<P: StatefulProps> With<P> {
+    /// Unwrap the output.  
+    pub fn into_inner(self) -> P::Output {
+        self.0
+    }
+}
+
+impl<P: StatefulProps> Deref for With<P> {
+    type Target = P::Output;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<P: StatefulProps> Props for With<P>
+where
+    P::Output: Send + 'static,
+{
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        let props = MatchedRoute::of(&req)
+            .and_then(|route| route.metadata::<Arc<P>>())
+            .or_else(|| State::<Arc<P>>::of(&req).map(State::into_inner));
+        Box::pin(async move {
+            let props = props.ok_or_else(|| {
+                Error::internal(format!(
+                    "props `{}` were not given to the route",
+                    std::any::type_name::<P>()
+                ))
+                .with_code(WindmillErrorCode::Internal)
+            })?;
+            let (req, params, output) = props.call(req, params).await?;
+            Ok((req, params, With(output)))
+        })
+    }
+}
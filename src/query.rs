@@ -0,0 +1,241 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use http_types::Url;
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+};
+
+type DeError = de::value::Error;
+
+/// A props that deserializes the query string of a request into `T`.
+///
+/// Fields that may be missing should be `Option`s, and keys that may repeat, like
+/// `?tag=a&tag=b`, should be `Vec`s.  A query that does not fit `T` is rejected with
+/// `400 Bad Request`.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::Response;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Page {
+///     page: u32,
+///     sort: Option<String>,
+/// }
+///
+/// #[endpoint]
+/// async fn list(query: Query<Page>) -> Result<Response, Error> {
+///     Ok(Response::from(format!("page {}", query.0.page)))
+/// }
+/// ```
+///
+/// ```
+/// # use windmill::*;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct Search {
+///     page: u32,
+///     sort: Option<String>,
+///     tag: Vec<String>,
+/// }
+///
+/// let Query(search) = Query::<Search>::parse("page=2&tag=a&tag=b%20c").unwrap();
+/// assert_eq!(search, Search { page: 2, sort: None, tag: vec!["a".into(), "b c".into()] });
+///
+/// let err = Query::<Search>::parse("page=two").unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::BadRequest);
+/// ```
+#[derive(Debug)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> Query<T> {
+    /// Deserialize a raw query string, without the leading `?`.
+    pub fn parse(query: &str) -> Result<Self, Error> {
+        let mut url = Url::parse("http://localhost/").unwrap();
+        url.set_query(Some(query));
+        Self::from_url(&url)
+    }
+
+    fn from_url(url: &Url) -> Result<Self, Error> {
+        deserialize_pairs(url, "query string").map(Query)
+    }
+}
+
+/// Decode `application/x-www-form-urlencoded` data, which has the same format as a query string.
+pub(crate) fn from_form<T: DeserializeOwned>(form: &str) -> Result<T, Error> {
+    let mut url = Url::parse("http://localhost/").unwrap();
+    url.set_query(Some(form));
+    deserialize_pairs(&url, "form body")
+}
+
+fn deserialize_pairs<T: DeserializeOwned>(url: &Url, what: &str) -> Result<T, Error> {
+    let mut pairs: Vec<(String, Vec<String>)> = vec![];
+    for (key, value) in url.query_pairs() {
+        match pairs.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(value.into_owned()),
+            None => pairs.push((key.into_owned(), vec![value.into_owned()])),
+        }
+    }
+
+    T::deserialize(Pairs(pairs)).map_err(|e| {
+        Error::bad_request(format!("invalid {}: {}", what, e))
+            .with_code(WindmillErrorCode::DeserializeFailed)
+    })
+}
+
+impl<T> Props for Query<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let query = Self::from_url(req.url())?;
+            Ok((req, params, query))
+        })
+    }
+}
+
+/// A props holding the raw query string of a request, without the leading `?`.  The string is
+/// empty when the request has no query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawQuery(pub String);
+
+impl Props for RawQuery {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let query = RawQuery(req.url().query().unwrap_or_default().to_owned());
+            Ok((req, params, query))
+        })
+    }
+}
+
+/// The decoded query, with the values of repeated keys grouped together.
+struct Pairs(Vec<(String, Vec<String>)>);
+
+/// Every value of a single key.
+pub(crate) struct Values(Vec<String>);
+
+impl<'de> de::Deserializer<'de> for Pairs {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_map(MapDeserializer::new(
+            self.0
+                .into_iter()
+                .map(|(key, values)| (key, Values(values))),
+        ))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeError> for Values {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl Values {
+    /// A single value, which also deserializes numbers and booleans by parsing them.
+    pub(crate) fn one(value: String) -> Self {
+        Values(vec![value])
+    }
+
+    /// The value used when a single one is expected: the last, as with most frameworks.
+    fn last(mut self) -> String {
+        self.0.pop().unwrap_or_default()
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                let value = self.last();
+                match value.parse() {
+                    Ok(parsed) => visitor.$visit(parsed),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&value), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Values {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        if self.0.len() == 1 {
+            visitor.visit_string(self.last())
+        } else {
+            self.deserialize_seq(visitor)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_seq(SeqDeserializer::new(
+            self.0.into_iter().map(|value| Values(vec![value])),
+        ))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_enum(self.last().into_deserializer())
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
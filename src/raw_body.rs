@@ -0,0 +1,173 @@
+use crate::{
+    error::Error,
+    params::Params,
+    props::{Props, PropsFuture},
+    util::{body_read_error, body_too_large, max_body_size},
+};
+use async_std::io::{self, prelude::*, Read};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// A props that hands the endpoint the request body as it arrives, without reading any of it.
+///
+/// The body is read through [`read_chunk`](#method.read_chunk), or as an `AsyncRead`, so an
+/// upload of any size can be processed in the memory of the buffer it is read into.  The
+/// [maximum body size](struct.Config.html#method.with_max_body_size) still applies: a declared
+/// `Content-Length` over it is rejected before the endpoint runs, and reading past it fails.  Raise
+/// it for servers that accept large uploads.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{io::{self, BufReader}, net::{TcpListener, TcpStream}, prelude::*, task};
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// const LEN: usize = 4 * 1024 * 1024;
+///
+/// // Checksum the upload 8 KiB at a time.
+/// #[endpoint]
+/// async fn upload(mut body: RawBody, sent: State<Arc<AtomicUsize>>) -> Result<Response, Error> {
+///     let mut buf = [0u8; 8 * 1024];
+///     let (mut received, mut checksum, mut sent_at_first_chunk) = (0, 0u64, None);
+///     loop {
+///         let n = body.read_chunk(&mut buf).await?;
+///         if n == 0 {
+///             break;
+///         }
+///         sent_at_first_chunk.get_or_insert(sent.load(Ordering::SeqCst));
+///         received += n;
+///         checksum = buf[..n].iter().fold(checksum, |sum, b| sum.wrapping_mul(31).wrapping_add(*b as u64));
+///     }
+///     Ok(Response::from(format!("{} {} {}", received, checksum, sent_at_first_chunk.unwrap_or(0))))
+/// }
+///
+/// let sent = Arc::new(AtomicUsize::new(0));
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"upload"), ___upload);
+/// router.state(sent.clone());
+/// let router = Arc::new(router);
+///
+/// let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+/// let expected = data.iter().fold(0u64, |sum, b| sum.wrapping_mul(31).wrapping_add(*b as u64));
+///
+/// let body = task::block_on(async {
+///     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+///     let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+///     let (server, _) = listener.accept().await.unwrap();
+///     let counter = sent.clone();
+///     task::spawn(async move {
+///         for chunk in data.chunks(64 * 1024) {
+///             client.write_all(chunk).await.unwrap();
+///             counter.fetch_add(chunk.len(), Ordering::SeqCst);
+///         }
+///     });
+///
+///     let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+///     req.insert_header("content-length", LEN.to_string()).unwrap();
+///     req.local_mut().insert(Arc::new(Config::new("127.0.0.1:0").with_max_body_size(LEN)));
+///     req.set_body(http_types::Body::from_reader(BufReader::new(server), Some(LEN)));
+///     router.clone().respond(req).await.body_string().await.unwrap()
+/// });
+/// let parts: Vec<usize> = body.split(' ').map(|part| part.parse().unwrap_or(0)).collect();
+/// assert_eq!(parts[0], LEN);
+/// assert_eq!(body.split(' ').nth(1).unwrap(), expected.to_string());
+/// // The endpoint was reading while the client was still sending.
+/// assert!(parts[2] < LEN, "{} of {} bytes sent before the first chunk", parts[2], LEN);
+///
+/// // Without a larger limit, the upload is too large.
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+/// req.insert_header("content-length", LEN.to_string()).unwrap();
+/// req.set_body(vec![0u8; LEN]);
+/// let res = task::block_on(router.clone().respond(req));
+/// assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+///
+/// let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+/// req.insert_header("transfer-encoding", "chunked").unwrap();
+/// req.set_body(http_types::Body::from_reader(io::Cursor::new(vec![0u8; LEN]), None));
+/// let res = task::block_on(router.respond(req));
+/// assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+/// ```
+pub struct RawBody {
+    body: http_types::Body,
+    len: Option<usize>,
+    read: usize,
+    body_limit: usize,
+}
+
+impl RawBody {
+    /// The length of the body, if the request declared one.
+    pub fn content_length(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// The number of bytes read so far.
+    pub fn bytes_read(&self) -> usize {
+        self.read
+    }
+
+    /// Read the next chunk of the body into `buf`, returning how many bytes were read, or 0 once
+    /// the body is complete.
+    ///
+    /// Reading past the maximum body size results in a `413 Payload Too Large` error, a body the
+    /// client stopped sending in a `408 Request Timeout` error, and a body that cannot be read in
+    /// a `400 Bad Request` error.
+    pub async fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read(buf)
+            .await
+            .map_err(|e| match self.read > self.body_limit {
+                true => body_too_large(self.body_limit),
+                false => body_read_error(e),
+            })
+    }
+}
+
+impl Read for RawBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.read > this.body_limit {
+            return Poll::Ready(Err(too_large()));
+        }
+        match Pin::new(&mut this.body).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                this.read += n;
+                match this.read > this.body_limit {
+                    true => Poll::Ready(Err(too_large())),
+                    false => Poll::Ready(Ok(n)),
+                }
+            }
+            poll => poll,
+        }
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "body too large")
+}
+
+impl Props for RawBody {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let body_limit = max_body_size(&req);
+            if matches!(req.len(), Some(len) if len > body_limit) {
+                return Err(body_too_large(body_limit));
+            }
+
+            let body = RawBody {
+                len: req.len(),
+                body: req.take_body(),
+                read: 0,
+                body_limit,
+            };
+            Ok((req, params, body))
+        })
+    }
+}
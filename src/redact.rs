@@ -0,0 +1,213 @@
+use crate::{config::Config, util::percent_decode};
+use serde_json::{Map, Value};
+use std::{borrow::Cow, sync::Arc};
+
+/// The value that replaces redacted data.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// A policy describing which parts of a request must never be written to logs, dumps or captures.
+///
+/// Every surface in windmill that records request data runs it through the policy configured with
+/// [`Config::with_redaction`](struct.Config.html#method.with_redaction).  An empty policy, the
+/// default, leaves everything untouched and costs nothing.
+///
+/// ## Examples
+/// ```
+/// # use windmill::Redaction;
+/// let policy = Redaction::new()
+///     .header("authorization")
+///     .query_param("token")
+///     .field("*.password");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Redaction {
+    headers: Vec<String>,
+    query_params: Vec<String>,
+    fields: Vec<Vec<String>>,
+}
+
+impl Redaction {
+    /// Create an empty policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redact the value of a header.  Header names are compared case-insensitively.
+    pub fn header(mut self, name: impl Into<String>) -> Self {
+        self.headers.push(name.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Redact the value of a query string parameter.
+    pub fn query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_params.push(name.into());
+        self
+    }
+
+    /// Redact a field in a JSON body.
+    ///
+    /// The path is a list of keys separated by `.`, where `*` matches any single key and `**` any
+    /// number of keys, none included.  Arrays are searched element by element without consuming a
+    /// segment of the path, so `users.password` matches the `password` of every element of a
+    /// `users` array.
+    /// ```
+    /// # use windmill::Redaction;
+    /// # use serde_json::json;
+    /// let mut body = json!({
+    ///     "password": "hunter2",
+    ///     "user": { "password": "swordfish", "profile": { "password": "letmein" } },
+    /// });
+    ///
+    /// // `*` is one level deep, and misses the password of the profile.
+    /// let mut one_level = body.clone();
+    /// Redaction::new().field("*.password").redact_json(&mut one_level);
+    /// assert_eq!(one_level["user"]["password"], "[REDACTED]");
+    /// assert_eq!(one_level["user"]["profile"]["password"], "letmein");
+    /// assert_eq!(one_level["password"], "hunter2");
+    ///
+    /// Redaction::new().field("**.password").redact_json(&mut body);
+    /// assert_eq!(body, json!({
+    ///     "password": "[REDACTED]",
+    ///     "user": { "password": "[REDACTED]", "profile": { "password": "[REDACTED]" } },
+    /// }));
+    /// ```
+    pub fn field(mut self, path: &str) -> Self {
+        self.fields
+            .push(path.split('.').map(String::from).collect());
+        self
+    }
+
+    /// Whether the policy redacts nothing.
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.query_params.is_empty() && self.fields.is_empty()
+    }
+
+    /// Redact a header value.
+    /// ```
+    /// # use windmill::Redaction;
+    /// let policy = Redaction::new().header("Authorization");
+    /// assert_eq!(policy.redact_header("authorization", "Bearer abc"), "[REDACTED]");
+    /// assert_eq!(policy.redact_header("accept", "*/*"), "*/*");
+    /// ```
+    pub fn redact_header<'a>(&self, name: &str, value: &'a str) -> Cow<'a, str> {
+        if self
+            .headers
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case(name))
+        {
+            Cow::Borrowed(REDACTED)
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    /// Redact the values of sensitive parameters in a query string.  Names are compared once
+    /// their `%XX` escapes are decoded, so an escaped name is redacted like the plain one.
+    /// ```
+    /// # use windmill::Redaction;
+    /// let policy = Redaction::new().query_param("token");
+    /// assert_eq!(
+    ///     policy.redact_query("page=2&token=abc&token=def"),
+    ///     "page=2&token=[REDACTED]&token=[REDACTED]"
+    /// );
+    /// assert_eq!(policy.redact_query("tok%65n=abc"), "tok%65n=[REDACTED]");
+    /// ```
+    pub fn redact_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        if self.query_params.is_empty() {
+            return Cow::Borrowed(query);
+        }
+
+        let redacted: Vec<Cow<str>> = query
+            .split('&')
+            .map(|pair| {
+                let name = pair.split('=').next().unwrap_or(pair);
+                let decoded = percent_decode(&name.replace('+', " "));
+                if self.query_params.iter().any(|param| *param == decoded) {
+                    Cow::Owned(format!("{}={}", name, REDACTED))
+                } else {
+                    Cow::Borrowed(pair)
+                }
+            })
+            .collect();
+
+        Cow::Owned(redacted.join("&"))
+    }
+
+    /// Redact sensitive fields of a JSON body in place.
+    /// ```
+    /// # use windmill::Redaction;
+    /// # use serde_json::json;
+    /// let policy = Redaction::new().field("*.password").field("token");
+    /// let mut body = json!({
+    ///     "token": "abc",
+    ///     "user": { "name": "ada", "password": "hunter2" },
+    ///     "admins": [{ "password": "swordfish" }],
+    /// });
+    ///
+    /// policy.redact_json(&mut body);
+    /// assert_eq!(body, json!({
+    ///     "token": "[REDACTED]",
+    ///     "user": { "name": "ada", "password": "[REDACTED]" },
+    ///     "admins": [{ "password": "[REDACTED]" }],
+    /// }));
+    /// ```
+    pub fn redact_json(&self, value: &mut Value) {
+        for path in self.fields.iter() {
+            redact_path(value, path);
+        }
+    }
+
+    /// Redact a raw body, which is only possible when it is JSON.  Bodies that are not JSON are
+    /// replaced entirely when the policy redacts any fields, since their sensitive parts cannot be
+    /// located.
+    pub fn redact_body<'a>(&self, body: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.fields.is_empty() || body.is_empty() {
+            return Cow::Borrowed(body);
+        }
+
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                self.redact_json(&mut value);
+                Cow::Owned(serde_json::to_vec(&value).unwrap_or_default())
+            }
+            Err(_) => Cow::Borrowed(REDACTED.as_bytes()),
+        }
+    }
+}
+
+/// The query string of `req`, redacted with the policy of the server it came through, if it has
+/// one.
+pub(crate) fn redacted_query(req: &http_types::Request) -> Option<String> {
+    let query = req.url().query()?;
+    Some(match req.local().get::<Arc<Config>>() {
+        Some(config) => config.redaction().redact_query(query).into_owned(),
+        None => query.to_owned(),
+    })
+}
+
+fn redact_path(value: &mut Value, path: &[String]) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(|value| redact_path(value, path)),
+        Value::Object(map) => redact_fields(map, path),
+        _ => {}
+    }
+}
+
+fn redact_fields(map: &mut Map<String, Value>, path: &[String]) {
+    match path {
+        [] => {}
+        [first, rest @ ..] if first == "**" => {
+            // No key, then one more key with `**` still ahead.
+            redact_fields(map, rest);
+            map.values_mut().for_each(|value| redact_path(value, path));
+        }
+        [last] => map
+            .iter_mut()
+            .filter(|(key, _)| last == "*" || *key == last)
+            .for_each(|(_, value)| *value = Value::String(REDACTED.into())),
+        [first, rest @ ..] => map
+            .iter_mut()
+            .filter(|(key, _)| first == "*" || *key == first)
+            .for_each(|(_, value)| redact_path(value, rest)),
+    }
+}
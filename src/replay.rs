@@ -0,0 +1,290 @@
+use crate::{config::Config, redact::redacted_query, router::Router, util::read_body};
+use http_types::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The version written to every recorded trace.
+const TRACE_VERSION: u32 = 1;
+
+/// Records the requests handled by a [`Router`](struct.Router.html) to a file so they can be
+/// [`replay`](fn.replay.html)ed later.
+///
+/// Each request is written as one line of JSON holding the method, the path and query, the
+/// headers, the body and the status of the response.  Headers, query parameters and JSON body
+/// fields are redacted with the server's
+/// [`Config::with_redaction`](struct.Config.html#method.with_redaction) policy before they are
+/// written.  Sampling is deterministic: with a rate of `0.25` exactly every fourth request is
+/// recorded.
+///
+/// ## Examples
+/// ```no_run
+/// # use windmill::*;
+/// let mut router = Router::new();
+/// router.record(Recorder::create("traces.ndjson").unwrap().sample_rate(0.1));
+/// ```
+///
+/// What the redaction policy names never reaches the file:
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// let path = std::env::temp_dir().join("windmill-recorder-redaction.ndjson");
+/// let _ = std::fs::remove_file(&path);
+///
+/// let mut router = Router::new();
+/// router.record(Recorder::create(&path).unwrap());
+/// let policy = Redaction::new()
+///     .header("authorization")
+///     .query_param("token")
+///     .field("**.password");
+/// let config = Config::new("127.0.0.1:0").with_redaction(policy);
+/// let client = TestClient::new(router).with_config(config);
+///
+/// task::block_on(async {
+///     client
+///         .post("/login?tok%65n=secret")
+///         .header("authorization", "Bearer secret")
+///         .body(r#"{"user":{"name":"ada","password":"secret"}}"#)
+///         .send()
+///         .await;
+/// });
+///
+/// let trace = std::fs::read_to_string(&path).unwrap();
+/// assert!(!trace.contains("secret"), "{}", trace);
+/// assert!(trace.contains("/login?tok%65n=[REDACTED]"), "{}", trace);
+/// assert!(trace.contains(r#"["authorization","[REDACTED]"]"#), "{}", trace);
+/// assert!(trace.contains("ada"), "{}", trace);
+/// ```
+pub struct Recorder {
+    file: Mutex<File>,
+    sample_rate: f64,
+    seen: AtomicU64,
+}
+
+impl Recorder {
+    /// Record to the file at `path`, appending to it if it already exists.  Every request is
+    /// recorded until a [`sample_rate`](#method.sample_rate) is set.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            sample_rate: 1.0,
+            seen: AtomicU64::new(0),
+        })
+    }
+
+    /// Record only the given fraction of requests, between `0.0` and `1.0`.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether the next request should be recorded.
+    pub(crate) fn sample(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::SeqCst) as f64;
+        ((n + 1.0) * self.sample_rate).floor() > (n * self.sample_rate).floor()
+    }
+
+    /// Capture the parts of `req` that are recorded, buffering its body so the endpoint can still
+    /// read it.
+    pub(crate) async fn capture(&self, req: &mut http_types::Request) -> Trace {
+        let config = req.local().get::<Arc<Config>>().cloned();
+        let redaction = config.as_ref().map(|config| config.redaction());
+
+        let mut path = req.url().path().to_owned();
+        if let Some(query) = redacted_query(req) {
+            path.push('?');
+            path.push_str(&query);
+        }
+
+        let headers = req
+            .iter()
+            .flat_map(|(name, values)| {
+                values.iter().map(move |value| {
+                    let value = match redaction {
+                        Some(redaction) => redaction.redact_header(name.as_str(), value.as_str()),
+                        None => value.as_str().into(),
+                    };
+                    (name.as_str().to_owned(), value.into_owned())
+                })
+            })
+            .collect();
+
+        let body = read_body(req).await.unwrap_or_default();
+        let recorded = match redaction {
+            Some(redaction) => redaction.redact_body(&body).into_owned(),
+            None => body.clone(),
+        };
+        req.set_body(body);
+
+        Trace {
+            version: TRACE_VERSION,
+            method: req.method().to_string(),
+            path,
+            headers,
+            body: String::from_utf8_lossy(&recorded).into_owned(),
+            status: 0,
+        }
+    }
+
+    /// Write a captured trace along with the status it was answered with.  Failing to write a
+    /// trace never fails the request.
+    pub(crate) fn write(&self, mut trace: Trace, status: StatusCode) {
+        trace.status = status as u16;
+        if let Ok(mut line) = serde_json::to_vec(&trace) {
+            line.push(b'\n');
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            let _ = file.write_all(&line);
+        }
+    }
+}
+
+/// A single recorded request.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Trace {
+    version: u32,
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    status: u16,
+}
+
+/// The outcome of [`replay`](fn.replay.html)ing a file of recorded requests.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// The number of requests that were replayed.
+    pub replayed: usize,
+    /// The requests whose status differs from the recorded one.
+    pub diffs: Vec<ReplayDiff>,
+}
+
+/// A replayed request whose status differs from the recorded one.
+#[derive(Debug, PartialEq)]
+pub struct ReplayDiff {
+    /// The line of the file the request was recorded on, starting at 1.
+    pub line: usize,
+    /// The method of the request.
+    pub method: String,
+    /// The path and query of the request.
+    pub path: String,
+    /// The status that was recorded.
+    pub recorded: u16,
+    /// The status returned when replaying.
+    pub replayed: u16,
+}
+
+/// Replay the requests recorded by a [`Recorder`](struct.Recorder.html) against `router`.
+///
+/// Every request is dispatched in process through the same pipeline the server uses, and its
+/// status is compared against the recorded one.  Blank lines are skipped; a line that is not a
+/// trace, or a trace with an unknown version, is an `InvalidData` error.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::{Method, Response, StatusCode};
+/// #[endpoint]
+/// async fn ok() -> Result<Response, Error> {
+///     Ok(Response::new(StatusCode::Ok))
+/// }
+///
+/// #[endpoint]
+/// async fn gone() -> Result<Response, Error> {
+///     Ok(Response::new(StatusCode::Gone))
+/// }
+///
+/// let dir = std::env::temp_dir();
+/// let traffic = dir.join("windmill-replay-traffic.ndjson");
+/// let recorded = dir.join("windmill-replay-recorded.ndjson");
+/// let _ = std::fs::remove_file(&recorded);
+/// std::fs::write(&traffic, concat!(
+///     r#"{"version":1,"method":"GET","path":"/users?page=2","headers":[],"body":"","status":200}"#, "\n",
+///     r#"{"version":1,"method":"POST","path":"/users","headers":[["content-length","2"]],"body":"{}","status":200}"#, "\n",
+///     r#"{"version":1,"method":"GET","path":"/missing","headers":[],"body":"","status":404}"#, "\n",
+/// )).unwrap();
+///
+/// // Record the traffic by sending it through a router.
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"), ___ok);
+/// router.add(Method::Post, route!(/"users"), ___ok);
+/// router.record(Recorder::create(&recorded).unwrap());
+/// let report = async_std::task::block_on(replay(&traffic, router)).unwrap();
+/// assert!(report.diffs.is_empty());
+///
+/// // The same handlers reproduce every recorded status.
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"), ___ok);
+/// router.add(Method::Post, route!(/"users"), ___ok);
+/// let report = async_std::task::block_on(replay(&recorded, router)).unwrap();
+/// assert_eq!(report.replayed, 3);
+/// assert!(report.diffs.is_empty());
+///
+/// // Changing a handler shows up as a diff.
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"), ___gone);
+/// router.add(Method::Post, route!(/"users"), ___ok);
+/// let report = async_std::task::block_on(replay(&recorded, router)).unwrap();
+/// assert_eq!(report.diffs, vec![ReplayDiff {
+///     line: 1,
+///     method: "GET".into(),
+///     path: "/users?page=2".into(),
+///     recorded: 200,
+///     replayed: 410,
+/// }]);
+/// ```
+pub async fn replay(path: impl AsRef<Path>, router: Router) -> io::Result<ReplayReport> {
+    let router = Arc::new(router);
+    let base = Url::parse("http://localhost/").unwrap();
+    let mut report = ReplayReport::default();
+
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let trace: Trace = serde_json::from_str(&line).map_err(invalid_data)?;
+        if trace.version != TRACE_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported trace version {}",
+                trace.version
+            )));
+        }
+
+        let method = trace.method.parse::<Method>().map_err(invalid_data)?;
+        let url = base.join(&trace.path).map_err(invalid_data)?;
+        let mut req = http_types::Request::new(method, url);
+        for (name, value) in trace.headers.iter() {
+            req.append_header(name.as_str(), value.as_str())
+                .map_err(invalid_data)?;
+        }
+        req.set_body(trace.body);
+
+        let res = router.clone().respond(req).await;
+        report.replayed += 1;
+        if res.status() as u16 != trace.status {
+            report.diffs.push(ReplayDiff {
+                line: i + 1,
+                method: trace.method,
+                path: trace.path,
+                recorded: trace.status,
+                replayed: res.status() as u16,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,236 @@
+use crate::{
+    error::request_head,
+    extensions::Extensions,
+    forwarded,
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use http_types::{
+    headers::{self, HeaderName, HeaderValue},
+    Method, Url,
+};
+use std::net::{IpAddr, SocketAddr};
+
+/// The address of the client a request came from, attached to it by the server.
+#[derive(Clone, Copy)]
+pub(crate) struct PeerAddr(pub(crate) SocketAddr);
+
+/// A props with the method, url and headers of the request, and the address of the client that
+/// sent it.
+///
+/// The head is copied from the request once, when an endpoint takes it as an argument, and the
+/// accessors borrow from it, so an endpoint that only checks a header does not need to take the
+/// whole `http_types::Request`.  Requests dispatched in process with
+/// [`Router::respond`](struct.Router.html#method.respond) have no peer address.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::{sync::Arc, time::Duration};
+/// #[endpoint]
+/// async fn whoami(head: RequestHead) -> Result<Response, Error> {
+///     let token = head
+///         .header("authorization")
+///         .map(|values| values[0].as_str().to_owned())
+///         .ok_or_else(|| Error::unauthorized("missing authorization"))?;
+///     let peer = head.peer_addr().map(|addr| addr.ip().to_string());
+///     assert!(head.headers().any(|(name, _)| name.as_str() == "authorization"));
+///     Ok(Response::from(format!(
+///         "{} {} {} {:?}",
+///         head.method(),
+///         head.url().path(),
+///         token,
+///         peer
+///     )))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"whoami"), ___whoami);
+///
+/// // In process there is no peer.
+/// let router = Arc::new(router);
+/// let mut req = Request::new(Method::Get, Url::parse("http://localhost/whoami").unwrap());
+/// req.insert_header("authorization", "Bearer abc").unwrap();
+/// let res = task::block_on(async { router.clone().respond(req).await.body_string().await });
+/// assert_eq!(res.unwrap(), "GET /whoami Bearer abc None");
+///
+/// let req = Request::new(Method::Get, Url::parse("http://localhost/whoami").unwrap());
+/// let res = task::block_on(router.clone().respond(req));
+/// assert_eq!(res.status(), StatusCode::Unauthorized);
+///
+/// // Over a connection the peer is the client.
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"whoami"), ___whoami);
+/// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+/// let (stop, stopped) = channel(1);
+/// let server = std::thread::spawn(move || {
+///     Server::new(Config::new(addr.to_string()))
+///         .run_until(router, async move { let _ = stopped.recv().await; })
+///         .map_err(|e| e.to_string())
+/// });
+///
+/// task::block_on(async {
+///     let mut stream = loop {
+///         match TcpStream::connect(addr).await {
+///             Ok(stream) => break stream,
+///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+///         }
+///     };
+///     stream
+///         .write_all(b"GET /whoami HTTP/1.1\r\nhost: localhost\r\nauthorization: Bearer abc\r\nconnection: close\r\n\r\n")
+///         .await
+///         .unwrap();
+///     let mut res = String::new();
+///     stream.read_to_string(&mut res).await.unwrap();
+///     assert!(res.ends_with(r#"GET /whoami Bearer abc Some("127.0.0.1")"#), "{}", res);
+///     stop.send(()).await;
+/// });
+///
+/// server.join().unwrap().unwrap();
+/// ```
+pub struct RequestHead {
+    head: http_types::Request,
+    peer_addr: Option<SocketAddr>,
+    client_addr: Option<IpAddr>,
+    scheme: String,
+}
+
+impl RequestHead {
+    /// The method of the request.
+    pub fn method(&self) -> Method {
+        self.head.method()
+    }
+
+    /// The url of the request.
+    pub fn url(&self) -> &Url {
+        self.head.url()
+    }
+
+    /// The values of the header `name`, if the request has it.
+    pub fn header(&self, name: &str) -> Option<&Vec<HeaderValue>> {
+        self.head.header(&name.parse::<HeaderName>().ok()?)
+    }
+
+    /// The headers of the request, with their values.
+    pub fn headers(&self) -> headers::Iter<'_> {
+        self.head.iter()
+    }
+
+    /// The address of the client that sent the request, if it came over a tcp connection.
+    /// Requests over a [Unix socket](struct.Config.html#method.unix) have none.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The address of the client the request comes from, which is the peer unless the peer is a
+    /// [trusted proxy](struct.Config.html#method.with_trusted_proxies).
+    ///
+    /// A trusted proxy reports the addresses it forwarded the request for in a `Forwarded` header,
+    /// or else an `X-Forwarded-For` header.  They are read from the right, the closest to the
+    /// server, and the first address that is not a trusted proxy itself is the client's, since
+    /// anything further left could have been sent by the client.  A value that is not an address,
+    /// such as `unknown`, stops at the proxy that reported it.
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{io, net::TcpStream, prelude::*, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::Duration;
+    /// #[endpoint]
+    /// async fn client(head: RequestHead) -> Result<Response, Error> {
+    ///     let addr = head.client_addr().map(|addr| addr.to_string()).unwrap_or_default();
+    ///     Ok(Response::from(format!("{} {}", head.scheme(), addr)))
+    /// }
+    ///
+    /// let serve = |config: Config| {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"client"), ___client);
+    ///     let server = Server::bind(config).unwrap();
+    ///     let addr = server.local_addr().unwrap();
+    ///     std::thread::spawn(move || server.run(router).map_err(|e| e.to_string()));
+    ///     addr
+    /// };
+    /// let behind_proxy = serve(Config::new("127.0.0.1:0").with_trusted_proxies(&["127.0.0.1", "10.0.0.0/8"]));
+    /// let exposed = serve(Config::new("127.0.0.1:0"));
+    ///
+    /// let get = |addr, headers: &'static str| async move {
+    ///     let mut stream = TcpStream::connect(addr).await.unwrap();
+    ///     let req = format!("GET /client HTTP/1.1\r\nhost: localhost\r\n{}\r\n", headers);
+    ///     stream.write_all(req.as_bytes()).await.unwrap();
+    ///     let (mut res, mut buf) = (vec![], [0; 1024]);
+    ///     while let Ok(n) = io::timeout(Duration::from_millis(200), stream.read(&mut buf)).await {
+    ///         if n == 0 {
+    ///             break;
+    ///         }
+    ///         res.extend_from_slice(&buf[..n]);
+    ///     }
+    ///     let res = String::from_utf8(res).unwrap();
+    ///     res.rsplit("\r\n\r\n").next().unwrap().to_owned()
+    /// };
+    ///
+    /// task::block_on(async {
+    ///     assert_eq!(get(behind_proxy, "").await, "http 127.0.0.1");
+    ///     let headers = "x-forwarded-for: 203.0.113.7\r\nx-forwarded-proto: https\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "https 203.0.113.7");
+    ///
+    ///     // Through a load balancer and an internal proxy, with a spoofed address in front.
+    ///     let headers = "x-forwarded-for: 1.1.1.1, 203.0.113.7, 10.0.0.2\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "http 203.0.113.7");
+    ///     let headers = "x-forwarded-for: 1.1.1.1\r\nx-forwarded-for: 203.0.113.7\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "http 203.0.113.7");
+    ///
+    ///     let headers = "forwarded: for=192.0.2.60;proto=https, for=\"[2001:db8::17]:4711\"\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "https 2001:db8::17");
+    ///     let headers = "forwarded: for=192.0.2.60, for=10.1.1.1:80\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "http 192.0.2.60");
+    ///
+    ///     // Malformed hops stop at the proxy that reported them.
+    ///     let headers = "x-forwarded-for: 203.0.113.7, not-an-ip, 10.0.0.2\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "http 10.0.0.2");
+    ///     let headers = "forwarded: for=unknown\r\nx-forwarded-proto: gopher\r\n";
+    ///     assert_eq!(get(behind_proxy, headers).await, "http 127.0.0.1");
+    ///
+    ///     // Peers that are not trusted cannot claim to be someone else.
+    ///     let headers = "x-forwarded-for: 203.0.113.7\r\nx-forwarded-proto: https\r\nforwarded: for=192.0.2.60\r\n";
+    ///     assert_eq!(get(exposed, headers).await, "http 127.0.0.1");
+    /// });
+    /// ```
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        self.client_addr
+    }
+
+    /// The scheme the client sent the request with, `http` or `https`, as reported by a
+    /// [trusted proxy](struct.Config.html#method.with_trusted_proxies) in the `proto` of a
+    /// `Forwarded` header or an `X-Forwarded-Proto` header, if the peer is one.  Proxies append
+    /// to these headers, so the last value is used.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The [extensions](struct.Extensions.html) of the request, shared with the middlewares and
+    /// props that ran before.
+    pub fn extensions(&self) -> Extensions {
+        Extensions::of(&self.head)
+    }
+}
+
+impl Props for RequestHead {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let head = RequestHead {
+                head: request_head(&req),
+                peer_addr: req.local().get::<PeerAddr>().map(|peer| peer.0),
+                client_addr: forwarded::client_addr(&req),
+                scheme: forwarded::scheme(&req),
+            };
+            Ok((req, params, head))
+        })
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    extensions::Extensions,
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// The header requests carry their ID in, unless
+/// [`Config::with_request_id_header`](struct.Config.html#method.with_request_id_header) names
+/// another.
+pub(crate) const DEFAULT_REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest ID adopted from a request.
+const MAX_LEN: usize = 128;
+
+/// The ID of a request, for correlating the logs of the services it goes through.
+///
+/// The router adopts the ID sent in the `x-request-id` header of a request, or generates one,
+/// 32 random hex digits, when there is none or it is not 1 to 128 visible ASCII characters.  The
+/// ID replaces the header on the request, so an [`HttpClient`](struct.HttpClient.html)
+/// propagating it passes it on, and is sent back in the same header of every response, the ones
+/// the router makes itself such as `404 Not Found` included.  Endpoints can take it as an
+/// argument, and middlewares and the [error formatter](struct.Router.html#method.set_error_formatter)
+/// can read it with [`of`](#method.of).  The header is set with
+/// [`Config::with_request_id_header`](struct.Config.html#method.with_request_id_header).
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode};
+/// #[endpoint]
+/// async fn show(id: RequestId) -> Result<Response, Error> {
+///     Ok(Response::from(id.to_string()))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"show"), ___show);
+/// router.set_error_formatter(|e: &Error, req: &Request| {
+///     let id = RequestId::of(req).unwrap();
+///     let mut res = Response::new(e.code());
+///     res.set_body(format!("{} ({})", e.msg(), id));
+///     res
+/// });
+/// let client = TestClient::new(router);
+/// let request_id = |res: &Response| res.header(&"x-request-id".parse().unwrap()).unwrap()[0].to_string();
+///
+/// task::block_on(async {
+///     let mut res = client.get("/show").header("x-request-id", "abc-123").send().await;
+///     assert_eq!(request_id(&res), "abc-123");
+///     assert_eq!(res.body_string().await.unwrap(), "abc-123");
+///
+///     let first = client.get("/show").send().await;
+///     let second = client.get("/show").send().await;
+///     assert_eq!(request_id(&first).len(), 32);
+///     assert_ne!(request_id(&first), request_id(&second));
+///
+///     let mut res = client.get("/missing").header("x-request-id", "abc-456").send().await;
+///     assert_eq!(res.status(), StatusCode::NotFound);
+///     assert_eq!(request_id(&res), "abc-456");
+///     assert_eq!(res.body_string().await.unwrap(), "not found (abc-456)");
+///
+///     // An ID too long to be adopted is replaced.
+///     let res = client.get("/show").header("x-request-id", &"x".repeat(200)).send().await;
+///     assert_eq!(request_id(&res).len(), 32);
+/// });
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"show"), ___show);
+/// let config = Config::new("127.0.0.1:0").with_request_id_header("x-correlation-id");
+/// let client = TestClient::new(router).with_config(config);
+/// task::block_on(async {
+///     let res = client.get("/show").header("x-correlation-id", "abc-789").send().await;
+///     assert_eq!(res.header(&"x-correlation-id".parse().unwrap()).unwrap()[0], "abc-789");
+///     assert!(res.header(&"x-request-id".parse().unwrap()).is_none());
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    /// Get the ID of a request, if it has been through a router.
+    pub fn of(req: &http_types::Request) -> Option<Self> {
+        Extensions::of(req).get::<Self>()
+    }
+
+    /// The ID as it is sent.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The ID sent in `value`, if it is 1 to 128 visible ASCII characters.
+    pub(crate) fn adopt(value: &str) -> Option<Self> {
+        if value.is_empty() || value.len() > MAX_LEN {
+            return None;
+        }
+        match value.bytes().all(|b| b.is_ascii_graphic()) {
+            true => Some(Self(value.into())),
+            false => None,
+        }
+    }
+
+    /// A new ID of 32 random hex digits.
+    pub(crate) fn generate() -> Self {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        // The count keeps the IDs apart, and the randomly keyed hasher keeps them unguessable.
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let random = |half: u64| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(count);
+            hasher.write_u64(half);
+            hasher.finish()
+        };
+        Self(format!("{:016x}{:016x}", random(0), random(1)).into())
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Props for RequestId {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let id = Self::of(&req).ok_or_else(|| {
+                Error::internal("request was not routed").with_code(WindmillErrorCode::Internal)
+            })?;
+            Ok((req, params, id))
+        })
+    }
+}
@@ -0,0 +1,714 @@
+use crate::{
+    codec::{Codec, JsonCodec},
+    cookie::Cookie,
+    error::{Error, WindmillErrorCode},
+};
+use async_std::io::Cursor;
+use http_types::{mime, Mime, StatusCode};
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// What an endpoint can return as its response.
+///
+/// Implemented for `Response` itself and the responders [`Text`](struct.Text.html),
+/// [`Bytes`](struct.Bytes.html), [`Respond`](struct.Respond.html),
+/// [`Redirect`](struct.Redirect.html), [`NamedFile`](struct.NamedFile.html) and
+/// [`Sse`](struct.Sse.html).  An endpoint that returns `()` answers with `204 No Content`, with
+/// neither a body nor a `Content-Type`, and one that returns an `Option` answers with
+/// `404 Not Found` when it is `None`, rendered like any other error of the endpoint.
+///
+/// A body that fails to serialize answers the request with `500 Internal Server Error`, naming
+/// the type that could not be encoded, and the error is logged with the method and path of the
+/// request like any other server error.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::{Serialize, Serializer};
+/// struct Broken;
+///
+/// impl Serialize for Broken {
+///     fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+///         Err(serde::ser::Error::custom("not today"))
+///     }
+/// }
+///
+/// async fn broken(_req: http_types::Request, _params: Params) -> Result<Respond<Broken>, Error> {
+///     Ok(Respond::new(Broken))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, Route::from("/broken"), broken);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/broken").send().await;
+///     assert_eq!(res.status(), StatusCode::InternalServerError);
+///     let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///     assert_eq!(body["code"], "internal");
+///     assert!(body["msg"].as_str().unwrap().contains("Broken"), "{}", body);
+/// });
+/// ```
+///
+/// Nothing to answer with:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, StatusCode};
+/// #[endpoint]
+/// async fn remove() -> Result<(), Error> {
+///     Ok(())
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Delete, route!(/"users"/id), ___remove);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.delete("/users/7").send().await;
+///     assert_eq!(res.status(), StatusCode::NoContent);
+///     assert!(res.header(&"content-type".parse().unwrap()).is_none());
+///     assert!(res.header(&"content-length".parse().unwrap()).is_none());
+///     assert_eq!(res.body_string().await.unwrap(), "");
+/// });
+/// ```
+///
+/// Something that may be missing:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, StatusCode};
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct User {
+///     name: &'static str,
+/// }
+///
+/// struct UserId(u64);
+///
+/// impl Props for UserId {
+///     type Fut = PropsFuture<Self>;
+///
+///     fn call(req: http_types::Request, params: Params) -> Self::Fut {
+///         Box::pin(async move {
+///             let id = params.parse("id")?;
+///             Ok((req, params, UserId(id)))
+///         })
+///     }
+/// }
+///
+/// #[endpoint]
+/// async fn user(id: UserId) -> Result<Option<Respond<User>>, Error> {
+///     match id.0 {
+///         0 => Err(Error::forbidden("user 0 is reserved")),
+///         7 => Ok(Some(Respond::new(User { name: "ada" }))),
+///         _ => Ok(None),
+///     }
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"/id), ___user);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/users/7").send().await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     assert_eq!(res.body_string().await.unwrap(), r#"{"name":"ada"}"#);
+///
+///     let mut res = client.get("/users/8").send().await;
+///     assert_eq!(res.status(), StatusCode::NotFound);
+///     assert_eq!(res.body_string().await.unwrap(), r#"{"code":"not_found","msg":"not found"}"#);
+///
+///     let res = client.get("/users/0").send().await;
+///     assert_eq!(res.status(), StatusCode::Forbidden);
+/// });
+/// ```
+pub trait IntoResponse {
+    /// Turn the value into a response.
+    fn into_response(self) -> Result<http_types::Response, Error>;
+}
+
+impl IntoResponse for http_types::Response {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoResponse for () {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(http_types::Response::new(StatusCode::NoContent))
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Option<T> {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        match self {
+            Some(res) => res.into_response(),
+            None => Err(Error::not_found("not found").with_code(WindmillErrorCode::NotFound)),
+        }
+    }
+}
+
+impl IntoResponse for Text {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(self.into())
+    }
+}
+
+impl IntoResponse for Bytes {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(self.into())
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        Ok(self.into())
+    }
+}
+
+/// A response with a body encoded by a [`Codec`](trait.Codec.html), JSON unless another one is
+/// given, and the status and headers chosen by the endpoint.
+///
+/// A `Content-Type` header replaces the type of the codec.  Responses with the status
+/// `204 No Content` or `304 Not Modified` are sent without a body.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use serde::{Deserialize, Serialize};
+/// # use std::sync::Arc;
+/// #[derive(Deserialize, Serialize)]
+/// struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn create(user: Body<User>) -> Result<Respond<User>, Error> {
+///     let user = user.into_inner();
+///     let location = format!("/users/{}", user.id);
+///     Ok(Respond::new(user).with_status(StatusCode::Created).with_header("location", location))
+/// }
+///
+/// #[endpoint]
+/// async fn remove() -> Result<Respond<()>, Error> {
+///     Ok(Respond::new(()).with_status(StatusCode::NoContent))
+/// }
+///
+/// #[endpoint]
+/// async fn profile() -> Result<Respond<User>, Error> {
+///     let user = User { id: 7, name: "ada".into() };
+///     Ok(Respond::new(user).with_header("content-type", "application/vnd.user+json"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"users"), ___create);
+/// router.add(Method::Delete, route!(/"users"/id), ___remove);
+/// router.add(Method::Get, route!(/"users"/"me"), ___profile);
+/// let router = Arc::new(router);
+///
+/// let send = |method: Method, path: &str, body: &str| {
+///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///     let mut req = Request::new(method, url);
+///     req.insert_header("content-length", body.len().to_string()).unwrap();
+///     req.set_body(body.to_owned());
+///     task::block_on(router.clone().respond(req))
+/// };
+/// let header = |res: &Response, name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+///
+/// let res = send(Method::Post, "/users", r#"{"id":7,"name":"ada"}"#);
+/// assert_eq!(res.status(), StatusCode::Created);
+/// assert_eq!(header(&res, "location").unwrap(), "/users/7");
+/// assert_eq!(header(&res, "content-type").unwrap(), "application/json");
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), r#"{"id":7,"name":"ada"}"#);
+///
+/// let res = send(Method::Delete, "/users/7", "");
+/// assert_eq!(res.status(), StatusCode::NoContent);
+/// assert_eq!(header(&res, "content-type"), None);
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "");
+///
+/// let res = send(Method::Get, "/users/me", "");
+/// assert_eq!(res.status(), StatusCode::Ok);
+/// assert_eq!(header(&res, "content-type").unwrap(), "application/vnd.user+json");
+/// ```
+///
+/// Functions added as endpoints without the macro can return it too:
+/// ```
+/// # use windmill::*;
+/// # use http_types::{Method, Request, StatusCode};
+/// async fn accepted(_req: Request, _params: Params) -> Result<Respond<&'static str, TextCodec>, Error> {
+///     Ok(Respond::new("queued").with_codec(TextCodec).with_status(StatusCode::Accepted))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"jobs"), accepted);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Respond<T, C = JsonCodec> {
+    body: T,
+    codec: C,
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+}
+
+impl<T: Serialize> Respond<T> {
+    /// Create a `200 OK` response with `body` encoded as JSON.
+    pub fn new(body: T) -> Self {
+        Self {
+            body,
+            codec: JsonCodec,
+            status: StatusCode::Ok,
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl<T: Serialize, C: Codec> Respond<T, C> {
+    /// Encode the body with `codec` instead.
+    pub fn with_codec<D: Codec>(self, codec: D) -> Respond<T, D> {
+        Respond {
+            body: self.body,
+            codec,
+            status: self.status,
+            headers: self.headers,
+        }
+    }
+
+    /// Send the response with a status other than `200 OK`.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Add a header to the response.  Headers with the same name are all sent, except
+    /// `Content-Type` which replaces the previous one.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set a cookie on the client with a `Set-Cookie` header.
+    pub fn with_cookie(self, cookie: Cookie) -> Self {
+        self.with_header("set-cookie", cookie.to_string())
+    }
+
+    /// The body.
+    pub fn body(&self) -> &T {
+        &self.body
+    }
+}
+
+impl<T: Serialize, C: Codec> IntoResponse for Respond<T, C> {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        let mut res = http_types::Response::new(self.status);
+        if !matches!(self.status, StatusCode::NoContent | StatusCode::NotModified) {
+            res.set_body(self.codec.encode(&self.body)?);
+            let _ = res.set_content_type(self.codec.mime());
+        }
+
+        for (name, value) in self.headers.iter() {
+            let set = if name.eq_ignore_ascii_case("content-type") {
+                res.insert_header(name.as_str(), value.as_str()).map(|_| ())
+            } else {
+                res.append_header(name.as_str(), value.as_str())
+            };
+            set.map_err(|e| {
+                Error::internal(format!("invalid header `{}`: {}", name, e))
+                    .with_code(WindmillErrorCode::Internal)
+            })?;
+        }
+        Ok(res)
+    }
+}
+
+/// A plain text response body, sent as `text/plain; charset=utf-8`.
+///
+/// Rust strings are always UTF-8, so the `charset` parameter is always set to match, even when
+/// the type is changed with [`with_type`](#method.with_type).  Text built from a `&'static str`
+/// is sent without being copied.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::Response;
+/// #[endpoint]
+/// async fn robots() -> Result<Response, Error> {
+///     Ok(Text::from("User-agent: *\nDisallow:\n").into())
+/// }
+/// ```
+///
+/// ```
+/// # use windmill::Text;
+/// # use http_types::Response;
+/// const PAGE: &str = "<h1>hi</h1>";
+///
+/// let text = Text::from(PAGE).with_type("text/html".parse().unwrap());
+/// assert_eq!(text.as_str().as_ptr(), PAGE.as_ptr());
+///
+/// let res: Response = text.into();
+/// let content_type = res.header(&"content-type".parse().unwrap()).unwrap()[0].to_string();
+/// assert_eq!(content_type, "text/html;charset=utf-8");
+/// assert_eq!(res.len(), Some(PAGE.len()));
+/// ```
+///
+/// The text is sent as it is, without the quotes a JSON string would have, from endpoints made
+/// with the macro and without it alike:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{io, net::TcpStream, prelude::*, task};
+/// # use http_types::Method;
+/// # use std::time::Duration;
+/// #[endpoint]
+/// async fn ping() -> Result<Text, Error> {
+///     Ok(Text::from("pong"))
+/// }
+///
+/// async fn version(_req: http_types::Request, _params: Params) -> Result<Bytes, Error> {
+///     Ok(Bytes::from((b"1.4.2".to_vec(), "application/x-version".parse().unwrap())))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"ping"), ___ping);
+/// router.add(Method::Get, Route::from("/version"), version);
+/// let server = Server::bind(Config::new("localhost:0")).unwrap();
+/// let addr = server.local_addr().unwrap();
+/// std::thread::spawn(move || server.run(router).map_err(|e| e.to_string()));
+///
+/// let get = |path: &'static str| async move {
+///     let mut stream = TcpStream::connect(addr).await.unwrap();
+///     let req = format!("GET {} HTTP/1.1\r\nhost: localhost\r\n\r\n", path);
+///     stream.write_all(req.as_bytes()).await.unwrap();
+///     let (mut res, mut buf) = (vec![], [0; 1024]);
+///     while let Ok(n) = io::timeout(Duration::from_millis(200), stream.read(&mut buf)).await {
+///         if n == 0 {
+///             break;
+///         }
+///         res.extend_from_slice(&buf[..n]);
+///     }
+///     String::from_utf8(res).unwrap()
+/// };
+///
+/// task::block_on(async {
+///     let res = get("/ping").await;
+///     assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+///     assert!(res.contains("\r\ncontent-type: text/plain;charset=utf-8\r\n"), "{}", res);
+///     assert!(res.contains("\r\ncontent-length: 4\r\n"), "{}", res);
+///     assert!(res.ends_with("\r\n\r\npong"), "{}", res);
+///
+///     let res = get("/version").await;
+///     assert!(res.contains("\r\ncontent-type: application/x-version\r\n"), "{}", res);
+///     assert!(res.contains("\r\ncontent-length: 5\r\n"), "{}", res);
+///     assert!(res.ends_with("\r\n\r\n1.4.2"), "{}", res);
+/// });
+/// ```
+///
+/// Errors are still sent as JSON:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[endpoint]
+/// async fn greet(name: RawQuery) -> Result<Response, Error> {
+///     if name.0.is_empty() {
+///         return Err(Error::bad_request("name required"));
+///     }
+///     Ok(Text::from(format!("hello {}", name.0)).into())
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"greet"), ___greet);
+/// let router = Arc::new(router);
+///
+/// let get = |url: &str| async_std::task::block_on(router.clone().respond(Request::new(Method::Get, Url::parse(url).unwrap())));
+/// let content_type = |res: &Response| res.header(&"content-type".parse().unwrap()).unwrap()[0].to_string();
+///
+/// assert_eq!(content_type(&get("http://localhost/greet?ada")), "text/plain;charset=utf-8");
+/// let res = get("http://localhost/greet");
+/// assert_eq!(res.status(), StatusCode::BadRequest);
+/// assert!(content_type(&res).starts_with("application/json"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Text {
+    body: Cow<'static, str>,
+    essence: Cow<'static, str>,
+    status: StatusCode,
+}
+
+impl Text {
+    /// Create a `200 OK` text response.
+    pub fn new(body: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            body: body.into(),
+            essence: Cow::Borrowed("text/plain"),
+            status: StatusCode::Ok,
+        }
+    }
+
+    /// Send the text with a different type, such as `text/html`.  Any parameters of `mime` are
+    /// replaced with `charset=utf-8`.
+    pub fn with_type(mut self, mime: Mime) -> Self {
+        self.essence = Cow::Owned(mime.essence().to_owned());
+        self
+    }
+
+    /// Send the text with a status other than `200 OK`.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// The text.
+    pub fn as_str(&self) -> &str {
+        &self.body
+    }
+}
+
+impl From<&'static str> for Text {
+    fn from(body: &'static str) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<String> for Text {
+    fn from(body: String) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Cow<'static, str>> for Text {
+    fn from(body: Cow<'static, str>) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Text> for http_types::Response {
+    fn from(text: Text) -> Self {
+        let mime: Mime = format!("{};charset=utf-8", text.essence)
+            .parse()
+            .unwrap_or(mime::PLAIN);
+        let body = match text.body {
+            Cow::Borrowed(body) => static_body(body.as_bytes()),
+            Cow::Owned(body) => body.into_bytes().into(),
+        };
+        respond(text.status, body, mime)
+    }
+}
+
+/// A raw bytes response body, sent as `application/octet-stream` unless another type is given.
+///
+/// Bytes built from a `&'static [u8]` are sent without being copied.  Bytes of another type can
+/// be made from a body and its type together.
+///
+/// ## Examples
+/// ```
+/// # use windmill::Bytes;
+/// # use http_types::Response;
+/// static PIXEL: &[u8] = &[0x47, 0x49, 0x46, 0x38, 0x39, 0x61];
+///
+/// let bytes = Bytes::from(PIXEL);
+/// assert_eq!(bytes.as_slice().as_ptr(), PIXEL.as_ptr());
+///
+/// let res: Response = bytes.with_type("image/gif".parse().unwrap()).into();
+/// assert_eq!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str(), "image/gif");
+///
+/// let res: Response = Bytes::from(vec![0, 159, 146, 150]).into();
+/// assert_eq!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str(), "application/octet-stream");
+/// assert_eq!(res.len(), Some(4));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bytes {
+    body: Cow<'static, [u8]>,
+    mime: Mime,
+    status: StatusCode,
+}
+
+impl Bytes {
+    /// Create a `200 OK` bytes response.
+    pub fn new(body: impl Into<Cow<'static, [u8]>>) -> Self {
+        Self {
+            body: body.into(),
+            mime: mime::BYTE_STREAM,
+            status: StatusCode::Ok,
+        }
+    }
+
+    /// Send the bytes with a type other than `application/octet-stream`.
+    pub fn with_type(mut self, mime: Mime) -> Self {
+        self.mime = mime;
+        self
+    }
+
+    /// Send the bytes with a status other than `200 OK`.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// The bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+impl From<&'static [u8]> for Bytes {
+    fn from(body: &'static [u8]) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(body: Vec<u8>) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Cow<'static, [u8]>> for Bytes {
+    fn from(body: Cow<'static, [u8]>) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<(Vec<u8>, Mime)> for Bytes {
+    fn from((body, mime): (Vec<u8>, Mime)) -> Self {
+        Self::new(body).with_type(mime)
+    }
+}
+
+impl From<Bytes> for http_types::Response {
+    fn from(bytes: Bytes) -> Self {
+        let body = match bytes.body {
+            Cow::Borrowed(body) => static_body(body),
+            Cow::Owned(body) => body.into(),
+        };
+        respond(bytes.status, body, bytes.mime)
+    }
+}
+
+/// A redirect to another location, sent with a `Location` header and an empty body.
+///
+/// The location is either an absolute url or a path, relative to the url of the request, and is
+/// checked when the redirect is made: a location that is empty or holds whitespace, control or
+/// non-ASCII characters cannot be sent in a header, and is an internal error.  Percent-encode
+/// such characters first.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// #[endpoint]
+/// async fn old() -> Result<Redirect, Error> {
+///     Redirect::permanent("/new")
+/// }
+///
+/// #[endpoint]
+/// async fn new() -> Result<Response, Error> {
+///     Ok(Response::from("new"))
+/// }
+///
+/// #[endpoint]
+/// async fn login() -> Result<Redirect, Error> {
+///     Redirect::see_other("https://accounts.example.com/login?next=%2Fnew")
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"old"), ___old);
+/// router.add(Method::Get, route!(/"new"), ___new);
+/// router.add(Method::Post, route!(/"login"), ___login);
+/// let client = TestClient::new(router);
+/// let location = |res: &Response| res.header(&"location".parse().unwrap()).map(|v| v[0].to_string());
+///
+/// task::block_on(async {
+///     let mut res = client.get("/old").send().await;
+///     assert_eq!(res.status(), StatusCode::MovedPermanently);
+///     assert_eq!(location(&res).as_deref(), Some("/new"));
+///     assert_eq!(res.body_string().await.unwrap(), "");
+///
+///     let res = client.post("/login").send().await;
+///     assert_eq!(res.status(), StatusCode::SeeOther);
+///     assert_eq!(location(&res).as_deref(), Some("https://accounts.example.com/login?next=%2Fnew"));
+/// });
+///
+/// let redirect = Redirect::temporary("/maintenance").unwrap();
+/// assert_eq!(redirect.status(), StatusCode::TemporaryRedirect);
+/// assert_eq!(redirect.location(), "/maintenance");
+///
+/// assert!(Redirect::temporary("/new\r\nset-cookie: a=b").is_err());
+/// assert!(Redirect::temporary("/café").is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    location: String,
+    status: StatusCode,
+}
+
+impl Redirect {
+    /// A `301 Moved Permanently` redirect, for a resource that has moved for good.  Clients may
+    /// remember it, and may change a `POST` into a `GET` when following it.
+    pub fn permanent(location: impl Into<String>) -> Result<Self, Error> {
+        Self::new(StatusCode::MovedPermanently, location.into())
+    }
+
+    /// A `307 Temporary Redirect`, followed with the same method and body.
+    pub fn temporary(location: impl Into<String>) -> Result<Self, Error> {
+        Self::new(StatusCode::TemporaryRedirect, location.into())
+    }
+
+    /// A `303 See Other` redirect, followed with a `GET`, such as to the result of a form post.
+    pub fn see_other(location: impl Into<String>) -> Result<Self, Error> {
+        Self::new(StatusCode::SeeOther, location.into())
+    }
+
+    fn new(status: StatusCode, location: String) -> Result<Self, Error> {
+        let valid = !location.is_empty() && location.bytes().all(|b| b.is_ascii_graphic());
+        if !valid {
+            return Err(
+                Error::internal(format!("invalid redirect location {:?}", location))
+                    .with_code(WindmillErrorCode::Internal),
+            );
+        }
+        Ok(Self { location, status })
+    }
+
+    /// The status of the redirect.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Where the redirect points.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+impl From<Redirect> for http_types::Response {
+    fn from(redirect: Redirect) -> Self {
+        let mut res = http_types::Response::new(redirect.status);
+        let _ = res.insert_header("location", redirect.location);
+        res
+    }
+}
+
+/// A body that reads straight from static data instead of copying it into a buffer first.
+fn static_body(bytes: &'static [u8]) -> http_types::Body {
+    http_types::Body::from_reader(Cursor::new(bytes), Some(bytes.len()))
+}
+
+fn respond(status: StatusCode, body: http_types::Body, mime: Mime) -> http_types::Response {
+    let mut res = http_types::Response::new(status);
+    res.set_body(body);
+    let _ = res.set_content_type(mime);
+    res
+}
@@ -1,56 +1,769 @@
-use crate::params::Params;
-use std::{future::Future, pin::Pin};
+#[cfg(feature = "openapi")]
+use crate::openapi::{schema_of, RouteDoc};
+use crate::{
+    extensions::Extensions,
+    host::HostPattern,
+    kill_switch::RouteSwitch,
+    params::Params,
+    props::StatefulProps,
+    timeout::Timeouts,
+    upload::{UploadProgress, UploadProgressHook},
+    util::try_percent_decode,
+};
+use std::{
+    any::{type_name, Any},
+    borrow::Cow,
+    fmt::{self, Display},
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 pub(crate) type ResponseFuture = Pin<Box<dyn Future<Output = http_types::Response> + Send + Sync>>;
 pub(crate) type RouteFn = Box<dyn Fn(http_types::Request, Params) -> ResponseFuture + Send + Sync>;
 
 /// A route constructed using the [`route!`](macro.route.html) macro.  
+#[derive(Default)]
 pub struct Route {
     pub static_segments: Vec<StaticSegment>,
     pub dynamic_segments: Vec<DynamicSegment>,
     pub handler: Option<RouteFn>,
+    pub class: Option<&'static str>,
+    pub https_only: bool,
+    pub health_check: bool,
+    pub name: Option<&'static str>,
+    pub tags: Vec<&'static str>,
+    pub metadata: Extensions,
+    pub timeouts: Option<Timeouts>,
+    pub handler_timeout: Option<Duration>,
+    pub run_to_completion: bool,
+    pub etag: bool,
+    #[doc(hidden)]
+    pub switch: Arc<RouteSwitch>,
+    #[doc(hidden)]
+    pub host: Option<Arc<HostPattern>>,
+    #[doc(hidden)]
+    pub upload_progress: Option<UploadProgressHook>,
+    #[doc(hidden)]
+    pub rest: Option<&'static str>,
+    #[doc(hidden)]
+    pub optional: usize,
+    #[cfg(feature = "openapi")]
+    #[doc(hidden)]
+    pub doc: RouteDoc,
+}
+
+impl Route {
+    /// Put the route in a class for [weighted admission](struct.Router.html#method.max_in_flight).
+    /// Routes without a class belong to the `"default"` class.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// let route = route!(/"admin"/"health").class("admin");
+    /// ```
+    pub fn class(mut self, class: &'static str) -> Self {
+        self.class = Some(class);
+        self
+    }
+
+    /// Redirect plaintext requests for the route to https, see
+    /// [`Router::https_redirect`](struct.Router.html#method.https_redirect).  
+    pub fn https_only(mut self) -> Self {
+        self.https_only = true;
+        self
+    }
+
+    /// Mark the route as a health check, which is never redirected to https so load balancers can
+    /// keep probing it over plaintext.  
+    pub fn health_check(mut self) -> Self {
+        self.health_check = true;
+        self
+    }
+
+    /// Name the route, for middlewares to refer to it by through
+    /// [`MatchedRoute`](struct.MatchedRoute.html), and to build its paths with
+    /// [`Router::url_for`](struct.Router.html#method.url_for).  
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Summarize what the route does in its [OpenAPI document](struct.Router.html#method.openapi).  
+    #[cfg(feature = "openapi")]
+    pub fn summary(mut self, summary: &'static str) -> Self {
+        self.doc.summary = Some(summary);
+        self
+    }
+
+    /// Describe the JSON request body of the route with the schema of `T` in its
+    /// [OpenAPI document](struct.Router.html#method.openapi).  
+    #[cfg(feature = "openapi")]
+    pub fn request_schema<T: schemars::JsonSchema>(mut self) -> Self {
+        self.doc.request = Some(schema_of::<T>);
+        self
+    }
+
+    /// Describe the JSON response body of the route with the schema of `T` in its
+    /// [OpenAPI document](struct.Router.html#method.openapi).  
+    #[cfg(feature = "openapi")]
+    pub fn response_schema<T: schemars::JsonSchema>(mut self) -> Self {
+        self.doc.response = Some(schema_of::<T>);
+        self
+    }
+
+    /// Only match paths where `predicate` accepts the value of the dynamic segment `name`, like
+    /// `route!(/"articles"/id if is_digits)` does.  The predicate sees the value with its `%XX`
+    /// escapes decoded.  
+    ///
+    /// ## Panics
+    /// If the route has no dynamic segment `name`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// let route = Route::from("/articles/{id}").constrain("id", |id| id.parse::<u64>().is_ok());
+    /// ```
+    pub fn constrain(mut self, name: &str, predicate: fn(&str) -> bool) -> Self {
+        let route = self.to_string();
+        let segment = self
+            .dynamic_segments
+            .iter_mut()
+            .find(|segment| segment.name == name)
+            .unwrap_or_else(|| panic!("route {} has no dynamic segment `{}`", route, name));
+        segment.constraint = Some(predicate);
+        self
+    }
+
+    /// Tag the route, for middlewares that treat groups of routes alike.  
+    pub fn tag(mut self, tag: &'static str) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Attach a typed value to the route that middlewares and endpoints can read through
+    /// [`MatchedRoute::metadata`](struct.MatchedRoute.html#method.metadata).  A route holds one
+    /// value of each type, attaching another of the same type replaces it.  
+    pub fn metadata<T: Send + Sync + 'static>(self, value: T) -> Self {
+        self.metadata.insert(value);
+        self
+    }
+
+    /// Give the route an instance of the [stateful props](trait.StatefulProps.html) `P`, for its
+    /// endpoint to take as [`With<P>`](struct.With.html).  
+    pub fn props<P: StatefulProps>(self, props: P) -> Self {
+        self.metadata(Arc::new(props))
+    }
+
+    /// Hold the route to its own [time budgets](struct.Timeouts.html) instead of the ones set with
+    /// [`Config::with_timeouts`](struct.Config.html#method.with_timeouts).  
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
+    /// Answer with `504 Gateway Timeout` and drop the endpoint if it has not returned its
+    /// response within `timeout` of the request being routed, instead of the timeout set with
+    /// [`Config::with_handler_timeout`](struct.Config.html#method.with_handler_timeout).  The
+    /// endpoint can read when that is with [`Deadline`](struct.Deadline.html).  
+    pub fn handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep running the endpoint when its client closes the connection, instead of
+    /// [cancelling it](struct.Router.html#method.client_aborts), for endpoints with side effects
+    /// that must not stop halfway.  
+    pub fn run_to_completion(mut self) -> Self {
+        self.run_to_completion = true;
+        self
+    }
+
+    /// Tag the `200 OK` responses of the route with an [`ETag`](struct.ETag.html) and answer
+    /// conditional requests for it.
+    ///
+    /// Responses without an `ETag` header of their own are tagged with a hash of their body,
+    /// taken before [compression](struct.Config.html#method.with_compression) so the tag is the
+    /// same whatever the encoding.  A `GET` or `HEAD` whose `If-None-Match` matches the tag is
+    /// answered with `304 Not Modified`, keeping the headers of the response but not its body,
+    /// and a request whose `If-Match` does not match it with `412 Precondition Failed`.  The tag
+    /// is only known once the endpoint has run, so endpoints that change the resource should
+    /// check `If-Match` themselves with [`Preconditions`](struct.Preconditions.html) first.
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// #[endpoint]
+    /// async fn settings() -> Result<Response, Error> {
+    ///     let mut res = Response::from(r#"{"theme":"dark"}"#);
+    ///     res.insert_header("cache-control", "no-cache").unwrap();
+    ///     Ok(res)
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"settings").with_etag(), ___settings);
+    /// let client = TestClient::new(router);
+    /// let header = |res: &Response, name: &str| res.header(&name.parse().unwrap()).map(|values| values[0].to_string());
+    ///
+    /// task::block_on(async {
+    ///     let mut res = client.get("/settings").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     let etag = header(&res, "etag").unwrap();
+    ///     assert!(etag.starts_with('"') && etag.len() == 34, "{}", etag);
+    ///     assert_eq!(res.body_string().await.unwrap(), r#"{"theme":"dark"}"#);
+    ///
+    ///     let mut res = client.get("/settings").header("if-none-match", &etag).send().await;
+    ///     assert_eq!(res.status(), StatusCode::NotModified);
+    ///     assert_eq!(header(&res, "etag"), Some(etag.clone()));
+    ///     assert_eq!(header(&res, "cache-control").as_deref(), Some("no-cache"));
+    ///     assert_eq!(res.body_string().await.unwrap(), "");
+    ///
+    ///     // A weak validator matches too, and so does one of a list.
+    ///     let weak = format!(r#""other", W/{}"#, etag);
+    ///     let res = client.get("/settings").header("if-none-match", &weak).send().await;
+    ///     assert_eq!(res.status(), StatusCode::NotModified);
+    ///
+    ///     let res = client.get("/settings").header("if-none-match", r#""stale""#).send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///
+    ///     let res = client.get("/settings").header("if-match", r#""stale""#).send().await;
+    ///     assert_eq!(res.status(), StatusCode::PreconditionFailed);
+    ///     let res = client.get("/settings").header("if-match", &etag).send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    /// });
+    /// ```
+    pub fn with_etag(mut self) -> Self {
+        self.etag = true;
+        self
+    }
+
+    /// Report how far the request body has been read to `callback`, every time another
+    /// `interval` bytes have come in, whether the endpoint reads it whole or as a stream.  
+    ///
+    /// A last report with [`end`](struct.UploadProgress.html#structfield.end) set is made when
+    /// the body is complete, when the client goes away before sending all of it, or when the
+    /// request is answered without reading all of it.  
+    ///
+    /// The callback runs on the read path of the body, so the upload waits for it: keep it to a
+    /// few atomic updates or a `try_send` on a bounded channel, which drops reports rather than
+    /// slowing the upload down.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{io::BufReader, net::{TcpListener, TcpStream}, prelude::*, task};
+    /// # use http_types::{Method, Request, Response, Url};
+    /// # use std::{sync::{Arc, Mutex}, time::Duration};
+    /// #[endpoint]
+    /// async fn store(body: Body<String, TextCodec>) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("stored {}", body.len())))
+    /// }
+    ///
+    /// let reports = Arc::new(Mutex::new(vec![]));
+    /// let sink = reports.clone();
+    /// let mut router = Router::new();
+    /// router.add(
+    ///     Method::Post,
+    ///     route!(/"uploads").on_upload_progress(1000, move |progress| sink.lock().unwrap().push(progress)),
+    ///     ___store,
+    /// );
+    /// let router = Arc::new(router);
+    ///
+    /// // A client sending `len` bytes in chunks of 250, hanging up after `sent` of them.
+    /// let upload = |len: usize, sent: usize| {
+    ///     let router = router.clone();
+    ///     task::block_on(async move {
+    ///         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    ///         let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    ///         let (server, _) = listener.accept().await.unwrap();
+    ///         task::spawn(async move {
+    ///             for _ in 0..sent / 250 {
+    ///                 client.write_all(&[b'x'; 250]).await.unwrap();
+    ///                 task::sleep(Duration::from_millis(2)).await;
+    ///             }
+    ///         });
+    ///
+    ///         let url = Url::parse("http://localhost/uploads").unwrap();
+    ///         let mut req = Request::new(Method::Post, url);
+    ///         req.insert_header("content-length", len.to_string()).unwrap();
+    ///         req.set_body(http_types::Body::from_reader(BufReader::new(server), Some(len)));
+    ///         router.respond(req).await
+    ///     })
+    /// };
+    ///
+    /// upload(4000, 4000);
+    /// let done: Vec<_> = reports.lock().unwrap().drain(..).collect();
+    /// let ticks: Vec<u64> = done.iter().filter(|p| p.end.is_none()).map(|p| p.received).collect();
+    /// assert_eq!(ticks, vec![1000, 2000, 3000, 4000]);
+    /// assert_eq!(done.last().unwrap().end, Some(UploadEnd::Completed));
+    /// assert_eq!(done.last().unwrap().content_length, Some(4000));
+    ///
+    /// // The client disconnects half way.
+    /// upload(4000, 2000);
+    /// let aborted: Vec<_> = reports.lock().unwrap().drain(..).collect();
+    /// assert_eq!(aborted.iter().filter(|p| p.end.is_none()).count(), 2);
+    /// assert_eq!(aborted.last().unwrap().end, Some(UploadEnd::Aborted));
+    /// assert_eq!(aborted.last().unwrap().received, 2000);
+    /// assert_eq!(aborted.iter().filter(|p| p.end.is_some()).count(), 1);
+    /// ```
+    pub fn on_upload_progress<F>(mut self, interval: u64, callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        self.upload_progress = Some(UploadProgressHook::new(interval, callback));
+        self
+    }
+}
+
+impl Route {
+    /// A copy of the route running `handler`, sharing its metadata and its
+    /// [switch](struct.RouterHandle.html).  
+    pub(crate) fn with_handler(&self, handler: RouteFn) -> Self {
+        Self {
+            static_segments: self.static_segments.clone(),
+            dynamic_segments: self.dynamic_segments.clone(),
+            handler: Some(handler),
+            class: self.class,
+            https_only: self.https_only,
+            health_check: self.health_check,
+            name: self.name,
+            tags: self.tags.clone(),
+            metadata: self.metadata.clone(),
+            timeouts: self.timeouts,
+            handler_timeout: self.handler_timeout,
+            run_to_completion: self.run_to_completion,
+            etag: self.etag,
+            switch: self.switch.clone(),
+            host: self.host.clone(),
+            upload_progress: self.upload_progress.clone(),
+            rest: self.rest,
+            optional: self.optional,
+            #[cfg(feature = "openapi")]
+            doc: self.doc,
+        }
+    }
+
+    /// The route with the segments of `prefix` in front of its own.  
+    pub(crate) fn prefixed(self, prefix: &Route) -> Self {
+        assert!(
+            prefix.optional == 0 && prefix.rest.is_none(),
+            "prefix {} must end with a segment that is always there",
+            prefix
+        );
+        let shift = prefix.len();
+        let static_segments = prefix
+            .static_segments
+            .iter()
+            .copied()
+            .chain(
+                self.static_segments
+                    .into_iter()
+                    .map(|segment| StaticSegment {
+                        position: segment.position + shift,
+                        ..segment
+                    }),
+            )
+            .collect();
+        let dynamic_segments = prefix
+            .dynamic_segments
+            .iter()
+            .copied()
+            .chain(
+                self.dynamic_segments
+                    .into_iter()
+                    .map(|segment| DynamicSegment {
+                        position: segment.position + shift,
+                        ..segment
+                    }),
+            )
+            .collect();
+        Self {
+            static_segments,
+            dynamic_segments,
+            ..self
+        }
+    }
+
+    /// Whether both routes match exactly the same paths.  
+    pub(crate) fn same_paths(&self, other: &Route) -> bool {
+        let statics = |route: &Route| {
+            let mut statics: Vec<_> = route
+                .static_segments
+                .iter()
+                .map(|segment| (segment.position, segment.value))
+                .collect();
+            statics.sort();
+            statics
+        };
+        let dynamics = |route: &Route| {
+            let mut dynamics: Vec<_> = route
+                .dynamic_segments
+                .iter()
+                .map(|segment| {
+                    let constraint = segment.constraint.map(|accepts| accepts as usize);
+                    (segment.position, segment.ty.map(|ty| ty.name), constraint)
+                })
+                .collect();
+            dynamics.sort();
+            dynamics
+        };
+        statics(self) == statics(other)
+            && dynamics(self) == dynamics(other)
+            && self.rest.is_some() == other.rest.is_some()
+            && self.optional == other.optional
+    }
+
+    /// Whether some path is matched by both routes, which is then ambiguous between them.  
+    ///
+    /// Routes are compared at their full length: a path leaving out the optional segments of a
+    /// route goes to a route of its length without them.  
+    ///
+    /// A typed segment does not meet a static segment that does not parse as its type, nor a
+    /// typed segment of another type: a path matching both is left to the route added first.
+    /// Likewise a constrained segment does not meet a static segment it rejects, nor a dynamic
+    /// segment with another constraint or none, the constrained route being tried first.  Dynamic
+    /// segments with the same constraint meet as unconstrained segments do.  
+    ///
+    /// If `case_insensitive` is set, static segments spelled in different cases meet.  
+    pub(crate) fn overlaps(&self, other: &Route, case_insensitive: bool) -> bool {
+        let (len, other_len) = (self.len(), other.len());
+        let lengths_meet = match (self.rest.is_some(), other.rest.is_some()) {
+            (false, false) => len == other_len,
+            (true, false) => other_len >= len,
+            (false, true) => len >= other_len,
+            (true, true) => true,
+        };
+        let static_at = |route: &Route, position| {
+            route
+                .static_segments
+                .iter()
+                .find(|segment| segment.position == position)
+                .map(|segment| segment.value)
+        };
+        let dynamic_at = |route: &Route, position| {
+            route
+                .dynamic_segments
+                .iter()
+                .find(|segment| segment.position == position)
+                .copied()
+        };
+        let type_at = |route: &Route, position| dynamic_at(route, position)?.ty;
+        let constraint_at = |route: &Route, position| dynamic_at(route, position)?.constraint;
+        let same_constraint = |position| {
+            let constraint = |route: &Route| constraint_at(route, position).map(|f| f as usize);
+            constraint(self) == constraint(other)
+        };
+        let accepts = |route: &Route, position, value: &str| {
+            !matches!(type_at(route, position), Some(ty) if (ty.parse)(value).is_err())
+                && !matches!(constraint_at(route, position), Some(accepts) if !accepts(value))
+        };
+        lengths_meet
+            && (0..len.min(other_len)).all(|position| {
+                match (static_at(self, position), static_at(other, position)) {
+                    (Some(value), Some(other_value)) => {
+                        value == other_value
+                            || (case_insensitive && value.eq_ignore_ascii_case(other_value))
+                    }
+                    (Some(value), None) => accepts(other, position, value),
+                    (None, Some(value)) => accepts(self, position, value),
+                    (None, None) if !same_constraint(position) => false,
+                    (None, None) => match (type_at(self, position), type_at(other, position)) {
+                        (Some(ty), Some(other_ty)) => ty.name == other_ty.name,
+                        _ => true,
+                    },
+                }
+            })
+    }
+
+    /// The first typed segment of the route whose value in `segments` does not parse, with
+    /// the reason.  
+    pub(crate) fn unparsed_segment<'r>(
+        &'r self,
+        segments: &[RawSegment],
+    ) -> Option<(&'r DynamicSegment, String)> {
+        self.dynamic_segments.iter().find_map(|segment| {
+            let ty = segment.ty?;
+            (ty.parse)(&segments.get(segment.position)?.value)
+                .err()
+                .map(|e| (segment, e))
+        })
+    }
+
+    /// The first constrained segment of the route whose value in `segments` is rejected.  
+    pub(crate) fn rejected_segment(&self, segments: &[RawSegment]) -> Option<&DynamicSegment> {
+        self.dynamic_segments.iter().find(|segment| {
+            let value = segments.get(segment.position).map(|segment| &segment.value);
+            matches!((segment.constraint, value), (Some(accepts), Some(value)) if !accepts(value))
+        })
+    }
+
+    /// Whether the values in `segments` satisfy the constraints of the route and parse as the
+    /// types of its segments, the constraints being checked first.  
+    pub(crate) fn accepts(&self, segments: &[RawSegment]) -> bool {
+        self.rejected_segment(segments).is_none() && self.unparsed_segment(segments).is_none()
+    }
+
+    /// The number of segments of the paths the route matches, at most.  
+    pub(crate) fn len(&self) -> usize {
+        self.static_segments.len() + self.dynamic_segments.len()
+    }
+
+    /// The number of segments of the paths the route matches, at least, leaving out its optional
+    /// segments.  
+    pub(crate) fn min_len(&self) -> usize {
+        self.len() - self.optional
+    }
+
+    /// The path of the route up to its first `len` segments, as an OpenAPI path template such as
+    /// `/example/{id}`, with the segment matching the rest of the path if there is one at the full
+    /// length.  
+    #[cfg(feature = "openapi")]
+    pub(crate) fn path_template(&self, len: usize) -> String {
+        let mut template = String::new();
+        for position in 0..len {
+            match self.static_segments.iter().find(|s| s.position == position) {
+                Some(segment) => template.push_str(&format!("/{}", segment.value)),
+                None => {
+                    let segment = self
+                        .dynamic_segments
+                        .iter()
+                        .find(|s| s.position == position);
+                    template.push_str(&format!("/{{{}}}", segment.map_or("", |s| s.name)));
+                }
+            }
+        }
+        if let (Some(rest), true) = (self.rest, len == self.len()) {
+            template.push_str(&format!("/{{{}}}", rest));
+        }
+        if template.is_empty() {
+            template.push('/');
+        }
+        template
+    }
+
+    /// How specific the host rule of the route is, then the number of dynamic segments with a
+    /// type or a constraint, routes that are more specific being tried first among routes of the
+    /// same shape.  
+    pub(crate) fn specificity(&self) -> (Option<(bool, usize)>, usize) {
+        let host = self.host.as_ref().map(|host| host.specificity());
+        let typed = self
+            .dynamic_segments
+            .iter()
+            .filter(|segment| segment.ty.is_some() || segment.constraint.is_some())
+            .count();
+        (host, typed)
+    }
+}
+
+impl From<&'static str> for Route {
+    /// Parse a pattern such as `/tenants/{tenant_id}`, the form routes are displayed in, where
+    /// segments in braces are dynamic.  A last segment such as `{path*}` matches the rest of the
+    /// path, any number of segments.  The last dynamic segments can be optional, such as
+    /// `{month?}`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::Route;
+    /// let route = Route::from("/tenants/{tenant_id}/orders");
+    /// assert_eq!(route.to_string(), "/tenants/{tenant_id}/orders");
+    /// assert_eq!(Route::from("/").to_string(), "/");
+    /// assert_eq!(Route::from("/assets/{path*}").to_string(), "/assets/{path*}");
+    /// assert_eq!(Route::from("/reports/{year?}/{month?}").to_string(), "/reports/{year?}/{month?}");
+    /// ```
+    ///
+    /// ## Panics
+    /// If a segment follows the segment matching the rest of the path, or an optional segment is
+    /// followed by one that is not.  
+    /// ```should_panic
+    /// # use windmill::Route;
+    /// let route = Route::from("/reports/{year?}/summary");
+    /// ```
+    fn from(pattern: &'static str) -> Self {
+        let mut route = Route::default();
+        pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .enumerate()
+            .for_each(|(position, segment)| {
+                assert!(
+                    route.rest.is_none(),
+                    "only the last segment of route `{}` can match the rest of the path",
+                    pattern
+                );
+                let dynamic = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}'));
+                let optional = matches!(dynamic, Some(name) if name.ends_with('?'));
+                assert!(
+                    optional || route.optional == 0,
+                    "only the last segments of route `{}` can be optional",
+                    pattern
+                );
+                match dynamic {
+                    Some(name) if name.ends_with('*') => {
+                        route.rest = Some(&name[..name.len() - 1]);
+                    }
+                    Some(name) if optional => {
+                        route.optional += 1;
+                        route.dynamic_segments.push(DynamicSegment {
+                            name: &name[..name.len() - 1],
+                            position,
+                            ty: None,
+                            constraint: None,
+                        });
+                    }
+                    Some(name) => route.dynamic_segments.push(DynamicSegment {
+                        name,
+                        position,
+                        ty: None,
+                        constraint: None,
+                    }),
+                    None => route.static_segments.push(StaticSegment {
+                        value: segment,
+                        position,
+                    }),
+                }
+            });
+        route
+    }
+}
+
+impl fmt::Display for Route {
+    /// Render the route as a pattern such as `/example/{id}`, or `/reports/{year}/{month?}` with
+    /// optional segments.  
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.len();
+        if len == 0 && self.rest.is_none() {
+            return write!(f, "/");
+        }
+
+        for position in 0..len {
+            if let Some(segment) = self.static_segments.iter().find(|s| s.position == position) {
+                write!(f, "/{}", segment.value)?;
+            } else if let Some(segment) = self
+                .dynamic_segments
+                .iter()
+                .find(|s| s.position == position)
+            {
+                match position < self.min_len() {
+                    true => write!(f, "/{{{}}}", segment.name)?,
+                    false => write!(f, "/{{{}?}}", segment.name)?,
+                }
+            }
+        }
+        if let Some(rest) = self.rest {
+            write!(f, "/{{{}*}}", rest)?;
+        }
+        Ok(())
+    }
 }
 
 #[doc(hidden)]
+#[derive(Clone, Copy)]
 pub struct StaticSegment {
     pub value: &'static str,
     pub position: usize,
 }
 
+impl StaticSegment {
+    /// Whether `value` is the segment, without regard to ASCII case if `case_insensitive` is set.  
+    pub(crate) fn matches(&self, value: &str, case_insensitive: bool) -> bool {
+        self.value == value || (case_insensitive && self.value.eq_ignore_ascii_case(value))
+    }
+}
+
 #[doc(hidden)]
+#[derive(Clone, Copy)]
 pub struct DynamicSegment {
     pub name: &'static str,
     pub position: usize,
+    pub ty: Option<SegmentType>,
+    pub constraint: Option<fn(&str) -> bool>,
+}
+
+/// The type a dynamic segment must parse as for its route to match.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct SegmentType {
+    pub name: &'static str,
+    pub parse: fn(&str) -> Result<Arc<dyn Any + Send + Sync>, String>,
+}
+
+impl SegmentType {
+    pub fn of<T>() -> Self
+    where
+        T: FromStr + Send + Sync + 'static,
+        T::Err: Display,
+    {
+        Self {
+            name: type_name::<T>(),
+            parse: parse_segment::<T>,
+        }
+    }
+}
+
+fn parse_segment<T>(value: &str) -> Result<Arc<dyn Any + Send + Sync>, String>
+where
+    T: FromStr + Send + Sync + 'static,
+    T::Err: Display,
+{
+    match value.parse::<T>() {
+        Ok(value) => Ok(Arc::new(value)),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 pub(crate) struct RawSegment<'s> {
-    pub(crate) value: &'s str,
+    /// The segment as it appears in the path.
+    pub(crate) raw: &'s str,
+    /// The segment with its `%XX` escapes decoded, including `%2F` which did not split it.
+    pub(crate) value: Cow<'s, str>,
     position: usize,
 }
 
 pub(crate) struct RawRoute<'s> {
     pub raw_segments: Vec<RawSegment<'s>>,
+    /// Whether a segment decodes to invalid UTF-8, so the path matches no route.
+    pub(crate) malformed: bool,
 }
 
 impl<'s> RawRoute<'s> {
     pub(crate) fn from_path(path: &'s str) -> Self {
-        Self {
-            raw_segments: path
-                .split("/")
-                .skip(1)
-                .enumerate()
-                .map(|(i, segment)| RawSegment {
-                    value: segment,
+        let mut malformed = false;
+        let raw_segments = path
+            .split('/')
+            .skip(1)
+            .enumerate()
+            .map(|(i, raw)| {
+                let value = match raw.contains('%') {
+                    true => try_percent_decode(raw).map(Cow::Owned).unwrap_or_else(|| {
+                        malformed = true;
+                        Cow::Borrowed(raw)
+                    }),
+                    false => Cow::Borrowed(raw),
+                };
+                RawSegment {
+                    raw,
+                    value,
                     position: i,
-                })
-                .collect(),
+                }
+            })
+            .collect();
+        Self {
+            raw_segments,
+            malformed,
         }
     }
 }
 
 impl<'s> PartialEq<RawSegment<'s>> for StaticSegment {
     fn eq(&self, other: &RawSegment) -> bool {
-        self.position == other.position && self.value == other.value
+        self.position == other.position && self.value == other.value.as_ref()
     }
 }
 
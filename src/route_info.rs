@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+/// A route registered with a router, as listed by
+/// [`Router::routes`](struct.Router.html#method.routes).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RouteInfo {
+    /// The method of the route.
+    pub method: String,
+    /// The route pattern, e.g. `/users/{id}`.
+    pub pattern: String,
+    /// The names of the dynamic segments of the route, in the order they appear, ending with
+    /// the segment matching the rest of the path if there is one.
+    pub params: Vec<&'static str>,
+    /// The name of the route, if it was given one.
+    pub name: Option<&'static str>,
+}
+
+/// Lay `routes` out in aligned columns, one route per line under a header.
+pub(crate) fn table(routes: &[RouteInfo]) -> String {
+    let width = |header: &str, column: &dyn Fn(&RouteInfo) -> usize| {
+        routes.iter().map(column).fold(header.len(), usize::max)
+    };
+    let method_width = width("METHOD", &|route| route.method.len());
+    let pattern_width = width("PATTERN", &|route| route.pattern.len());
+
+    let line = |method: &str, pattern: &str, name: &str| {
+        let line = format!(
+            "{:method_width$}  {:pattern_width$}  {}",
+            method,
+            pattern,
+            name,
+            method_width = method_width,
+            pattern_width = pattern_width
+        );
+        line.trim_end().to_owned()
+    };
+    std::iter::once(line("METHOD", "PATTERN", "NAME"))
+        .chain(routes.iter().map(|route| {
+            line(
+                &route.method,
+                &route.pattern,
+                route.name.unwrap_or_default(),
+            )
+        }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
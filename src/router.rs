@@ -1,17 +1,93 @@
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Metrics};
+#[cfg(feature = "openapi")]
+use crate::openapi::{self, ApiInfo};
 use crate::{
+    access_log::ReceivedAt,
+    admission::{Admission, ClassMetrics, DEFAULT_CLASS},
+    codec::{Codec, JsonCodec},
+    config::Config,
+    cors::Cors,
+    decorator::Decorator,
+    disconnect::{self, ClientAborted, ClientConnection},
+    drain::BodyTracker,
     endpoint::Endpoint,
+    error::{request_head, Error, ErrorFormatter, RouterError, WindmillErrorCode},
+    etag::{self, Preconditions},
+    explain::{explain_route, Candidate, MatchExplanation, Outcome},
+    extensions::Extensions,
+    host::{request_host, HostCaptures, HostPattern},
+    https::HttpsRedirect,
+    kill_switch::{admin_endpoint, RouterHandle},
+    limits::EffectiveLimits,
+    live_reload::LiveReload,
+    matched::{MatchedRoute, RequestLabel},
+    middleware::{MiddlewareFn, Next},
+    observer::{RequestInfo, RequestObserver, ResponseInfo},
+    panic::{self, CatchUnwind},
     params::Params,
-    route::{RawRoute, ResponseFuture, Route},
+    replay::Recorder,
+    request_id::{RequestId, DEFAULT_REQUEST_ID_HEADER},
+    route::{RawRoute, ResponseFuture, Route, RouteFn},
+    route_info::{self, RouteInfo},
+    serve_dir::{serve_file, ServeDir},
+    state::AppState,
+    timeout::{self, Deadline, TimeoutCounters, TimeoutMetrics},
+    trailing_slash::{self, TrailingSlash},
+    trie::RouteTable,
+    urls::Urls,
+    util::{response, set_content_length},
+    ws::{self, WebSocket},
 };
-use http_types::{mime, Method, Mime, StatusCode};
-use std::{collections::HashMap, future::Future, sync::Arc};
+use http_types::{
+    headers::{self, HeaderName},
+    mime, Method, StatusCode,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+type Authorize = Arc<dyn Fn(&http_types::Request) -> bool + Send + Sync>;
+
+/// How routes added with [`Router::any`](struct.Router.html#method.any) list their method.
+const ANY: &str = "*";
 
 /// The router for routing requests.  
 ///
 /// A route in the router is composed of an `http-types::Method`, a
 /// [`Route`](struct.Route.html), and an endpoint.  
 pub struct Router {
-    table: HashMap<Method, Vec<Route>>,
+    table: HashMap<Method, RouteTable>,
+    any: RouteTable,
+    decorators: Vec<Decorator>,
+    observers: Vec<Arc<dyn RequestObserver>>,
+    debug_explain: bool,
+    recorder: Option<Recorder>,
+    admission: Arc<Admission>,
+    https: Option<HttpsRedirect>,
+    live_reload: Option<LiveReload>,
+    state: AppState,
+    pre_routing: Vec<MiddlewareFn>,
+    middlewares: Vec<MiddlewareFn>,
+    not_found: Option<RouteFn>,
+    handle: RouterHandle,
+    route_admin: Option<Authorize>,
+    error_formatter: Option<ErrorFormatter>,
+    timeouts: Arc<TimeoutCounters>,
+    client_aborts: Arc<AtomicU64>,
+    cors: Option<Cors>,
+    trailing_slash: TrailingSlash,
+    reject_unparsed_params: bool,
+    case_insensitive: bool,
+    urls: Urls,
+    #[cfg(feature = "openapi")]
+    openapi: Option<ApiInfo>,
 }
 
 impl Router {
@@ -25,6 +101,30 @@ impl Router {
     pub fn new() -> Self {
         Router {
             table: HashMap::new(),
+            any: RouteTable::default(),
+            decorators: vec![],
+            observers: vec![],
+            debug_explain: false,
+            recorder: None,
+            admission: Arc::new(Admission::new()),
+            https: None,
+            live_reload: None,
+            state: AppState::default(),
+            pre_routing: vec![],
+            middlewares: vec![],
+            not_found: None,
+            handle: RouterHandle::default(),
+            route_admin: None,
+            error_formatter: None,
+            timeouts: Arc::default(),
+            client_aborts: Arc::default(),
+            cors: None,
+            trailing_slash: TrailingSlash::default(),
+            reject_unparsed_params: false,
+            case_insensitive: false,
+            urls: Urls::default(),
+            #[cfg(feature = "openapi")]
+            openapi: None,
         }
     }
 
@@ -50,10 +150,377 @@ impl Router {
     /// router.add(Method::Get, route!(/"example2"), ___example2);
     /// ```
     ///
-    /// ## Precedence and ambiguity
+    /// ## Matching
+    ///
+    /// A route matches paths with as many segments as it has, each static segment equal to the
+    /// segment of the path at its position.  A last `{name*}` segment matches the rest of the path,
+    /// any number of segments.  The routes are looked up in a trie of their segments, which at
+    /// every segment prefers a static segment to a dynamic one, and both to the rest of the path.  
+    ///
+    /// Segments are compared and passed in params with their `%XX` escapes decoded, after the
+    /// path is split, so an encoded `%2F` stays in its segment.  A `+` is not a space in a path.
+    /// Paths that do not decode to UTF-8 are `400 Bad Request`.  The rest of the path is passed as
+    /// it was sent, escapes and all, since decoding it would lose where its segments end.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let mut params: Vec<_> = params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    ///     params.sort();
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     Ok(Response::from(format!("{} {}", pattern, params.join(" "))))
+    /// }
+    ///
+    /// let mut me = Router::new();
+    /// me.add(Method::Get, route!(/"me"/"orders"), show);
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id/"orders"/order), show);
+    /// router.add(Method::Get, route!(/"users"/id), show);
+    /// router.add(Method::Get, Route::from("/files/{path*}"), show);
+    /// router.add(Method::Get, route!(/"caf\u{e9}"/item), show);
+    /// // Mounted later, beside routes with fewer and more segments.
+    /// router.mount("/users", me);
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |path: &str| {
+    ///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    ///     let res = task::block_on(router.clone().respond(Request::new(Method::Get, url)));
+    ///     (res.status(), task::block_on(res.body_string()).unwrap())
+    /// };
+    ///
+    /// assert_eq!(get("/users/7").1, "/users/{id} id=7");
+    /// assert_eq!(get("/users/7/orders/9").1, "/users/{id}/orders/{order} id=7 order=9");
+    /// assert_eq!(get("/users/me/orders/9").1, "/users/{id}/orders/{order} id=me order=9");
+    /// assert_eq!(get("/users/me/orders").1, "/users/me/orders ");
+    /// assert_eq!(get("/users/me").1, "/users/{id} id=me");
+    /// assert_eq!(get("/files").1, "/files/{path*} path=");
+    /// assert_eq!(get("/files/css/site.css").1, "/files/{path*} path=css/site.css");
+    ///
+    /// // Escapes are decoded once the path is split into segments.
+    /// assert_eq!(get("/users/hello%20world").1, "/users/{id} id=hello world");
+    /// assert_eq!(get("/users/%E2%9C%93").1, "/users/{id} id=\u{2713}");
+    /// assert_eq!(get("/users/a+b").1, "/users/{id} id=a+b");
+    /// assert_eq!(get("/users/a%2Fb").1, "/users/{id} id=a/b");
+    /// assert_eq!(get("/users/a%2Fb/orders/9").1, "/users/{id}/orders/{order} id=a/b order=9");
+    /// assert_eq!(get("/caf%C3%A9/cr%C3%AApe").1, "/caf\u{e9}/{item} item=cr\u{ea}pe");
+    /// assert_eq!(get("/files/a%2Fb/c%20d").1, "/files/{path*} path=a%2Fb/c%20d");
+    /// assert_eq!(get("/users/%FF").0, StatusCode::BadRequest);
+    ///
+    /// // Too few or too many segments.
+    /// assert_eq!(get("/users").0, StatusCode::NotFound);
+    /// assert_eq!(get("/users/7/orders").0, StatusCode::NotFound);
+    /// assert_eq!(get("/users/7/").0, StatusCode::NotFound);
+    /// assert_eq!(get("/users/7/orders/9/items").0, StatusCode::NotFound);
+    /// ```
+    ///
+    /// ## Typed segments
+    ///
+    /// A dynamic segment given a type, as in `route!(/"users"/user_id: u64)`, only matches values
+    /// that parse as the type, and other paths fall through to the routes after it, or to a
+    /// `404 Not Found` unless [`reject_unparsed_params`](#method.reject_unparsed_params) is set.
+    /// Because of this a typed segment can sit beside static segments that do not parse as its
+    /// type, and beside typed segments of other types, which are tried in the order they were
+    /// added.  The parsed values are in [`Params::typed`](struct.Params.html#method.typed).  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     let typed = (params.typed::<u64>("id"), params.typed::<bool>("flag"));
+    ///     Ok(Response::from(format!("{} {:?}", pattern, typed)))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/user_id: u64/"posts"/post_id: u64), show);
+    /// router.add(Method::Get, route!(/"items"/id: u64), show);
+    /// router.add(Method::Get, route!(/"items"/"new"), show);
+    /// router.add(Method::Get, route!(/"items"/flag: bool), show);
+    /// let client = TestClient::new(router);
+    /// let client = &client;
+    /// let get = |path: &'static str| async move {
+    ///     let mut res = client.get(path).send().await;
+    ///     (res.status(), res.body_string().await.unwrap())
+    /// };
+    ///
+    /// task::block_on(async {
+    ///     assert_eq!(get("/users/1/posts/3").await.0, StatusCode::Ok);
+    ///     assert_eq!(get("/users/abc/posts/3").await.0, StatusCode::NotFound);
+    ///     assert_eq!(get("/users/1/posts/-3").await.0, StatusCode::NotFound);
+    ///     assert_eq!(get("/items/7").await.1, "/items/{id} (Some(7), None)");
+    ///     assert_eq!(get("/items/new").await.1, "/items/new (None, None)");
+    ///     assert_eq!(get("/items/true").await.1, "/items/{flag} (None, Some(true))");
+    ///     assert_eq!(get("/items/old").await.0, StatusCode::NotFound);
+    /// });
+    /// ```
+    ///
+    /// ## Constrained segments
+    ///
+    /// A dynamic segment followed by `if` and a function, as in `route!(/"articles"/id if
+    /// is_digits)`, only matches values the function accepts, and other paths fall through like
+    /// they do for typed segments.  A constrained segment can sit beside a static segment it
+    /// rejects and beside any other dynamic segment, and routes of the same shape with more typed
+    /// or constrained segments are tried first, whatever the order they were added in.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response};
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     let mut params: Vec<_> = params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    ///     params.sort();
+    ///     Ok(Response::from(format!("{} {}", pattern, params.join(" "))))
+    /// }
+    ///
+    /// fn is_digits(value: &str) -> bool {
+    ///     !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// // The slug route is added first, but the constrained route is tried before it.
+    /// router.add(Method::Get, route!(/"articles"/slug), show);
+    /// router.add(Method::Get, route!(/"articles"/id if is_digits), show);
+    /// router.add(Method::Get, route!(/"posts"/id if is_digits), show);
+    /// router.add(Method::Get, route!(/"posts"/"latest"), show);
+    /// router.add(Method::Get, route!(/"tags"/tag if (|tag: &str| tag.len() <= 8)), show);
+    /// router.add(Method::Get, Route::from("/users/{id}").constrain("id", is_digits), show);
+    /// let client = TestClient::new(router);
+    /// let client = &client;
+    /// let get = |path: &'static str| async move {
+    ///     let mut res = client.get(path).send().await;
+    ///     (res.status().to_string(), res.body_string().await.unwrap())
+    /// };
+    ///
+    /// task::block_on(async {
+    ///     assert_eq!(get("/articles/42").await.1, "/articles/{id} id=42");
+    ///     assert_eq!(get("/articles/hello-world").await.1, "/articles/{slug} slug=hello-world");
+    ///     assert_eq!(get("/articles/4%32").await.1, "/articles/{id} id=42");
+    ///     assert_eq!(get("/posts/7").await.1, "/posts/{id} id=7");
+    ///     assert_eq!(get("/posts/latest").await.1, "/posts/latest ");
+    ///     assert_eq!(get("/tags/rust").await.1, "/tags/{tag} tag=rust");
+    ///     assert_eq!(get("/tags/much-too-long").await.0, "404");
+    ///     assert_eq!(get("/users/7").await.1, "/users/{id} id=7");
+    ///     assert_eq!(get("/users/ada").await.0, "404");
+    /// });
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"articles"/id if is_digits), show);
+    /// assert_eq!(
+    ///     router.explain(Method::Get, "/articles/hello").compact(),
+    ///     vec![r#"GET /articles/{id}: segment 1 (id) rejected "hello""#],
+    /// );
+    /// // A static segment the constraint accepts is still ambiguous.
+    /// assert!(router.try_add(Method::Get, route!(/"articles"/"1984"), show).is_err());
+    /// // As is the same constrained route added again.
+    /// let err = router.try_add(Method::Get, route!(/"articles"/id if is_digits), show);
+    /// match err.unwrap_err() {
+    ///     RouterError::Conflict { method, route, existing } => {
+    ///         assert_eq!(method, Method::Get);
+    ///         assert_eq!((route.as_str(), existing.as_str()), ("/articles/{id}", "/articles/{id}"));
+    ///     }
+    ///     err => panic!("unexpected {}", err),
+    /// }
+    /// ```
+    ///
+    /// ## Optional segments
+    ///
+    /// The last dynamic segments of a route can be marked optional with a leading `?`, as in
+    /// `route!(/"reports"/year/?month)`, and the route also matches paths leaving them out, with
+    /// no param for the segments left out.  A route of the shorter length that has the segments
+    /// of the path wins over the route leaving out its optional segments, whatever the order
+    /// they were added in.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     let mut params: Vec<_> = params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    ///     params.sort();
+    ///     Ok(Response::from(format!("{} {}", pattern, params.join(" "))))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"reports"/year/?month), show);
+    /// router.add(Method::Get, route!(/"archive"/year/?month/?day), show);
+    /// // Added after the optional route, but tried before it for paths of two segments.
+    /// router.add(Method::Get, route!(/"archive"/year), show);
+    /// router.add(Method::Get, Route::from("/logs/{day?}"), show);
+    /// let client = TestClient::new(router);
+    /// let client = &client;
+    /// let get = |path: &'static str| async move {
+    ///     let mut res = client.get(path).send().await;
+    ///     (res.status(), res.body_string().await.unwrap())
+    /// };
+    ///
+    /// task::block_on(async {
+    ///     assert_eq!(get("/reports/2024").await.1, "/reports/{year}/{month?} year=2024");
+    ///     assert_eq!(get("/reports/2024/03").await.1, "/reports/{year}/{month?} month=03 year=2024");
+    ///     assert_eq!(get("/reports").await.0, StatusCode::NotFound);
+    ///     assert_eq!(get("/reports/2024/03/01").await.0, StatusCode::NotFound);
+    ///     assert_eq!(get("/archive/2024").await.1, "/archive/{year} year=2024");
+    ///     assert_eq!(get("/archive/2024/03").await.1, "/archive/{year}/{month?}/{day?} month=03 year=2024");
+    ///     assert_eq!(
+    ///         get("/archive/2024/03/01").await.1,
+    ///         "/archive/{year}/{month?}/{day?} day=01 month=03 year=2024",
+    ///     );
+    ///     assert_eq!(get("/logs").await.1, "/logs/{day?} ");
+    ///     assert_eq!(get("/logs/today").await.1, "/logs/{day?} day=today");
+    /// });
+    /// ```
+    ///
+    /// Only the last segments of a route can be optional.  
+    /// ```compile_fail
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// let route = route!(/"reports"/?year/"summary");
+    /// ```
+    /// ```compile_fail
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// let route = route!(/"reports"/?year/month);
+    /// ```
+    ///
+    /// A type must follow the `:` of a dynamic segment, static segments cannot be typed, and the
+    /// type must implement `FromStr`.  
+    /// ```compile_fail
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// let route = route!(/"users"/user_id:/"posts");
+    /// ```
+    /// ```compile_fail
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// let route = route!(/"users": u64);
+    /// ```
+    /// ```compile_fail
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// struct UserId(u64);
+    /// let route = route!(/"users"/user_id: UserId);
+    /// ```
+    ///
+    /// ## HEAD requests
+    ///
+    /// A `HEAD` request that matches no `HEAD` route is answered by the `GET` route, whose
+    /// response is sent with its status and headers but without its body, with a
+    /// `Content-Length` of what the body would have been when it is known.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// async fn user(_req: Request, params: Params) -> Result<Response, Error> {
+    ///     match params.get("id").unwrap().as_str() {
+    ///         "0" => Err(Error::not_found("no such user")),
+    ///         id => Ok(Response::from(format!("user {}", id))),
+    ///     }
+    /// }
+    ///
+    /// async fn probe(_req: Request, _params: Params) -> Result<Response, Error> {
+    ///     let mut res = Response::new(StatusCode::NoContent);
+    ///     res.insert_header("x-probe", "head").unwrap();
+    ///     Ok(res)
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id), user);
+    /// router.add(Method::Get, route!(/"health"), user);
+    /// router.add(Method::Head, route!(/"health"), probe);
+    /// let router = Arc::new(router);
+    ///
+    /// let send = |method: Method, path: &str| {
+    ///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    ///     let mut res = task::block_on(router.clone().respond(Request::new(method, url)));
+    ///     let header = |name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+    ///     let headers = (header("content-type"), header("content-length"), header("x-probe"));
+    ///     (res.status(), headers, task::block_on(res.body_string()).unwrap())
+    /// };
+    ///
+    /// for path in &["/users/7", "/users/0"] {
+    ///     let (get_status, (get_type, get_length, _), get_body) = send(Method::Get, path);
+    ///     assert_eq!(get_length, Some(get_body.len().to_string()));
+    ///     let (status, (content_type, content_length, _), body) = send(Method::Head, path);
+    ///     assert_eq!(status, get_status);
+    ///     assert!(content_type.is_some());
+    ///     assert_eq!(content_type, get_type);
+    ///     assert_eq!(content_length, Some(get_body.len().to_string()));
+    ///     assert_eq!(body, "");
+    /// }
+    ///
+    /// // A route for `HEAD` itself wins.
+    /// let (status, (_, _, probe), _) = send(Method::Head, "/health");
+    /// assert_eq!((status, probe.as_deref()), (StatusCode::NoContent, Some("head")));
+    ///
+    /// let (status, _, body) = send(Method::Head, "/missing");
+    /// assert_eq!((status, body.as_str()), (StatusCode::NotFound, ""));
+    /// let url = Url::parse("http://localhost/users/7").unwrap();
+    /// let res = task::block_on(router.clone().respond(Request::new(Method::Delete, url)));
+    /// assert_eq!(res.header(&"allow".parse().unwrap()).unwrap()[0].as_str(), "GET, HEAD");
+    /// ```
+    ///
+    /// ## Responses
+    ///
+    /// Responses with a body whose length is known are sent with a `Content-Length`, and with the
+    /// `Content-Type` of the codec of the route when the endpoint did not set one.  The responses
+    /// the router makes itself, for errors and unmatched paths, are JSON and always have both.  
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, StatusCode};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn create(user: Body<User>) -> Result<Respond<User>, Error> {
+    ///     let user = user.into_inner();
+    ///     match user.name.as_str() {
+    ///         "" => Err(Error::bad_request("name required")),
+    ///         _ => Ok(Respond::new(user)),
+    ///     }
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"users"), ___create);
+    /// let client = TestClient::new(router);
     ///
-    /// When adding routes to the router the order they are added in is sometimes important.  
+    /// task::block_on(async {
+    ///     for (method, path, body, status) in vec![
+    ///         (Method::Post, "/users", r#"{"name":"ada"}"#, StatusCode::Ok),
+    ///         (Method::Post, "/users", r#"{"name":""}"#, StatusCode::BadRequest),
+    ///         (Method::Post, "/users", "{", StatusCode::BadRequest),
+    ///         (Method::Get, "/missing", "", StatusCode::NotFound),
+    ///     ] {
+    ///         let mut res = client.request(method, path).body(body).send().await;
+    ///         assert_eq!(res.status(), status);
+    ///         let header = |name: &str| res.header(&name.parse().unwrap()).unwrap()[0].to_string();
+    ///         assert_eq!(header("content-type"), "application/json");
+    ///         let length = header("content-length");
+    ///         assert_eq!(length, res.body_string().await.unwrap().len().to_string());
+    ///     }
+    /// });
     /// ```
+    ///
+    /// ## Ambiguity
+    ///
+    /// A route may not match any path matched by a route already added under the same method,
+    /// since only one of them could ever be run.  Two routes are ambiguous when they have as
+    /// many segments and at every position either the same static segment or a dynamic one on
+    /// either side.  `add` panics on an ambiguous route, naming both routes, and
+    /// [`try_add`](#method.try_add) returns the conflict instead.  
+    /// ```should_panic
     /// # #![feature(proc_macro_hygiene)]
     /// # use windmill::*;
     /// # use http_types::{Method, Response, StatusCode};
@@ -61,109 +528,2687 @@ impl Router {
     /// # #[endpoint] async fn example() -> Result<Response, Error> { Ok(Response::from("greetings")) }
     /// # #[endpoint] async fn example2() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
     /// router.add(Method::Get, route!(/a/b/c), ___example);
+    /// // Panics: the literal `"a"` is also matched by the dynamic segment of the first route.
     /// router.add(Method::Get, route!(/"a"/b/c), ___example2);
     /// ```
-    /// In the example above the second route will never get run because the first route matches
-    /// the literal `"a"` as a dynamic segment.  To solve this simply insert _more specific_
-    /// routes before _less specific_ routes as seen in the example below.  
+    pub fn add(&mut self, method: Method, route: Route, endpoint: impl Endpoint + Send + Sync) {
+        self.add_with_codec(method, route, endpoint, JsonCodec);
+    }
+
+    /// Add a route, or return the [conflict](enum.RouterError.html) with the route already added
+    /// that matches some of the same paths, leaving the router unchanged.  
+    ///
+    /// ## Examples
     /// ```
     /// # #![feature(proc_macro_hygiene)]
     /// # use windmill::*;
     /// # use http_types::{Method, Response, StatusCode};
-    /// # let mut router = Router::new();
     /// # #[endpoint] async fn example() -> Result<Response, Error> { Ok(Response::from("greetings")) }
-    /// # #[endpoint] async fn example2() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
-    /// router.add(Method::Get, route!(/"a"/b/c), ___example2);
-    /// router.add(Method::Get, route!(/a/b/c), ___example);
+    /// let mut router = Router::new();
+    /// router.try_add(Method::Get, route!(/"users"/id), ___example).unwrap();
+    /// router.try_add(Method::Get, route!(/"users"/id/"orders"), ___example).unwrap();
+    /// // Another method is not ambiguous.
+    /// router.try_add(Method::Delete, route!(/"users"/user_id), ___example).unwrap();
+    ///
+    /// let err = router.try_add(Method::Get, route!(/"users"/"me"), ___example).unwrap_err();
+    /// assert_eq!(err.to_string(), "route GET /users/me conflicts with GET /users/{id}");
+    /// let err = router.try_add(Method::Get, route!(/group/id), ___example).unwrap_err();
+    /// assert_eq!(err.to_string(), "route GET /{group}/{id} conflicts with GET /users/{id}");
     /// ```
-    pub fn add(&mut self, method: Method, mut route: Route, endpoint: impl Endpoint + Send + Sync) {
-        let entry = self
-            .table
-            .entry(method)
-            .or_insert_with(|| Vec::<Route>::new());
+    pub fn try_add(
+        &mut self,
+        method: Method,
+        mut route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+    ) -> Result<(), RouterError> {
+        route.handler = Some(handler(endpoint, JsonCodec));
+        self.try_insert(method, route)
+    }
 
-        let handler = move |req: http_types::Request, params: Params| -> ResponseFuture {
-            Box::pin(async move {
-                match endpoint.call(req, params).await {
-                    Ok(res) => res,
-                    Err(e) => {
-                        let mut res = response(e.code(), mime::JSON);
-                        let bytes = serde_json::to_vec(e.msg()).unwrap();
-                        res.set_body(bytes);
-                        res
-                    }
-                }
-            })
-        };
+    /// Add a route under each of `methods`, all running the same endpoint.  Panics like
+    /// [`add`](#method.add) if the route is ambiguous under one of them.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// async fn echo(req: Request, _params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from(req.method().to_string()))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add_methods(&[Method::Get, Method::Post], route!(/"search"), echo);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     for method in vec![Method::Get, Method::Post] {
+    ///         let mut res = client.request(method, "/search").send().await;
+    ///         assert_eq!(res.body_string().await.unwrap(), method.to_string());
+    ///     }
+    ///     let res = client.delete("/search").send().await;
+    ///     assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+    ///     assert_eq!(res.header(&"allow".parse().unwrap()).unwrap()[0].as_str(), "GET, HEAD, POST");
+    /// });
+    /// ```
+    pub fn add_methods(
+        &mut self,
+        methods: &[Method],
+        route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+    ) {
+        // The routes share one handler, so the endpoint is never cloned.
+        let shared = Arc::new(handler(endpoint, JsonCodec));
+        for method in methods {
+            let shared = shared.clone();
+            let run = move |req: http_types::Request, params: Params| -> ResponseFuture {
+                shared(req, params)
+            };
+            self.insert(*method, route.with_handler(Box::new(run)));
+        }
+    }
 
-        route.handler = Some(Box::new(handler));
-        entry.push(route);
+    /// Add a route answering requests of every method.  The routes added for the method of a
+    /// request are tried first, so a route of one method wins over a route added with `any` on
+    /// the same path, whatever the order they were added in.  A path matched by a route added
+    /// with `any` is never `405 Method Not Allowed`.  
+    ///
+    /// Routes added with `any` are only ambiguous with each other, and are listed by
+    /// [`routes`](#method.routes) with the method `*`.  They are left out of the
+    /// [OpenAPI document](#method.openapi), which describes operations of one method.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// async fn proxy(req: Request, _params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("proxied {}", req.method())))
+    /// }
+    ///
+    /// async fn health(_req: Request, _params: Params) -> Result<Response, Error> {
+    ///     Ok(Response::from("healthy"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.any(Route::from("/api/{path*}"), proxy);
+    /// // Added after the route for any method, but tried before it.
+    /// router.add(Method::Get, route!(/"api"/"health"), health);
+    /// router.add(Method::Get, route!(/"users"), health);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     let mut res = client.get("/api/health").send().await;
+    ///     assert_eq!(res.body_string().await.unwrap(), "healthy");
+    ///     let mut res = client.post("/api/health").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     assert_eq!(res.body_string().await.unwrap(), "proxied POST");
+    ///     let mut res = client.delete("/api/orders/7").send().await;
+    ///     assert_eq!(res.body_string().await.unwrap(), "proxied DELETE");
+    ///
+    ///     // Other paths still only allow their own methods.
+    ///     let res = client.post("/users").send().await;
+    ///     assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+    ///     assert_eq!(res.header(&"allow".parse().unwrap()).unwrap()[0].as_str(), "GET, HEAD");
+    /// });
+    ///
+    /// let mut router = Router::new();
+    /// router.any(route!(/"api"/version), proxy);
+    /// let err = router.try_any(route!(/"api"/"v1"), proxy).unwrap_err();
+    /// assert_eq!(err.to_string(), "route /api/v1 for any method conflicts with /api/{version}");
+    /// // A route of one method is not ambiguous with it.
+    /// router.try_add(Method::Get, route!(/"api"/"v1"), proxy).unwrap();
+    /// ```
+    pub fn any(&mut self, route: Route, endpoint: impl Endpoint + Send + Sync) {
+        if let Err(e) = self.try_any(route, endpoint) {
+            panic!("{}", e);
+        }
     }
 
-    pub(crate) async fn lookup(
-        self: Arc<Self>,
-        req: http_types::Request,
-    ) -> Box<dyn Future<Output = http_types::Response> + Unpin + Send + Sync> {
-        let method = req.method();
-        let raw_route = RawRoute::from_path(req.url().path().into());
+    /// Add a route answering requests of every method like [`any`](#method.any), or return the
+    /// conflict with a route already added for any method, leaving the router unchanged.  
+    pub fn try_any(
+        &mut self,
+        mut route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+    ) -> Result<(), RouterError> {
+        route.handler = Some(handler(endpoint, JsonCodec));
+        self.try_insert_any(route)
+    }
 
-        match self
-            .table
-            .get(&method)
-            .map(|routes| routes.iter().find(|route| paths_match(route, &raw_route)))
-        {
-            Some(Some(route)) => {
-                let mut params = HashMap::new();
-
-                route.dynamic_segments.iter().for_each(|dynamic_segment| {
-                    params.insert(
-                        dynamic_segment.name,
-                        raw_route.raw_segments[dynamic_segment.position]
-                            .value
-                            .into(),
-                    );
-                });
+    /// Add a route whose bodies are in the format of `codec`.  
+    ///
+    /// Errors returned by the endpoint are encoded with the codec, or as JSON when the codec cannot
+    /// represent them, like the `code` and `msg` object of errors with an
+    /// [error code](enum.WindmillErrorCode.html) in the [`TextCodec`](struct.TextCodec.html).
+    /// Responses without a content type of their own are given the codec's.  Endpoints decode request bodies with
+    /// the [`Body`](struct.Body.html) props.  [`add`](#method.add) uses the
+    /// [`JsonCodec`](struct.JsonCodec.html).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[endpoint]
+    /// async fn double(n: Body<u64, TextCodec>) -> Result<Response, Error> {
+    ///     TextCodec.respond(&(*n * 2))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add_with_codec(Method::Post, route!(/"double"), ___double, TextCodec);
+    /// let router = Arc::new(router);
+    ///
+    /// let mut req = Request::new(Method::Post, Url::parse("http://localhost/double").unwrap());
+    /// req.insert_header("content-length", "3").unwrap();
+    /// req.set_body("two");
+    /// let mut res = task::block_on(router.respond(req));
+    ///
+    /// assert_eq!(res.status(), StatusCode::BadRequest);
+    /// assert!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str().starts_with("application/json"));
+    /// let body: serde_json::Value = serde_json::from_str(&task::block_on(res.body_string()).unwrap()).unwrap();
+    /// assert_eq!(body["code"], "deserialize_failed");
+    /// assert!(body["msg"].as_str().unwrap().starts_with("invalid text body"));
+    /// ```
+    pub fn add_with_codec<C: Codec>(
+        &mut self,
+        method: Method,
+        mut route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+        codec: C,
+    ) {
+        route.handler = Some(handler(endpoint, codec));
+        self.insert(method, route);
+    }
 
-                Box::new((route.handler.as_ref().unwrap())(req, params))
-            }
-            _ => Box::new(Box::pin(not_found())),
+    /// Add a route that upgrades `GET` requests to a [`WebSocket`](struct.WebSocket.html) and
+    /// hands it to `handler`.  
+    ///
+    /// Requests that are not WebSocket upgrades get `426 Upgrade Required`, and upgrades with an
+    /// invalid `Sec-WebSocket-Key` get `400 Bad Request`.  The `101 Switching Protocols` response
+    /// goes through the middlewares and decorators like any other, and once it is sent the
+    /// connection belongs to the handler until it returns.  Dispatching a request in process with
+    /// [`respond`](#method.respond) only checks the handshake.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{io, net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Request, StatusCode, Url};
+    /// # use std::{sync::Arc, time::Duration};
+    /// async fn echo(mut ws: WebSocket) -> io::Result<()> {
+    ///     let room = ws.params().get("room").cloned().unwrap_or_default();
+    ///     while let Some(message) = ws.recv().await? {
+    ///         match message {
+    ///             Message::Text(text) => ws.send(Message::Text(format!("{}: {}", room, text))).await?,
+    ///             Message::Binary(bytes) => ws.send(Message::Binary(bytes)).await?,
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add_ws(route!(/"rooms"/room), echo);
+    ///
+    /// // Requests that are not upgrades.
+    /// let router = Arc::new(router);
+    /// let res = task::block_on(router.clone().respond(Request::new(Method::Get, Url::parse("http://localhost/rooms/lobby").unwrap())));
+    /// assert_eq!(res.status(), StatusCode::UpgradeRequired);
+    /// assert_eq!(res.header(&"upgrade".parse().unwrap()).unwrap()[0].as_str(), "websocket");
+    /// let router = Arc::try_unwrap(router).ok().unwrap();
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     let config = Config::new(addr.to_string());
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// // A client frame, masked as clients must.
+    /// let frame = |opcode: u8, payload: &[u8]| {
+    ///     let mask = [0x12, 0x34, 0x56, 0x78];
+    ///     let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    ///     frame.extend_from_slice(&mask);
+    ///     frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    ///     frame
+    /// };
+    /// // A server frame, returning its opcode and payload.
+    /// async fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    ///     let mut head = [0u8; 2];
+    ///     stream.read_exact(&mut head).await.unwrap();
+    ///     let mut payload = vec![0u8; (head[1] & 0x7f) as usize];
+    ///     stream.read_exact(&mut payload).await.unwrap();
+    ///     (head[0] & 0x0f, payload)
+    /// }
+    ///
+    /// // An upgraded connection.
+    /// async fn upgrade(addr: std::net::SocketAddr) -> TcpStream {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     stream
+    ///         .write_all(
+    ///             b"GET /rooms/lobby HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\n\
+    ///               connection: Upgrade\r\nsec-websocket-version: 13\r\n\
+    ///               sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let mut head = vec![];
+    ///     while !head.ends_with(b"\r\n\r\n") {
+    ///         let mut byte = [0u8; 1];
+    ///         stream.read_exact(&mut byte).await.unwrap();
+    ///         head.push(byte[0]);
+    ///     }
+    ///     let head = String::from_utf8(head).unwrap();
+    ///     assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"), "{}", head);
+    ///     assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"), "{}", head);
+    ///     stream
+    /// }
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = upgrade(addr).await;
+    ///
+    ///     stream.write_all(&frame(0x1, b"hello")).await.unwrap();
+    ///     assert_eq!(read_frame(&mut stream).await, (0x1, b"lobby: hello".to_vec()));
+    ///
+    ///     stream.write_all(&frame(0x9, b"are you there")).await.unwrap();
+    ///     assert_eq!(read_frame(&mut stream).await, (0xA, b"are you there".to_vec()));
+    ///
+    ///     stream.write_all(&frame(0x2, &[1, 2, 3])).await.unwrap();
+    ///     assert_eq!(read_frame(&mut stream).await, (0x2, vec![1, 2, 3]));
+    ///
+    ///     stream.write_all(&frame(0x8, &1000u16.to_be_bytes())).await.unwrap();
+    ///     assert_eq!(read_frame(&mut stream).await, (0x8, 1000u16.to_be_bytes().to_vec()));
+    ///     let mut rest = vec![];
+    ///     stream.read_to_end(&mut rest).await.unwrap();
+    ///     assert!(rest.is_empty());
+    ///
+    ///     // 1005 is reserved for reporting a close without a code, so it is never echoed.
+    ///     let mut stream = upgrade(addr).await;
+    ///     stream.write_all(&frame(0x8, &1005u16.to_be_bytes())).await.unwrap();
+    ///     assert_eq!(read_frame(&mut stream).await, (0x8, 1002u16.to_be_bytes().to_vec()));
+    ///
+    ///     stop.send(()).await;
+    /// });
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn add_ws<F, Fut>(&mut self, mut route: Route, handler: F)
+    where
+        F: Fn(WebSocket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        route.handler = Some(ws::handler(handler));
+        self.insert(Method::Get, route);
+    }
+
+    fn insert(&mut self, method: Method, route: Route) {
+        if let Err(e) = self.try_insert(method, route) {
+            panic!("{}", e);
         }
     }
-}
 
-fn paths_match(route: &Route, raw_route: &RawRoute) -> bool {
-    if raw_route.raw_segments.len() == route.static_segments.len() + route.dynamic_segments.len() {
-        let static_matches = || {
-            route
-                .static_segments
-                .iter()
-                .fold(true, |is_match, static_segment| {
-                    is_match && (&raw_route.raw_segments[static_segment.position] == static_segment)
-                })
-        };
+    fn try_insert(&mut self, method: Method, route: Route) -> Result<(), RouterError> {
+        let case_insensitive = self.case_insensitive;
+        let entry = self.table.entry(method).or_insert_with(RouteTable::default);
+        if let Some(existing) = entry.iter().find(|existing| {
+            existing.overlaps(&route, case_insensitive) && existing.host == route.host
+        }) {
+            return Err(RouterError::Conflict {
+                method,
+                route: route.to_string(),
+                existing: existing.to_string(),
+            });
+        }
+        self.urls.register(&route)?;
 
-        let dynamic_matches = || {
-            route
-                .dynamic_segments
-                .iter()
-                .fold(true, |is_match, dynamic_segment| {
-                    is_match
-                        && (&raw_route.raw_segments[dynamic_segment.position] == dynamic_segment)
-                })
-        };
+        self.admission
+            .register(route.class.unwrap_or(DEFAULT_CLASS));
+        self.handle.register_route(&method.to_string(), &route);
+        entry.push(route);
+        Ok(())
+    }
+
+    fn try_insert_any(&mut self, route: Route) -> Result<(), RouterError> {
+        let case_insensitive = self.case_insensitive;
+        if let Some(existing) = self.any.iter().find(|existing| {
+            existing.overlaps(&route, case_insensitive) && existing.host == route.host
+        }) {
+            return Err(RouterError::AnyConflict {
+                route: route.to_string(),
+                existing: existing.to_string(),
+            });
+        }
+        self.urls.register(&route)?;
 
-        static_matches() && dynamic_matches()
-    } else {
-        false
+        self.admission
+            .register(route.class.unwrap_or(DEFAULT_CLASS));
+        self.handle.register_route(ANY, &route);
+        self.any.push(route);
+        Ok(())
+    }
+
+    /// Answer requests that match no route with `endpoint` instead of an empty
+    /// `404 Not Found`.  
+    ///
+    /// The endpoint receives the original request with empty params, and its errors are encoded
+    /// as JSON like those of endpoints added with [`add`](#method.add).  It replaces the
+    /// explanation [`debug_explain`](#method.debug_explain) puts in the body of 404 responses.
+    /// Requests whose path matches a route under another method still get
+    /// `405 Method Not Allowed`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// struct Path(String);
+    ///
+    /// impl Props for Path {
+    ///     type Fut = PropsFuture<Self>;
+    ///
+    ///     fn call(req: Request, params: Params) -> Self::Fut {
+    ///         let path = Path(req.url().path().to_owned());
+    ///         Box::pin(async move { Ok((req, params, path)) })
+    ///     }
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn missing(path: Path) -> Result<Response, Error> {
+    ///     Err(Error::not_found(serde_json::json!({ "error": "not_found", "path": path.0 })))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.set_not_found(___missing);
+    /// let router = Arc::new(router);
+    ///
+    /// let url = Url::parse("http://localhost/nothing/here").unwrap();
+    /// let mut res = task::block_on(router.respond(Request::new(Method::Get, url)));
+    /// assert_eq!(res.status(), StatusCode::NotFound);
+    /// assert_eq!(
+    ///     task::block_on(res.body_string()).unwrap(),
+    ///     r#"{"error":"not_found","path":"/nothing/here"}"#
+    /// );
+    /// ```
+    pub fn set_not_found(&mut self, endpoint: impl Endpoint + Send + Sync) {
+        self.not_found = Some(handler(endpoint, JsonCodec));
     }
-}
 
-async fn not_found() -> http_types::Response {
-    http_types::Response::new(StatusCode::NotFound)
+    /// Render every error the router turns into a response with `format`, instead of sending the
+    /// message of the error as the body in the format of the route's codec.  
+    ///
+    /// The formatter receives the error and the method, url, headers and
+    /// [`Extensions`](struct.Extensions.html) of the request, without its body.  It applies to
+    /// errors returned by endpoints and props, such as bodies that fail to parse, and to the
+    /// responses the router makes itself: `404 Not Found`, `405 Method Not Allowed`, which keeps
+    /// its `Allow` header, and `503 Service Unavailable` for
+    /// [disabled routes](struct.RouterHandle.html).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[derive(Clone)]
+    /// struct RequestId(String);
+    ///
+    /// #[endpoint]
+    /// async fn create(n: Body<u64>) -> Result<Response, Error> {
+    ///     Ok(Response::from(n.to_string()))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"numbers"), ___create);
+    /// router.pre_routing(|req: Request, params: Params, next: Next| {
+    ///     Extensions::of(&req).insert(RequestId("req-1".into()));
+    ///     next.run(req, params)
+    /// });
+    /// router.set_error_formatter(|e: &Error, req: &Request| {
+    ///     let request_id = Extensions::of(req).get::<RequestId>().map(|id| id.0);
+    ///     let mut res = Response::new(e.code());
+    ///     res.set_body(serde_json::json!({
+    ///         "error": { "code": e.code() as u16, "message": e.msg(), "request_id": request_id }
+    ///     }).to_string());
+    ///     res
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let send = |method: Method, path: &str, body: &str| {
+    ///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    ///     let mut req = Request::new(method, url);
+    ///     req.insert_header("content-type", "application/json").unwrap();
+    ///     req.set_body(body);
+    ///     let res = task::block_on(router.clone().respond(req));
+    ///     let status = res.status();
+    ///     let body: serde_json::Value = serde_json::from_str(&task::block_on(res.body_string()).unwrap()).unwrap();
+    ///     (status, body["error"]["message"].clone(), body["error"]["request_id"].clone())
+    /// };
+    ///
+    /// let (status, message, request_id) = send(Method::Post, "/numbers", "seven");
+    /// assert_eq!(status, StatusCode::BadRequest);
+    /// assert!(message.is_string());
+    /// assert_eq!(request_id, "req-1");
+    ///
+    /// assert_eq!(send(Method::Get, "/missing", ""), (StatusCode::NotFound, "not found".into(), "req-1".into()));
+    /// assert_eq!(send(Method::Get, "/numbers", "").1, "method not allowed");
+    /// ```
+    pub fn set_error_formatter<F>(&mut self, format: F)
+    where
+        F: Fn(&Error, &http_types::Request) -> http_types::Response + Send + Sync + 'static,
+    {
+        self.error_formatter = Some(ErrorFormatter::new(format));
+    }
+
+    /// Mount the routes of `sub` under `prefix`, keeping their methods and the order they were
+    /// added in.  
+    ///
+    /// The prefix is a [`Route`](struct.Route.html), either from the [`route!`](macro.route.html)
+    /// macro or a pattern such as `"/tenants/{tenant_id}"`.  Dynamic segments of the prefix are
+    /// passed to the mounted endpoints in their params alongside their own.  Only the routes of
+    /// `sub` are mounted, its middlewares, decorators and other settings are not.  
+    ///
+    /// ## Panics
+    /// If a mounted route matches some of the paths of a route already registered under the same
+    /// method and host, as [`add`](#method.add) does.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[endpoint]
+    /// async fn order(route: MatchedRoute) -> Result<Response, Error> {
+    ///     Ok(Response::from(route.pattern()))
+    /// }
+    ///
+    /// // Built in its own module in a real service.
+    /// fn orders() -> Router {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"orders"/order_id), ___order);
+    ///     router
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.mount("/api/v1/tenants/{tenant_id}", orders());
+    /// router.middleware(|req: Request, params: Params, next: Next| async move {
+    ///     let ids = format!("{:?} {:?}", params.get("tenant_id"), params.get("order_id"));
+    ///     let mut res = next.run(req, params).await;
+    ///     res.insert_header("x-ids", ids).unwrap();
+    ///     res
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let url = Url::parse("http://localhost/api/v1/tenants/acme/orders/7").unwrap();
+    /// let mut res = task::block_on(router.respond(Request::new(Method::Get, url)));
+    /// assert_eq!(res.header(&"x-ids".parse().unwrap()).unwrap()[0].as_str(), r#"Some("acme") Some("7")"#);
+    /// assert_eq!(task::block_on(res.body_string()).unwrap(), "/api/v1/tenants/{tenant_id}/orders/{order_id}");
+    /// ```
+    ///
+    /// Mounting a route that matches some of the paths of a route already registered panics.
+    /// ```should_panic
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::from("user")) }
+    /// let mut users = Router::new();
+    /// users.add(Method::Get, route!(/"users"/"me"), ___user);
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"api"/"users"/user_id), ___user);
+    /// // Panics: `/api/users/me` is also matched by `/api/users/{user_id}`.
+    /// router.mount(route!(/"api"), users);
+    /// ```
+    pub fn mount(&mut self, prefix: impl Into<Route>, sub: Router) {
+        self.merge_routes(&prefix.into(), None, sub);
+    }
+
+    /// Serve the routes of `sub` only to requests whose host matches `pattern`, capturing labels
+    /// of the host as params.  
+    ///
+    /// The pattern is a host name whose labels can be:
+    /// - `{name}`, any single DNS label, captured as `name`;
+    /// - `{name*}`, as the first label only, one or more labels such as `a.b`, captured as `name`;
+    /// - `*`, any single DNS label, not captured.
+    ///
+    /// Hosts are compared without their port and in lowercase, so captures are always lowercase.
+    /// A captured label must be a valid DNS label, internationalized names match in their
+    /// punycode form.  The captures are passed to the endpoints in their params and are available
+    /// to middlewares through [`HostCaptures`](struct.HostCaptures.html).  Requests whose host
+    /// does not match fall through to the next route.  Among routes with the same segments, those
+    /// for a single host are tried first, then those with a pattern, those with more exact labels
+    /// first, and last the routes that serve any host, whatever order they were added in.  Only
+    /// the routes of `sub` are served, its middlewares, decorators and other settings are not.  
+    ///
+    /// ## Panics
+    /// If the pattern has an invalid label or captures several labels anywhere but first, or if
+    /// a route of `sub` matches exactly the same paths and hosts as a route already registered
+    /// under the same method.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::{Arc, Mutex};
+    /// #[endpoint]
+    /// async fn tenant(captures: HostCaptures) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("tenant {}", captures.get("tenant").unwrap())))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn service(captures: HostCaptures) -> Result<Response, Error> {
+    ///     Ok(Response::from(format!("service {}", captures.get("service").unwrap())))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn landing() -> Result<Response, Error> {
+    ///     Ok(Response::from("landing"))
+    /// }
+    ///
+    /// let mut tenants = Router::new();
+    /// tenants.add(Method::Get, route!(/"dashboard"), ___tenant);
+    /// let mut services = Router::new();
+    /// services.add(Method::Get, route!(/"dashboard"), ___service);
+    ///
+    /// let lines = Arc::new(Mutex::new(vec![]));
+    /// let sink = lines.clone();
+    ///
+    /// let mut router = Router::new();
+    /// router.host("{tenant}.example.com", tenants);
+    /// router.host("{service*}.internal.example.com", services);
+    /// router.add(Method::Get, route!(/"dashboard"), ___landing);
+    /// router.middleware(request_log(move |line| sink.lock().unwrap().push(line)));
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |host: &str| {
+    ///     let mut req = Request::new(Method::Get, Url::parse("http://localhost/dashboard").unwrap());
+    ///     req.insert_header("host", host).unwrap();
+    ///     let router = router.clone();
+    ///     task::block_on(async move { router.respond(req).await.body_string().await.unwrap() })
+    /// };
+    ///
+    /// assert_eq!(get("acme.example.com"), "tenant acme");
+    /// assert_eq!(get("ACME.Example.com:8080"), "tenant acme");
+    /// assert_eq!(get("billing.eu.internal.example.com"), "service billing.eu");
+    ///
+    /// // Hosts that do not match fall through to the routes for any host.
+    /// assert_eq!(get("example.com"), "landing");
+    /// assert_eq!(get("a.b.example.com"), "landing");
+    /// assert_eq!(get("-acme.example.com"), "landing");
+    /// assert_eq!(get("[::1]:8080"), "landing");
+    ///
+    /// // Internationalized names match in punycode.
+    /// assert_eq!(get("xn--bcher-kva.example.com"), "tenant xn--bcher-kva");
+    /// let req = Request::new(Method::Get, Url::parse("http://bücher.example.com/dashboard").unwrap());
+    /// let res = task::block_on(async { router.clone().respond(req).await.body_string().await });
+    /// assert_eq!(res.unwrap(), "tenant xn--bcher-kva");
+    ///
+    /// let lines = lines.lock().unwrap();
+    /// assert!(lines[0].starts_with("GET /dashboard 200 "), "{}", lines[0]);
+    /// assert!(lines[0].ends_with(" tenant=acme"), "{}", lines[0]);
+    /// assert!(lines[2].ends_with(" service=billing.eu"), "{}", lines[2]);
+    /// assert!(lines[3].ends_with("ms"), "{}", lines[3]);
+    /// ```
+    ///
+    /// A single host beats a pattern, which beats the routes for any host:
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response};
+    /// #[endpoint]
+    /// async fn default() -> Result<Response, Error> {
+    ///     Ok(Response::from("default"))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn wildcard() -> Result<Response, Error> {
+    ///     Ok(Response::from("wildcard"))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn admin() -> Result<Response, Error> {
+    ///     Ok(Response::from("admin"))
+    /// }
+    ///
+    /// let mut wildcard = Router::new();
+    /// wildcard.add(Method::Get, route!(/"users"), ___wildcard);
+    /// let mut admin = Router::new();
+    /// admin.add(Method::Get, route!(/"users"), ___admin);
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"), ___default);
+    /// router.host("*.example.com", wildcard);
+    /// router.host("admin.example.com", admin);
+    /// let client = TestClient::new(router);
+    ///
+    /// let get = |host: &'static str| {
+    ///     let client = client.clone();
+    ///     task::block_on(async move {
+    ///         let mut res = client.get("/users").header("host", host).send().await;
+    ///         res.body_string().await.unwrap()
+    ///     })
+    /// };
+    /// assert_eq!(get("admin.example.com"), "admin");
+    /// assert_eq!(get("admin.example.com:8443"), "admin");
+    /// assert_eq!(get("api.example.com"), "wildcard");
+    /// assert_eq!(get("example.com"), "default");
+    /// assert_eq!(get("example.org"), "default");
+    /// ```
+    pub fn host(&mut self, pattern: &'static str, sub: Router) {
+        let pattern = Arc::new(HostPattern::parse(pattern));
+        self.merge_routes(&Route::default(), Some(pattern), sub);
+    }
+
+    /// Move the routes of `other`, built in another module, into the router, or return the
+    /// [conflict](enum.RouterError.html) with a route already added, leaving the router
+    /// unchanged.  
+    ///
+    /// Routes keep their paths, names, host rules and settings, and conflict like they would if
+    /// added with [`try_add`](#method.try_add): a route of `other` that matches some of the same
+    /// paths as a route of the router under the same method is an error, and so is a name given
+    /// to routes with different paths.  The middlewares and pre-routing middlewares of `other`
+    /// are added after those of the router and run for every request, and its
+    /// [not found endpoint](#method.set_not_found), if it has one, replaces the router's.  Its
+    /// other settings, such as its decorators, observers, error formatter or state, are dropped.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// #[endpoint]
+    /// async fn user() -> Result<Response, Error> {
+    ///     Ok(Response::from("user"))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn order() -> Result<Response, Error> {
+    ///     Ok(Response::from("order"))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn no_user() -> Result<Response, Error> {
+    ///     Err(Error::not_found("no such user"))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn no_order() -> Result<Response, Error> {
+    ///     Err(Error::not_found("no such order"))
+    /// }
+    ///
+    /// // Built in their own modules in a real service.
+    /// fn users() -> Router {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"users"/id).name("user"), ___user);
+    ///     router.set_not_found(___no_user);
+    ///     router
+    /// }
+    ///
+    /// fn orders() -> Router {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"orders"/id).name("order"), ___order);
+    ///     router.set_not_found(___no_order);
+    ///     router.middleware(|req: Request, params: Params, next: Next| async move {
+    ///         let mut res = next.run(req, params).await;
+    ///         res.insert_header("x-orders", "seen").unwrap();
+    ///         res
+    ///     });
+    ///     router
+    /// }
+    ///
+    /// let mut router = users();
+    /// router.merge(orders()).unwrap();
+    /// assert_eq!(router.url_for("order", &[("id", "7")]).unwrap(), "/orders/7");
+    ///
+    /// // Merging a route that is already served fails, and changes nothing.
+    /// let mut other = Router::new();
+    /// other.add(Method::Get, route!(/"admin"), ___user);
+    /// other.add(Method::Get, route!(/"users"/"me"), ___user);
+    /// let err = router.merge(other).unwrap_err();
+    /// assert_eq!(err.to_string(), "route GET /users/me conflicts with GET /users/{id}");
+    ///
+    /// let mut other = Router::new();
+    /// other.add(Method::Get, route!(/"people"/id).name("user"), ___user);
+    /// let err = router.merge(other).unwrap_err();
+    /// assert_eq!(err.to_string(), "route /people/{id} is named `user` like /users/{id}");
+    ///
+    /// let client = TestClient::new(router);
+    /// task::block_on(async {
+    ///     let mut res = client.get("/orders/7").send().await;
+    ///     assert_eq!(res.body_string().await.unwrap(), "order");
+    ///     let res = client.get("/users/7").send().await;
+    ///     assert_eq!(res.header(&"x-orders".parse().unwrap()).unwrap()[0].as_str(), "seen");
+    ///
+    ///     let res = client.get("/admin").send().await;
+    ///     assert_eq!(res.status(), StatusCode::NotFound);
+    ///
+    ///     // The not found endpoint merged last wins.
+    ///     let mut res = client.get("/nothing").send().await;
+    ///     assert_eq!(res.status(), StatusCode::NotFound);
+    ///     assert!(res.body_string().await.unwrap().contains("no such order"));
+    /// });
+    /// ```
+    pub fn merge(&mut self, other: Router) -> Result<(), RouterError> {
+        let case_insensitive = self.case_insensitive;
+        let mut urls = self.urls.clone();
+        for (method, routes) in other.table.iter() {
+            for route in routes.iter() {
+                let existing = self.table.get(method).and_then(|existing| {
+                    existing.iter().find(|existing| {
+                        existing.overlaps(route, case_insensitive) && existing.host == route.host
+                    })
+                });
+                if let Some(existing) = existing {
+                    return Err(RouterError::Conflict {
+                        method: *method,
+                        route: route.to_string(),
+                        existing: existing.to_string(),
+                    });
+                }
+                urls.register(route)?;
+            }
+        }
+        for route in other.any.iter() {
+            let existing = self.any.iter().find(|existing| {
+                existing.overlaps(route, case_insensitive) && existing.host == route.host
+            });
+            if let Some(existing) = existing {
+                return Err(RouterError::AnyConflict {
+                    route: route.to_string(),
+                    existing: existing.to_string(),
+                });
+            }
+            urls.register(route)?;
+        }
+
+        for (method, routes) in other.table {
+            for route in routes {
+                self.try_insert(method, route)?;
+            }
+        }
+        for route in other.any {
+            self.try_insert_any(route)?;
+        }
+        self.pre_routing.extend(other.pre_routing);
+        self.middlewares.extend(other.middlewares);
+        if other.not_found.is_some() {
+            self.not_found = other.not_found;
+        }
+        Ok(())
+    }
+
+    /// Serve the files of `dir` for `GET` requests to any path under `url_prefix`.  
+    ///
+    /// The path under the prefix names a file relative to the directory, and is matched however
+    /// many segments it has.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// let dist = std::env::temp_dir().join("windmill-serve-dir-doc");
+    /// std::fs::create_dir_all(dist.join("css")).unwrap();
+    /// std::fs::write(dist.join("index.html"), "<h1>app</h1>").unwrap();
+    /// std::fs::write(dist.join("app.js"), "start()").unwrap();
+    /// std::fs::write(dist.join("css/site.css"), "h1 {}").unwrap();
+    /// std::fs::write(std::env::temp_dir().join("windmill-serve-dir-secret.txt"), "secret").unwrap();
+    ///
+    /// let mut router = Router::new();
+    /// router.serve_dir("/assets", ServeDir::new(&dist).with_index());
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |path: &str| {
+    ///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    ///     let res = task::block_on(router.clone().respond(Request::new(Method::Get, url)));
+    ///     let content_type = res.header(&"content-type".parse().unwrap()).map(|v| v[0].to_string());
+    ///     (res.status(), content_type, task::block_on(res.body_string()).unwrap())
+    /// };
+    ///
+    /// let (status, content_type, body) = get("/assets/css/site.css");
+    /// assert_eq!((status, body.as_str()), (StatusCode::Ok, "h1 {}"));
+    /// assert_eq!(content_type.unwrap(), "text/css");
+    /// assert_eq!(get("/assets/app.js").2, "start()");
+    /// assert_eq!(get("/assets/").2, "<h1>app</h1>");
+    /// assert_eq!(get("/assets").2, "<h1>app</h1>");
+    ///
+    /// assert_eq!(get("/assets/missing.js").0, StatusCode::NotFound);
+    /// assert_eq!(get("/assets/css/missing/deeper.css").0, StatusCode::NotFound);
+    ///
+    /// // Paths that try to leave the directory.
+    /// assert_eq!(get("/assets/..%2fwindmill-serve-dir-secret.txt").0, StatusCode::BadRequest);
+    /// assert_eq!(get("/assets/css/%2E%2E%2F..%2Fwindmill-serve-dir-secret.txt").0, StatusCode::BadRequest);
+    /// assert_eq!(get("/assets/..%5cwindmill-serve-dir-secret.txt").0, StatusCode::BadRequest);
+    /// assert_eq!(get("/assets/%2e%2e/windmill-serve-dir-secret.txt").0, StatusCode::NotFound);
+    /// ```
+    pub fn serve_dir(&mut self, url_prefix: &'static str, dir: ServeDir) {
+        let mut route = Route::from(url_prefix).metadata(dir);
+        route.rest = Some("path");
+        self.add(Method::Get, route, serve_file);
+    }
+
+    /// Add the routes of `sub` under `prefix`, restricted to hosts matching `host` if given.  
+    fn merge_routes(&mut self, prefix: &Route, host: Option<Arc<HostPattern>>, sub: Router) {
+        for (method, routes) in sub.table {
+            for route in routes {
+                let mut route = route.prefixed(prefix);
+                if host.is_some() {
+                    route.host = host.clone();
+                }
+                if let Err(e) = self.try_insert(method, route) {
+                    panic!("mounting: {}", e);
+                }
+            }
+        }
+        for route in sub.any {
+            let mut route = route.prefixed(prefix);
+            if host.is_some() {
+                route.host = host.clone();
+            }
+            if let Err(e) = self.try_insert_any(route) {
+                panic!("mounting: {}", e);
+            }
+        }
+    }
+
+    /// Run at most `max` endpoints at once, admitting waiting requests by the weights of their
+    /// route [classes](struct.Route.html#method.class) rather than first come, first served.  
+    ///
+    /// Every class is guaranteed a share of `max` proportional to its weight, and at least one
+    /// slot.  A busy class may use capacity beyond its share, but never the unused share of
+    /// another class, so saturating one class cannot starve the others.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::{sync::Arc, time::{Duration, Instant}};
+    /// #[endpoint]
+    /// async fn slow() -> Result<Response, Error> {
+    ///     task::sleep(Duration::from_millis(300)).await;
+    ///     Ok(Response::new(StatusCode::Ok))
+    /// }
+    ///
+    /// #[endpoint]
+    /// async fn health() -> Result<Response, Error> {
+    ///     Ok(Response::new(StatusCode::Ok))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"api").class("public"), ___slow);
+    /// router.add(Method::Get, route!(/"health").class("admin"), ___health);
+    /// router.max_in_flight(4);
+    /// router.class_weight("public", 3);
+    /// router.class_weight("admin", 1);
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |path: &str| Request::new(Method::Get, Url::parse("http://localhost").unwrap().join(path).unwrap());
+    /// task::block_on(async {
+    ///     let public: Vec<_> = (0..10)
+    ///         .map(|_| task::spawn(router.clone().respond(get("/api"))))
+    ///         .collect();
+    ///     task::sleep(Duration::from_millis(50)).await;
+    ///
+    ///     let public_metrics = router.class_metrics().into_iter().find(|m| m.class == "public").unwrap();
+    ///     assert_eq!((public_metrics.in_flight, public_metrics.queued), (3, 7));
+    ///
+    ///     let start = Instant::now();
+    ///     let res = router.clone().respond(get("/health")).await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     assert!(start.elapsed() < Duration::from_millis(100));
+    ///
+    ///     for res in public {
+    ///         assert_eq!(res.await.status(), StatusCode::Ok);
+    ///     }
+    /// });
+    /// ```
+    pub fn max_in_flight(&mut self, max: usize) {
+        self.admission.set_capacity(max);
+    }
+
+    /// Set the weight of a route class for [`max_in_flight`](#method.max_in_flight).  Classes
+    /// have a weight of 1 unless set.  
+    pub fn class_weight(&mut self, class: &'static str, weight: u32) {
+        self.admission.set_weight(class, weight);
+    }
+
+    /// The number of running and waiting requests in every route class, sorted by class name.  
+    pub fn class_metrics(&self) -> Vec<ClassMetrics> {
+        self.admission.metrics()
+    }
+
+    /// How many responses exceeded each of their [time budgets](struct.Timeouts.html).  
+    pub fn timeout_metrics(&self) -> TimeoutMetrics {
+        self.timeouts.metrics()
+    }
+
+    /// How many endpoints were cancelled because their client closed the connection.
+    ///
+    /// While an endpoint has not returned its response, the server watches the connection, and
+    /// drops the endpoint once the client has closed it, rather than finishing work nobody will
+    /// read.  The cancellation is logged, and [observers](#method.observe) see it as a
+    /// `400 Bad Request` with [`client_aborted`](struct.ResponseInfo.html#method.client_aborted)
+    /// set, rather than as an error.  A client that only shuts down its sending side counts as
+    /// gone too.  Bytes sent after the request, such as a body the endpoint has not read, hide
+    /// the close until they are read, and connections over a Unix socket are not watched.
+    /// Endpoints with side effects that must not stop halfway can opt out with
+    /// [`Route::run_to_completion`](struct.Route.html#method.run_to_completion).
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::Duration};
+    /// static SLOW_STARTED: AtomicBool = AtomicBool::new(false);
+    /// static SLOW_FINISHED: AtomicBool = AtomicBool::new(false);
+    /// static CHARGE_STARTED: AtomicBool = AtomicBool::new(false);
+    /// static CHARGE_FINISHED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// async fn slow(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    ///     SLOW_STARTED.store(true, Ordering::SeqCst);
+    ///     task::sleep(Duration::from_millis(500)).await;
+    ///     SLOW_FINISHED.store(true, Ordering::SeqCst);
+    ///     Ok(Response::from("done"))
+    /// }
+    ///
+    /// async fn charge(_req: http_types::Request, _params: Params) -> Result<Response, Error> {
+    ///     CHARGE_STARTED.store(true, Ordering::SeqCst);
+    ///     task::sleep(Duration::from_millis(200)).await;
+    ///     CHARGE_FINISHED.store(true, Ordering::SeqCst);
+    ///     Ok(Response::from("charged"))
+    /// }
+    ///
+    /// struct Aborts(Arc<Mutex<Vec<bool>>>);
+    ///
+    /// impl RequestObserver for Aborts {
+    ///     fn on_request_end(&self, _req: &RequestInfo, res: &ResponseInfo, _latency: Duration) {
+    ///         self.0.lock().unwrap().push(res.client_aborted());
+    ///     }
+    /// }
+    ///
+    /// let aborts = Arc::new(Mutex::new(vec![]));
+    /// let mut router = Router::new();
+    /// router.observe(Aborts(aborts.clone()));
+    /// router.add(Method::Get, Route::from("/slow"), slow);
+    /// router.add(Method::Post, Route::from("/charge").run_to_completion(), charge);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     let config = Config::new(addr.to_string());
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// // Send a request and hang up once its endpoint has started.
+    /// let hang_up = |req: &'static str, started: &'static AtomicBool| async move {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     stream.write_all(req.as_bytes()).await.unwrap();
+    ///     while !started.load(Ordering::SeqCst) {
+    ///         task::sleep(Duration::from_millis(10)).await;
+    ///     }
+    ///     drop(stream);
+    /// };
+    ///
+    /// task::block_on(async {
+    ///     hang_up("GET /slow HTTP/1.1\r\nhost: localhost\r\n\r\n", &SLOW_STARTED).await;
+    ///     hang_up("POST /charge HTTP/1.1\r\nhost: localhost\r\ncontent-length: 0\r\n\r\n", &CHARGE_STARTED).await;
+    ///     task::sleep(Duration::from_millis(800)).await;
+    ///
+    ///     // The slow endpoint was dropped, the charge ran to the end.
+    ///     assert!(!SLOW_FINISHED.load(Ordering::SeqCst));
+    ///     assert!(CHARGE_FINISHED.load(Ordering::SeqCst));
+    ///     assert_eq!(*aborts.lock().unwrap(), vec![true, false]);
+    ///     stop.send(()).await;
+    /// });
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn client_aborts(&self) -> u64 {
+        self.client_aborts.load(Ordering::Relaxed)
+    }
+
+    /// The limits that apply to a request with `method` and `path`, or `None` if no route matches.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id), ___ok);
+    /// router.add(Method::Get, route!(/"admin"/"health").class("admin"), ___ok);
+    /// assert_eq!(router.effective_limits(Method::Get, "/users/7").unwrap().max_in_flight, None);
+    ///
+    /// router.max_in_flight(10);
+    /// router.class_weight("admin", 4);
+    ///
+    /// let limits = router.effective_limits(Method::Get, "/users/7").unwrap();
+    /// assert_eq!(limits.route, "/users/{id}");
+    /// assert_eq!(limits.class, "default");
+    /// assert_eq!((limits.max_in_flight, limits.class_share), (Some(10), Some(2)));
+    ///
+    /// let limits = router.effective_limits(Method::Get, "/admin/health").unwrap();
+    /// assert_eq!((limits.class_weight, limits.class_share), (4, Some(8)));
+    ///
+    /// assert!(router.effective_limits(Method::Post, "/users/7").is_none());
+    /// ```
+    pub fn effective_limits(&self, method: Method, path: &str) -> Option<EffectiveLimits> {
+        let raw_route = RawRoute::from_path(path);
+        let route = self
+            .table
+            .get(&method)
+            .into_iter()
+            .chain(Some(&self.any))
+            .find_map(|routes| {
+                routes.find(&raw_route.raw_segments, self.case_insensitive, |route| {
+                    Some(route).filter(|route| route.accepts(&raw_route.raw_segments))
+                })
+            })?;
+
+        let class = route.class.unwrap_or(DEFAULT_CLASS);
+        let (class_weight, max_in_flight, class_share) = self.admission.limits(class);
+        Some(EffectiveLimits {
+            method: method.to_string(),
+            route: route.to_string(),
+            class: class.into(),
+            class_weight,
+            max_in_flight,
+            class_share,
+        })
+    }
+
+    /// Add a hook that decorates every response with extra headers once the endpoint has run.  
+    ///
+    /// The hook receives the request's [`Extensions`](struct.Extensions.html), so it can see
+    /// anything props stored there, such as the identity of the caller.  It has `budget` to
+    /// produce its headers; if it takes longer the response is sent without them rather than
+    /// being delayed.  Decorators run in the order they were added.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # use std::time::Duration;
+    /// #[derive(Clone)]
+    /// struct Caller(String);
+    ///
+    /// async fn remaining_quota(caller: &str) -> u64 {
+    ///     // ask the quota service
+    ///     # let _ = caller;
+    ///     # 42
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.decorate(Duration::from_millis(5), |ext: Extensions| async move {
+    ///     match ext.get::<Caller>() {
+    ///         Some(caller) => {
+    ///             let remaining = remaining_quota(&caller.0).await;
+    ///             vec![("x-ratelimit-remaining".into(), remaining.to_string())]
+    ///         }
+    ///         None => vec![],
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// With a mock quota service that is slow to answer for some callers, their remaining quota
+    /// is left out while the headers of a faster hook are still attached.  No enforcement
+    /// middleware is needed, only something storing the caller in the extensions.  
+    /// ```
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, Url};
+    /// # use std::{sync::Arc, time::{Duration, Instant}};
+    /// #[derive(Clone)]
+    /// struct Caller(String);
+    ///
+    /// // Stands in for the quota service, which takes a second to answer for batch jobs.
+    /// async fn remaining_quota(caller: &str) -> u64 {
+    ///     if caller == "batch" {
+    ///         task::sleep(Duration::from_secs(1)).await;
+    ///     }
+    ///     99
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, Route::from("/orders"), |req: Request, _: Params| {
+    ///     // Where the auth service would store the caller.
+    ///     if let Some(caller) = req.header(&"x-caller".parse().unwrap()) {
+    ///         Extensions::of(&req).insert(Caller(caller[0].to_string()));
+    ///     }
+    ///     async { Ok::<_, Error>(Response::from("orders")) }
+    /// });
+    /// router.decorate(Duration::from_millis(5), |ext: Extensions| async move {
+    ///     match ext.contains::<Caller>() {
+    ///         true => vec![("x-ratelimit-limit".into(), "100".into())],
+    ///         false => vec![],
+    ///     }
+    /// });
+    /// router.decorate(Duration::from_millis(5), |ext: Extensions| async move {
+    ///     match ext.get::<Caller>() {
+    ///         Some(caller) => {
+    ///             let remaining = remaining_quota(&caller.0).await;
+    ///             vec![("x-ratelimit-remaining".into(), remaining.to_string())]
+    ///         }
+    ///         None => vec![],
+    ///     }
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |caller: Option<&str>| {
+    ///     let mut req = Request::new(Method::Get, Url::parse("http://localhost/orders").unwrap());
+    ///     if let Some(caller) = caller {
+    ///         req.insert_header("x-caller", caller).unwrap();
+    ///     }
+    ///     let started = Instant::now();
+    ///     let res = task::block_on(router.clone().respond(req));
+    ///     let elapsed = started.elapsed();
+    ///     let header = |name: &str| res.header(&name.parse().unwrap()).map(|v| v[0].to_string());
+    ///     (header("x-ratelimit-limit"), header("x-ratelimit-remaining"), elapsed)
+    /// };
+    ///
+    /// let (limit, remaining, _) = get(Some("web"));
+    /// assert_eq!((limit.as_deref(), remaining.as_deref()), (Some("100"), Some("99")));
+    ///
+    /// // The slow hook runs out of its budget and its header is left out, without the response
+    /// // waiting for the quota service.
+    /// let (limit, remaining, elapsed) = get(Some("batch"));
+    /// assert_eq!((limit.as_deref(), remaining), (Some("100"), None));
+    /// assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    ///
+    /// let (limit, remaining, _) = get(None);
+    /// assert_eq!((limit, remaining), (None, None));
+    /// ```
+    pub fn decorate<F, Fut>(&mut self, budget: Duration, hook: F)
+    where
+        F: Fn(Extensions) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Vec<(String, String)>> + Send + Sync + 'static,
+    {
+        self.decorators.push(Decorator::new(budget, hook));
+    }
+
+    /// Tell `observer` about every request the router handles, when it arrives and once its
+    /// response is ready.  See [`RequestObserver`](trait.RequestObserver.html).  
+    pub fn observe(&mut self, observer: impl RequestObserver) {
+        self.observers.push(Arc::new(observer));
+    }
+
+    /// Serve the Prometheus text rendering of `metrics` at `path`, behind the `metrics` feature.
+    /// See [`Metrics`](struct.Metrics.html).  
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&mut self, path: &'static str, metrics: Metrics) {
+        self.add(
+            Method::Get,
+            Route::from(path).metadata(metrics),
+            metrics::serve_metrics,
+        );
+    }
+
+    /// Run `middleware` around every request the router dispatches, including those that match no
+    /// route.  
+    ///
+    /// A middleware receives the request, the params of the matched route and the rest of the
+    /// chain as a [`Next`](struct.Next.html).  The request has already been matched against the
+    /// routes, so the middleware can read the declared name, tags and metadata of its route from
+    /// [`MatchedRoute::of`](struct.MatchedRoute.html#method.of).  Middlewares run in the order they were added, and
+    /// the last one hands over to the endpoint.  Responding without calling `next.run`
+    /// short-circuits the chain.  [`request_log`](fn.request_log.html) and
+    /// [`access_log`](fn.access_log.html) are middlewares that log every request.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::{Arc, Mutex};
+    /// #[endpoint]
+    /// async fn user() -> Result<Response, Error> {
+    ///     Ok(Response::from("user"))
+    /// }
+    ///
+    /// let order = Arc::new(Mutex::new(vec![]));
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id), ___user);
+    ///
+    /// let seen = order.clone();
+    /// router.middleware(move |req: Request, params: Params, next: Next| {
+    ///     let id = params.get("id").cloned().unwrap_or_default();
+    ///     seen.lock().unwrap().push(format!("first {}", id));
+    ///     next.run(req, params)
+    /// });
+    ///
+    /// let seen = order.clone();
+    /// router.middleware(move |req: Request, params: Params, next: Next| {
+    ///     seen.lock().unwrap().push("second".to_owned());
+    ///     let authorized = req.header(&"authorization".parse().unwrap()).is_some();
+    ///     async move {
+    ///         if authorized {
+    ///             next.run(req, params).await
+    ///         } else {
+    ///             Response::new(StatusCode::Unauthorized)
+    ///         }
+    ///     }
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |url: &str, authorized: bool| {
+    ///     let mut req = Request::new(Method::Get, Url::parse(url).unwrap());
+    ///     if authorized {
+    ///         req.insert_header("authorization", "yes").unwrap();
+    ///     }
+    ///     task::block_on(router.clone().respond(req))
+    /// };
+    ///
+    /// let mut res = get("http://localhost/users/7", true);
+    /// assert_eq!(task::block_on(res.body_string()).unwrap(), "user");
+    /// assert_eq!(*order.lock().unwrap(), vec!["first 7", "second"]);
+    ///
+    /// // The second middleware short-circuits before the endpoint.
+    /// assert_eq!(get("http://localhost/users/7", false).status(), StatusCode::Unauthorized);
+    ///
+    /// // Middlewares see requests that match no route.
+    /// order.lock().unwrap().clear();
+    /// assert_eq!(get("http://localhost/missing", true).status(), StatusCode::NotFound);
+    /// assert_eq!(*order.lock().unwrap(), vec!["first ", "second"]);
+    /// ```
+    pub fn middleware<F, Fut>(&mut self, middleware: F)
+    where
+        F: Fn(http_types::Request, Params, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = http_types::Response> + Send + Sync + 'static,
+    {
+        self.middlewares.push(Box::new(move |req, params, next| {
+            Box::pin(middleware(req, params, next))
+        }));
+    }
+
+    /// Run `middleware` around every request before it is matched against the routes, for work
+    /// that must happen earlier, such as rewriting the path.  Pre-routing middlewares run in the
+    /// order they were added, all of them before any added with
+    /// [`middleware`](#method.middleware), and always receive empty params.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[endpoint]
+    /// async fn order(route: MatchedRoute) -> Result<Response, Error> {
+    ///     Ok(Response::from(route.pattern()))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"orders"/id), ___order);
+    /// router.pre_routing(|mut req: Request, params: Params, next: Next| {
+    ///     assert!(MatchedRoute::of(&req).is_none());
+    ///     let path = req.url().path().trim_start_matches("/v1").to_owned();
+    ///     req.url_mut().set_path(&path);
+    ///     next.run(req, params)
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let req = Request::new(Method::Get, Url::parse("http://localhost/v1/orders/7").unwrap());
+    /// let mut res = task::block_on(router.respond(req));
+    /// assert_eq!(task::block_on(res.body_string()).unwrap(), "/orders/{id}");
+    /// ```
+    pub fn pre_routing<F, Fut>(&mut self, middleware: F)
+    where
+        F: Fn(http_types::Request, Params, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = http_types::Response> + Send + Sync + 'static,
+    {
+        self.pre_routing.push(Box::new(move |req, params, next| {
+            Box::pin(middleware(req, params, next))
+        }));
+    }
+
+    /// The routes of the router, grouped by method in the order of their names, and in the
+    /// order they were added within a method, followed by the routes added with
+    /// [`any`](#method.any) under the method `*`.  Mounted routes are listed with their prefix.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"users"), ___ok);
+    /// router.add(Method::Get, route!(/"users"/id/"posts"/post_id).name("post"), ___ok);
+    /// router.add(Method::Get, Route::from("/files/{path*}"), ___ok);
+    ///
+    /// let routes: Vec<RouteInfo> = router.routes().collect();
+    /// assert_eq!(routes, vec![
+    ///     RouteInfo {
+    ///         method: "GET".into(),
+    ///         pattern: "/users/{id}/posts/{post_id}".into(),
+    ///         params: vec!["id", "post_id"],
+    ///         name: Some("post"),
+    ///     },
+    ///     RouteInfo {
+    ///         method: "GET".into(),
+    ///         pattern: "/files/{path*}".into(),
+    ///         params: vec!["path"],
+    ///         name: None,
+    ///     },
+    ///     RouteInfo {
+    ///         method: "POST".into(),
+    ///         pattern: "/users".into(),
+    ///         params: vec![],
+    ///         name: None,
+    ///     },
+    /// ]);
+    /// ```
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo> + '_ {
+        let mut methods: Vec<_> = self
+            .table
+            .iter()
+            .map(|(method, routes)| (method.to_string(), routes))
+            .collect();
+        methods.sort_by(|(method, _), (other, _)| method.cmp(other));
+        methods.push((ANY.to_string(), &self.any));
+        methods.into_iter().flat_map(|(method, routes)| {
+            routes.iter().map(move |route| {
+                let mut dynamic_segments = route.dynamic_segments.clone();
+                dynamic_segments.sort_by_key(|segment| segment.position);
+                RouteInfo {
+                    method: method.to_string(),
+                    pattern: route.to_string(),
+                    params: dynamic_segments
+                        .iter()
+                        .map(|segment| segment.name)
+                        .chain(route.rest)
+                        .collect(),
+                    name: route.name,
+                }
+            })
+        })
+    }
+
+    /// Print the [routes](#method.routes) in a table of their methods, patterns and names.
+    /// [`Config::with_print_routes`](struct.Config.html#method.with_print_routes) has the server
+    /// do it once it is listening.  
+    ///
+    /// ```text
+    /// METHOD  PATTERN                      NAME
+    /// GET     /users/{id}/posts/{post_id}  post
+    /// GET     /files/{path*}
+    /// POST    /users
+    /// ```
+    pub fn print_routes(&self) {
+        let routes: Vec<_> = self.routes().collect();
+        println!("{}", route_info::table(&routes));
+    }
+
+    /// The OpenAPI 3 document of the routes of the router, one operation per route, with the
+    /// dynamic segments of its path as path parameters.  
+    ///
+    /// The parameters of [typed segments](#method.add) of the primitive number types and `bool`
+    /// have the schema of their type, the others are strings.  Request and response bodies are
+    /// described as JSON, with the schemas given to a route with
+    /// [`request_schema`](struct.Route.html#method.request_schema) and
+    /// [`response_schema`](struct.Route.html#method.response_schema), or as any object otherwise,
+    /// the request body only for `POST`, `PUT` and `PATCH` routes.  Named routes have their name
+    /// as their `operationId`, and a [`summary`](struct.Route.html#method.summary) if they were
+    /// given one.  
+    ///
+    /// A route with [optional segments](#method.add) is described at each of its lengths, since
+    /// path parameters are always required, and only the path with all its segments has the
+    /// `operationId`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # use schemars::JsonSchema;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use serde_json::json;
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct NewUser {
+    ///     name: String,
+    /// }
+    ///
+    /// #[derive(Serialize, JsonSchema)]
+    /// struct User {
+    ///     id: u64,
+    ///     name: String,
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(
+    ///     Method::Post,
+    ///     route!(/"users").name("create_user").request_schema::<NewUser>().response_schema::<User>(),
+    ///     ___ok,
+    /// );
+    /// router.add(
+    ///     Method::Get,
+    ///     route!(/"users"/id: u64).name("user").summary("Get a user").response_schema::<User>(),
+    ///     ___ok,
+    /// );
+    /// router.add(Method::Get, Route::from("/files/{path*}"), ___ok);
+    ///
+    /// let document = router.openapi(ApiInfo::new("Users", "1.0.0").with_description("Manages users."));
+    /// let ok = |schema: serde_json::Value| json!({
+    ///     "200": { "description": "OK", "content": { "application/json": { "schema": schema } } },
+    /// });
+    /// assert_eq!(document, json!({
+    ///     "openapi": "3.0.3",
+    ///     "info": { "title": "Users", "version": "1.0.0", "description": "Manages users." },
+    ///     "paths": {
+    ///         "/users": {
+    ///             "post": {
+    ///                 "operationId": "create_user",
+    ///                 "requestBody": {
+    ///                     "required": true,
+    ///                     "content": {
+    ///                         "application/json": { "schema": { "$ref": "#/components/schemas/NewUser" } },
+    ///                     },
+    ///                 },
+    ///                 "responses": ok(json!({ "$ref": "#/components/schemas/User" })),
+    ///             },
+    ///         },
+    ///         "/users/{id}": {
+    ///             "get": {
+    ///                 "operationId": "user",
+    ///                 "summary": "Get a user",
+    ///                 "parameters": [{
+    ///                     "name": "id",
+    ///                     "in": "path",
+    ///                     "required": true,
+    ///                     "schema": { "type": "integer", "minimum": 0 },
+    ///                 }],
+    ///                 "responses": ok(json!({ "$ref": "#/components/schemas/User" })),
+    ///             },
+    ///         },
+    ///         "/files/{path}": {
+    ///             "get": {
+    ///                 "parameters": [{
+    ///                     "name": "path",
+    ///                     "in": "path",
+    ///                     "required": true,
+    ///                     "schema": { "type": "string" },
+    ///                 }],
+    ///                 "responses": ok(json!({ "type": "object" })),
+    ///             },
+    ///         },
+    ///     },
+    ///     "components": {
+    ///         "schemas": {
+    ///             "NewUser": {
+    ///                 "type": "object",
+    ///                 "required": ["name"],
+    ///                 "properties": { "name": { "type": "string" } },
+    ///             },
+    ///             "User": {
+    ///                 "type": "object",
+    ///                 "required": ["id", "name"],
+    ///                 "properties": {
+    ///                     "id": { "type": "integer", "format": "uint64", "minimum": 0.0 },
+    ///                     "name": { "type": "string" },
+    ///                 },
+    ///             },
+    ///         },
+    ///     },
+    /// }));
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"reports"/year/?month).name("report"), ___ok);
+    /// let paths = &router.openapi(ApiInfo::new("Reports", "1.0.0"))["paths"];
+    /// assert_eq!(paths["/reports/{year}"]["get"]["parameters"].as_array().unwrap().len(), 1);
+    /// assert!(paths["/reports/{year}"]["get"]["operationId"].is_null());
+    /// assert_eq!(paths["/reports/{year}/{month}"]["get"]["parameters"].as_array().unwrap().len(), 2);
+    /// assert_eq!(paths["/reports/{year}/{month}"]["get"]["operationId"], "report");
+    /// ```
+    #[cfg(feature = "openapi")]
+    pub fn openapi(&self, info: ApiInfo) -> serde_json::Value {
+        let mut methods: Vec<_> = self.table.iter().collect();
+        methods.sort_by_key(|(method, _)| method.to_string());
+        let routes = methods
+            .into_iter()
+            .flat_map(|(method, routes)| routes.iter().map(move |route| (method, route)));
+        openapi::document(&info, routes)
+    }
+
+    /// Serve the [OpenAPI document](#method.openapi) of the router at `/openapi.json`, and a
+    /// Swagger UI page showing it at `/docs`.  
+    ///
+    /// The document is made for every request, so it has the routes added after this is called.
+    /// It does not list the two routes themselves.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// let mut router = Router::new();
+    /// router.serve_openapi(ApiInfo::new("Users", "1.0.0"));
+    /// router.add(Method::Get, route!(/"users"/id), ___ok);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     let mut res = client.get("/openapi.json").send().await;
+    ///     assert_eq!(res.status(), StatusCode::Ok);
+    ///     let content_type = |res: &Response| res.header(&"content-type".parse().unwrap()).unwrap()[0].to_string();
+    ///     assert!(content_type(&res).starts_with("application/json"));
+    ///     let document: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+    ///     assert_eq!(document["info"]["title"], "Users");
+    ///     assert!(document["paths"]["/users/{id}"]["get"].is_object());
+    ///     assert!(document["paths"]["/openapi.json"].is_null());
+    ///
+    ///     let mut res = client.get("/docs").send().await;
+    ///     assert!(content_type(&res).starts_with("text/html"));
+    ///     assert!(res.body_string().await.unwrap().contains(r#"url: "/openapi.json""#));
+    ///
+    ///     assert_eq!(client.post("/openapi.json").send().await.status(), StatusCode::NotFound);
+    /// });
+    /// ```
+    #[cfg(feature = "openapi")]
+    pub fn serve_openapi(&mut self, info: ApiInfo) {
+        self.openapi = Some(info);
+    }
+
+    /// The response of the OpenAPI routes served by [`serve_openapi`](#method.serve_openapi), if
+    /// `req` is for one of them.  
+    #[cfg(feature = "openapi")]
+    fn openapi_response(&self, req: &http_types::Request) -> Option<http_types::Response> {
+        let info = self
+            .openapi
+            .as_ref()
+            .filter(|_| req.method() == Method::Get)?;
+        match req.url().path() {
+            "/openapi.json" => {
+                let document = self.openapi(info.clone()).to_string();
+                Some(response(StatusCode::Ok, mime::JSON, document.into_bytes()))
+            }
+            "/docs" => {
+                let page = openapi::swagger_ui(info.title(), "/openapi.json");
+                Some(response(StatusCode::Ok, mime::HTML, page.into_bytes()))
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "openapi"))]
+    fn openapi_response(&self, _req: &http_types::Request) -> Option<http_types::Response> {
+        None
+    }
+
+    /// Explain how a request with `method` and `path` would be routed.  
+    ///
+    /// Every route registered for `method` is compared against `path` in lookup order, recording
+    /// the first reason it did not match.  Routes registered for other methods that do match the
+    /// path are reported as near misses.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::from("user")) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id), ___user);
+    /// router.add(Method::Get, route!(/"teams"/id), ___user);
+    /// router.add(Method::Put, route!(/"users"/id/"avatar"), ___user);
+    ///
+    /// let explanation = router.explain(Method::Get, "/users/7/avatar");
+    /// assert!(explanation.matched().is_none());
+    /// assert_eq!(explanation.compact(), vec![
+    ///     "GET /users/{id}: expected 2 segments, got 3",
+    ///     "GET /teams/{id}: expected 2 segments, got 3",
+    ///     "PUT /users/{id}/avatar: matched",
+    /// ]);
+    ///
+    /// let explanation = router.explain(Method::Get, "/groups/7");
+    /// assert_eq!(
+    ///     explanation.candidates[0].outcome,
+    ///     Outcome::StaticMismatch { position: 0, expected: "users".into(), actual: "groups".into() }
+    /// );
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"posts"/id: u64), ___user);
+    /// assert_eq!(
+    ///     router.explain(Method::Get, "/posts/latest").compact(),
+    ///     vec![r#"GET /posts/{id}: segment 1 (id) expected u64, got "latest""#],
+    /// );
+    /// ```
+    pub fn explain(&self, method: Method, path: &str) -> MatchExplanation {
+        let raw_route = RawRoute::from_path(path);
+        let describe = |method: &dyn std::fmt::Display, route: &Route| Candidate {
+            method: method.to_string(),
+            route: route.to_string(),
+            outcome: explain_route(route, &raw_route, self.case_insensitive),
+            disabled: route.switch.is_disabled(),
+        };
+
+        let mut candidates: Vec<_> = self
+            .table
+            .get(&method)
+            .map(|routes| {
+                routes
+                    .iter()
+                    .map(|route| describe(&method, route))
+                    .collect()
+            })
+            .unwrap_or_default();
+        candidates.extend(self.any.iter().map(|route| describe(&ANY, route)));
+
+        let near_misses = self
+            .table
+            .iter()
+            .filter(|(other, _)| **other != method)
+            .flat_map(|(other, routes)| routes.iter().map(move |route| (other, route)))
+            .map(|(other, route)| describe(other, route))
+            .filter(|candidate| candidate.outcome == Outcome::Matched)
+            .collect();
+
+        MatchExplanation {
+            method: method.to_string(),
+            path: path.into(),
+            candidates,
+            near_misses,
+        }
+    }
+
+    /// Record the requests handled by this router so they can be [`replay`](fn.replay.html)ed.  
+    ///
+    /// ## Examples
+    /// ```no_run
+    /// # use windmill::*;
+    /// let mut router = Router::new();
+    /// router.record(Recorder::create("traces.ndjson").unwrap().sample_rate(0.5));
+    /// ```
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Redirect plaintext requests to https according to `policy`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::{sync::Arc, time::Duration};
+    /// #[endpoint]
+    /// async fn ok() -> Result<Response, Error> {
+    ///     Ok(Response::new(StatusCode::Ok))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"account").https_only(), ___ok);
+    /// router.add(Method::Get, route!(/"healthz").health_check(), ___ok);
+    /// router.https_redirect(HttpsRedirect::new().all_routes().hsts(Duration::from_secs(31_536_000)));
+    /// let router = Arc::new(router);
+    ///
+    /// let get = |url: &str| Request::new(Method::Get, Url::parse(url).unwrap());
+    ///
+    /// let res = task::block_on(router.clone().respond(get("http://example.com/account?tab=2")));
+    /// assert_eq!(res.status(), StatusCode::PermanentRedirect);
+    /// assert_eq!(res.header(&"location".parse().unwrap()).unwrap()[0].as_str(), "https://example.com/account?tab=2");
+    /// assert!(res.header(&"strict-transport-security".parse().unwrap()).is_none());
+    ///
+    /// let res = task::block_on(router.clone().respond(get("http://example.com/healthz")));
+    /// assert_eq!(res.status(), StatusCode::Ok);
+    /// assert!(res.header(&"strict-transport-security".parse().unwrap()).is_none());
+    ///
+    /// let res = task::block_on(router.respond(get("https://example.com/account")));
+    /// assert_eq!(res.status(), StatusCode::Ok);
+    /// assert!(res.header(&"strict-transport-security".parse().unwrap()).is_some());
+    /// ```
+    pub fn https_redirect(&mut self, policy: HttpsRedirect) {
+        self.https = Some(policy);
+    }
+
+    /// Answer CORS preflight requests and allow cross-origin requests according to `cors`.  
+    ///
+    /// See [`Cors`](struct.Cors.html) for an example.  
+    pub fn cors(&mut self, cors: Cors) {
+        self.cors = Some(cors);
+    }
+
+    /// Match paths ending in a slash according to `policy`, [`Strict`](enum.TrailingSlash.html)
+    /// unless set.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     Ok(Response::from(format!("{} {:?}", pattern, params.get("id"))))
+    /// }
+    ///
+    /// let router = |policy: TrailingSlash| {
+    ///     let mut router = Router::new();
+    ///     router.add(Method::Get, route!(/"example"/id), show);
+    ///     router.add(Method::Get, route!(/"example"), show);
+    ///     router.trailing_slash(policy);
+    ///     Arc::new(router)
+    /// };
+    /// let get = |router: &Arc<Router>, path: &str| {
+    ///     let url = Url::parse(&format!("http://localhost{}", path)).unwrap();
+    ///     let res = task::block_on(router.clone().respond(Request::new(Method::Get, url)));
+    ///     let location = res.header(&"location".parse().unwrap()).map(|v| v[0].to_string());
+    ///     (res.status(), location, task::block_on(res.body_string()).unwrap())
+    /// };
+    ///
+    /// let strict = router(TrailingSlash::Strict);
+    /// assert_eq!(get(&strict, "/example/5").2, r#"/example/{id} Some("5")"#);
+    /// assert_eq!(get(&strict, "/example/5/").0, StatusCode::NotFound);
+    /// // The empty segment is the id.
+    /// assert_eq!(get(&strict, "/example/").2, r#"/example/{id} Some("")"#);
+    ///
+    /// let ignore = router(TrailingSlash::Ignore);
+    /// assert_eq!(get(&ignore, "/example/5/").2, r#"/example/{id} Some("5")"#);
+    /// assert_eq!(get(&ignore, "/example/").2, "/example None");
+    /// assert_eq!(get(&ignore, "/example").2, "/example None");
+    /// assert_eq!(get(&ignore, "/example/5//").0, StatusCode::NotFound);
+    /// assert_eq!(get(&ignore, "/").0, StatusCode::NotFound);
+    /// assert_eq!(get(&ignore, "//").0, StatusCode::NotFound);
+    ///
+    /// let redirect = router(TrailingSlash::Redirect);
+    /// let (status, location, _) = get(&redirect, "/example/5/?page=2");
+    /// assert_eq!((status, location.unwrap().as_str()), (StatusCode::PermanentRedirect, "/example/5?page=2"));
+    /// assert_eq!(get(&redirect, "/example/5").2, r#"/example/{id} Some("5")"#);
+    /// // The path with the slash matches a route itself.
+    /// assert_eq!(get(&redirect, "/example/").2, r#"/example/{id} Some("")"#);
+    /// assert_eq!(get(&redirect, "/missing/").0, StatusCode::NotFound);
+    /// assert_eq!(get(&redirect, "/").0, StatusCode::NotFound);
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// The path of the route named `name`, with its dynamic segments replaced by `params`, see
+    /// [`Urls::url_for`](struct.Urls.html#method.url_for).  Endpoints can take the
+    /// [`Urls`](struct.Urls.html) of the router as an argument to do the same.  
+    ///
+    /// Naming two routes with different paths alike is a conflict, which makes
+    /// [`add`](#method.add) and [`mount`](#method.mount) panic and [`try_add`](#method.try_add)
+    /// return an error.  The same name can be given to the routes of a path under several methods.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// let mut orders = Router::new();
+    /// orders.add(Method::Get, route!(/"orders"/order_id/"items"/item_id).name("item"), ___ok);
+    ///
+    /// let mut router = Router::new();
+    /// router.mount("/tenants/{tenant_id}", orders);
+    /// router.add(Method::Get, route!(/"users"/id).name("user"), ___ok);
+    /// router.add(Method::Put, route!(/"users"/id).name("user"), ___ok);
+    ///
+    /// let item = router.url_for("item", &[("tenant_id", "acme"), ("order_id", "7"), ("item_id", "3")]);
+    /// assert_eq!(item.unwrap(), "/tenants/acme/orders/7/items/3");
+    /// assert_eq!(router.url_for("user", &[("id", "ada@example.com")]).unwrap(), "/users/ada%40example.com");
+    ///
+    /// let err = router.try_add(Method::Get, route!(/"people"/id).name("user"), ___ok).unwrap_err();
+    /// assert_eq!(err.to_string(), "route /people/{id} is named `user` like /users/{id}");
+    /// assert_eq!(router.url_for("user", &[("id", "7")]).unwrap(), "/users/7");
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, Error> {
+        self.urls.url_for(name, params)
+    }
+
+    /// Answer a request that matches no route only because a typed segment did not parse with
+    /// `400 Bad Request` naming the param, instead of `404 Not Found`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn post() -> Result<Response, Error> { Ok(Response::from("post")) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/user_id: u64/"posts"/post_id: u64), ___post);
+    /// router.reject_unparsed_params(true);
+    /// let client = TestClient::new(router);
+    ///
+    /// task::block_on(async {
+    ///     let mut res = client.get("/users/abc/posts/3").send().await;
+    ///     assert_eq!(res.status(), StatusCode::BadRequest);
+    ///     let body = res.body_string().await.unwrap();
+    ///     assert!(body.contains("invalid param `user_id`: invalid digit found in string"), "{}", body);
+    ///
+    ///     assert_eq!(client.get("/users/1/posts/3").send().await.status(), StatusCode::Ok);
+    ///     assert_eq!(client.get("/users/1/comments/3").send().await.status(), StatusCode::NotFound);
+    /// });
+    /// ```
+    pub fn reject_unparsed_params(&mut self, enabled: bool) {
+        self.reject_unparsed_params = enabled;
+    }
+
+    /// Compare static segments without regard to ASCII case, so `/Users/7` matches
+    /// `route!(/"users"/id)`.  The values of dynamic segments are passed in params as they were
+    /// sent.  Routes whose static segments differ only in case are then ambiguous, so set it
+    /// before adding routes.  Static segments are compared exactly by default.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode};
+    /// async fn show(req: Request, params: Params) -> Result<Response, Error> {
+    ///     let pattern = MatchedRoute::of(&req).unwrap().pattern().to_owned();
+    ///     Ok(Response::from(format!("{} id={}", pattern, params.get("id").unwrap())))
+    /// }
+    ///
+    /// let routes = |case_insensitive: bool| {
+    ///     let mut router = Router::new();
+    ///     router.case_insensitive(case_insensitive);
+    ///     router.add(Method::Get, route!(/"users"/id), show);
+    ///     router.add(Method::Get, route!(/"Users"/id/"Posts"), show);
+    ///     router
+    /// };
+    /// let get = |client: &TestClient, path: &'static str| {
+    ///     task::block_on(async {
+    ///         let mut res = client.get(path).send().await;
+    ///         (res.status(), res.body_string().await.unwrap())
+    ///     })
+    /// };
+    ///
+    /// let client = TestClient::new(routes(false));
+    /// assert_eq!(get(&client, "/users/Ada").1, "/users/{id} id=Ada");
+    /// assert_eq!(get(&client, "/USERS/Ada").0, StatusCode::NotFound);
+    /// assert_eq!(get(&client, "/Users/Ada/Posts").1, "/Users/{id}/Posts id=Ada");
+    /// assert_eq!(get(&client, "/users/Ada/posts").0, StatusCode::NotFound);
+    ///
+    /// let client = TestClient::new(routes(true));
+    /// assert_eq!(get(&client, "/users/Ada").1, "/users/{id} id=Ada");
+    /// assert_eq!(get(&client, "/USERS/Ada").1, "/users/{id} id=Ada");
+    /// assert_eq!(get(&client, "/uSeRs/aDa/pOsTs").1, "/Users/{id}/Posts id=aDa");
+    ///
+    /// // Spelled in another case, a static segment is ambiguous with the route already added.
+    /// let mut router = routes(true);
+    /// assert!(router.try_add(Method::Get, route!(/"USERS"/id), show).is_err());
+    /// let mut router = routes(false);
+    /// assert!(router.try_add(Method::Get, route!(/"USERS"/id), show).is_ok());
+    /// ```
+    pub fn case_insensitive(&mut self, enabled: bool) {
+        self.case_insensitive = enabled;
+    }
+
+    /// The path requests for `path` are matched with.  
+    fn match_path<'p>(&self, path: &'p str) -> &'p str {
+        match (self.trailing_slash, trailing_slash::trim(path)) {
+            (TrailingSlash::Ignore, Some(trimmed)) => trimmed,
+            _ => path,
+        }
+    }
+
+    /// Where to redirect `req` under [`TrailingSlash::Redirect`](enum.TrailingSlash.html), when
+    /// only its path without the trailing slash matches a route.  
+    fn canonical_location(
+        &self,
+        req: &http_types::Request,
+        raw_route: &RawRoute,
+        host: Option<&str>,
+    ) -> Option<String> {
+        if self.trailing_slash != TrailingSlash::Redirect
+            || self.find(req.method(), raw_route, host).is_some()
+        {
+            return None;
+        }
+        let trimmed = trailing_slash::trim(req.url().path())?;
+        self.find(req.method(), &RawRoute::from_path(trimmed), host)?;
+        Some(match req.url().query() {
+            Some(query) => format!("{}?{}", trimmed, query),
+            None => trimmed.to_owned(),
+        })
+    }
+
+    /// A handle for disabling and enabling named routes while the router is serving.  
+    pub fn handle(&self) -> RouterHandle {
+        self.handle.clone()
+    }
+
+    /// Serve the state of the named routes at `GET /__routes`, and let them be disabled and enabled
+    /// with `POST /__routes/{name}/disable` and `POST /__routes/{name}/enable`, the same as
+    /// through a [`RouterHandle`](struct.RouterHandle.html).  The body of a disable request is the
+    /// incident message.  Requests that `authorize` rejects get `401 Unauthorized`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::task;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"search").name("search"), ___ok);
+    /// router.route_admin(|req: &Request| {
+    ///     req.header(&"authorization".parse().unwrap())
+    ///         .map(|value| value[0].as_str() == "Bearer s3cret")
+    ///         .unwrap_or(false)
+    /// });
+    /// let router = Arc::new(router);
+    ///
+    /// let send = |method: Method, path: &str, token: &str, body: &str| {
+    ///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+    ///     let mut req = Request::new(method, url);
+    ///     req.insert_header("authorization", format!("Bearer {}", token)).unwrap();
+    ///     req.set_body(body);
+    ///     let mut res = task::block_on(router.clone().respond(req));
+    ///     (res.status(), task::block_on(res.body_string()).unwrap())
+    /// };
+    ///
+    /// let (status, _) = send(Method::Post, "/__routes/search/disable", "guess", "");
+    /// assert_eq!(status, StatusCode::Unauthorized);
+    ///
+    /// let (status, _) = send(Method::Post, "/__routes/search/disable", "s3cret", "reindexing");
+    /// assert_eq!(status, StatusCode::Ok);
+    /// assert_eq!(send(Method::Get, "/search", "", "").0, StatusCode::ServiceUnavailable);
+    /// assert!(router.explain(Method::Get, "/search").matched().unwrap().disabled);
+    ///
+    /// let (_, routes) = send(Method::Get, "/__routes", "s3cret", "");
+    /// assert_eq!(
+    ///     routes,
+    ///     r#"[{"disabled":"reindexing","method":"GET","name":"search","rejected":1,"route":"/search"}]"#
+    /// );
+    ///
+    /// send(Method::Post, "/__routes/search/enable", "s3cret", "");
+    /// assert_eq!(send(Method::Get, "/search", "", ""), (StatusCode::Ok, "ok".to_owned()));
+    /// assert_eq!(send(Method::Post, "/__routes/nope/enable", "s3cret", "").0, StatusCode::NotFound);
+    /// ```
+    pub fn route_admin<F>(&mut self, authorize: F)
+    where
+        F: Fn(&http_types::Request) -> bool + Send + Sync + 'static,
+    {
+        self.route_admin = Some(Arc::new(authorize));
+    }
+
+    /// Serve [`explain`](#method.explain) at `/__explain?method=GET&path=/users/abc` and include a
+    /// compact explanation in the body of 404 responses.  
+    ///
+    /// This only takes effect in debug builds, release builds ignore it.  
+    pub fn debug_explain(&mut self, enabled: bool) {
+        self.debug_explain = enabled;
+    }
+
+    fn explaining(&self) -> bool {
+        cfg!(debug_assertions) && self.debug_explain
+    }
+
+    /// Inject a [`LiveReload`](struct.LiveReload.html) script into HTML responses and serve the
+    /// `/__reload` endpoint it polls.  
+    ///
+    /// This only takes effect in debug builds, release builds ignore it.  
+    pub fn live_reload(&mut self, live_reload: LiveReload) {
+        self.live_reload = Some(live_reload);
+    }
+
+    fn reloading(&self) -> Option<&LiveReload> {
+        self.live_reload.as_ref().filter(|_| cfg!(debug_assertions))
+    }
+
+    /// Share `state` with every endpoint, which receive a clone of it through
+    /// [`State<S>`](struct.State.html).  Registering state of the same type again replaces it.  
+    pub fn state<S: Clone + Send + Sync + 'static>(&mut self, state: S) {
+        self.state.insert(state);
+    }
+
+    pub(crate) fn app_state(&self) -> AppState {
+        self.state.clone()
+    }
+
+    /// Dispatch a request in process, the same way the server does.  
+    ///
+    /// When no route matches the path under the request's method but routes under other methods
+    /// do, the response is `405 Method Not Allowed` with an `Allow` header listing those methods.
+    /// A path that matches no route at all is `404 Not Found`.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[endpoint]
+    /// async fn example() -> Result<Response, Error> {
+    ///     Ok(Response::new(StatusCode::Ok))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"example"/id), ___example);
+    /// router.add(Method::Put, route!(/"example"/id), ___example);
+    /// let router = Arc::new(router);
+    ///
+    /// let url = Url::parse("http://localhost/example/5").unwrap();
+    /// let res = async_std::task::block_on(router.clone().respond(Request::new(Method::Delete, url)));
+    /// assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+    /// assert_eq!(res.header(&"allow".parse().unwrap()).unwrap()[0].as_str(), "GET, HEAD, PUT");
+    ///
+    /// let url = Url::parse("http://localhost/nothing/here").unwrap();
+    /// let res = async_std::task::block_on(router.respond(Request::new(Method::Delete, url)));
+    /// assert_eq!(res.status(), StatusCode::NotFound);
+    /// ```
+    ///
+    /// ## Early responses
+    /// When the endpoint responds without reading the whole request body, what is left is drained
+    /// if it fits in the [drain budget](struct.Config.html#method.with_drain_budget).  Otherwise the
+    /// response is marked `Connection: close` and the server closes the connection after sending
+    /// it, so the rest of the body is never mistaken for the next request.
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Request, Response, StatusCode, Url};
+    /// # use std::sync::Arc;
+    /// #[endpoint]
+    /// async fn upload() -> Result<Response, Error> {
+    ///     Err(Error::unauthorized("login required"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"upload"), ___upload);
+    /// let router = Arc::new(router);
+    ///
+    /// let upload = |len: usize| {
+    ///     let mut req = Request::new(Method::Post, Url::parse("http://localhost/upload").unwrap());
+    ///     req.insert_header("content-length", len.to_string()).unwrap();
+    ///     req.set_body(vec![0u8; len]);
+    ///     req
+    /// };
+    /// let connection = |res: &Response| res.header(&"connection".parse().unwrap()).map(|v| v[0].to_string());
+    ///
+    /// let res = async_std::task::block_on(router.clone().respond(upload(1024)));
+    /// assert_eq!(res.status(), StatusCode::Unauthorized);
+    /// assert_eq!(connection(&res), None);
+    ///
+    /// let res = async_std::task::block_on(router.respond(upload(10 * 1024 * 1024)));
+    /// assert_eq!(res.status(), StatusCode::Unauthorized);
+    /// assert_eq!(connection(&res).as_deref(), Some("close"));
+    /// ```
+    pub async fn respond(self: Arc<Self>, req: http_types::Request) -> http_types::Response {
+        self.lookup(req).await
+    }
+
+    pub(crate) fn lookup(self: Arc<Self>, mut req: http_types::Request) -> ResponseFuture {
+        let extensions = Extensions::default();
+        req.local_mut().insert(extensions.clone());
+        req.local_mut().insert(self.state.clone());
+        req.local_mut().insert(self.urls.clone());
+        if let Some(formatter) = self.error_formatter.as_ref() {
+            req.local_mut().insert(formatter.clone());
+        }
+
+        Box::pin(async move {
+            let trace = match self.recorder.as_ref() {
+                Some(recorder) if recorder.sample() => Some(recorder.capture(&mut req).await),
+                _ => None,
+            };
+
+            let config = req.local().get::<Arc<Config>>().cloned();
+            let request_id_header: HeaderName = config
+                .as_deref()
+                .map_or(DEFAULT_REQUEST_ID_HEADER, Config::request_id_header)
+                .parse()
+                .unwrap();
+            let request_id = req
+                .header(&request_id_header)
+                .and_then(|values| values.last())
+                .and_then(|value| RequestId::adopt(value.as_str()))
+                .unwrap_or_else(RequestId::generate);
+            let _ = req.insert_header(request_id_header.clone(), request_id.as_str());
+            extensions.insert(request_id.clone());
+            let observed = match self.observers.is_empty() {
+                true => None,
+                false => Some((ReceivedAt::of(&req), self.request_info(&req))),
+            };
+            if let Some((_, info)) = observed.as_ref() {
+                for observer in self.observers.iter() {
+                    observer.on_request_start(info);
+                }
+            }
+            let tracker = BodyTracker::wrap(&mut req);
+            let secure = self.https.as_ref().map(|https| https.is_secure(&req));
+            let path = req.url().path().to_owned();
+            #[cfg(feature = "compression")]
+            let accept_encoding = req
+                .header(&"accept-encoding".parse().unwrap())
+                .map(|values| {
+                    values
+                        .iter()
+                        .map(|value| value.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                });
+            let origin = req
+                .header(&"origin".parse().unwrap())
+                .and_then(|values| values.last())
+                .map(|value| value.as_str().to_owned());
+            let preflight = match self.cors.as_ref() {
+                Some(_) if Cors::is_preflight(&req) => {
+                    let host = request_host(&req);
+                    let raw_route = RawRoute::from_path(self.match_path(&path));
+                    self.allowed_methods(&raw_route, host.as_deref())
+                }
+                _ => vec![],
+            };
+
+            let res = if let (Some(cors), false) = (self.cors.as_ref(), preflight.is_empty()) {
+                let res = cors.preflight(origin.as_deref(), preflight.clone());
+                Box::pin(async move { res })
+            } else if self.explaining() && path == "/__explain" {
+                Box::pin(explain_endpoint(self.clone(), req))
+            } else if let (Some(authorize), true) =
+                (self.route_admin.as_ref(), path.starts_with("/__routes"))
+            {
+                let authorized = authorize(&req);
+                Box::pin(admin_endpoint(self.handle.clone(), authorized, req))
+            } else if let (Some(live_reload), "/__reload") = (self.reloading(), path.as_str()) {
+                let live_reload = live_reload.clone();
+                Box::pin(async move { live_reload.poll(&req).await })
+            } else if let Some(res) = self.openapi_response(&req) {
+                Box::pin(async move { res })
+            } else {
+                Next::new(self.clone()).run(req, Params::default())
+            };
+
+            let mut res = res.await;
+            for decorator in self.decorators.iter() {
+                decorator.apply(&extensions, &mut res).await;
+            }
+            if let Some(live_reload) = self.reloading() {
+                live_reload.inject(&mut res).await;
+            }
+            #[cfg(feature = "compression")]
+            {
+                if let Some(compression) = config.as_deref().and_then(Config::compression) {
+                    compression
+                        .apply(accept_encoding.as_deref(), &mut res)
+                        .await;
+                }
+            }
+            tracker.settle(config.as_deref(), &mut res).await;
+            if let (Some(cors), true) = (self.cors.as_ref(), preflight.is_empty()) {
+                cors.apply(origin.as_deref(), &mut res);
+            }
+            if let (Some(https), Some(secure)) = (self.https.as_ref(), secure) {
+                https.apply_hsts(secure, &mut res);
+            }
+            if let (Some(recorder), Some(trace)) = (self.recorder.as_ref(), trace) {
+                recorder.write(trace, res.status());
+            }
+            let _ = res.insert_header(request_id_header, request_id.as_str());
+            if let Some((start, info)) = observed {
+                let response_info = ResponseInfo {
+                    status: res.status(),
+                    size: res.len(),
+                    client_aborted: ClientAborted::is_set(&res),
+                };
+                let latency = start.elapsed();
+                for observer in self.observers.iter() {
+                    observer.on_request_end(&info, &response_info, latency);
+                }
+            }
+            res
+        })
+    }
+
+    /// Match `req` against the routes, attaching the [`MatchedRoute`](struct.MatchedRoute.html) to
+    /// it and returning its params.  A request that matches no route gets no params.  
+    pub(crate) fn route(&self, req: &mut http_types::Request) -> Params {
+        let method = req.method();
+        let path = req.url().path().to_owned();
+        let raw_route = RawRoute::from_path(self.match_path(&path));
+
+        let host = request_host(req);
+
+        let mut params = Params::default();
+        if let Some((route, captures)) = self.find(method, &raw_route, host.as_deref()) {
+            route.dynamic_segments.iter().for_each(|dynamic_segment| {
+                // Optional segments the path leaves out have no param.
+                let value = match raw_route.raw_segments.get(dynamic_segment.position) {
+                    Some(segment) => &segment.value,
+                    None => return,
+                };
+                params.insert(dynamic_segment.name, value.to_string());
+                if let Some(Ok(typed)) = dynamic_segment.ty.map(|ty| (ty.parse)(value)) {
+                    params.insert_typed(dynamic_segment.name, typed);
+                }
+            });
+            if let Some(rest) = route.rest {
+                let segments: Vec<&str> = raw_route.raw_segments[route.len()..]
+                    .iter()
+                    .map(|segment| segment.raw)
+                    .collect();
+                params.insert(rest, segments.join("/"));
+            }
+            captures.iter().for_each(|(name, value)| {
+                params.insert(name, value.into());
+            });
+            req.local_mut().insert(MatchedRoute::new(method, route));
+            if !captures.is_empty() {
+                req.local_mut().insert(captures);
+            }
+        }
+        params
+    }
+
+    /// The request as seen by observers, labelled with the route its path and host match.  
+    fn request_info(&self, req: &http_types::Request) -> RequestInfo {
+        let method = req.method();
+        let raw_route = RawRoute::from_path(self.match_path(req.url().path()));
+        let host = request_host(req);
+        RequestInfo {
+            method,
+            route: self
+                .find(method, &raw_route, host.as_deref())
+                .map(|(route, _)| route.to_string()),
+        }
+    }
+
+    /// The routes tried for a request of `method`, in order: the routes of the method, the `GET`
+    /// routes for a `HEAD` request, and the routes added with [`any`](#method.any).  
+    fn tables(&self, method: Method) -> impl Iterator<Item = &RouteTable> {
+        let get = match method {
+            Method::Head => self.table.get(&Method::Get),
+            _ => None,
+        };
+        self.table
+            .get(&method)
+            .into_iter()
+            .chain(get)
+            .chain(Some(&self.any))
+    }
+
+    /// The most specific route of `method` matching the path and host of a request, with the
+    /// labels its host rule captured.  `HEAD` requests fall back to the `GET` routes, and every
+    /// method to the routes added with [`any`](#method.any).  
+    fn find(
+        &self,
+        method: Method,
+        raw_route: &RawRoute,
+        host: Option<&str>,
+    ) -> Option<(&Route, HostCaptures)> {
+        self.tables(method)
+            .find_map(|routes| self.find_in(routes, raw_route, host))
+    }
+
+    /// The most specific route of exactly `method` matching the path and host of a request.  
+    fn find_exact(
+        &self,
+        method: Method,
+        raw_route: &RawRoute,
+        host: Option<&str>,
+    ) -> Option<(&Route, HostCaptures)> {
+        self.find_in(self.table.get(&method)?, raw_route, host)
+    }
+
+    /// The most specific route of `routes` matching the path and host of a request.  
+    fn find_in<'r>(
+        &self,
+        routes: &'r RouteTable,
+        raw_route: &RawRoute,
+        host: Option<&str>,
+    ) -> Option<(&'r Route, HostCaptures)> {
+        if raw_route.malformed {
+            return None;
+        }
+        routes.find(&raw_route.raw_segments, self.case_insensitive, |route| {
+            if !route.accepts(&raw_route.raw_segments) {
+                return None;
+            }
+            match route.host.as_ref() {
+                Some(pattern) => Some((route, pattern.captures(host?)?)),
+                None => Some((route, HostCaptures::default())),
+            }
+        })
+    }
+
+    /// The error of the first typed segment that did not parse, when that alone kept a route of
+    /// `method` from matching the path and host of a request.  
+    fn unparsed_param(
+        &self,
+        method: Method,
+        raw_route: &RawRoute,
+        host: Option<&str>,
+    ) -> Option<Error> {
+        let (segment, e) = self.tables(method).find_map(|routes| {
+            routes.find(&raw_route.raw_segments, self.case_insensitive, |route| {
+                if let Some(pattern) = route.host.as_ref() {
+                    pattern.captures(host?)?;
+                }
+                route.unparsed_segment(&raw_route.raw_segments)
+            })
+        })?;
+        Some(
+            Error::bad_request(format!("invalid param `{}`: {}", segment.name, e))
+                .with_code(WindmillErrorCode::DeserializeFailed),
+        )
+    }
+
+    pub(crate) fn pre_routing_middlewares(&self) -> &[MiddlewareFn] {
+        &self.pre_routing
+    }
+
+    pub(crate) fn middlewares(&self) -> &[MiddlewareFn] {
+        &self.middlewares
+    }
+
+    /// Run the endpoint of the route matching `req` with `params`.  A `HEAD` request that matches
+    /// no `HEAD` route is answered like a `GET` request, without the body.  
+    pub(crate) fn dispatch(&self, req: http_types::Request, params: Params) -> ResponseFuture {
+        let answered_by_get = req.method() == Method::Head && {
+            let raw_route = RawRoute::from_path(self.match_path(req.url().path()));
+            let host = request_host(&req);
+            self.find_exact(Method::Head, &raw_route, host.as_deref())
+                .is_none()
+        };
+        let res = self.dispatch_route(req, params);
+        match answered_by_get {
+            true => Box::pin(async move { without_body(res.await) }),
+            false => res,
+        }
+    }
+
+    fn dispatch_route(&self, mut req: http_types::Request, params: Params) -> ResponseFuture {
+        let method = req.method();
+        let path = req.url().path().to_owned();
+        let raw_route = RawRoute::from_path(self.match_path(&path));
+        let host = request_host(&req);
+        if let Some(location) = self.canonical_location(&req, &raw_route, host.as_deref()) {
+            let mut res = http_types::Response::new(StatusCode::PermanentRedirect);
+            let _ = res.insert_header("location", location);
+            return Box::pin(async move { res });
+        }
+        match self.find(method, &raw_route, host.as_deref()) {
+            Some((route, _)) => {
+                let https = self
+                    .https
+                    .as_ref()
+                    .filter(|https| https.applies(route.https_only, route.health_check));
+                if let Some((https, location)) =
+                    https.and_then(|https| Some((https, https.location(&req)?)))
+                {
+                    let res = https.redirect(&location);
+                    return Box::pin(async move { res });
+                }
+                if route.switch.is_disabled() {
+                    if let Some(e) = route.switch.reject() {
+                        let res = self.error_response(&req, e);
+                        return Box::pin(async move { res });
+                    }
+                }
+
+                if let Some(hook) = route.upload_progress.as_ref() {
+                    hook.wrap(&mut req);
+                }
+
+                let fail = |req: &http_types::Request| {
+                    let formatter = self
+                        .error_formatter
+                        .clone()
+                        .map(|formatter| (formatter, request_head(req)));
+                    move |e: Error| match formatter {
+                        Some((formatter, head)) => formatter.format(&e, &head),
+                        None => error_body(e),
+                    }
+                };
+                let config = req.local().get::<Arc<Config>>().cloned();
+                let timeouts = route.timeouts.or_else(|| Some(config.as_ref()?.timeouts()));
+                let handler_timeout = route
+                    .handler_timeout
+                    .or_else(|| config.as_ref()?.handler_timeout());
+                if let Some(limit) = handler_timeout {
+                    req.local_mut().insert(Deadline::at(Instant::now() + limit));
+                }
+                let handler_fail = handler_timeout.map(|limit| (limit, fail(&req)));
+                let etag_fail = match route.etag {
+                    true => Some((Preconditions::of(&req), fail(&req))),
+                    false => None,
+                };
+                let fail = timeouts.map(|_| fail(&req));
+                let request = RequestLabel::of(&req);
+                let client = ClientConnection::of(&req).filter(|_| !route.run_to_completion);
+
+                let mut res = (route.handler.as_ref().unwrap())(req, params);
+                if self.admission.is_limited() {
+                    let permit = self.admission.acquire(route.class.unwrap_or(DEFAULT_CLASS));
+                    res = Box::pin(async move {
+                        let _permit = permit.await;
+                        res.await
+                    });
+                }
+                if let Some((limit, fail)) = handler_fail {
+                    res = timeout::enforce_handler_timeout(limit, request.clone(), res, fail);
+                }
+                if let (Some(timeouts), Some(fail)) = (timeouts, fail) {
+                    let counters = self.timeouts.clone();
+                    res = timeout::enforce(timeouts, counters, request.clone(), res, fail);
+                }
+                if let Some((preconditions, fail)) = etag_fail {
+                    res = etag::conditional(preconditions, res, fail);
+                }
+                match client {
+                    Some(client) => {
+                        let aborts = self.client_aborts.clone();
+                        disconnect::cancel_on_disconnect(client, aborts, request, res)
+                    }
+                    None => res,
+                }
+            }
+            _ if raw_route.malformed => {
+                let e = error(
+                    StatusCode::BadRequest,
+                    WindmillErrorCode::BadRequest,
+                    "path is not valid percent-encoded UTF-8",
+                );
+                let res = self.error_response(&req, e);
+                Box::pin(async move { res })
+            }
+            _ => {
+                let unparsed = match self.reject_unparsed_params {
+                    true => self.unparsed_param(method, &raw_route, host.as_deref()),
+                    false => None,
+                };
+                if let Some(e) = unparsed {
+                    let res = self.error_response(&req, e);
+                    return Box::pin(async move { res });
+                }
+                let allowed = self.allowed_methods(&raw_route, host.as_deref());
+                if allowed.is_empty() {
+                    return self.not_found(req);
+                }
+                let e = error(
+                    StatusCode::MethodNotAllowed,
+                    WindmillErrorCode::MethodNotAllowed,
+                    "method not allowed",
+                );
+                let mut res = self.error_response(&req, e);
+                let _ = res.insert_header("allow", allowed.join(", "));
+                Box::pin(async move { res })
+            }
+        }
+    }
+
+    /// Answer a request that matches no route.  
+    fn not_found(&self, req: http_types::Request) -> ResponseFuture {
+        match self.not_found.as_ref() {
+            Some(handler) => handler(req, Params::default()),
+            None if self.explaining() => {
+                let explanation = self.explain(req.method(), req.url().path());
+                let e = Error::not_found(explanation.compact())
+                    .with_code(WindmillErrorCode::RouteNotFound);
+                let res = self.error_response(&req, e);
+                Box::pin(async move { res })
+            }
+            None => {
+                let e = error(
+                    StatusCode::NotFound,
+                    WindmillErrorCode::RouteNotFound,
+                    "not found",
+                );
+                let res = self.error_response(&req, e);
+                Box::pin(async move { res })
+            }
+        }
+    }
+
+    /// Turn an error raised by the router itself into a response, with the
+    /// [error formatter](#method.set_error_formatter) if there is one.  
+    fn error_response(&self, req: &http_types::Request, e: Error) -> http_types::Response {
+        match self.error_formatter.as_ref() {
+            Some(formatter) => formatter.format(&e, req),
+            None => error_body(e),
+        }
+    }
+
+    /// The methods with a route matching `raw_route` and `host`, sorted by name.  
+    fn allowed_methods(&self, raw_route: &RawRoute, host: Option<&str>) -> Vec<String> {
+        let mut methods: Vec<String> = self
+            .table
+            .keys()
+            .filter(|method| self.find(**method, raw_route, host).is_some())
+            .map(|method| method.to_string())
+            .collect();
+        if methods.iter().any(|method| method == "GET")
+            && !methods.iter().any(|method| method == "HEAD")
+        {
+            methods.push(Method::Head.to_string());
+        }
+        methods.sort();
+        methods
+    }
+}
+
+async fn explain_endpoint(router: Arc<Router>, req: http_types::Request) -> http_types::Response {
+    let query = |name: &str| {
+        req.url()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let method = query("method").and_then(|method| method.parse::<Method>().ok());
+    match (method, query("path")) {
+        (Some(method), Some(path)) => {
+            let body = serde_json::json!(router.explain(method, &path)).to_string();
+            response(StatusCode::Ok, mime::JSON, body.into_bytes())
+        }
+        _ => {
+            let body = serde_json::json!("method and path query parameters required").to_string();
+            response(StatusCode::BadRequest, mime::JSON, body.into_bytes())
+        }
+    }
+}
+
+/// Wrap `endpoint` into a handler that encodes its errors with `codec`.  
+fn handler<C: Codec>(endpoint: impl Endpoint + Send + Sync, codec: C) -> RouteFn {
+    let codec = Arc::new(codec);
+    Box::new(
+        move |req: http_types::Request, params: Params| -> ResponseFuture {
+            let codec = codec.clone();
+            let formatter =
+                ErrorFormatter::of(&req).map(|formatter| (formatter, request_head(&req)));
+            let request = RequestLabel::of(&req);
+            let res = CatchUnwind(endpoint.call(req, params));
+            Box::pin(async move {
+                let res = match res.await {
+                    Ok(Err(e)) if e.code().is_server_error() => {
+                        eprintln!("{}: {}", request, e);
+                        Err(e)
+                    }
+                    Ok(res) => res,
+                    Err(payload) => {
+                        eprintln!(
+                            "{}: endpoint panicked: {}",
+                            request,
+                            panic::message(&*payload)
+                        );
+                        Err(error(
+                            StatusCode::InternalServerError,
+                            WindmillErrorCode::Internal,
+                            "internal server error",
+                        ))
+                    }
+                };
+                match res {
+                    Ok(mut res) => {
+                        if res.header(&headers::CONTENT_TYPE).is_none() && res.len() != Some(0) {
+                            let _ = res.set_content_type(codec.mime());
+                        }
+                        set_content_length(&mut res);
+                        res
+                    }
+                    Err(e) => match formatter {
+                        Some((formatter, head)) => formatter.format(&e, &head),
+                        None => {
+                            let (mime, bytes) = match codec.encode(&e.body()) {
+                                Ok(bytes) => (codec.mime(), bytes),
+                                Err(_) => (mime::JSON, e.body().to_string().into_bytes()),
+                            };
+                            let mut res = response(e.code(), mime, bytes);
+                            e.apply_headers(&mut res);
+                            res
+                        }
+                    },
+                }
+            })
+        },
+    )
+}
+
+fn error(code: StatusCode, error_code: WindmillErrorCode, msg: &str) -> Error {
+    Error::new(code, msg).with_code(error_code)
+}
+
+/// The response to a `HEAD` request answered by a `GET` route: `res` without its body, but with
+/// the `Content-Length` of the body when it is known.  
+fn without_body(mut res: http_types::Response) -> http_types::Response {
+    if let Some(len) = res.take_body().len() {
+        let _ = res.insert_header("content-length", len.to_string());
+    }
+    res
 }
 
-fn response(code: StatusCode, mime: Mime) -> http_types::Response {
-    let mut res = http_types::Response::new(code);
-    let _ = res.set_content_type(mime);
+/// The response for `e` when no error formatter is set.  
+pub(crate) fn error_body(e: Error) -> http_types::Response {
+    let mut res = response(e.code(), mime::JSON, e.body().to_string().into_bytes());
+    e.apply_headers(&mut res);
     res
 }
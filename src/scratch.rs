@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+
+/// The most a scratch buffer keeps between requests, so a connection that once received a large
+/// body does not hold on to its memory while idle.  
+const MAX_RETAINED: usize = 64 * 1024;
+
+/// A buffer a connection reads the bodies of its requests into, attached to each request by the
+/// server.  A connection handles one request at a time, so the buffer it lends out is returned
+/// before the next request needs it, and reading a body allocates nothing once the buffer has
+/// grown to the size of the bodies the client sends.  
+#[derive(Clone, Default)]
+pub(crate) struct ScratchBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl ScratchBuffer {
+    pub(crate) fn of(req: &http_types::Request) -> Option<Self> {
+        req.local().get::<Self>().cloned()
+    }
+
+    /// Borrow the buffer, empty.  A buffer that is already lent out is not shared, the caller
+    /// gets a new one instead.  
+    pub(crate) fn take(&self) -> Vec<u8> {
+        let mut buf = std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()));
+        buf.clear();
+        buf
+    }
+
+    /// Give the buffer back for the next request.  
+    pub(crate) fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() > MAX_RETAINED {
+            return;
+        }
+        buf.clear();
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = buf;
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    config::Config,
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use async_std::task;
+use http_types::{mime, StatusCode};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// How to serialize a response body that is estimated to be larger than the configured threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SerializePolicy {
+    /// Serialize on the current task, yielding to the executor every time roughly `every` more
+    /// bytes have been written so other requests on the same thread keep making progress.
+    Yield { every: usize },
+    /// Serialize on the blocking thread pool.
+    Blocking,
+}
+
+impl Default for SerializePolicy {
+    fn default() -> Self {
+        SerializePolicy::Yield { every: 64 * 1024 }
+    }
+}
+
+/// A props that serializes large JSON response bodies without monopolizing the executor thread.
+///
+/// The threshold and policy come from the server's [`Config`](struct.Config.html).  The size of a
+/// body is estimated from its first element, so a cheap check decides whether the extra work is
+/// worth it.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use http_types::Response;
+/// #[endpoint]
+/// async fn export(serializer: Serializer) -> Result<Response, Error> {
+///     let rows: Vec<u64> = (0..1_000_000).collect();
+///     serializer.response(rows).await
+/// }
+/// ```
+///
+/// Yielding lets a trivial task on the same single threaded executor finish while a large body is
+/// still being serialized:
+/// ```
+/// # use windmill::*;
+/// # use async_std::{prelude::*, task};
+/// # use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+/// let serializer = Serializer::new(1024, SerializePolicy::Yield { every: 4096 });
+/// let trivial_done = Arc::new(AtomicBool::new(false));
+///
+/// let big = {
+///     let trivial_done = trivial_done.clone();
+///     async move {
+///         let bytes = serializer.to_vec((0..200_000u64).collect()).await.unwrap();
+///         (bytes.len(), trivial_done.load(Ordering::SeqCst))
+///     }
+/// };
+/// let trivial = async {
+///     task::yield_now().await;
+///     trivial_done.store(true, Ordering::SeqCst);
+/// };
+///
+/// let ((_, finished_first), _) = task::block_on(big.join(trivial));
+/// assert!(finished_first);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Serializer {
+    threshold: usize,
+    policy: SerializePolicy,
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new(1024 * 1024, SerializePolicy::default())
+    }
+}
+
+impl Serializer {
+    /// Create a serializer that applies `policy` to bodies estimated above `threshold` bytes.
+    pub fn new(threshold: usize, policy: SerializePolicy) -> Self {
+        Self { threshold, policy }
+    }
+
+    /// Serialize `items` as a JSON array.
+    pub async fn to_vec<T>(&self, items: Vec<T>) -> Result<Vec<u8>, Error>
+    where
+        T: Serialize + Send + 'static,
+    {
+        let estimate = match items.first() {
+            Some(first) => {
+                serde_json::to_vec(first)
+                    .map_err(serialize_error::<T>)?
+                    .len()
+                    * items.len()
+            }
+            None => 0,
+        };
+
+        if estimate <= self.threshold {
+            return serde_json::to_vec(&items).map_err(serialize_error::<T>);
+        }
+
+        match self.policy {
+            SerializePolicy::Blocking => {
+                task::spawn_blocking(move || {
+                    serde_json::to_vec(&items).map_err(serialize_error::<T>)
+                })
+                .await
+            }
+            SerializePolicy::Yield { every } => {
+                let mut buf = Vec::with_capacity(estimate + 2);
+                let mut since_yield = 0;
+
+                buf.push(b'[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b',');
+                    }
+                    let before = buf.len();
+                    serde_json::to_writer(&mut buf, item).map_err(serialize_error::<T>)?;
+                    since_yield += buf.len() - before;
+
+                    if since_yield >= every {
+                        since_yield = 0;
+                        task::yield_now().await;
+                    }
+                }
+                buf.push(b']');
+
+                Ok(buf)
+            }
+        }
+    }
+
+    /// Serialize `items` as the body of a JSON response.
+    pub async fn response<T>(&self, items: Vec<T>) -> Result<http_types::Response, Error>
+    where
+        T: Serialize + Send + 'static,
+    {
+        let body = self.to_vec(items).await?;
+        let mut res = http_types::Response::new(StatusCode::Ok);
+        let _ = res.set_content_type(mime::JSON);
+        res.set_body(body);
+        Ok(res)
+    }
+}
+
+impl Props for Serializer {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let serializer = match req.local().get::<Arc<Config>>() {
+                Some(config) => Self::new(
+                    config.large_response_threshold(),
+                    config.large_response_policy(),
+                ),
+                None => Self::default(),
+            };
+            Ok((req, params, serializer))
+        })
+    }
+}
+
+fn serialize_error<T>(e: serde_json::Error) -> Error {
+    let msg = format!("unable to encode {}: {}", std::any::type_name::<T>(), e);
+    Error::internal(msg).with_code(WindmillErrorCode::Internal)
+}
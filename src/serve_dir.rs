@@ -0,0 +1,91 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    matched::MatchedRoute,
+    named_file::{FileConditions, NamedFile},
+    params::Params,
+    util::percent_decode,
+};
+use std::path::{Path, PathBuf};
+
+/// A directory of files served under a url prefix with
+/// [`Router::serve_dir`](struct.Router.html#method.serve_dir).
+///
+/// Files are [sent](struct.NamedFile.html#method.respond) like a
+/// [`NamedFile`](struct.NamedFile.html), with a `Content-Type` guessed from their extension, and
+/// answer `If-Modified-Since` and `Range` requests.  Paths that name no file are
+/// `404 Not Found`, and paths with a segment that would leave the directory, such as `..` or
+/// `..%2f`, are `400 Bad Request`.
+#[derive(Clone, Debug)]
+pub struct ServeDir {
+    root: PathBuf,
+    index: bool,
+}
+
+impl ServeDir {
+    /// Serve the files under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index: false,
+        }
+    }
+
+    /// Answer requests for a directory with the `index.html` in it, rather than `404 Not Found`.
+    pub fn with_index(mut self) -> Self {
+        self.index = true;
+        self
+    }
+
+    /// The directory the files are served from.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The file on disk for `path`, the part of the request path under the url prefix.
+    fn resolve(&self, path: &str) -> Result<PathBuf, Error> {
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            let segment = percent_decode(segment);
+            match segment.as_str() {
+                "" | "." => {}
+                ".." => return Err(bad_path()),
+                _ if segment.contains(&['/', '\\', '\0'][..]) => return Err(bad_path()),
+                _ => file.push(segment),
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// The endpoint of the routes added with `serve_dir`, which carry their `ServeDir` as metadata.
+pub(crate) async fn serve_file(
+    req: http_types::Request,
+    params: Params,
+) -> Result<http_types::Response, Error> {
+    let dir = MatchedRoute::of(&req)
+        .and_then(|route| route.metadata::<ServeDir>())
+        .ok_or_else(|| {
+            Error::internal("request was not routed to a directory")
+                .with_code(WindmillErrorCode::Internal)
+        })?;
+    let mut file = dir.resolve(params.get("path").map_or("", String::as_str))?;
+
+    let is_dir = async_std::fs::metadata(&file)
+        .await
+        .map(|metadata| metadata.is_dir())
+        .unwrap_or(false);
+    if is_dir {
+        if !dir.index {
+            return Err(
+                Error::not_found("file not found").with_code(WindmillErrorCode::FileNotFound)
+            );
+        }
+        file.push("index.html");
+    }
+    let conditions = FileConditions::of(&req);
+    NamedFile::open(file).await?.respond(&conditions).await
+}
+
+fn bad_path() -> Error {
+    Error::bad_request("path leaves the served directory").with_code(WindmillErrorCode::BadRequest)
+}
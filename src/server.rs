@@ -1,15 +1,106 @@
-use crate::{config::Config, router::Router};
-use async_std::{
-    net::{TcpListener, TcpStream},
-    prelude::*,
-    task,
+use crate::{
+    access_log::ReceivedAt,
+    config::Config,
+    connection_limit::{ConnectionCount, ConnectionOverflow},
+    disconnect::ClientConnection,
+    expect::ExpectContinue,
+    head::{Head, Verdict},
+    lifecycle::{StartHook, Startup, StopHook},
+    listener::{BoundSocket, Stream},
+    request_head::PeerAddr,
+    router::Router,
+    scratch::ScratchBuffer,
+    ws::Upgrade,
+};
+#[cfg(feature = "tls")]
+use async_std::net::TcpStream;
+use async_std::{future, io, prelude::*, task};
+#[cfg(feature = "tls")]
+use futures_rustls::server::TlsStream;
+use http_types::{Error, StatusCode};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{Shutdown, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
-use http_types::Error;
-use std::sync::Arc;
 
-/// The server that listens for requests.  
+/// The server that listens for requests.
+///
+/// ## Expect: 100-continue
+/// A client that sends `Expect: 100-continue` with a request waits for `100 Continue` before
+/// uploading the body.  The server sends it once the endpoint starts reading the body, so a
+/// request that is answered without it, because it matches no route, its `Content-Length` is
+/// over the [maximum body size](struct.Config.html#method.with_max_body_size) or a middleware
+/// turns it away, gets its final response right away and the body is never sent.  The connection
+/// is closed after such a response.  HTTP/1.0 clients are never sent `100 Continue`.
+///
+/// ```
+/// # use windmill::*;
+/// # use async_std::{io, net::TcpStream, prelude::*, task};
+/// # use http_types::{Method, Response};
+/// # use std::time::Duration;
+/// async fn upload(mut req: http_types::Request, _params: Params) -> Result<Response, Error> {
+///     let body = read_body(&mut req).await?;
+///     Ok(Response::from(format!("{} bytes", body.len())))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, Route::from("/upload"), upload);
+/// let server = Server::bind(Config::new("localhost:0").with_max_body_size(1024)).unwrap();
+/// let addr = server.local_addr().unwrap();
+/// std::thread::spawn(move || server.run(router).map_err(|e| e.to_string()));
+///
+/// let head = |path: &str, len: usize| {
+///     format!("POST {} HTTP/1.1\r\nhost: localhost\r\nexpect: 100-continue\r\ncontent-length: {}\r\n\r\n", path, len)
+/// };
+/// // Read what the server sends until it goes quiet, well before curl would stop waiting.
+/// let read = |stream: &TcpStream| {
+///     let mut stream = stream.clone();
+///     async move {
+///         let (mut res, mut buf) = (vec![], [0; 1024]);
+///         while let Ok(n) = io::timeout(Duration::from_millis(200), stream.read(&mut buf)).await {
+///             if n == 0 {
+///                 break;
+///             }
+///             res.extend_from_slice(&buf[..n]);
+///         }
+///         String::from_utf8(res).unwrap()
+///     }
+/// };
+///
+/// task::block_on(async {
+///     let mut stream = TcpStream::connect(addr).await.unwrap();
+///     stream.write_all(head("/upload", 5).as_bytes()).await.unwrap();
+///     assert_eq!(read(&stream).await, "HTTP/1.1 100 Continue\r\n\r\n");
+///     stream.write_all(b"hello").await.unwrap();
+///     let res = read(&stream).await;
+///     assert!(res.starts_with("HTTP/1.1 200 OK"), "{}", res);
+///     assert!(res.ends_with("5 bytes"), "{}", res);
+///
+///     // Rejected before the client uploads anything.
+///     let mut stream = TcpStream::connect(addr).await.unwrap();
+///     stream.write_all(head("/missing", 5).as_bytes()).await.unwrap();
+///     let res = read(&stream).await;
+///     assert!(res.starts_with("HTTP/1.1 404 Not Found"), "{}", res);
+///
+///     let mut stream = TcpStream::connect(addr).await.unwrap();
+///     stream.write_all(head("/upload", 4096).as_bytes()).await.unwrap();
+///     let res = read(&stream).await;
+///     assert!(res.starts_with("HTTP/1.1 413 Payload Too Large"), "{}", res);
+/// });
+/// ```
 pub struct Server {
-    config: Config,
+    config: Arc<Config>,
+    open: ConnectionCount,
+    on_start: Vec<StartHook>,
+    on_stop: Vec<StopHook>,
 }
 
 impl Server {
@@ -22,7 +113,116 @@ impl Server {
     /// let server = Server::new(config);
     /// ```
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config: Arc::new(config),
+            open: ConnectionCount::default(),
+            on_start: vec![],
+            on_stop: vec![],
+        }
+    }
+
+    /// Run `hook` when the server starts, once its address is bound but before it accepts
+    /// connections, for setup like connecting to a database or warming caches.  
+    ///
+    /// Start hooks run one after the other in the order they were added, and clients that
+    /// connect meanwhile wait for them to finish, so no request is served before the server is
+    /// set up.  A hook can register the state it creates on the router through the
+    /// [`Startup`](struct.Startup.html) it is given.  If a hook fails, the server stops there:
+    /// the hooks after it and the stop hooks do not run, and `run` returns the error.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+    /// #[derive(Clone)]
+    /// struct Pool(&'static str);
+    ///
+    /// #[endpoint]
+    /// async fn ready(pool: State<Pool>) -> Result<Response, Error> {
+    ///     Ok(Response::from(pool.0))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"ready"), ___ready);
+    ///
+    /// let started = Arc::new(AtomicBool::new(false));
+    /// let stopped = Arc::new(AtomicBool::new(false));
+    /// let (started_hook, stopped_hook) = (started.clone(), stopped.clone());
+    /// let server = Server::bind(Config::new("127.0.0.1:0"))
+    ///     .unwrap()
+    ///     .on_start(move |startup: Startup| async move {
+    ///         task::sleep(Duration::from_millis(200)).await;
+    ///         startup.state(Pool("connected"));
+    ///         started_hook.store(true, Ordering::SeqCst);
+    ///         Ok(())
+    ///     })
+    ///     .on_stop(move || async move {
+    ///         stopped_hook.store(true, Ordering::SeqCst);
+    ///     });
+    /// let addr = server.local_addr().unwrap();
+    /// let (stop, stop_received) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     server
+    ///         .run_until(router, async move { let _ = stop_received.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     // The request is sent while the start hook is still running, and waits for it.
+    ///     let mut stream = TcpStream::connect(addr).await.unwrap();
+    ///     assert!(!started.load(Ordering::SeqCst));
+    ///     stream
+    ///         .write_all(b"GET /ready HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.ends_with("connected"), "{}", res);
+    ///     assert!(started.load(Ordering::SeqCst));
+    ///
+    ///     assert!(!stopped.load(Ordering::SeqCst));
+    ///     stop.send(()).await;
+    /// });
+    /// server.join().unwrap().unwrap();
+    /// assert!(stopped.load(Ordering::SeqCst));
+    ///
+    /// // A failing start hook keeps the server from serving.
+    /// let err = Server::new(Config::new("127.0.0.1:0"))
+    ///     .on_start(|_| async { Err("database unreachable".into()) })
+    ///     .run(Router::new())
+    ///     .unwrap_err();
+    /// assert!(err.to_string().contains("database unreachable"), "{}", err);
+    /// ```
+    pub fn on_start<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(Startup) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), Box<dyn std::error::Error>>> + 'static,
+    {
+        self.on_start
+            .push(Box::new(move |startup| Box::pin(hook(startup))));
+        self
+    }
+
+    /// Run `hook` when the server stops, once it has stopped accepting connections and the
+    /// requests it was handling are done, for teardown like closing a database pool.  Stop
+    /// hooks run one after the other in the order they were added, before `run_until` returns.
+    /// See [`on_start`](#method.on_start).  
+    pub fn on_stop<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.on_stop.push(Box::new(move || Box::pin(hook())));
+        self
+    }
+
+    /// The number of connections the server has open, which keeps counting once the server runs.
+    /// See [`ConnectionCount`](struct.ConnectionCount.html).  
+    pub fn connections(&self) -> ConnectionCount {
+        self.open.clone()
     }
 
     /// Start accepting requests on the server using the provided router.  
@@ -39,38 +239,843 @@ impl Server {
     /// }
     /// ```
     pub fn run(self, router: Router) -> Result<(), Box<dyn std::error::Error>> {
+        self.run_until(router, future::pending())
+    }
+
+    /// Start accepting requests on the server using the provided router, until `shutdown`
+    /// completes.  
+    ///
+    /// Once `shutdown` completes the server stops accepting connections, closes idle ones and
+    /// lets requests that are being handled finish.  Each of those connections is closed after
+    /// its response is sent.  Connections still open after the
+    /// [shutdown timeout](struct.Config.html#method.with_shutdown_timeout) are closed, and then
+    /// `run_until` returns.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, sync::channel, task};
+    /// # use http_types::{Method, Response};
+    /// # use std::time::Duration;
+    /// #[endpoint]
+    /// async fn slow() -> Result<Response, Error> {
+    ///     task::sleep(Duration::from_millis(300)).await;
+    ///     Ok(Response::from("done"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"slow"), ___slow);
+    ///
+    /// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    /// let (stop, stopped) = channel(1);
+    /// let server = std::thread::spawn(move || {
+    ///     let config = Config::new(addr.to_string());
+    ///     Server::new(config)
+    ///         .run_until(router, async move { let _ = stopped.recv().await; })
+    ///         .map_err(|e| e.to_string())
+    /// });
+    ///
+    /// task::block_on(async {
+    ///     let mut stream = loop {
+    ///         match TcpStream::connect(addr).await {
+    ///             Ok(stream) => break stream,
+    ///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+    ///         }
+    ///     };
+    ///     stream.write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\n\r\n").await.unwrap();
+    ///     task::sleep(Duration::from_millis(100)).await;
+    ///
+    ///     stop.send(()).await;
+    ///     task::sleep(Duration::from_millis(50)).await;
+    ///     assert!(TcpStream::connect(addr).await.is_err());
+    ///
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.starts_with("HTTP/1.1 200"));
+    ///     assert!(res.ends_with("done"));
+    /// });
+    ///
+    /// server.join().unwrap().unwrap();
+    /// ```
+    pub fn run_until(
+        self,
+        router: Router,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.into_bound()
+            .map_err(|e| format!("Unable to bind to tcp socket: {}", e))?
+            .run_until(router, shutdown)
+    }
+
+    /// Bind to the address in `config` without serving yet, to learn the address that was bound
+    /// before requests are sent to it.  
+    ///
+    /// The address can be a host name, and the first of its addresses that can be bound is used.
+    /// If none can, the error lists each address with the reason it could not be bound.  Binding
+    /// to port 0 takes a free port, which [`local_addr`](struct.BoundServer.html#method.local_addr)
+    /// tells.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use async_std::{net::TcpStream, prelude::*, task};
+    /// # use http_types::{Method, Response};
+    /// #[endpoint]
+    /// async fn hello() -> Result<Response, Error> {
+    ///     Ok(Response::from("hello"))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"hello"), ___hello);
+    ///
+    /// let server = Server::bind(Config::new("localhost:0")).unwrap();
+    /// let addr = server.local_addr().unwrap();
+    /// assert!(addr.ip().is_loopback());
+    /// assert_ne!(addr.port(), 0);
+    /// std::thread::spawn(move || server.run(router).map_err(|e| e.to_string()));
+    ///
+    /// // The socket is listening already, so there is no need to wait for the server.
+    /// task::block_on(async {
+    ///     let mut stream = TcpStream::connect(addr).await.unwrap();
+    ///     stream
+    ///         .write_all(b"GET /hello HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut res = String::new();
+    ///     stream.read_to_string(&mut res).await.unwrap();
+    ///     assert!(res.ends_with("hello"));
+    /// });
+    ///
+    /// // An address that is taken cannot be bound again.
+    /// let err = Server::bind(Config::new(addr.to_string())).err().unwrap();
+    /// assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+    ///
+    /// assert!(Server::bind(Config::new("not an address")).is_err());
+    /// ```
+    pub fn bind(config: Config) -> io::Result<BoundServer> {
+        Server::new(config).into_bound()
+    }
+
+    fn into_bound(self) -> io::Result<BoundServer> {
+        let socket = BoundSocket::bind(&self.config)?;
+        Ok(BoundServer {
+            local_addr: socket.local_addr()?,
+            socket,
+            server: self,
+        })
+    }
+
+    /// Wait for open connections to finish their requests, closing them once the shutdown
+    /// timeout has passed.  
+    async fn drain(&self, connections: &Connections) {
+        #[cfg(feature = "client")]
+        {
+            if let Some(client) = self.config.http_client() {
+                client.close_idle();
+            }
+        }
+
+        let deadline = Instant::now() + self.config.shutdown_timeout();
+        loop {
+            connections.close_idle();
+            if connections.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            task::sleep(Duration::from_millis(10)).await;
+        }
+        connections.close_all();
+    }
+}
+
+/// A server bound to its address, created with [`Server::bind`](struct.Server.html#method.bind).  
+pub struct BoundServer {
+    server: Server,
+    socket: BoundSocket,
+    local_addr: Option<SocketAddr>,
+}
+
+impl BoundServer {
+    /// The address the server is bound to, with the port that was picked if the config asked for
+    /// port 0.  Servers on a [Unix socket](struct.Config.html#method.unix) have none.  
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The number of connections the server has open.  See
+    /// [`Server::connections`](struct.Server.html#method.connections).  
+    pub fn connections(&self) -> ConnectionCount {
+        self.server.connections()
+    }
+
+    /// Run `hook` when the server starts.  See
+    /// [`Server::on_start`](struct.Server.html#method.on_start).  
+    pub fn on_start<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(Startup) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), Box<dyn std::error::Error>>> + 'static,
+    {
+        self.server = self.server.on_start(hook);
+        self
+    }
+
+    /// Run `hook` when the server stops.  See
+    /// [`Server::on_stop`](struct.Server.html#method.on_stop).  
+    pub fn on_stop<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.server = self.server.on_stop(hook);
+        self
+    }
+
+    /// Start accepting requests using the provided router.  See
+    /// [`Server::run`](struct.Server.html#method.run).  
+    pub fn run(self, router: Router) -> Result<(), Box<dyn std::error::Error>> {
+        self.run_until(router, future::pending())
+    }
+
+    /// Start accepting requests using the provided router, until `shutdown` completes.  See
+    /// [`Server::run_until`](struct.Server.html#method.run_until).  
+    pub fn run_until(
+        self,
+        router: Router,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let BoundServer {
+            mut server, socket, ..
+        } = self;
+        let on_start = std::mem::take(&mut server.on_start);
+        let on_stop = std::mem::take(&mut server.on_stop);
+        let state = router.app_state();
         let router = Arc::new(router);
+        let connections = Arc::new(Connections::default());
         Ok(task::block_on(async {
-            let listener = TcpListener::bind(self.config.addr())
-                .await
-                .map_err(|e| format!("Unable to bind to tcp socket: {}", e))?;
+            for hook in on_start {
+                let startup = Startup {
+                    state: state.clone(),
+                };
+                hook(startup).await?;
+            }
+            let listener = socket.listen();
+
+            #[cfg(feature = "tls")]
+            let tls = match server.config.tls() {
+                Some(tls) => Some(
+                    tls.acceptor()
+                        .map_err(|e| format!("Unable to load tls certificate: {}", e))?,
+                ),
+                None => None,
+            };
+            #[cfg(feature = "tls")]
+            let scheme = if tls.is_some() { "https" } else { "http" };
+            #[cfg(not(feature = "tls"))]
+            let scheme = "http";
+
+            let addr = listener.url(scheme)?;
+            println!("listening on {}", listener);
+            if server.config.print_routes() {
+                router.print_routes();
+            }
 
-            let addr = format!("http://{}", listener.local_addr()?);
-            println!("listening on {}", addr);
+            let mut shutdown = Box::pin(shutdown);
 
-            let mut incoming = listener.incoming();
+            let max_connections = server.config.max_connections();
+            loop {
+                let next = async {
+                    if server.config.connection_overflow() == ConnectionOverflow::Wait {
+                        server.open.room(max_connections).await;
+                    }
+                    Some(listener.accept().await)
+                }
+                .race(async {
+                    (&mut shutdown).await;
+                    None
+                })
+                .await;
+                let stream = match next {
+                    Some(stream) => stream?,
+                    None => break,
+                };
+                let slot = match server.open.try_acquire(max_connections) {
+                    Some(slot) => slot,
+                    None => {
+                        #[cfg(feature = "tls")]
+                        let reject = tls.is_none();
+                        #[cfg(not(feature = "tls"))]
+                        let reject = true;
+                        if reject {
+                            task::spawn(reject_connection(stream, server.config.clone()));
+                        }
+                        continue;
+                    }
+                };
 
-            while let Some(stream) = incoming.next().await {
                 let router = router.clone();
+                let config = server.config.clone();
                 let addr = addr.clone();
-                let stream = stream?;
-                task::spawn(async {
-                    if let Err(err) = accept(addr, stream, router).await {
+                let connections = connections.clone();
+                #[cfg(feature = "tls")]
+                let tls = tls.clone();
+                task::spawn(async move {
+                    #[cfg(feature = "tls")]
+                    let conn = match (tls, stream) {
+                        (Some(tls), Stream::Tcp(stream)) => {
+                            match tls.accept(stream.clone()).await {
+                                Ok(tls_stream) => Connection::tls(stream, tls_stream, &config),
+                                Err(err) => {
+                                    eprintln!("tls handshake failed: {}", err);
+                                    return;
+                                }
+                            }
+                        }
+                        (_, stream) => Connection::new(stream, &config),
+                    };
+                    #[cfg(not(feature = "tls"))]
+                    let conn = Connection::new(stream, &config);
+                    let id = connections.insert(conn.clone());
+                    if let Err(err) = accept(addr, conn, router, config).await {
                         eprintln!("{}", err);
                     }
+                    connections.remove(id);
+                    drop(slot);
                 });
             }
+
+            drop(listener);
+            server.drain(&connections).await;
+            for hook in on_stop {
+                hook().await;
+            }
             Ok(())
         })
         .map_err(|e: Box<dyn std::error::Error>| format!("Unable to spawn blocking task: {}", e))?)
     }
 }
 
-async fn accept(addr: String, stream: TcpStream, router: Arc<Router>) -> Result<(), Error> {
+async fn accept(
+    addr: String,
+    conn: Connection,
+    router: Arc<Router>,
+    config: Arc<Config>,
+) -> Result<(), Error> {
     let router = router.clone();
-    async_h1::accept(&addr, stream.clone(), |req| async {
-        let response = router.clone().lookup(req).await.await;
+    let peer_addr = conn.stream.peer_addr().map(PeerAddr);
+    let served = AtomicUsize::new(0);
+    let max_requests = config.max_requests_per_connection();
+    let result = async_h1::accept(&addr, conn.clone(), |mut req| async {
+        let served = served.fetch_add(1, Ordering::SeqCst) + 1;
+        if matches!(max_requests, Some(max) if served > max) {
+            // Pipelined after the last request the connection may send, which closed it.
+            let msg = "connection has sent its last request";
+            return Err(Error::from_str(StatusCode::ServiceUnavailable, msg));
+        }
+        conn.set_state(HANDLING);
+        let http_1_0 = matches!(conn.verdict(), Some(Verdict::Serve1_0 { .. }));
+        conn.expect.reset(!http_1_0 && ExpectContinue::wanted(&req));
+        req.local_mut().insert(conn.expect.clone());
+        req.local_mut().insert(ReceivedAt(Instant::now()));
+        req.local_mut().insert(config.clone());
+        req.local_mut()
+            .insert(ClientConnection(conn.stream.clone()));
+        req.local_mut().insert(conn.scratch.clone());
+        if let Some(peer_addr) = peer_addr {
+            req.local_mut().insert(peer_addr);
+        }
+        let mut response = router.clone().lookup(req).await;
+        if let Some(upgrade) = response.local_mut().remove::<Upgrade>() {
+            upgrade.run(&response, conn.clone()).await;
+            conn.detach();
+            return Ok(http_types::Response::new(StatusCode::Ok));
+        }
+        if let Some(Verdict::Serve1_0 { keep_alive }) = conn.verdict() {
+            respond_1_0(&mut response, keep_alive).await?;
+        }
+        let last = matches!(max_requests, Some(max) if served >= max);
+        if last || conn.timed_out() {
+            let _ = response.insert_header("connection", "close");
+        }
+        if wants_close(&response) {
+            conn.close_after_response();
+        }
+        conn.set_state(RESPONDING);
         Ok(response)
     })
-    .await
+    .await;
+
+    let mut conn = conn;
+    let refusal: &[u8] = match conn.verdict() {
+        Some(Verdict::Refuse) => b"HTTP/1.1 505 HTTP Version Not Supported\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        Some(Verdict::MissingHost) => b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        _ => b"",
+    };
+    if !refusal.is_empty() {
+        let _ = conn.write_all(refusal).await;
+    }
+
+    // Ends a tls session cleanly, so clients can tell the last response was not truncated.
+    let _ = future::poll_fn(|cx| io::Write::poll_close(Pin::new(&mut conn), cx)).await;
+    let over_limit = matches!(max_requests, Some(max) if served.into_inner() > max);
+    match conn.timed_out() || over_limit {
+        true => Ok(()),
+        false => result,
+    }
+}
+
+/// Answer a connection over the limit with `503 Service Unavailable` and close it, giving up if
+/// the client does not read the response within the write timeout.  
+async fn reject_connection(stream: Stream, config: Arc<Config>) {
+    let res = b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    let write = async { (&stream).write_all(res).await };
+    let _ = match config.write_timeout() {
+        Some(timeout) => io::timeout(timeout, write).await,
+        None => write.await,
+    };
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Adapt a response for an HTTP/1.0 client, which keeps the connection open only when it asked
+/// to and cannot read a chunked body.  
+async fn respond_1_0(res: &mut http_types::Response, keep_alive: bool) -> Result<(), Error> {
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    let _ = res.insert_header("connection", connection);
+
+    if res.len().is_none() {
+        let mut body = vec![];
+        res.take_body().read_to_end(&mut body).await?;
+        res.set_body(body);
+    }
+    Ok(())
+}
+
+fn wants_close(res: &http_types::Response) -> bool {
+    res.header(&"connection".parse().unwrap())
+        .map(|values| {
+            values
+                .iter()
+                .any(|value| value.as_str().eq_ignore_ascii_case("close"))
+        })
+        .unwrap_or(false)
+}
+
+/// The open client connections of a server, so they can be closed when it shuts down.  
+#[derive(Default)]
+struct Connections {
+    open: Mutex<HashMap<usize, Connection>>,
+    next_id: AtomicUsize,
+}
+
+impl Connections {
+    fn insert(&self, conn: Connection) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.lock().insert(id, conn);
+        id
+    }
+
+    fn remove(&self, id: usize) {
+        self.lock().remove(&id);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Close every connection once its current response is sent, and close connections waiting
+    /// for a request right away.  
+    fn close_idle(&self) {
+        for conn in self.lock().values() {
+            conn.close_after_response();
+            if conn.state() == IDLE {
+                let _ = conn.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    fn close_all(&self) {
+        for conn in self.lock().values() {
+            let _ = conn.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, Connection>> {
+        self.open.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The connection is waiting for a request.  
+const IDLE: u8 = 0;
+/// The router is handling a request from the connection.  
+const HANDLING: u8 = 1;
+/// The response is being written, the connection is idle again once it reads the next request.  
+const RESPONDING: u8 = 2;
+
+/// A client connection that stops reading once a response has asked for it to be closed.  
+///
+/// The next read after such a response sees the end of the stream, so any unread request body
+/// left on the connection is never parsed as another request, and the connection is dropped once
+/// the response has been written.  
+#[derive(Clone)]
+struct Connection {
+    stream: Stream,
+    head: Arc<Mutex<Head>>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<Mutex<TlsStream<TcpStream>>>>,
+    closing: Arc<AtomicBool>,
+    detached: Arc<AtomicBool>,
+    state: Arc<AtomicU8>,
+    expect: ExpectContinue,
+    scratch: ScratchBuffer,
+    waits: Arc<Mutex<Waits>>,
+    header_read_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+}
+
+impl Connection {
+    fn new(stream: Stream, config: &Config) -> Self {
+        let host = stream.host();
+        Self {
+            stream,
+            head: Arc::new(Mutex::new(Head::new(config.min_http_version(), host))),
+            #[cfg(feature = "tls")]
+            tls: None,
+            closing: Arc::new(AtomicBool::new(false)),
+            detached: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(AtomicU8::new(IDLE)),
+            expect: ExpectContinue::default(),
+            scratch: ScratchBuffer::default(),
+            waits: Arc::new(Mutex::new(Waits {
+                idle_since: Instant::now(),
+                head_started: None,
+                read: Wait::default(),
+                write: Wait::default(),
+                timed_out: false,
+            })),
+            header_read_timeout: config.header_read_timeout(),
+            read_timeout: config.read_timeout(),
+            write_timeout: config.write_timeout(),
+            keep_alive_timeout: config.keep_alive_timeout(),
+        }
+    }
+
+    /// A connection that reads and writes through `tls`, an encrypted session over `stream`.  
+    #[cfg(feature = "tls")]
+    fn tls(stream: TcpStream, tls: TlsStream<TcpStream>, config: &Config) -> Self {
+        Self {
+            tls: Some(Arc::new(Mutex::new(tls))),
+            ..Self::new(Stream::Tcp(stream), config)
+        }
+    }
+
+    fn close_after_response(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop writing responses to the connection after it was upgraded to another protocol, and
+    /// close it instead of reading another request.  
+    fn detach(&self) {
+        self.detached.store(true, Ordering::SeqCst);
+        self.close_after_response();
+        self.set_state(RESPONDING);
+    }
+
+    fn state(&self) -> u8 {
+        self.state.load(Ordering::SeqCst)
+    }
+
+    fn set_state(&self, state: u8) {
+        self.state.store(state, Ordering::SeqCst);
+    }
+
+    fn head(&self) -> std::sync::MutexGuard<'_, Head> {
+        self.head.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn verdict(&self) -> Option<Verdict> {
+        self.head().verdict()
+    }
+
+    fn waits(&self) -> std::sync::MutexGuard<'_, Waits> {
+        self.waits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a read or write on the connection gave up waiting for the client.  
+    fn timed_out(&self) -> bool {
+        self.waits().timed_out
+    }
+
+    /// Fail a read that has waited longer than the read timeout, past the keep-alive timeout
+    /// while waiting for a request, or past the header read timeout while the head of a request is
+    /// read.  The connection is closed after the response, if there is one.  
+    fn timed_read(
+        &self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        let mut waits = self.waits();
+        let head_deadline = match (self.state(), waits.head_started) {
+            (IDLE, Some(started)) => self
+                .header_read_timeout
+                .and_then(|limit| started.checked_add(limit)),
+            (IDLE, None) => self
+                .keep_alive_timeout
+                .and_then(|limit| waits.idle_since.checked_add(limit)),
+            _ => None,
+        };
+        let read_timeout = self.read_timeout;
+        let deadline = |since: Instant| {
+            let read_deadline = read_timeout.and_then(|limit| since.checked_add(limit));
+            match (head_deadline, read_deadline) {
+                (Some(head), Some(read)) => Some(head.min(read)),
+                (head, read) => head.or(read),
+            }
+        };
+        match poll {
+            Poll::Pending if waits.read.expired(cx, deadline) => {
+                waits.timed_out = true;
+                drop(waits);
+                self.close_after_response();
+                let e = io::Error::new(io::ErrorKind::TimedOut, "client stopped sending");
+                Poll::Ready(Err(e))
+            }
+            Poll::Pending => Poll::Pending,
+            ready => {
+                if let Poll::Ready(Ok(n)) = ready {
+                    if n > 0 && self.state() == IDLE {
+                        waits.head_started.get_or_insert_with(Instant::now);
+                    }
+                }
+                waits.read.progress();
+                ready
+            }
+        }
+    }
+
+    /// Fail a write that has waited longer than the write timeout.  
+    fn timed_write<T>(
+        &self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        let mut waits = self.waits();
+        let write_timeout = self.write_timeout;
+        let deadline = |since: Instant| write_timeout.and_then(|limit| since.checked_add(limit));
+        match poll {
+            Poll::Pending if waits.write.expired(cx, deadline) => {
+                waits.timed_out = true;
+                let e = io::Error::new(io::ErrorKind::TimedOut, "client stopped reading");
+                Poll::Ready(Err(e))
+            }
+            Poll::Pending => Poll::Pending,
+            ready => {
+                waits.write.progress();
+                ready
+            }
+        }
+    }
+
+    fn poll_stream(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls.as_ref() {
+                let mut tls = tls.lock().unwrap_or_else(|e| e.into_inner());
+                return Pin::new(&mut *tls).poll_read(cx, buf);
+            }
+        }
+        Pin::new(&mut &self.stream).poll_read(cx, buf)
+    }
+
+    /// Read from the stream, buffering the start of each request to check its version first.  
+    fn poll_request(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.closing.load(Ordering::SeqCst) && self.state() != HANDLING {
+            return Poll::Ready(Ok(0));
+        }
+
+        // Read the start of each request before the parser does, to check its version.
+        let mut head = self.head();
+        loop {
+            match head.verdict() {
+                Some(Verdict::Refuse) | Some(Verdict::MissingHost) | Some(Verdict::Close) => {
+                    return Poll::Ready(Ok(0))
+                }
+                Some(_) => break,
+                None => {
+                    let mut chunk = [0; 4096];
+                    match self.poll_stream(cx, &mut chunk) {
+                        Poll::Ready(Ok(0)) => head.finish(),
+                        Poll::Ready(Ok(n)) => {
+                            self.waits().head_started.get_or_insert_with(Instant::now);
+                            head.push(&chunk[..n])
+                        }
+                        poll => return poll,
+                    }
+                }
+            }
+        }
+        match head.take(buf) {
+            0 => {
+                drop(head);
+                self.poll_stream(cx, buf)
+            }
+            n => Poll::Ready(Ok(n)),
+        }
+    }
+
+    /// Send `100 Continue` to a client waiting for it, before reading the body the endpoint asked
+    /// for.  
+    fn poll_continue(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(unsent) = self.expect.unsent() {
+            let poll = self.poll_write_stream(cx, unsent);
+            match self.timed_write(cx, poll) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => self.expect.wrote(n),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if self.expect.flushing() {
+            let poll = self.poll_flush_stream(cx);
+            match self.timed_write(cx, poll) {
+                Poll::Ready(Ok(())) => self.expect.flushed(),
+                poll => return poll,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_stream(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls.as_ref() {
+                let mut tls = tls.lock().unwrap_or_else(|e| e.into_inner());
+                return Pin::new(&mut *tls).poll_write(cx, buf);
+            }
+        }
+        Pin::new(&mut &self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush_stream(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls.as_ref() {
+                let mut tls = tls.lock().unwrap_or_else(|e| e.into_inner());
+                return Pin::new(&mut *tls).poll_flush(cx);
+            }
+        }
+        Pin::new(&mut &self.stream).poll_flush(cx)
+    }
+}
+
+impl io::Read for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // The request being handled may still read its body, reads after it are for the next one.
+        if self.state() == RESPONDING {
+            self.set_state(IDLE);
+            self.head().reset();
+            let mut waits = self.waits();
+            waits.idle_since = Instant::now();
+            waits.head_started = None;
+        }
+        if self.state() == HANDLING {
+            match self.poll_continue(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let poll = self.poll_request(cx, buf);
+        self.timed_read(cx, poll)
+    }
+}
+
+impl io::Write for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.detached.load(Ordering::SeqCst) {
+            return Poll::Ready(Ok(buf.len()));
+        }
+        let poll = self.poll_write_stream(cx, buf);
+        self.timed_write(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.detached.load(Ordering::SeqCst) {
+            return Poll::Ready(Ok(()));
+        }
+        let poll = self.poll_flush_stream(cx);
+        self.timed_write(cx, poll)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls.as_ref() {
+                let mut tls = tls.lock().unwrap_or_else(|e| e.into_inner());
+                return Pin::new(&mut *tls).poll_close(cx);
+            }
+        }
+        Pin::new(&mut &self.stream).poll_close(cx)
+    }
+}
+
+type Timer = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// How long a connection has been waiting on its client.  
+struct Waits {
+    /// When the connection became ready for its next request.
+    idle_since: Instant,
+    /// When the first byte of the next request arrived.
+    head_started: Option<Instant>,
+    read: Wait,
+    write: Wait,
+    timed_out: bool,
+}
+
+/// A read or write that is waiting on the client, since it first found nothing to do.  
+#[derive(Default)]
+struct Wait {
+    since: Option<Instant>,
+    timer: Option<(Instant, Timer)>,
+}
+
+impl Wait {
+    /// The client sent or read something, the next wait starts afresh.  
+    fn progress(&mut self) {
+        self.since = None;
+        self.timer = None;
+    }
+
+    /// Whether the wait has passed the deadline `deadline` computes from when it started, waking
+    /// the task once it does otherwise.  
+    fn expired(
+        &mut self,
+        cx: &mut Context<'_>,
+        deadline: impl FnOnce(Instant) -> Option<Instant>,
+    ) -> bool {
+        let now = Instant::now();
+        let at = match deadline(*self.since.get_or_insert(now)) {
+            Some(at) => at,
+            None => return false,
+        };
+        if at <= now {
+            return true;
+        }
+        if self.timer.as_ref().map(|(timer_at, _)| *timer_at) != Some(at) {
+            self.timer = Some((at, Box::pin(task::sleep(at - now))));
+        }
+        match self.timer.as_mut() {
+            Some((_, timer)) => timer.as_mut().poll(cx).is_ready(),
+            None => false,
+        }
+    }
 }
@@ -0,0 +1,440 @@
+use crate::{
+    cookie::{Cookie, Cookies, SameSite},
+    error::{request_head, Error, ErrorFormatter, WindmillErrorCode},
+    extensions::Extensions,
+    middleware::{MiddlewareFuture, Next},
+    params::Params,
+    props::{Props, PropsFuture},
+    request_id::RequestId,
+    router::error_body,
+    util::{constant_time_eq, hex, hmac},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// The values of a session, by key.
+pub type SessionData = serde_json::Map<String, serde_json::Value>;
+
+/// The future returned by the methods of a [`SessionStore`](trait.SessionStore.html).
+pub type SessionFuture<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + Sync + 'static>>;
+
+/// Where the data of sessions is kept between requests, by session id.
+///
+/// An error from the store is the response to the request: `load` fails it before the endpoint
+/// runs, and `store` and `destroy` after.  [`MemoryStore`](struct.MemoryStore.html) keeps
+/// sessions in the memory of the server, for development.
+pub trait SessionStore: Send + Sync + 'static {
+    /// The data of the session `id`, or `None` if there is no such session or it has expired.
+    fn load(&self, id: &str) -> SessionFuture<Option<SessionData>>;
+
+    /// Save the data of the session `id`, to expire after `ttl`.
+    fn store(&self, id: &str, data: SessionData, ttl: Duration) -> SessionFuture<()>;
+
+    /// Remove the session `id`.
+    fn destroy(&self, id: &str) -> SessionFuture<()>;
+}
+
+/// A [`SessionStore`](trait.SessionStore.html) that keeps sessions in memory.  Sessions are lost
+/// when the server stops and are not shared between servers, so it is meant for development.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, (SessionData, Instant)>>>,
+}
+
+impl MemoryStore {
+    /// The number of sessions stored, including expired ones not yet removed.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no sessions are stored.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (SessionData, Instant)>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> SessionFuture<Option<SessionData>> {
+        let mut sessions = self.lock();
+        let data = match sessions.get(id) {
+            Some((_, expires)) if *expires <= Instant::now() => {
+                sessions.remove(id);
+                None
+            }
+            Some((data, _)) => Some(data.clone()),
+            None => None,
+        };
+        Box::pin(async move { Ok(data) })
+    }
+
+    fn store(&self, id: &str, data: SessionData, ttl: Duration) -> SessionFuture<()> {
+        self.lock()
+            .insert(id.to_owned(), (data, Instant::now() + ttl));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn destroy(&self, id: &str) -> SessionFuture<()> {
+        self.lock().remove(id);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Sessions kept in a [`SessionStore`](trait.SessionStore.html) and identified by a cookie,
+/// added to a router as a [middleware](struct.Router.html#method.middleware).
+///
+/// The cookie holds the id of the session signed with HMAC-SHA256 under a secret key.  A request
+/// whose cookie is missing, tampered with or names a session the store no longer has starts with
+/// a fresh, empty session.  Endpoints take the [`Session`](struct.Session.html) as an argument.
+/// The session is stored, and its cookie set, only after a response to a request that changed
+/// it, which also restarts its time to live.
+///
+/// The cookie is named `session`, is sent with requests to every path and hidden from scripts,
+/// is `SameSite=Lax` and lives for a day by default.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response};
+/// # use std::time::Duration;
+/// #[endpoint]
+/// async fn visit(session: Session) -> Result<Response, Error> {
+///     let visits = session.get::<u64>("visits").unwrap_or(0) + 1;
+///     session.insert("visits", visits)?;
+///     Ok(Response::from(visits.to_string()))
+/// }
+///
+/// #[endpoint]
+/// async fn peek(session: Session) -> Result<Response, Error> {
+///     Ok(Response::from(session.get::<u64>("visits").unwrap_or(0).to_string()))
+/// }
+///
+/// #[endpoint]
+/// async fn logout(session: Session) -> Result<(), Error> {
+///     session.destroy();
+///     Ok(())
+/// }
+///
+/// let store = MemoryStore::default();
+/// let sessions = Sessions::new(store.clone(), b"a secret of at least thirty-two bytes".to_vec())
+///     .with_cookie_name("sid")
+///     .with_same_site(SameSite::Strict)
+///     .with_secure()
+///     .with_ttl(Duration::from_secs(3600));
+///
+/// let mut router = Router::new();
+/// router.middleware(sessions.middleware());
+/// router.add(Method::Post, route!(/"visit"), ___visit);
+/// router.add(Method::Get, route!(/"peek"), ___peek);
+/// router.add(Method::Post, route!(/"logout"), ___logout);
+/// let client = TestClient::new(router);
+///
+/// let set_cookie = |res: &Response| {
+///     res.header(&"set-cookie".parse().unwrap()).map(|values| values[0].as_str().to_owned())
+/// };
+///
+/// task::block_on(async {
+///     let mut res = client.post("/visit").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "1");
+///     let cookie = set_cookie(&res).unwrap();
+///     assert!(cookie.starts_with("sid="), "{}", cookie);
+///     assert!(cookie.ends_with("; Max-Age=3600; Path=/; Secure; HttpOnly; SameSite=Strict"));
+///     let cookie = cookie.split(';').next().unwrap().to_owned();
+///
+///     // The second request carries the cookie of the first.
+///     let mut res = client.post("/visit").header("cookie", &cookie).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "2");
+///     assert_eq!(set_cookie(&res).unwrap().split(';').next(), Some(cookie.as_str()));
+///
+///     // A session that did not change is not stored again.
+///     let mut res = client.get("/peek").header("cookie", &cookie).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "2");
+///     assert_eq!(set_cookie(&res), None);
+///
+///     // A tampered cookie is a fresh session.
+///     let tampered = cookie.replacen("sid=", "sid=0", 1);
+///     let mut res = client.get("/peek").header("cookie", &tampered).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "0");
+///     let mut res = client.get("/peek").header("cookie", "sid=forged.0000").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "0");
+///
+///     let res = client.post("/logout").header("cookie", &cookie).send().await;
+///     assert!(set_cookie(&res).unwrap().starts_with("sid=; Max-Age=0;"));
+///     assert!(store.is_empty());
+///     let mut res = client.get("/peek").header("cookie", &cookie).send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "0");
+/// });
+/// ```
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    secret: Vec<u8>,
+    cookie_name: String,
+    same_site: SameSite,
+    secure: bool,
+    ttl: Duration,
+}
+
+impl Sessions {
+    /// Keep sessions in `store`, signing their cookies with `secret`.
+    ///
+    /// ## Panics
+    /// Panics if `secret` is shorter than 32 bytes.
+    pub fn new(store: impl SessionStore, secret: impl Into<Vec<u8>>) -> Self {
+        let secret = secret.into();
+        if secret.len() < 32 {
+            panic!(
+                "session secret must be at least 32 bytes, got {}",
+                secret.len()
+            );
+        }
+        Self {
+            store: Arc::new(store),
+            secret,
+            cookie_name: "session".to_owned(),
+            same_site: SameSite::Lax,
+            secure: false,
+            ttl: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Set the name of the session cookie.
+    pub fn with_cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Set when the session cookie is sent with cross-site requests.
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Only send the session cookie over https.
+    pub fn with_secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Set how long a session lives after it last changed.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The middleware loading the session of each request before the endpoint, and saving it
+    /// after.
+    pub fn middleware(
+        self,
+    ) -> impl Fn(http_types::Request, Params, Next) -> MiddlewareFuture + Send + Sync + 'static
+    {
+        let sessions = Arc::new(self);
+        move |req, params, next| {
+            let sessions = sessions.clone();
+            Box::pin(async move {
+                let cookies = Cookies::of(&req);
+                let id = cookies
+                    .get(&sessions.cookie_name)
+                    .and_then(|value| sessions.verify(value));
+                let loaded = match id {
+                    Some(id) => match sessions.store.load(&id).await {
+                        Ok(data) => data.map(|data| (id, data)),
+                        Err(e) => return error_response(&req, e),
+                    },
+                    None => None,
+                };
+
+                let session = Session::new(loaded);
+                Extensions::of(&req).insert(session.clone());
+                let request = format!("{} {}", req.method(), req.url().path());
+                let formatter =
+                    ErrorFormatter::of(&req).map(|formatter| (formatter, request_head(&req)));
+                let mut res = next.run(req, params).await;
+                if let Err(e) = sessions.save(&session, &mut res).await {
+                    eprintln!("{}: unable to save session: {}", request, e);
+                    res = match formatter {
+                        Some((formatter, head)) => formatter.format(&e, &head),
+                        None => error_body(e),
+                    };
+                }
+                res
+            })
+        }
+    }
+
+    /// The session id signed in the cookie `value`, if the signature is valid.
+    fn verify(&self, value: &str) -> Option<String> {
+        let dot = value.rfind('.')?;
+        let (id, signature) = (&value[..dot], &value[dot + 1..]);
+        let expected = hex(&hmac(&self.secret, id.as_bytes()));
+        match constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+            true => Some(id.to_owned()),
+            false => None,
+        }
+    }
+
+    /// Store the changes made to `session`, setting or removing its cookie on `res`.
+    async fn save(&self, session: &Session, res: &mut http_types::Response) -> Result<(), Error> {
+        let (id, data, destroyed) = {
+            let mut inner = session.lock();
+            let destroyed = inner.destroyed.take();
+            match inner.changed {
+                true => {
+                    let id = inner
+                        .id
+                        .get_or_insert_with(|| RequestId::generate().as_str().to_owned())
+                        .clone();
+                    (Some(id), inner.data.clone(), destroyed)
+                }
+                false => (None, SessionData::new(), destroyed),
+            }
+        };
+
+        if let Some(destroyed) = destroyed.as_ref() {
+            self.store.destroy(destroyed).await?;
+        }
+        match id {
+            Some(id) => {
+                self.store.store(&id, data, self.ttl).await?;
+                let signature = hex(&hmac(&self.secret, id.as_bytes()));
+                self.cookie(format!("{}.{}", id, signature), self.ttl)
+                    .append_to(res);
+            }
+            None if destroyed.is_some() => {
+                self.cookie(String::new(), Duration::from_secs(0))
+                    .append_to(res);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn cookie(&self, value: String, max_age: Duration) -> Cookie {
+        let cookie = Cookie::new(self.cookie_name.as_str(), value)
+            .with_max_age(max_age)
+            .with_path("/")
+            .with_http_only()
+            .with_same_site(self.same_site);
+        match self.secure {
+            true => cookie.with_secure(),
+            false => cookie,
+        }
+    }
+}
+
+/// The session of a request, loaded by the [`Sessions`](struct.Sessions.html) middleware.
+///
+/// Values are kept as JSON, so any type that serializes can be inserted, and read back as any
+/// type it deserializes into.  Clones share the same session, which endpoints take as a props
+/// and middlewares find in the [`Extensions`](struct.Extensions.html) of the request.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Mutex<SessionState>>,
+}
+
+struct SessionState {
+    id: Option<String>,
+    data: SessionData,
+    changed: bool,
+    destroyed: Option<String>,
+}
+
+impl Session {
+    fn new(loaded: Option<(String, SessionData)>) -> Self {
+        let (id, data) = match loaded {
+            Some((id, data)) => (Some(id), data),
+            None => (None, SessionData::new()),
+        };
+        Self {
+            inner: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                changed: false,
+                destroyed: None,
+            })),
+        }
+    }
+
+    /// The id of the session, `None` for a new session until the response that stores it.
+    pub fn id(&self) -> Option<String> {
+        self.lock().id.clone()
+    }
+
+    /// Get the value of `key`, `None` if there is none or it is not a `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.lock().data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Set the value of `key`.
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) -> Result<(), Error> {
+        let value = serde_json::to_value(value).map_err(|e| {
+            Error::internal(format!("unable to encode session value: {}", e))
+                .with_code(WindmillErrorCode::Internal)
+        })?;
+        let mut inner = self.lock();
+        inner.data.insert(key.into(), value);
+        inner.changed = true;
+        Ok(())
+    }
+
+    /// Remove the value of `key`, returning whether there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut inner = self.lock();
+        let removed = inner.data.remove(key).is_some();
+        inner.changed |= removed;
+        removed
+    }
+
+    /// Whether the session has no values.
+    pub fn is_empty(&self) -> bool {
+        self.lock().data.is_empty()
+    }
+
+    /// End the session, removing it from the store and its cookie from the client.  Values
+    /// inserted afterwards start a new session, with a new id.
+    pub fn destroy(&self) {
+        let mut inner = self.lock();
+        if let Some(id) = inner.id.take() {
+            inner.destroyed = Some(id);
+        }
+        inner.data.clear();
+        inner.changed = false;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Props for Session {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let session = Extensions::of(&req).get::<Session>().ok_or_else(|| {
+                Error::internal("no session middleware configured")
+                    .with_code(WindmillErrorCode::Internal)
+            })?;
+            Ok((req, params, session))
+        })
+    }
+}
+
+/// The response for `e`, with the error formatter of the router `req` was sent to if it has one.
+fn error_response(req: &http_types::Request, e: Error) -> http_types::Response {
+    match ErrorFormatter::of(req) {
+        Some(formatter) => formatter.format(&e, req),
+        None => error_body(e),
+    }
+}
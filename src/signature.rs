@@ -0,0 +1,458 @@
+use crate::{
+    config::Config,
+    error::{Error, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    util::{constant_time_eq, hex, hmac, read_body},
+};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, sync::Arc};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+type KeyLookup = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// The canonical form of a request that signatures are computed over, following AWS Signature
+/// Version 4.
+///
+/// The canonical request is the method, path, query, signed headers and body hash of a request,
+/// each on its own line:
+///
+/// - The path has each segment percent-decoded and re-encoded, leaving only `A-Z a-z 0-9 - _ . ~`
+///   unencoded, so `%7e` and `~` or `%2A` and `*` canonicalize the same.  An empty path is `/`.
+/// - The query has each key and value re-encoded the same way, with `+` left as a literal `+`.
+///   Pairs are sorted by key and then by value, so repeated keys keep a stable order, and a key
+///   without a value becomes `key=`.
+/// - Header names are lowercased and sorted.  Values have their ends trimmed and runs of spaces
+///   inside them folded into one, and repeated headers are joined with `,`.
+/// - The body is hashed with SHA-256.  The body is read into memory and put back, so the endpoint
+///   can still read it.
+///
+/// ## Examples
+/// ```
+/// # use windmill::CanonicalRequest;
+/// # use http_types::{Method, Request, Url};
+/// # async_std::task::block_on(async {
+/// let url = Url::parse("http://example.amazonaws.com/?Param2=value2&Param1=value1").unwrap();
+/// let mut req = Request::new(Method::Get, url);
+/// req.insert_header("host", "example.amazonaws.com").unwrap();
+/// req.insert_header("x-amz-date", "20150830T123600Z").unwrap();
+///
+/// let canonical = CanonicalRequest::new(&mut req, &["host", "x-amz-date"]).await.unwrap();
+/// assert_eq!(canonical.as_str(), "GET\n\
+///     /\n\
+///     Param1=value1&Param2=value2\n\
+///     host:example.amazonaws.com\n\
+///     x-amz-date:20150830T123600Z\n\
+///     \n\
+///     host;x-amz-date\n\
+///     e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+/// # });
+/// ```
+///
+/// Encoding differences between clients do not change the canonical form:
+/// ```
+/// # use windmill::CanonicalRequest;
+/// # use http_types::{Method, Request, Url};
+/// # async_std::task::block_on(async {
+/// let canonical = |url: &str, header: &str| {
+///     let mut req = Request::new(Method::Post, Url::parse(url).unwrap());
+///     req.insert_header("x-meta", header).unwrap();
+///     req.insert_header("content-length", "2").unwrap();
+///     req.set_body("{}");
+///     async_std::task::block_on(CanonicalRequest::new(&mut req, &["X-Meta"])).unwrap()
+/// };
+///
+/// let a = canonical("http://localhost/a%7eb/c*d?tag=2&tag=1&q=x%2By&empty", "  one   two ");
+/// let b = canonical("http://localhost/a~b/c%2Ad?empty=&q=x%2by&tag=1&tag=2", "one two");
+/// assert_eq!(a, b);
+/// assert_eq!(a.as_str().lines().nth(1), Some("/a~b/c%2Ad"));
+/// assert_eq!(a.as_str().lines().nth(2), Some("empty=&q=x%2By&tag=1&tag=2"));
+/// assert_eq!(a.as_str().lines().nth(3), Some("x-meta:one two"));
+/// assert_eq!(a.signed_headers(), "x-meta");
+/// # });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalRequest {
+    canonical: String,
+    signed_headers: String,
+}
+
+impl CanonicalRequest {
+    /// Build the canonical form of `req`, covering the headers named in `signed_headers`.
+    ///
+    /// A signed header missing from the request is a `400 Bad Request` error, as is a body that
+    /// cannot be read.
+    pub async fn new(
+        req: &mut http_types::Request,
+        signed_headers: &[&str],
+    ) -> Result<Self, Error> {
+        let body = read_body(req).await?;
+        let payload_hash = hex(&Sha256::digest(&body));
+        req.set_body(body);
+
+        let mut names: Vec<String> = signed_headers
+            .iter()
+            .map(|name| name.trim().to_ascii_lowercase())
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let mut headers = String::new();
+        for name in names.iter() {
+            let values = req
+                .header(
+                    &name
+                        .parse()
+                        .map_err(|_| bad_request("invalid signed header"))?,
+                )
+                .ok_or_else(|| bad_request(&format!("missing signed header `{}`", name)))?;
+            let values: Vec<String> = values.iter().map(|value| fold(value.as_str())).collect();
+            let _ = writeln!(headers, "{}:{}", name, values.join(","));
+        }
+        let signed_headers = names.join(";");
+
+        let canonical = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method(),
+            canonical_path(req.url().path()),
+            canonical_query(req.url().query().unwrap_or_default()),
+            headers,
+            signed_headers,
+            payload_hash,
+        );
+        Ok(Self {
+            canonical,
+            signed_headers,
+        })
+    }
+
+    /// The canonical request.
+    pub fn as_str(&self) -> &str {
+        &self.canonical
+    }
+
+    /// The hex encoded SHA-256 hash of the canonical request.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::CanonicalRequest;
+    /// # use http_types::{Method, Request, Url};
+    /// # async_std::task::block_on(async {
+    /// let mut req = Request::new(Method::Get, Url::parse("http://example.amazonaws.com/").unwrap());
+    /// req.insert_header("host", "example.amazonaws.com").unwrap();
+    /// req.insert_header("x-amz-date", "20150830T123600Z").unwrap();
+    ///
+    /// let canonical = CanonicalRequest::new(&mut req, &["host", "x-amz-date"]).await.unwrap();
+    /// assert_eq!(
+    ///     canonical.hash(),
+    ///     "bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63",
+    /// );
+    /// # });
+    /// ```
+    pub fn hash(&self) -> String {
+        hex(&Sha256::digest(self.canonical.as_bytes()))
+    }
+
+    /// The names of the signed headers, lowercased, sorted and joined with `;`.
+    pub fn signed_headers(&self) -> &str {
+        &self.signed_headers
+    }
+}
+
+/// Verifies AWS Signature Version 4 signatures on incoming requests.
+///
+/// The guard looks up the secret key for the access key id in a request's `Authorization` header
+/// with a callback, recomputes the signature over the [canonical request](struct.CanonicalRequest.html)
+/// and compares the two in constant time.  The signed headers must include `host`, and the
+/// request must carry the `x-amz-date` it was signed at.  The date is checked against the
+/// credential scope but not against the clock, so a guard alone does not stop replays.
+///
+/// Register a guard with [`Config::with_signature_guard`](struct.Config.html#method.with_signature_guard)
+/// and take [`Signed`](struct.Signed.html) as a props in endpoints that require a signature.
+///
+/// ## Examples
+/// ```
+/// # use windmill::SignatureGuard;
+/// # use http_types::{Method, Request, Url};
+/// # async_std::task::block_on(async {
+/// let guard = SignatureGuard::new(|key_id| match key_id {
+///     "AKIDEXAMPLE" => Some("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned()),
+///     _ => None,
+/// });
+///
+/// let signed = |signature: &str| {
+///     let mut req = Request::new(Method::Get, Url::parse("http://example.amazonaws.com/").unwrap());
+///     req.insert_header("host", "example.amazonaws.com").unwrap();
+///     req.insert_header("x-amz-date", "20150830T123600Z").unwrap();
+///     req.insert_header("authorization", format!(
+///         "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+///          SignedHeaders=host;x-amz-date, Signature={}",
+///         signature,
+///     )).unwrap();
+///     req
+/// };
+///
+/// let mut req = signed("5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31");
+/// assert_eq!(guard.verify(&mut req).await.unwrap(), "AKIDEXAMPLE");
+///
+/// let mut req = signed("0000000000000000000000000000000000000000000000000000000000000000");
+/// let err = guard.verify(&mut req).await.unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::Unauthorized);
+/// # });
+/// ```
+pub struct SignatureGuard {
+    lookup: Box<KeyLookup>,
+}
+
+impl SignatureGuard {
+    /// Create a guard that finds the secret key for an access key id with `lookup`.
+    pub fn new<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            lookup: Box::new(lookup),
+        }
+    }
+
+    /// Verify the signature of `req`, returning the access key id it was signed with.
+    ///
+    /// A request without a valid signature is a `401 Unauthorized` error.
+    pub async fn verify(&self, req: &mut http_types::Request) -> Result<String, Error> {
+        let authorization = req
+            .header(&"authorization".parse().unwrap())
+            .and_then(|values| values.last())
+            .map(|value| value.as_str().to_owned())
+            .ok_or_else(|| unauthorized("missing authorization"))?;
+        let auth = Authorization::parse(&authorization)
+            .ok_or_else(|| unauthorized("malformed authorization"))?;
+
+        let scope: Vec<&str> = auth.scope.split('/').collect();
+        let (date, region, service) = match scope.as_slice() {
+            [date, region, service, "aws4_request"] => (*date, *region, *service),
+            _ => return Err(unauthorized("malformed credential scope")),
+        };
+
+        let timestamp = req
+            .header(&"x-amz-date".parse().unwrap())
+            .and_then(|values| values.last())
+            .map(|value| value.as_str().to_owned())
+            .ok_or_else(|| unauthorized("missing x-amz-date"))?;
+        if !timestamp.starts_with(date) {
+            return Err(unauthorized(
+                "x-amz-date does not match the credential scope",
+            ));
+        }
+
+        let signed_headers: Vec<&str> = auth.signed_headers.split(';').collect();
+        if !signed_headers.contains(&"host") {
+            return Err(unauthorized("the host header must be signed"));
+        }
+
+        let secret = (self.lookup)(&auth.key_id).ok_or_else(|| unauthorized("unknown key"))?;
+        let canonical = CanonicalRequest::new(req, &signed_headers).await?;
+
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            timestamp,
+            auth.scope,
+            canonical.hash()
+        );
+        let mut key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+        for part in [region, service, "aws4_request"].iter() {
+            key = hmac(&key, part.as_bytes());
+        }
+        let expected = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        if constant_time_eq(expected.as_bytes(), auth.signature.as_bytes()) {
+            Ok(auth.key_id)
+        } else {
+            Err(unauthorized("signature does not match"))
+        }
+    }
+}
+
+/// A props that requires a request to be signed, verified by the
+/// [`SignatureGuard`](struct.SignatureGuard.html) registered in the `Config`.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::sync::Arc;
+/// #[endpoint]
+/// async fn whoami(signed: Signed) -> Result<Response, Error> {
+///     Ok(Text::from(signed.key_id().to_owned()).into())
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"whoami"), ___whoami);
+/// let router = Arc::new(router);
+///
+/// let config = Arc::new(Config::new("127.0.0.1:4000").with_signature_guard(
+///     SignatureGuard::new(|_| Some("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned())),
+/// ));
+///
+/// let url = Url::parse("http://example.amazonaws.com/whoami").unwrap();
+/// let mut req = Request::new(Method::Get, url.clone());
+/// req.insert_header("host", "example.amazonaws.com").unwrap();
+/// req.insert_header("x-amz-date", "20150830T123600Z").unwrap();
+/// req.insert_header("authorization", "AWS4-HMAC-SHA256 \
+///     Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+///     SignedHeaders=host;x-amz-date, \
+///     Signature=03204af3c344c2327e148cca0bf802b414dc361ff2cc5f3daed8a7ac627ff897").unwrap();
+/// req.local_mut().insert(config.clone());
+/// let mut res = task::block_on(router.clone().respond(req));
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "AKIDEXAMPLE");
+///
+/// let mut req = Request::new(Method::Get, url);
+/// req.local_mut().insert(config);
+/// let res = task::block_on(router.respond(req));
+/// assert_eq!(res.status(), StatusCode::Unauthorized);
+/// ```
+pub struct Signed {
+    key_id: String,
+}
+
+impl Signed {
+    /// The access key id the request was signed with.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
+impl Props for Signed {
+    type Fut = PropsFuture<Self>;
+
+    fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let config = req
+                .local()
+                .get::<Arc<Config>>()
+                .cloned()
+                .filter(|config| config.signature_guard().is_some())
+                .ok_or_else(|| {
+                    Error::internal("no signature guard configured")
+                        .with_code(WindmillErrorCode::Internal)
+                })?;
+
+            let key_id = config.signature_guard().unwrap().verify(&mut req).await?;
+            Ok((req, params, Signed { key_id }))
+        })
+    }
+}
+
+struct Authorization {
+    key_id: String,
+    scope: String,
+    signed_headers: String,
+    signature: String,
+}
+
+impl Authorization {
+    fn parse(header: &str) -> Option<Self> {
+        let fields = header.trim().strip_prefix(ALGORITHM)?;
+        let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+        for field in fields.split(',') {
+            let mut field = field.trim().splitn(2, '=');
+            match (field.next()?, field.next()?) {
+                ("Credential", value) => credential = Some(value),
+                ("SignedHeaders", value) => signed_headers = Some(value),
+                ("Signature", value) => signature = Some(value),
+                _ => {}
+            }
+        }
+
+        let mut credential = credential?.splitn(2, '/');
+        Some(Self {
+            key_id: credential.next()?.to_owned(),
+            scope: credential.next()?.to_owned(),
+            signed_headers: signed_headers?.to_owned(),
+            signature: signature?.to_owned(),
+        })
+    }
+}
+
+fn canonical_path(path: &str) -> String {
+    if path.is_empty() {
+        return "/".to_owned();
+    }
+    path.split('/')
+        .map(|segment| encode(&decode(segment)))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(query: &str) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let key = pair.next().unwrap_or_default();
+            let value = pair.next().unwrap_or_default();
+            (encode(&decode(key)), encode(&decode(value)))
+        })
+        .collect();
+    pairs.sort();
+
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Trim a header value and fold runs of whitespace inside it into a single space.
+fn fold(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+fn bad_request(msg: &str) -> Error {
+    Error::bad_request(msg).with_code(WindmillErrorCode::SignatureInvalid)
+}
+
+fn unauthorized(msg: &str) -> Error {
+    Error::unauthorized(msg).with_code(WindmillErrorCode::SignatureInvalid)
+}
@@ -0,0 +1,241 @@
+use crate::{error::Error, responder::IntoResponse};
+use async_std::{
+    io::{self, BufReader},
+    stream::Stream,
+    task,
+};
+use http_types::StatusCode;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// How long a stream of events stays quiet before a keep-alive comment is sent, unless set.
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// An event sent to the browser by an [`Sse`](struct.Sse.html) response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    data: String,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Create an event carrying `data`, which may span several lines.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Name the event, so the browser dispatches it to the listeners for `event` rather than
+    /// `message`.
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the id the browser sends back in `Last-Event-ID` when it reconnects.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Tell the browser to wait `retry` before reconnecting.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl fmt::Display for Event {
+    /// Render the event in the `text/event-stream` format, line breaks in its name and id
+    /// removed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let single_line = |value: &str| value.replace(&['\r', '\n'][..], "");
+        if let Some(event) = self.event.as_ref() {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        if let Some(id) = self.id.as_ref() {
+            writeln!(f, "id: {}", single_line(id))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        for line in self.data.replace("\r\n", "\n").split(&['\r', '\n'][..]) {
+            writeln!(f, "data: {}", line)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// A response that streams [events](struct.Event.html) to the browser as server-sent events.
+///
+/// Each event is sent as soon as the stream produces it, and a comment is sent whenever the
+/// stream has been quiet for the keep-alive interval, 15 seconds unless set, so proxies do not
+/// close the connection.  The stream is dropped when the client goes away, which lets whatever
+/// feeds it notice, such as the sender of a channel.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{prelude::*, stream::Stream, sync::{channel, Receiver}, task};
+/// # use http_types::{Method, Request, StatusCode, Url};
+/// # use std::{pin::Pin, sync::{atomic::{AtomicBool, Ordering}, Arc}, task::{Context, Poll}, time::Duration};
+/// // A stream of events that records when it is dropped.
+/// struct Feed(Receiver<Event>, Arc<AtomicBool>);
+///
+/// impl Stream for Feed {
+///     type Item = Event;
+///     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+///         Pin::new(&mut self.0).poll_next(cx)
+///     }
+/// }
+///
+/// impl Drop for Feed {
+///     fn drop(&mut self) {
+///         self.1.store(true, Ordering::SeqCst);
+///     }
+/// }
+///
+/// #[endpoint]
+/// async fn events(feed: State<(Receiver<Event>, Arc<AtomicBool>)>) -> Result<Sse, Error> {
+///     let (events, dropped) = feed.into_inner();
+///     Ok(Sse::new(Feed(events, dropped)).with_keep_alive(Duration::from_millis(100)))
+/// }
+///
+/// let (tx, rx) = channel(16);
+/// let dropped = Arc::new(AtomicBool::new(false));
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"events"), ___events);
+/// router.state((rx, dropped.clone()));
+/// let router = Arc::new(router);
+///
+/// // Read up to the blank line that ends the next event.
+/// async fn next(body: &mut http_types::Body) -> String {
+///     let mut event = vec![];
+///     while !event.ends_with(b"\n\n") {
+///         let mut byte = [0u8; 1];
+///         body.read_exact(&mut byte).await.unwrap();
+///         event.push(byte[0]);
+///     }
+///     String::from_utf8(event).unwrap()
+/// }
+///
+/// task::block_on(async {
+///     let mut res = router.respond(Request::new(Method::Get, Url::parse("http://localhost/events").unwrap())).await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     assert_eq!(res.header(&"content-type".parse().unwrap()).unwrap()[0].as_str(), "text/event-stream");
+///     assert_eq!(res.header(&"cache-control".parse().unwrap()).unwrap()[0].as_str(), "no-cache");
+///     let mut body = res.take_body();
+///
+///     tx.send(Event::new("7").with_event("counter").with_id("7")).await;
+///     assert_eq!(next(&mut body).await, "event: counter\nid: 7\ndata: 7\n\n");
+///
+///     tx.send(Event::new("line one\nline two").with_retry(Duration::from_secs(3))).await;
+///     assert_eq!(next(&mut body).await, "retry: 3000\ndata: line one\ndata: line two\n\n");
+///
+///     // Nothing to send for a while.
+///     assert_eq!(next(&mut body).await, ": keep-alive\n\n");
+///
+///     // The client goes away.
+///     assert!(!dropped.load(Ordering::SeqCst));
+///     drop(body);
+///     assert!(dropped.load(Ordering::SeqCst));
+/// });
+/// ```
+pub struct Sse {
+    events: Pin<Box<dyn Stream<Item = Event> + Send + Sync>>,
+    keep_alive: Duration,
+}
+
+impl Sse {
+    /// Stream `events` to the browser.
+    pub fn new(events: impl Stream<Item = Event> + Send + Sync + 'static) -> Self {
+        Self {
+            events: Box::pin(events),
+            keep_alive: DEFAULT_KEEP_ALIVE,
+        }
+    }
+
+    /// Send a keep-alive comment whenever no event was sent for `keep_alive`.
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+}
+
+impl IntoResponse for Sse {
+    fn into_response(self) -> Result<http_types::Response, Error> {
+        let mut res = http_types::Response::new(StatusCode::Ok);
+        let _ = res.insert_header("content-type", "text/event-stream");
+        let _ = res.insert_header("cache-control", "no-cache");
+        let body = EventBody {
+            events: self.events,
+            keep_alive: self.keep_alive,
+            timer: None,
+            pending: vec![],
+            pos: 0,
+        };
+        res.set_body(http_types::Body::from_reader(BufReader::new(body), None));
+        Ok(res)
+    }
+}
+
+type Timer = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// The body of an `Sse` response, rendering each event as the stream produces it.
+struct EventBody {
+    events: Pin<Box<dyn Stream<Item = Event> + Send + Sync>>,
+    keep_alive: Duration,
+    timer: Option<Timer>,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl io::Read for EventBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.pos == this.pending.len() {
+            match this.events.as_mut().poll_next(cx) {
+                Poll::Ready(Some(event)) => this.refill(event.to_string().into_bytes()),
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => {
+                    let keep_alive = this.keep_alive;
+                    let timer = this
+                        .timer
+                        .get_or_insert_with(|| Box::pin(task::sleep(keep_alive)));
+                    match timer.as_mut().poll(cx) {
+                        Poll::Ready(()) => this.refill(b": keep-alive\n\n".to_vec()),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            }
+        }
+
+        let n = buf.len().min(this.pending.len() - this.pos);
+        buf[..n].copy_from_slice(&this.pending[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl EventBody {
+    /// Queue `bytes` to be read, and restart the keep-alive interval.
+    fn refill(&mut self, bytes: Vec<u8>) {
+        self.pending = bytes;
+        self.pos = 0;
+        self.timer = None;
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    extensions::Extensions,
+    params::Params,
+    props::{Props, PropsFuture},
+};
+use std::ops::Deref;
+
+/// Application state registered on a router, shared by every request it dispatches.
+#[derive(Clone, Default)]
+pub(crate) struct AppState(Extensions);
+
+impl AppState {
+    pub(crate) fn insert<S: Clone + Send + Sync + 'static>(&self, state: S) {
+        self.0.insert(state);
+    }
+}
+
+/// A clone of the state of type `S` registered with
+/// [`Router::state`](struct.Router.html#method.state).
+///
+/// The state is stored once in the router and cloned for each request that asks for it, so wrap
+/// anything expensive to clone, like a connection pool, in an `Arc`.  An endpoint asking for state
+/// that was never registered fails with `500 Internal Server Error`.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Request, Response, Url};
+/// # use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+/// #[endpoint]
+/// async fn home(hits: State<Arc<AtomicU64>>) -> Result<Response, Error> {
+///     hits.fetch_add(1, Ordering::SeqCst);
+///     Ok(Response::from("home"))
+/// }
+///
+/// #[endpoint]
+/// async fn about(hits: State<Arc<AtomicU64>>) -> Result<Response, Error> {
+///     let count = hits.fetch_add(1, Ordering::SeqCst) + 1;
+///     Ok(Response::from(format!("{} hits", count)))
+/// }
+///
+/// let hits = Arc::new(AtomicU64::new(0));
+/// let mut router = Router::new();
+/// router.state(hits.clone());
+/// router.add(Method::Get, route!(/"home"), ___home);
+/// router.add(Method::Get, route!(/"about"), ___about);
+/// let router = Arc::new(router);
+///
+/// let get = |url: &str| {
+///     let req = Request::new(Method::Get, Url::parse(url).unwrap());
+///     task::block_on(router.clone().respond(req))
+/// };
+///
+/// get("http://localhost/home");
+/// get("http://localhost/home");
+/// let mut res = get("http://localhost/about");
+/// assert_eq!(task::block_on(res.body_string()).unwrap(), "3 hits");
+/// assert_eq!(hits.load(Ordering::SeqCst), 3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct State<S>(pub S);
+
+impl<S: Clone + 'static> State<S> {
+    /// Get the state of type `S` for a request, if it was registered on the router.
+    pub fn of(req: &http_types::Request) -> Option<Self> {
+        req.local()
+            .get::<AppState>()
+            .and_then(|state| state.0.get::<S>())
+            .map(State)
+    }
+
+    /// Unwrap the state.
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S> Deref for State<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> Props for State<S> {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let state = Self::of(&req).ok_or_else(|| {
+                Error::internal(format!(
+                    "no state of type {} registered",
+                    std::any::type_name::<S>()
+                ))
+                .with_code(WindmillErrorCode::Internal)
+            })?;
+            Ok((req, params, state))
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{config::Config, router::Router};
+use http_types::{Method, Url};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// A client that sends requests to a router in memory, for tests.
+///
+/// Requests go through routing, middlewares, props and the endpoint exactly as they would when
+/// served, but without a server or a socket, so tests need no free port.  Requests carry the
+/// client's [`Config`](struct.Config.html) if it has one, for limits such as the maximum body
+/// size.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Deserialize, Serialize)]
+/// struct Greeting {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn greet(greeting: Body<Greeting>) -> Result<Response, Error> {
+///     let name = greeting.into_inner().name;
+///     let mut res = Response::new(StatusCode::Created);
+///     res.set_body(serde_json::json!({ "greeting": format!("hello {}", name) }).to_string());
+///     Ok(res)
+/// }
+///
+/// #[endpoint]
+/// async fn whoami(head: RequestHead) -> Result<Response, Error> {
+///     let user = head.header("authorization").map(|values| values[0].to_string());
+///     Ok(Response::from(user.unwrap_or_default()))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"greet"), ___greet);
+/// router.add(Method::Get, route!(/"example"/id), ___whoami);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/example/5").header("authorization", "x").send().await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     assert_eq!(res.body_string().await.unwrap(), "x");
+///
+///     let mut res = client.post("/greet").json(&Greeting { name: "ada".into() }).send().await;
+///     assert_eq!(res.status(), StatusCode::Created);
+///     let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///     assert_eq!(body["greeting"], "hello ada");
+///
+///     // Headers can repeat.
+///     let req = client.get("/example/5").header("authorization", "x").header("authorization", "y");
+///     assert_eq!(req.send().await.body_string().await.unwrap(), "x");
+///
+///     assert_eq!(client.delete("/greet").send().await.status(), StatusCode::MethodNotAllowed);
+/// });
+///
+/// // Limits come from the config.
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"greet"), ___greet);
+/// let client = TestClient::new(router).with_config(Config::new("127.0.0.1:0").with_max_body_size(8));
+/// let res = task::block_on(client.post("/greet").json(&Greeting { name: "ada".into() }).send());
+/// assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+/// ```
+#[derive(Clone)]
+pub struct TestClient {
+    router: Arc<Router>,
+    config: Option<Arc<Config>>,
+}
+
+impl TestClient {
+    /// Send requests to `router`.
+    pub fn new(router: impl Into<Arc<Router>>) -> Self {
+        Self {
+            router: router.into(),
+            config: None,
+        }
+    }
+
+    /// Send requests as a server with `config` would.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Some(Arc::new(config));
+        self
+    }
+
+    /// Start a request with `method` for `path`, which may have a query.
+    ///
+    /// ## Panics
+    /// If `path` does not make a valid url.
+    pub fn request(&self, method: Method, path: &str) -> TestRequest {
+        let url = Url::parse(&format!("http://localhost{}", path))
+            .unwrap_or_else(|e| panic!("invalid path `{}`: {}", path, e));
+        let mut req = http_types::Request::new(method, url);
+        if let Some(config) = self.config.as_ref() {
+            req.local_mut().insert(config.clone());
+        }
+        TestRequest {
+            router: self.router.clone(),
+            req,
+        }
+    }
+
+    /// Start a `GET` request for `path`.
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request(Method::Get, path)
+    }
+
+    /// Start a `HEAD` request for `path`.
+    pub fn head(&self, path: &str) -> TestRequest {
+        self.request(Method::Head, path)
+    }
+
+    /// Start a `POST` request for `path`.
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request(Method::Post, path)
+    }
+
+    /// Start a `PUT` request for `path`.
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request(Method::Put, path)
+    }
+
+    /// Start a `PATCH` request for `path`.
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request(Method::Patch, path)
+    }
+
+    /// Start a `DELETE` request for `path`.
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request(Method::Delete, path)
+    }
+}
+
+/// A request being built by a [`TestClient`](struct.TestClient.html).
+pub struct TestRequest {
+    router: Arc<Router>,
+    req: http_types::Request,
+}
+
+impl TestRequest {
+    /// Add a header, after any values it already has.
+    ///
+    /// ## Panics
+    /// If `name` or `value` is not valid in a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.req
+            .append_header(name, value)
+            .unwrap_or_else(|e| panic!("invalid header `{}: {}`: {}", name, value, e));
+        self
+    }
+
+    /// Send `body`, with a `Content-Length` if its length is known.
+    pub fn body(mut self, body: impl Into<http_types::Body>) -> Self {
+        let body = body.into();
+        if let Some(len) = body.len() {
+            self = self.header("content-length", &len.to_string());
+        }
+        self.req.set_body(body);
+        self
+    }
+
+    /// Send `value` as a JSON body.
+    ///
+    /// ## Panics
+    /// If `value` cannot be serialized.
+    pub fn json<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("test body should serialize to JSON");
+        let mut this = self.body(body);
+        let _ = this.req.insert_header("content-type", "application/json");
+        this
+    }
+
+    /// The request as built so far, to adjust it in ways the builder does not cover.
+    pub fn request_mut(&mut self) -> &mut http_types::Request {
+        &mut self.req
+    }
+
+    /// Dispatch the request to the router and return its response.
+    pub async fn send(self) -> http_types::Response {
+        self.router.respond(self.req).await
+    }
+}
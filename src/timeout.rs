@@ -0,0 +1,551 @@
+use crate::{
+    error::{Error, WindmillErrorCode},
+    matched::RequestLabel,
+    params::Params,
+    props::{Props, PropsFuture},
+    route::ResponseFuture,
+};
+use async_std::{
+    future,
+    io::{self, BufReader},
+    task,
+};
+use http_types::StatusCode;
+use serde::Serialize;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Time budgets for producing a response.
+///
+/// Each budget is independent and unset by default:
+///
+/// - `headers`: from the start of the request until the endpoint returns its response.
+/// - `first_byte`: from the start of the request until the first byte of the body.
+/// - `idle`: the longest gap between two chunks of the body.
+/// - `total`: from the start of the request until the body is finished.
+///
+/// Streaming responses, those whose body has no known length, are exempt from the `total` budget
+/// unless [`with_total_for_streams`](#method.with_total_for_streams) is set, so a long but steady
+/// stream is only ever cut off by the `idle` budget.  A budget exceeded before the headers are
+/// sent answers the request with `503 Service Unavailable`; one exceeded while the body is sent
+/// aborts the connection.  Either way the violated budget is logged and counted in
+/// [`Router::timeout_metrics`](struct.Router.html#method.timeout_metrics).
+///
+/// Set for every route with [`Config::with_timeouts`](struct.Config.html#method.with_timeouts), or
+/// for one route with [`Route::timeouts`](struct.Route.html#method.timeouts) which takes
+/// precedence.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{io::BufReader, net::{TcpListener, TcpStream}, prelude::*, task};
+/// # use http_types::{Method, Request, Response, StatusCode, Url};
+/// # use std::{sync::Arc, time::Duration};
+/// // A body written in `chunks`, each after its delay.
+/// async fn stream(chunks: Vec<(u64, &'static str)>) -> http_types::Body {
+///     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+///     let mut writer = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+///     let (reader, _) = listener.accept().await.unwrap();
+///     task::spawn(async move {
+///         for (delay, chunk) in chunks {
+///             task::sleep(Duration::from_millis(delay)).await;
+///             writer.write_all(chunk.as_bytes()).await.unwrap();
+///         }
+///     });
+///     http_types::Body::from_reader(BufReader::new(reader), None)
+/// }
+///
+/// #[endpoint]
+/// async fn slow_handler() -> Result<Response, Error> {
+///     task::sleep(Duration::from_millis(300)).await;
+///     Ok(Response::from("late"))
+/// }
+///
+/// #[endpoint]
+/// async fn slow_to_start() -> Result<Response, Error> {
+///     let mut res = Response::new(StatusCode::Ok);
+///     res.set_body(stream(vec![(300, "hello")]).await);
+///     Ok(res)
+/// }
+///
+/// #[endpoint]
+/// async fn stalling() -> Result<Response, Error> {
+///     let mut res = Response::new(StatusCode::Ok);
+///     res.set_body(stream(vec![(0, "a"), (20, "b"), (300, "c")]).await);
+///     Ok(res)
+/// }
+///
+/// #[endpoint]
+/// async fn steady() -> Result<Response, Error> {
+///     let mut res = Response::new(StatusCode::Ok);
+///     res.set_body(stream(vec![(30, "x"); 10]).await);
+///     Ok(res)
+/// }
+///
+/// let timeouts = Timeouts::new()
+///     .with_headers(Duration::from_millis(100))
+///     .with_first_byte(Duration::from_millis(100))
+///     .with_idle(Duration::from_millis(100))
+///     .with_total(Duration::from_millis(150));
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"slow_handler").timeouts(timeouts), ___slow_handler);
+/// router.add(Method::Get, route!(/"slow_to_start").timeouts(timeouts), ___slow_to_start);
+/// router.add(Method::Get, route!(/"stalling").timeouts(timeouts), ___stalling);
+/// router.add(Method::Get, route!(/"steady").timeouts(timeouts), ___steady);
+/// let router = Arc::new(router);
+///
+/// let get = |path: &str| {
+///     let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+///     router.clone().respond(Request::new(Method::Get, url))
+/// };
+///
+/// task::block_on(async {
+///     let res = get("/slow_handler").await;
+///     assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+///     assert!(res.body_string().await.unwrap().contains("headers timeout"));
+///
+///     let res = get("/slow_to_start").await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     let e = res.body_string().await.unwrap_err();
+///     assert!(e.to_string().contains("first byte timeout"), "{}", e);
+///
+///     let res = get("/stalling").await;
+///     let e = res.body_string().await.unwrap_err();
+///     assert!(e.to_string().contains("idle timeout"), "{}", e);
+///
+///     // 300ms of body, well past the total budget, but never idle for long.
+///     let res = get("/steady").await;
+///     assert_eq!(res.body_string().await.unwrap(), "x".repeat(10));
+/// });
+///
+/// assert_eq!(
+///     router.timeout_metrics(),
+///     TimeoutMetrics { headers: 1, first_byte: 1, idle: 1, total: 0 }
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    headers: Option<Duration>,
+    first_byte: Option<Duration>,
+    idle: Option<Duration>,
+    total: Option<Duration>,
+    total_for_streams: bool,
+}
+
+impl Timeouts {
+    /// Create a new instance of `Timeouts` with every budget unset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how long the endpoint may take to return its response.
+    pub fn with_headers(mut self, budget: Duration) -> Self {
+        self.headers = Some(budget);
+        self
+    }
+
+    /// Get the headers budget.
+    pub fn headers(&self) -> Option<Duration> {
+        self.headers
+    }
+
+    /// Set how long after the start of the request the first byte of the body may take.
+    pub fn with_first_byte(mut self, budget: Duration) -> Self {
+        self.first_byte = Some(budget);
+        self
+    }
+
+    /// Get the first byte budget.
+    pub fn first_byte(&self) -> Option<Duration> {
+        self.first_byte
+    }
+
+    /// Set the longest gap allowed between two chunks of the body.
+    pub fn with_idle(mut self, budget: Duration) -> Self {
+        self.idle = Some(budget);
+        self
+    }
+
+    /// Get the idle budget.
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle
+    }
+
+    /// Set how long the whole response may take, from the start of the request until the end of
+    /// the body.
+    pub fn with_total(mut self, budget: Duration) -> Self {
+        self.total = Some(budget);
+        self
+    }
+
+    /// Get the total budget.
+    pub fn total(&self) -> Option<Duration> {
+        self.total
+    }
+
+    /// Hold streaming responses to the total budget too.
+    pub fn with_total_for_streams(mut self) -> Self {
+        self.total_for_streams = true;
+        self
+    }
+
+    /// Whether streaming responses are held to the total budget.
+    pub fn total_for_streams(&self) -> bool {
+        self.total_for_streams
+    }
+
+    fn is_unset(&self) -> bool {
+        self.headers.is_none()
+            && self.first_byte.is_none()
+            && self.idle.is_none()
+            && self.total.is_none()
+    }
+}
+
+/// The budgets of [`Timeouts`](struct.Timeouts.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Budget {
+    Headers,
+    FirstByte,
+    Idle,
+    Total,
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Budget::Headers => "headers",
+            Budget::FirstByte => "first byte",
+            Budget::Idle => "idle",
+            Budget::Total => "total",
+        })
+    }
+}
+
+/// How many responses exceeded each budget.
+///
+/// Produced by [`Router::timeout_metrics`](struct.Router.html#method.timeout_metrics).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TimeoutMetrics {
+    pub headers: u64,
+    pub first_byte: u64,
+    pub idle: u64,
+    pub total: u64,
+}
+
+/// Counts and logs exceeded budgets, shared by every request of a router.
+#[derive(Default)]
+pub(crate) struct TimeoutCounters {
+    headers: AtomicU64,
+    first_byte: AtomicU64,
+    idle: AtomicU64,
+    total: AtomicU64,
+}
+
+impl TimeoutCounters {
+    fn exceeded(&self, budget: Budget, limit: Duration, request: &RequestLabel) -> String {
+        let counter = match budget {
+            Budget::Headers => &self.headers,
+            Budget::FirstByte => &self.first_byte,
+            Budget::Idle => &self.idle,
+            Budget::Total => &self.total,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let msg = format!("{} timeout of {:?} exceeded", budget, limit);
+        eprintln!("{}: {}", request, msg);
+        msg
+    }
+
+    pub(crate) fn metrics(&self) -> TimeoutMetrics {
+        TimeoutMetrics {
+            headers: self.headers.load(Ordering::Relaxed),
+            first_byte: self.first_byte.load(Ordering::Relaxed),
+            idle: self.idle.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Hold the response produced by `res` to `timeouts`, answering with `fail` if it misses the
+/// headers budget.  `request`, such as `GET /users/{id}`, identifies it in the log.
+pub(crate) fn enforce<F>(
+    timeouts: Timeouts,
+    counters: Arc<TimeoutCounters>,
+    request: RequestLabel,
+    res: ResponseFuture,
+    fail: F,
+) -> ResponseFuture
+where
+    F: FnOnce(Error) -> http_types::Response + Send + Sync + 'static,
+{
+    if timeouts.is_unset() {
+        return res;
+    }
+
+    let started = Instant::now();
+    Box::pin(async move {
+        let headers = earliest(
+            timeouts.headers.map(|limit| (Budget::Headers, limit)),
+            timeouts.total.map(|limit| (Budget::Total, limit)),
+        );
+        let mut res = match headers {
+            Some((budget, limit)) => match future::timeout(limit, res).await {
+                Ok(res) => res,
+                Err(_) => {
+                    let msg = counters.exceeded(budget, limit, &request);
+                    return fail(
+                        Error::new(StatusCode::ServiceUnavailable, msg)
+                            .with_code(WindmillErrorCode::Timeout),
+                    );
+                }
+            },
+            None => res.await,
+        };
+
+        let streaming = res.len().is_none();
+        let total = timeouts
+            .total
+            .filter(|_| !streaming || timeouts.total_for_streams);
+        if timeouts.first_byte.is_some() || timeouts.idle.is_some() || total.is_some() {
+            let body = res.take_body();
+            let len = body.len();
+            let body = http_types::Body::from_reader(
+                BufReader::new(TimedBody {
+                    body,
+                    started,
+                    last: None,
+                    first_byte: timeouts.first_byte,
+                    idle: timeouts.idle,
+                    total,
+                    timer: None,
+                    counters,
+                    request,
+                }),
+                len,
+            );
+            res.set_body(body);
+        }
+        res
+    })
+}
+
+/// When the endpoint of a request must have returned its response by, under the handler timeout
+/// of its route.
+///
+/// Set with [`Config::with_handler_timeout`](struct.Config.html#method.with_handler_timeout) or
+/// [`Route::handler_timeout`](struct.Route.html#method.handler_timeout), the timeout runs from
+/// when the request is routed, and covers the props of the endpoint, reading the body included.
+/// An endpoint still running when it is up is dropped and the request is answered with
+/// `504 Gateway Timeout`.  Endpoints can take the deadline as an argument, or read it with
+/// [`of`](#method.of), to give up on their own calls in time.  Requests for routes without a
+/// handler timeout have none.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// # use std::{sync::atomic::{AtomicBool, Ordering}, time::Duration};
+/// static FINISHED: AtomicBool = AtomicBool::new(false);
+///
+/// #[endpoint]
+/// async fn stuck() -> Result<Response, Error> {
+///     task::sleep(Duration::from_millis(300)).await;
+///     FINISHED.store(true, Ordering::SeqCst);
+///     Ok(Response::from("late"))
+/// }
+///
+/// #[endpoint]
+/// async fn budget(deadline: Deadline) -> Result<Response, Error> {
+///     let remaining = deadline.remaining().unwrap();
+///     Ok(Response::from(format!("{}", remaining <= Duration::from_millis(100))))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"stuck"), ___stuck);
+/// router.add(Method::Get, route!(/"budget"), ___budget);
+/// router.add(Method::Get, route!(/"slow"/"but"/"allowed").handler_timeout(Duration::from_secs(1)), ___stuck);
+/// let config = Config::new("127.0.0.1:0").with_handler_timeout(Duration::from_millis(100));
+/// let client = TestClient::new(router).with_config(config);
+///
+/// task::block_on(async {
+///     let mut res = client.get("/stuck").send().await;
+///     assert_eq!(res.status(), StatusCode::GatewayTimeout);
+///     let body: serde_json::Value = serde_json::from_str(&res.body_string().await.unwrap()).unwrap();
+///     assert_eq!(body["code"], "timeout");
+///     // The endpoint was dropped rather than left running.
+///     task::sleep(Duration::from_millis(400)).await;
+///     assert!(!FINISHED.load(Ordering::SeqCst));
+///
+///     let mut res = client.get("/budget").send().await;
+///     assert_eq!(res.body_string().await.unwrap(), "true");
+///
+///     let mut res = client.get("/slow/but/allowed").send().await;
+///     assert_eq!(res.status(), StatusCode::Ok);
+///     assert_eq!(res.body_string().await.unwrap(), "late");
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    pub(crate) fn at(instant: Instant) -> Self {
+        Self(Some(instant))
+    }
+
+    /// Get the deadline of a request, which is unset if its route has no handler timeout.
+    pub fn of(req: &http_types::Request) -> Self {
+        req.local().get::<Self>().copied().unwrap_or_default()
+    }
+
+    /// When the endpoint must have returned its response by.
+    pub fn instant(&self) -> Option<Instant> {
+        self.0
+    }
+
+    /// How long the endpoint has left, zero once the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|instant| instant.saturating_duration_since(Instant::now()))
+    }
+}
+
+impl Props for Deadline {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let deadline = Self::of(&req);
+            Ok((req, params, deadline))
+        })
+    }
+}
+
+/// Answer with `fail` and drop `res` if it has not produced the response within `limit`.
+/// `request`, such as `GET /users/{id}`, identifies it in the log.
+pub(crate) fn enforce_handler_timeout<F>(
+    limit: Duration,
+    request: RequestLabel,
+    res: ResponseFuture,
+    fail: F,
+) -> ResponseFuture
+where
+    F: FnOnce(Error) -> http_types::Response + Send + Sync + 'static,
+{
+    Box::pin(async move {
+        match future::timeout(limit, res).await {
+            Ok(res) => res,
+            Err(_) => {
+                let msg = format!("handler timeout of {:?} exceeded", limit);
+                eprintln!("{}: {}", request, msg);
+                fail(
+                    Error::new(StatusCode::GatewayTimeout, msg)
+                        .with_code(WindmillErrorCode::Timeout),
+                )
+            }
+        }
+    })
+}
+
+fn earliest(
+    a: Option<(Budget, Duration)>,
+    b: Option<(Budget, Duration)>,
+) -> Option<(Budget, Duration)> {
+    match (a, b) {
+        (Some(a), Some(b)) if b.1 < a.1 => Some(b),
+        (a, b) => a.or(b),
+    }
+}
+
+type Timer = Pin<Box<dyn std::future::Future<Output = ()> + Send + Sync>>;
+
+/// A response body that fails once it misses a budget, aborting the connection it is sent on.
+struct TimedBody {
+    body: http_types::Body,
+    started: Instant,
+    last: Option<Instant>,
+    first_byte: Option<Duration>,
+    idle: Option<Duration>,
+    total: Option<Duration>,
+    timer: Option<(Instant, Timer)>,
+    counters: Arc<TimeoutCounters>,
+    request: RequestLabel,
+}
+
+impl TimedBody {
+    /// The budget that runs out first, with its limit and when it runs out.
+    fn deadline(&self) -> Option<(Budget, Duration, Instant)> {
+        let gap = match self.last {
+            None => self
+                .first_byte
+                .map(|limit| (Budget::FirstByte, limit, self.started + limit)),
+            Some(last) => self.idle.map(|limit| (Budget::Idle, limit, last + limit)),
+        };
+        let total = self
+            .total
+            .map(|limit| (Budget::Total, limit, self.started + limit));
+        match (gap, total) {
+            (Some(gap), Some(total)) if total.2 < gap.2 => Some(total),
+            (gap, total) => gap.or(total),
+        }
+    }
+
+    fn exceeded(&self, budget: Budget, limit: Duration) -> io::Error {
+        let msg = self.counters.exceeded(budget, limit, &self.request);
+        io::Error::new(io::ErrorKind::TimedOut, msg)
+    }
+}
+
+impl io::Read for TimedBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let deadline = this.deadline();
+        if let Some((budget, limit, at)) = deadline {
+            if at <= Instant::now() {
+                return Poll::Ready(Err(this.exceeded(budget, limit)));
+            }
+        }
+
+        match Pin::new(&mut this.body).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) if n > 0 => {
+                this.last = Some(Instant::now());
+                return Poll::Ready(Ok(n));
+            }
+            Poll::Ready(ready) => return Poll::Ready(ready),
+            Poll::Pending => {}
+        }
+
+        let (budget, limit, at) = match deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+        if this.timer.as_ref().map(|(timer_at, _)| *timer_at) != Some(at) {
+            let sleep = task::sleep(at.saturating_duration_since(Instant::now()));
+            this.timer = Some((at, Box::pin(sleep)));
+        }
+        match this
+            .timer
+            .as_mut()
+            .map(|(_, timer)| timer.as_mut().poll(cx))
+        {
+            Some(Poll::Ready(())) => Poll::Ready(Err(this.exceeded(budget, limit))),
+            _ => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use futures_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use std::{io, path::PathBuf, sync::Arc};
+
+/// Where the certificate chain and private key of a TLS listener come from.
+pub(crate) enum TlsSource {
+    Files { cert_chain: PathBuf, key: PathBuf },
+    Pem { cert_chain: Vec<u8>, key: Vec<u8> },
+}
+
+impl TlsSource {
+    /// Load the certificate chain and key into an acceptor for incoming connections.
+    pub(crate) fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let (cert_chain, key) = match self {
+            TlsSource::Files { cert_chain, key } => {
+                (std::fs::read(cert_chain)?, std::fs::read(key)?)
+            }
+            TlsSource::Pem { cert_chain, key } => (cert_chain.clone(), key.clone()),
+        };
+
+        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut cert_chain.as_slice())?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        if certs.is_empty() {
+            return Err(invalid("no certificates found in the certificate chain"));
+        }
+
+        let key = rustls_pemfile::read_all(&mut key.as_slice())?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| invalid("no private key found"))?;
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(&e.to_string()))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
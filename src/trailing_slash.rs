@@ -0,0 +1,25 @@
+/// How the router treats a path ending in a slash, such as `/users/7/`, set with
+/// [`Router::trailing_slash`](struct.Router.html#method.trailing_slash).
+///
+/// The slash is an empty last segment, so in strict mode `/users/7/` does not match
+/// `/users/{id}`.  The root path `/` and paths that are only slashes are always matched as they
+/// are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Match the path as it was sent.
+    #[default]
+    Strict,
+    /// Match the path as if it did not end in a slash, so `/users/7/` and `/users/7` are the same.
+    Ignore,
+    /// Answer `308 Permanent Redirect` to the path without its slash, when only that path
+    /// matches a route.
+    Redirect,
+}
+
+/// `path` without its trailing slash, unless it has none or is only slashes.
+pub(crate) fn trim(path: &str) -> Option<&str> {
+    match path.strip_suffix('/') {
+        Some(trimmed) if !trimmed.trim_matches('/').is_empty() => Some(trimmed),
+        _ => None,
+    }
+}
@@ -0,0 +1,134 @@
+use crate::route::{RawSegment, Route};
+use std::collections::HashMap;
+
+/// The routes of one method, indexed by a trie of their segments.
+///
+/// Looking up a path walks the trie once over its segments instead of comparing it with every
+/// route.  At each segment a static child is tried before the dynamic child, and both before
+/// the routes matching the rest of the path from there, so the most specific route wins.  Among
+/// the routes ending at the same node, those with more typed or constrained segments are tried
+/// first, and routes leaving out their optional segments there are tried after the others.
+///
+/// Without regard to case, the static child spelled like the segment is tried before those
+/// spelled in another case.
+#[derive(Default)]
+pub(crate) struct RouteTable {
+    routes: Vec<Route>,
+    root: Node,
+}
+
+#[derive(Default)]
+struct Node {
+    statics: HashMap<&'static str, Node>,
+    dynamic: Option<Box<Node>>,
+    /// The routes ending at this node, in the order they were added.
+    routes: Vec<usize>,
+    /// The routes whose optional segments start at this node or below, tried after those
+    /// ending here.
+    optional: Vec<usize>,
+    /// The routes matching the rest of the path after this node, however many segments.
+    rest: Vec<usize>,
+}
+
+impl RouteTable {
+    pub(crate) fn push(&mut self, route: Route) {
+        let Self { routes: all, root } = self;
+        let mut node = root;
+        for position in 0..route.len() {
+            if position >= route.min_len() {
+                insert(&mut node.optional, all, &route, all.len());
+            }
+            let segment = route
+                .static_segments
+                .iter()
+                .find(|segment| segment.position == position);
+            node = match segment {
+                Some(segment) => node.statics.entry(segment.value).or_default(),
+                None => node.dynamic.get_or_insert_with(Default::default),
+            };
+        }
+        let routes = match route.rest {
+            Some(_) => &mut node.rest,
+            None => &mut node.routes,
+        };
+        insert(routes, all, &route, all.len());
+        all.push(route);
+    }
+
+    /// The most specific route matching `segments` that `accept` maps to a value, comparing
+    /// static segments without regard to ASCII case if `case_insensitive` is set.
+    pub(crate) fn find<'a, T>(
+        &'a self,
+        segments: &[RawSegment],
+        case_insensitive: bool,
+        mut accept: impl FnMut(&'a Route) -> Option<T>,
+    ) -> Option<T> {
+        self.root.find(segments, case_insensitive, &mut |index| {
+            accept(&self.routes[index])
+        })
+    }
+
+    /// The routes in the order they were added.
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Route> {
+        self.routes.iter()
+    }
+}
+
+/// Insert `index`, the index of `route`, into `routes`, after the routes as specific as it so
+/// those added first are tried first.
+fn insert(routes: &mut Vec<usize>, all: &[Route], route: &Route, index: usize) {
+    let specificity = route.specificity();
+    let at = routes
+        .iter()
+        .position(|index| all[*index].specificity() < specificity)
+        .unwrap_or(routes.len());
+    routes.insert(at, index);
+}
+
+impl IntoIterator for RouteTable {
+    type Item = Route;
+    type IntoIter = std::vec::IntoIter<Route>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.routes.into_iter()
+    }
+}
+
+impl Node {
+    fn find<T>(
+        &self,
+        segments: &[RawSegment],
+        case_insensitive: bool,
+        accept: &mut impl FnMut(usize) -> Option<T>,
+    ) -> Option<T> {
+        match segments.split_first() {
+            None => self
+                .routes
+                .iter()
+                .chain(self.optional.iter())
+                .chain(self.rest.iter())
+                .find_map(|index| accept(*index)),
+            Some((segment, remaining)) => self
+                .statics
+                .get(segment.value.as_ref())
+                .and_then(|child| child.find(remaining, case_insensitive, accept))
+                .or_else(|| {
+                    self.statics
+                        .iter()
+                        .filter(|(value, _)| {
+                            let segment: &str = &segment.value;
+                            case_insensitive
+                                && ***value != *segment
+                                && value.eq_ignore_ascii_case(segment)
+                        })
+                        .find_map(|(_, child)| child.find(remaining, case_insensitive, accept))
+                })
+                .or_else(|| {
+                    self.dynamic
+                        .as_ref()
+                        .and_then(|child| child.find(remaining, case_insensitive, accept))
+                })
+                .or_else(|| self.rest.iter().find_map(|index| accept(*index))),
+        }
+    }
+}
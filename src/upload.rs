@@ -0,0 +1,142 @@
+use async_std::io::{self, BufReader};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// How far the body of a request has been read, reported to the callback set with
+/// [`Route::on_upload_progress`](struct.Route.html#method.on_upload_progress).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UploadProgress {
+    /// The number of body bytes read so far.
+    pub received: u64,
+    /// The `Content-Length` of the request, if it declared one.
+    pub content_length: Option<u64>,
+    /// The time since the endpoint started handling the request.
+    pub elapsed: Duration,
+    /// How the upload ended, set only on the last report.
+    pub end: Option<UploadEnd>,
+}
+
+/// How an upload ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadEnd {
+    /// The whole body was read.
+    Completed,
+    /// The client went away or the body could not be read before it was complete.
+    Aborted,
+    /// The request was answered without reading the whole body.
+    Abandoned,
+}
+
+type ProgressFn = dyn Fn(UploadProgress) + Send + Sync;
+
+/// The progress callback of a route and how often it is called.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct UploadProgressHook {
+    interval: u64,
+    callback: Arc<ProgressFn>,
+}
+
+impl UploadProgressHook {
+    pub(crate) fn new<F>(interval: u64, callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        Self {
+            interval: interval.max(1),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Replace the body of `req` with one that reports its progress.
+    pub(crate) fn wrap(&self, req: &mut http_types::Request) {
+        let content_length = req
+            .header(&http_types::headers::CONTENT_LENGTH)
+            .and_then(|values| values.last())
+            .and_then(|value| value.as_str().trim().parse::<u64>().ok());
+        let body = req.take_body();
+        let len = body.len();
+        let reader = ProgressReader {
+            body,
+            hook: self.clone(),
+            started: Instant::now(),
+            received: 0,
+            content_length,
+            next_tick: self.interval,
+            ended: content_length == Some(0),
+        };
+        req.set_body(http_types::Body::from_reader(BufReader::new(reader), len));
+    }
+}
+
+struct ProgressReader {
+    body: http_types::Body,
+    hook: UploadProgressHook,
+    started: Instant,
+    received: u64,
+    content_length: Option<u64>,
+    next_tick: u64,
+    ended: bool,
+}
+
+impl ProgressReader {
+    fn report(&self, end: Option<UploadEnd>) {
+        (self.hook.callback)(UploadProgress {
+            received: self.received,
+            content_length: self.content_length,
+            elapsed: self.started.elapsed(),
+            end,
+        });
+    }
+
+    fn end(&mut self, end: UploadEnd) {
+        if !self.ended {
+            self.ended = true;
+            self.report(Some(end));
+        }
+    }
+
+    fn complete(&self) -> bool {
+        matches!(self.content_length, Some(len) if self.received >= len)
+    }
+}
+
+impl io::Read for ProgressReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.body).poll_read(cx, buf);
+        match &poll {
+            Poll::Ready(Ok(0)) if !buf.is_empty() => match this.content_length {
+                Some(_) if !this.complete() => this.end(UploadEnd::Aborted),
+                _ => this.end(UploadEnd::Completed),
+            },
+            Poll::Ready(Ok(n)) => {
+                this.received += *n as u64;
+                if this.received >= this.next_tick {
+                    this.next_tick = (this.received / this.hook.interval + 1) * this.hook.interval;
+                    this.report(None);
+                }
+                if this.complete() {
+                    this.end(UploadEnd::Completed);
+                }
+            }
+            Poll::Ready(Err(_)) => this.end(UploadEnd::Aborted),
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl Drop for ProgressReader {
+    fn drop(&mut self) {
+        self.end(UploadEnd::Abandoned);
+    }
+}
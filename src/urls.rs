@@ -0,0 +1,268 @@
+use crate::{
+    error::{Error, RouterError, WindmillErrorCode},
+    params::Params,
+    props::{Props, PropsFuture},
+    route::{Route, SegmentType},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// The paths of the named routes of a router, for links and `Location` headers that follow the
+/// routes as they change.
+///
+/// Routes are named with [`Route::name`](struct.Route.html#method.name).  The router attaches its
+/// `Urls` to every request, and endpoints can take it as an argument.  Outside of a request use
+/// [`Router::url_for`](struct.Router.html#method.url_for).
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::task;
+/// # use http_types::{Method, Response, StatusCode};
+/// #[endpoint]
+/// async fn create(urls: Urls) -> Result<Redirect, Error> {
+///     Redirect::see_other(urls.url_for("user", &[("id", "7")])?)
+/// }
+///
+/// #[endpoint]
+/// async fn user() -> Result<Response, Error> {
+///     Ok(Response::from("user"))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"users"), ___create);
+/// router.add(Method::Get, route!(/"users"/id).name("user"), ___user);
+/// let client = TestClient::new(router);
+///
+/// task::block_on(async {
+///     let res = client.post("/users").send().await;
+///     assert_eq!(res.status(), StatusCode::SeeOther);
+///     assert_eq!(res.header(&"location".parse().unwrap()).unwrap()[0].as_str(), "/users/7");
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Urls(Arc<HashMap<&'static str, NamedPath>>);
+
+#[derive(Clone)]
+struct NamedPath {
+    pattern: String,
+    segments: Vec<Segment>,
+    rest: Option<&'static str>,
+    optional: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    Static(&'static str),
+    Dynamic(&'static str, Option<SegmentType>),
+}
+
+impl NamedPath {
+    fn new(route: &Route) -> Self {
+        let segments = (0..route.len())
+            .filter_map(|position| {
+                let value = route
+                    .static_segments
+                    .iter()
+                    .find(|segment| segment.position == position)
+                    .map(|segment| Segment::Static(segment.value));
+                value.or_else(|| {
+                    route
+                        .dynamic_segments
+                        .iter()
+                        .find(|segment| segment.position == position)
+                        .map(|segment| Segment::Dynamic(segment.name, segment.ty))
+                })
+            })
+            .collect();
+        Self {
+            pattern: route.to_string(),
+            segments,
+            rest: route.rest,
+            optional: route.optional,
+        }
+    }
+
+    fn has_param(&self, name: &str) -> bool {
+        self.rest == Some(name)
+            || self
+                .segments
+                .iter()
+                .any(|segment| matches!(segment, Segment::Dynamic(param, _) if *param == name))
+    }
+}
+
+impl Urls {
+    /// The path of the route named `name` with its dynamic segments replaced by `params`,
+    /// percent-encoded.  A `{name*}` segment matching the rest of the path takes a value of any
+    /// number of segments separated by `/`.  The path ends before the first optional segment
+    /// without a param, and the params of the optional segments after it must be left out too.
+    ///
+    /// A name no route has, a missing param, a param the route does not have or one given twice,
+    /// and a value that does not parse as the type of its segment are bugs in the server, and are
+    /// errors with `500 Internal Server Error`.
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response};
+    /// # #[endpoint] async fn ok() -> Result<Response, Error> { Ok(Response::from("ok")) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/user_id: u64/"posts"/post_id).name("post"), ___ok);
+    /// router.add(Method::Get, route!(/"caf\u{e9}"/"menu").name("menu"), ___ok);
+    /// router.add(Method::Get, Route::from("/files/{path*}").name("file"), ___ok);
+    /// router.add(Method::Get, route!(/"reports"/year/?month/?day).name("report"), ___ok);
+    ///
+    /// let post = router.url_for("post", &[("post_id", "hello world"), ("user_id", "7")]).unwrap();
+    /// assert_eq!(post, "/users/7/posts/hello%20world");
+    /// // Reserved characters stay in their segment.
+    /// let post = router.url_for("post", &[("user_id", "7"), ("post_id", "a/b?c#d%e")]).unwrap();
+    /// assert_eq!(post, "/users/7/posts/a%2Fb%3Fc%23d%25e");
+    /// assert_eq!(router.url_for("menu", &[]).unwrap(), "/caf%C3%A9/menu");
+    /// assert_eq!(router.url_for("file", &[("path", "css/site 1.css")]).unwrap(), "/files/css/site%201.css");
+    /// assert_eq!(router.url_for("file", &[("path", "")]).unwrap(), "/files");
+    /// assert_eq!(router.url_for("report", &[("year", "2024")]).unwrap(), "/reports/2024");
+    /// assert_eq!(router.url_for("report", &[("year", "2024"), ("month", "03")]).unwrap(), "/reports/2024/03");
+    ///
+    /// let err = |params: &[(&str, &str)]| router.url_for("post", params).unwrap_err().to_string();
+    /// assert!(err(&[("user_id", "7")]).contains("missing param `post_id`"));
+    /// assert!(err(&[("user_id", "7"), ("post_id", "1"), ("page", "2")]).contains("unexpected param `page`"));
+    /// assert!(err(&[("user_id", "7"), ("post_id", "1"), ("user_id", "8")]).contains("unexpected param `user_id`"));
+    /// assert!(err(&[("user_id", "me"), ("post_id", "1")]).contains("invalid param `user_id`"));
+    /// assert!(router.url_for("report", &[("year", "2024"), ("day", "01")]).unwrap_err().to_string().contains("needs the optional param `month`"));
+    /// assert!(router.url_for("missing", &[]).is_err());
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, Error> {
+        let path = self
+            .0
+            .get(name)
+            .ok_or_else(|| url_error(format!("no route is named `{}`", name)))?;
+
+        let unexpected = params.iter().enumerate().find(|(i, (param, _))| {
+            !path.has_param(param) || params[..*i].iter().any(|(other, _)| other == param)
+        });
+        if let Some((_, (param, _))) = unexpected {
+            return Err(url_error(format!(
+                "unexpected param `{}` for route `{}` {}",
+                param, name, path.pattern
+            )));
+        }
+        let value = |param: &str| {
+            params
+                .iter()
+                .find(|(other, _)| *other == param)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| {
+                    url_error(format!(
+                        "missing param `{}` for route `{}` {}",
+                        param, name, path.pattern
+                    ))
+                })
+        };
+
+        let given = |param: &str| params.iter().any(|(other, _)| *other == param);
+        // The path ends before the first optional segment without a param.
+        let len = (path.segments.len() - path.optional..path.segments.len())
+            .find(|position| {
+                matches!(path.segments[*position], Segment::Dynamic(param, _) if !given(param))
+            })
+            .unwrap_or_else(|| path.segments.len());
+        let after = path.segments[len..]
+            .iter()
+            .find_map(|segment| match *segment {
+                Segment::Dynamic(param, _) if given(param) => Some(param),
+                _ => None,
+            });
+        if let (Some(param), Some(Segment::Dynamic(missing, _))) = (after, path.segments.get(len)) {
+            return Err(url_error(format!(
+                "param `{}` for route `{}` {} needs the optional param `{}` before it",
+                param, name, path.pattern, missing
+            )));
+        }
+
+        let mut url = String::new();
+        for segment in path.segments[..len].iter() {
+            url.push('/');
+            match *segment {
+                Segment::Static(value) => url.push_str(&encode_segment(value)),
+                Segment::Dynamic(param, ty) => {
+                    let value = value(param)?;
+                    if let Some(Err(e)) = ty.map(|ty| (ty.parse)(value)) {
+                        return Err(url_error(format!(
+                            "invalid param `{}` for route `{}` {}: {}",
+                            param, name, path.pattern, e
+                        )));
+                    }
+                    url.push_str(&encode_segment(value));
+                }
+            }
+        }
+        if let Some(rest) = path.rest {
+            let value = value(rest)?;
+            if !value.is_empty() {
+                for segment in value.split('/') {
+                    url.push('/');
+                    url.push_str(&encode_segment(segment));
+                }
+            }
+        }
+        if url.is_empty() {
+            url.push('/');
+        }
+        Ok(url)
+    }
+
+    /// Record the path of `route` under its name, if it has one.  A name may be given to routes
+    /// of several methods, but only if they have the same path.
+    pub(crate) fn register(&mut self, route: &Route) -> Result<(), RouterError> {
+        let name = match route.name {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let path = NamedPath::new(route);
+        match self.0.get(name) {
+            Some(existing) if existing.pattern != path.pattern => Err(RouterError::DuplicateName {
+                name,
+                route: path.pattern,
+                existing: existing.pattern.clone(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                Arc::make_mut(&mut self.0).insert(name, path);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Props for Urls {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let urls = req.local().get::<Urls>().cloned().ok_or_else(|| {
+                Error::internal("request was not routed").with_code(WindmillErrorCode::Internal)
+            })?;
+            Ok((req, params, urls))
+        })
+    }
+}
+
+fn url_error(message: String) -> Error {
+    Error::internal(message).with_code(WindmillErrorCode::Internal)
+}
+
+/// Encode every byte but the unreserved characters, so the value stays in one segment.
+fn encode_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
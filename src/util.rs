@@ -1,15 +1,511 @@
-pub async fn read_body(req: &mut http_types::Request) -> Vec<u8> {
+#[cfg(feature = "compression")]
+use crate::compression::decompress;
+use crate::{
+    config::Config,
+    error::{Error, WindmillErrorCode},
+};
+use hmac::{Hmac, Mac, NewMac};
+use http_types::{Mime, StatusCode};
+use sha2::Sha256;
+use std::{
+    fmt::Write,
+    sync::{Arc, Weak},
+};
+
+/// The default size limit of a request body.
+pub(crate) const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Read the body of a request into memory.  
+///
+/// A body is read when the request declares a non-zero `Content-Length`, or when it has no
+/// `Content-Length` but uses `Transfer-Encoding: chunked`.  Otherwise the body is empty.  A
+/// `Content-Length` that is not a number results in a `400 Bad Request` error.  
+///
+/// Exactly the declared `Content-Length` is read, never waiting on a kept-alive connection for
+/// more than the client said it would send.  A body that ends before that, like a chunked body
+/// with malformed framing, results in a `400 Bad Request` error.  
+///
+/// Bodies larger than the [maximum body size](struct.Config.html#method.with_max_body_size), 1 MiB
+/// by default, result in a `413 Payload Too Large` error.  A declared `Content-Length` over the
+/// limit is rejected without reading the body, and reading any other body stops as soon as it
+/// goes over.  
+///
+/// A body with a `Content-Encoding` of `gzip` or `deflate` is decompressed when the `compression`
+/// feature is enabled, and the limit applies to the decompressed body as well.  A body in any other
+/// encoding, or in any encoding at all without the feature, results in a
+/// `415 Unsupported Media Type` error, and a body that cannot be decompressed in a
+/// `400 Bad Request` error.  
+///
+/// ## Examples
+/// ```
+/// # use windmill::read_body;
+/// # use async_std::io::Cursor;
+/// # use http_types::{Body, Method, Request, Url};
+/// # async_std::task::block_on(async {
+/// let url = Url::parse("http://localhost/").unwrap();
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "27").unwrap();
+/// req.set_body(r#"{"foo":"bar","baz":"qux!!"}"#);
+/// assert_eq!(read_body(&mut req).await.unwrap().len(), 27);
+///
+/// // Bytes past the declared length are not part of the body.
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "5").unwrap();
+/// req.set_body("hello, and more");
+/// assert_eq!(read_body(&mut req).await.unwrap(), b"hello");
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "27").unwrap();
+/// req.set_body(Body::from_reader(Cursor::new(b"short".to_vec()), Some(27)));
+/// let err = read_body(&mut req).await.unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::BadRequest);
+/// assert_eq!(err.to_string(), "400 Bad Request: body cut short: 5 of 27 bytes received");
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "0").unwrap();
+/// assert!(read_body(&mut req).await.unwrap().is_empty());
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.set_body("ignored without a length");
+/// assert!(read_body(&mut req).await.unwrap().is_empty());
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("transfer-encoding", "chunked").unwrap();
+/// req.set_body("chunked body");
+/// assert_eq!(read_body(&mut req).await.unwrap(), b"chunked body");
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "abc").unwrap();
+/// let err = read_body(&mut req).await.unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::BadRequest);
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("transfer-encoding", "chunked").unwrap();
+/// req.set_body(vec![0; 2 * 1024 * 1024]);
+/// let err = read_body(&mut req).await.unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::PayloadTooLarge);
+///
+/// let mut req = Request::new(Method::Post, url.clone());
+/// req.insert_header("content-length", "4").unwrap();
+/// req.insert_header("content-encoding", "br").unwrap();
+/// req.set_body("!!!!");
+/// let err = read_body(&mut req).await.unwrap_err();
+/// assert_eq!(err.code(), http_types::StatusCode::UnsupportedMediaType);
+///
+/// let mut req = Request::new(Method::Post, url);
+/// req.insert_header("content-length", "4").unwrap();
+/// req.insert_header("content-encoding", "identity").unwrap();
+/// req.set_body("same");
+/// assert_eq!(read_body(&mut req).await.unwrap(), b"same");
+/// # });
+/// ```
+pub async fn read_body(req: &mut http_types::Request) -> Result<Vec<u8>, Error> {
+    let limit = max_body_size(req);
+    read_body_with_limit(req, limit).await
+}
+
+/// Read the body of a request into memory like [`read_body`](fn.read_body.html), with `limit` in
+/// place of the [maximum body size](struct.Config.html#method.with_max_body_size).  
+///
+/// ## Examples
+/// A props that reads small bodies only, and a client sending chunked bodies:
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use async_std::{io, net::TcpStream, prelude::*, task};
+/// # use http_types::{Method, Response};
+/// # use std::time::Duration;
+/// struct Note(Vec<u8>);
+///
+/// impl Props for Note {
+///     type Fut = PropsFuture<Self>;
+///
+///     fn call(mut req: http_types::Request, params: Params) -> Self::Fut {
+///         Box::pin(async move {
+///             let body = read_body_with_limit(&mut req, 16).await?;
+///             Ok((req, params, Note(body)))
+///         })
+///     }
+/// }
+///
+/// #[endpoint]
+/// async fn note(note: Note) -> Result<Response, Error> {
+///     Ok(Response::from(format!("{} bytes", note.0.len())))
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"notes"), ___note);
+///
+/// let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+/// std::thread::spawn(move || {
+///     Server::new(Config::new(addr.to_string())).run(router).map_err(|e| e.to_string())
+/// });
+///
+/// let send = |body: &'static str| async move {
+///     let mut stream = loop {
+///         match TcpStream::connect(addr).await {
+///             Ok(stream) => break stream,
+///             Err(_) => task::sleep(Duration::from_millis(10)).await,
+///         }
+///     };
+///     let head = "POST /notes HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\r\n";
+///     stream.write_all(format!("{}{}", head, body).as_bytes()).await.unwrap();
+///     let (mut res, mut buf) = (vec![], [0; 1024]);
+///     while let Ok(n) = io::timeout(Duration::from_millis(200), stream.read(&mut buf)).await {
+///         if n == 0 {
+///             break;
+///         }
+///         res.extend_from_slice(&buf[..n]);
+///     }
+///     String::from_utf8(res).unwrap()
+/// };
+///
+/// task::block_on(async {
+///     let res = send("5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n").await;
+///     assert!(res.starts_with("HTTP/1.1 200 OK\r\n") && res.ends_with("11 bytes"), "{}", res);
+///
+///     // Not a chunk size.
+///     let res = send("zz\r\nhello\r\n0\r\n\r\n").await;
+///     assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+///
+///     let res = send("14\r\ntwenty bytes long!!!\r\n0\r\n\r\n").await;
+///     assert!(res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{}", res);
+/// });
+/// ```
+pub async fn read_body_with_limit(
+    req: &mut http_types::Request,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+    read_body_into(req, &mut body, limit).await?;
+    Ok(body)
+}
+
+/// The body of a request taken by a [`BodyBytes`](struct.BodyBytes.html), which has read it off
+/// the request.  Props after it read the body from here, for as long as the endpoint holds it.  
+pub(crate) struct ReadBody(pub(crate) Weak<Vec<u8>>);
+
+impl ReadBody {
+    pub(crate) fn of(req: &http_types::Request) -> Option<Arc<Vec<u8>>> {
+        req.local().get::<Self>()?.0.upgrade()
+    }
+}
+
+/// Read the body of a request like [`read_body_with_limit`](fn.read_body_with_limit.html), into
+/// `body`, which is cleared first and grown once to a declared `Content-Length`.  
+pub(crate) async fn read_body_into(
+    req: &mut http_types::Request,
+    body: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), Error> {
     use async_std::prelude::*;
+    use std::io;
 
-    let has_body = req
+    body.clear();
+    if let Some(read) = ReadBody::of(req) {
+        if read.len() > limit {
+            return Err(body_too_large(limit));
+        }
+        body.extend_from_slice(&read);
+        return Ok(());
+    }
+    let encodings = content_encodings(req)?;
+    let declared = match req
         .header(&http_types::headers::CONTENT_LENGTH)
-        .map(|header_values| header_values.first().map(|value| value.as_str() != "0"))
-        .flatten()
-        .unwrap_or_else(|| false);
+        .and_then(|header_values| header_values.first())
+    {
+        Some(value) => {
+            let len = value.as_str().trim().parse::<u64>().map_err(|_| {
+                Error::bad_request(format!("invalid content-length: {}", value.as_str()))
+                    .with_code(WindmillErrorCode::BadRequest)
+            })?;
+            if len > limit as u64 {
+                return Err(body_too_large(limit));
+            }
+            // A byte over, for the read that finds the end of the body to land in.
+            body.reserve(len as usize + 1);
+            Some(len)
+        }
+        None => None,
+    };
+    let chunked = req
+        .header(&http_types::headers::TRANSFER_ENCODING)
+        .map(|header_values| {
+            header_values
+                .iter()
+                .any(|value| value.as_str().to_ascii_lowercase().contains("chunked"))
+        })
+        .unwrap_or(false);
 
-    let mut body = vec![];
+    let has_body = match declared {
+        Some(len) => len > 0,
+        None => chunked,
+    };
     if has_body {
-        let _ = req.read_to_end(&mut body).await;
+        (&mut *req)
+            .take(declared.unwrap_or(limit as u64 + 1))
+            .read_to_end(body)
+            .await
+            .map_err(|e| {
+                eprintln!(
+                    "{} {}: unable to read body: {}",
+                    req.method(),
+                    req.url().path(),
+                    e
+                );
+                body_read_error(e)
+            })?;
+        if body.len() > limit {
+            return Err(body_too_large(limit));
+        }
+        if let Some(len) = declared.filter(|len| (body.len() as u64) < *len) {
+            let msg = format!("{} of {} bytes received", body.len(), len);
+            let eof = io::Error::new(io::ErrorKind::UnexpectedEof, msg);
+            return Err(body_read_error(eof));
+        }
+    }
+    for encoding in encodings.iter().rev() {
+        *body = decompress(encoding, body, limit)?;
+    }
+    Ok(())
+}
+
+/// The encodings the body of `req` can be decompressed from.
+#[cfg(feature = "compression")]
+const SUPPORTED_ENCODINGS: &[&str] = &["gzip", "x-gzip", "deflate"];
+#[cfg(not(feature = "compression"))]
+const SUPPORTED_ENCODINGS: &[&str] = &[];
+
+/// The encodings in the `Content-Encoding` of `req` other than `identity`, in the order they were
+/// applied to the body, all of which must be supported.
+fn content_encodings(req: &http_types::Request) -> Result<Vec<String>, Error> {
+    let values = match req.header(&"content-encoding".parse().unwrap()) {
+        Some(values) => values,
+        None => return Ok(vec![]),
+    };
+    let mut encodings = vec![];
+    for value in values.iter() {
+        for encoding in value.as_str().split(',') {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            if encoding.is_empty() || encoding == "identity" {
+                continue;
+            }
+            if !SUPPORTED_ENCODINGS.contains(&encoding.as_str()) {
+                return Err(Error::new(
+                    StatusCode::UnsupportedMediaType,
+                    format!("unsupported content-encoding: {}", encoding),
+                )
+                .with_code(WindmillErrorCode::UnsupportedMediaType));
+            }
+            encodings.push(encoding);
+        }
+    }
+    Ok(encodings)
+}
+
+/// Without the `compression` feature no encoding is supported, so there is nothing to decompress.
+#[cfg(not(feature = "compression"))]
+fn decompress(_encoding: &str, _body: &[u8], _limit: usize) -> Result<Vec<u8>, Error> {
+    unreachable!("no content-encoding is supported")
+}
+
+/// A response of the framework's own, with `body` encoded as `mime` and its `Content-Type` and
+/// `Content-Length` set.
+pub(crate) fn response(code: StatusCode, mime: Mime, body: Vec<u8>) -> http_types::Response {
+    let mut res = http_types::Response::new(code);
+    let _ = res.set_content_type(mime);
+    res.set_body(body);
+    set_content_length(&mut res);
+    res
+}
+
+/// Set the `Content-Length` of `res` to the length of its body, when the length is known and the
+/// status allows a body.  A `Content-Length` set by the endpoint is left alone.
+pub(crate) fn set_content_length(res: &mut http_types::Response) {
+    let status = res.status();
+    if status.is_informational()
+        || matches!(status, StatusCode::NoContent | StatusCode::NotModified)
+    {
+        return;
+    }
+    if let (None, Some(len)) = (res.header(&http_types::headers::CONTENT_LENGTH), res.len()) {
+        let _ = res.insert_header(http_types::headers::CONTENT_LENGTH, len.to_string());
+    }
+}
+
+/// The maximum body size configured for the server handling `req`.
+pub(crate) fn max_body_size(req: &http_types::Request) -> usize {
+    req.local()
+        .get::<Arc<Config>>()
+        .map(|config| config.max_body_size())
+        .unwrap_or(DEFAULT_MAX_BODY_SIZE)
+}
+
+/// The error for a failed read of a request body, `408 Request Timeout` if the client stopped
+/// sending it.  A body cut short by the client leaves the connection in no state to be reused,
+/// so the error closes it.
+pub(crate) fn body_read_error(e: std::io::Error) -> Error {
+    use std::io::ErrorKind;
+
+    match e.kind() {
+        ErrorKind::TimedOut => Error::new(
+            StatusCode::RequestTimeout,
+            format!("timed out reading body: {}", e),
+        )
+        .with_code(WindmillErrorCode::RequestTimeout),
+        ErrorKind::UnexpectedEof
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe => Error::bad_request(format!("body cut short: {}", e))
+            .with_code(WindmillErrorCode::BadRequest)
+            .with_header("connection", "close"),
+        _ => Error::bad_request(format!("unable to read body: {}", e))
+            .with_code(WindmillErrorCode::BadRequest),
+    }
+}
+
+/// The HMAC-SHA256 of `data` under `key`.
+pub(crate) fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("hmac accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Compare `a` and `b` in a time that depends only on their lengths.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Encode `bytes` as lowercase hex.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+pub(crate) fn body_too_large(limit: usize) -> Error {
+    Error::new(
+        StatusCode::PayloadTooLarge,
+        format!("request body is larger than {} bytes", limit),
+    )
+    .with_code(WindmillErrorCode::BodyTooLarge)
+}
+
+/// Format a `SystemTime` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn fmt_http_date(time: std::time::SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = secs / 86400;
+    let rem = secs % 86400;
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parse an IMF-fixdate, the format of `fmt_http_date`, or `None` if `value` is not one.  The
+/// obsolete formats are not accepted.
+pub(crate) fn parse_http_date(value: &str) -> Option<std::time::SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = value.trim().split(' ');
+    let (_weekday, day, month, year, time, zone) = (
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    );
+    if parts.next().is_some() || zone != "GMT" || day.len() != 2 || year.len() != 4 {
+        return None;
+    }
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|part| match part.len() {
+        2 => part.parse::<u64>().ok(),
+        _ => None,
+    });
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Days since the epoch from a civil date, the inverse of the algorithm in `fmt_http_date`.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    if days < 0 {
+        return None;
+    }
+
+    let secs = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+}
+
+/// Decode `%XX` escapes, keeping malformed ones and invalid UTF-8 as they are.
+pub(crate) fn percent_decode(value: &str) -> String {
+    try_percent_decode(value).unwrap_or_else(|| value.to_owned())
+}
+
+/// Decode `%XX` escapes, keeping malformed ones as they are, or `None` if the result is not
+/// UTF-8.
+pub(crate) fn try_percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
     }
-    body
+    String::from_utf8(decoded).ok()
 }
@@ -0,0 +1,405 @@
+use crate::{
+    error::{Error, ErrorFormatter, WindmillErrorCode},
+    params::Params,
+    route::RouteFn,
+    router::error_body,
+    util::max_body_size,
+};
+use async_std::io::{self, prelude::*, BufReader};
+use http_types::StatusCode;
+use sha1::{Digest, Sha1};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// The GUID a handshake key is hashed with, from RFC 6455.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// A message received from or sent to a [`WebSocket`](struct.WebSocket.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// The connection of a client that upgraded to a WebSocket on a route added with
+/// [`Router::add_ws`](struct.Router.html#method.add_ws).
+///
+/// Pings are answered and pongs skipped while [`recv`](#method.recv) waits for the next
+/// message, and a close from the client is answered before `recv` reports it.  Messages larger
+/// than the [maximum body size](struct.Config.html#method.with_max_body_size) close the
+/// connection.  When the handler returns without closing it, the connection is closed as normal,
+/// or as an internal error if the handler failed.
+pub struct WebSocket {
+    io: BufReader<Box<dyn Transport>>,
+    req: http_types::Request,
+    params: Params,
+    max_message: usize,
+    closed: Arc<AtomicBool>,
+}
+
+impl WebSocket {
+    /// The upgrade request, without its body.
+    pub fn request(&self) -> &http_types::Request {
+        &self.req
+    }
+
+    /// The params of the route the upgrade request matched.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Wait for the next text or binary message, or `None` once the client has closed the
+    /// connection.
+    ///
+    /// A client that breaks the protocol is sent a close with the matching status code, and the
+    /// error is returned.  That includes a close with a code that is reserved or out of range,
+    /// which is answered with 1002 rather than echoed.
+    pub async fn recv(&mut self) -> io::Result<Option<Message>> {
+        let mut partial: Option<(u8, Vec<u8>)> = None;
+        loop {
+            if self.is_closed() {
+                return Ok(None);
+            }
+            let (fin, opcode, payload) = match self.read_frame().await {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.closed.store(true, Ordering::SeqCst);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            };
+
+            match opcode {
+                CLOSE | PING | PONG if !fin || payload.len() > 125 => {
+                    return Err(self.fail(1002, "invalid control frame").await);
+                }
+                CLOSE => {
+                    let code = match payload.get(..2) {
+                        Some(code) => u16::from_be_bytes([code[0], code[1]]),
+                        None if payload.is_empty() => 1000,
+                        None => return Err(self.fail(1002, "invalid close frame").await),
+                    };
+                    if !valid_close_code(code) {
+                        return Err(self.fail(1002, "invalid close code").await);
+                    }
+                    let _ = self.write_close(code).await;
+                    return Ok(None);
+                }
+                PING => self.write_frame(PONG, &payload).await?,
+                PONG => {}
+                TEXT | BINARY if partial.is_some() => {
+                    return Err(self.fail(1002, "expected a continuation frame").await);
+                }
+                TEXT | BINARY if fin => return self.message(opcode, payload).await.map(Some),
+                TEXT | BINARY => partial = Some((opcode, payload)),
+                CONTINUATION => {
+                    let (opcode, mut message) = match partial.take() {
+                        Some(partial) => partial,
+                        None => return Err(self.fail(1002, "unexpected continuation frame").await),
+                    };
+                    if message.len() + payload.len() > self.max_message {
+                        return Err(self.fail(1009, "message too large").await);
+                    }
+                    message.extend_from_slice(&payload);
+                    match fin {
+                        true => return self.message(opcode, message).await.map(Some),
+                        false => partial = Some((opcode, message)),
+                    }
+                }
+                _ => return Err(self.fail(1002, "unknown opcode").await),
+            }
+        }
+    }
+
+    /// Send a message to the client.
+    pub async fn send(&mut self, message: Message) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "websocket is closed",
+            ));
+        }
+        match message {
+            Message::Text(text) => self.write_frame(TEXT, text.as_bytes()).await,
+            Message::Binary(bytes) => self.write_frame(BINARY, &bytes).await,
+        }
+    }
+
+    /// Close the connection as normal.
+    pub async fn close(mut self) -> io::Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
+        self.write_close(1000).await
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    async fn message(&mut self, opcode: u8, payload: Vec<u8>) -> io::Result<Message> {
+        match opcode {
+            TEXT => match String::from_utf8(payload) {
+                Ok(text) => Ok(Message::Text(text)),
+                Err(_) => Err(self.fail(1007, "text message is not utf-8").await),
+            },
+            _ => Ok(Message::Binary(payload)),
+        }
+    }
+
+    async fn read_frame(&mut self) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0u8; 2];
+        self.io.read_exact(&mut head).await?;
+        if head[0] & 0x70 != 0 {
+            return Err(self.fail(1002, "reserved bits set").await);
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(self.fail(1002, "client frames must be masked").await);
+        }
+
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0u8; 2];
+                self.io.read_exact(&mut len).await?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                self.io.read_exact(&mut len).await?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        if len > self.max_message as u64 {
+            return Err(self.fail(1009, "message too large").await);
+        }
+
+        let mut mask = [0u8; 4];
+        self.io.read_exact(&mut mask).await?;
+        let mut payload = vec![0u8; len as usize];
+        self.io.read_exact(&mut payload).await?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((head[0] & 0x80 != 0, head[0] & 0x0f, payload))
+    }
+
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let io = self.io.get_mut();
+        io.write_all(&frame(opcode, payload)).await?;
+        io.flush().await
+    }
+
+    async fn write_close(&mut self, code: u16) -> io::Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        self.write_frame(CLOSE, &code.to_be_bytes()).await
+    }
+
+    /// Close the connection with `code` for a client that broke the protocol.
+    async fn fail(&mut self, code: u16, msg: &str) -> io::Error {
+        let _ = self.write_close(code).await;
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+}
+
+/// Whether a client may close with `code`: one defined by RFC 6455 that is not reserved for
+/// reporting locally, or one in the range left to libraries and applications.
+fn valid_close_code(code: u16) -> bool {
+    match code {
+        1000..=1003 | 1007..=1014 | 3000..=4999 => true,
+        _ => false,
+    }
+}
+
+/// An unmasked frame, as servers send them.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A connection a WebSocket can run over.
+pub(crate) trait Transport: io::Read + io::Write + Unpin + Send + Sync {}
+
+impl<T: io::Read + io::Write + Unpin + Send + Sync> Transport for T {}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+/// An accepted upgrade, attached to the `101 Switching Protocols` response for the server to run
+/// once it has the connection.
+pub(crate) struct Upgrade {
+    handler: Box<dyn FnOnce(WebSocket) -> HandlerFuture + Send + Sync>,
+    req: http_types::Request,
+    params: Params,
+    max_message: usize,
+}
+
+impl Upgrade {
+    /// Send `res` over `io` and hand the connection to the handler.
+    pub(crate) async fn run<T: Transport + Clone + 'static>(
+        self,
+        res: &http_types::Response,
+        io: T,
+    ) {
+        let mut head = String::from("HTTP/1.1 101 Switching Protocols\r\n");
+        for (name, values) in res.iter() {
+            for value in values.iter() {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("\r\n");
+
+        let mut writer = io.clone();
+        if writer.write_all(head.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+            return;
+        }
+
+        let request = format!("{} {}", self.req.method(), self.req.url().path());
+        let closed = Arc::new(AtomicBool::new(false));
+        let ws = WebSocket {
+            io: BufReader::new(Box::new(io)),
+            req: self.req,
+            params: self.params,
+            max_message: self.max_message,
+            closed: closed.clone(),
+        };
+        let code = match (self.handler)(ws).await {
+            Ok(()) => 1000,
+            Err(e) => {
+                eprintln!("{}: websocket handler failed: {}", request, e);
+                1011
+            }
+        };
+        if !closed.load(Ordering::SeqCst) {
+            let _ = writer
+                .write_all(&frame(CLOSE, &u16::to_be_bytes(code)))
+                .await;
+            let _ = writer.flush().await;
+        }
+    }
+}
+
+/// The route handler of a WebSocket route, which checks the handshake and attaches an `Upgrade`
+/// to its response.
+pub(crate) fn handler<F, Fut>(handler: F) -> RouteFn
+where
+    F: Fn(WebSocket) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+{
+    let handler = Arc::new(handler);
+    Box::new(move |mut req: http_types::Request, params: Params| {
+        let res = match accept_key(&req) {
+            Ok(accept) => {
+                let mut res = http_types::Response::new(StatusCode::SwitchingProtocols);
+                let _ = res.insert_header("upgrade", "websocket");
+                let _ = res.insert_header("connection", "Upgrade");
+                let _ = res.insert_header("sec-websocket-accept", accept);
+
+                let handler = handler.clone();
+                let max_message = max_body_size(&req);
+                req.take_body();
+                res.local_mut().insert(Upgrade {
+                    handler: Box::new(move |ws| Box::pin(handler(ws))),
+                    req,
+                    params,
+                    max_message,
+                });
+                res
+            }
+            Err(e) => {
+                let code = e.code();
+                let mut res = match ErrorFormatter::of(&req) {
+                    Some(formatter) => formatter.format(&e, &req),
+                    None => error_body(e),
+                };
+                if code == StatusCode::UpgradeRequired {
+                    let _ = res.insert_header("upgrade", "websocket");
+                    let _ = res.insert_header("sec-websocket-version", "13");
+                }
+                res
+            }
+        };
+        Box::pin(async move { res })
+    })
+}
+
+/// The `Sec-WebSocket-Accept` for a valid upgrade request.
+fn accept_key(req: &http_types::Request) -> Result<String, Error> {
+    let header = |name: &str| {
+        req.header(&name.parse().unwrap())
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|value| value.as_str().to_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let has_token = |name: &str, token: &str| {
+        header(name)
+            .iter()
+            .flat_map(|value| value.split(',').map(str::trim).collect::<Vec<_>>())
+            .any(|value| value.eq_ignore_ascii_case(token))
+    };
+
+    if !has_token("upgrade", "websocket") || !has_token("connection", "upgrade") {
+        return Err(error(
+            StatusCode::UpgradeRequired,
+            WindmillErrorCode::UpgradeRequired,
+            "websocket upgrade required",
+        ));
+    }
+    if header("sec-websocket-version").last().map(|v| v.trim()) != Some("13") {
+        return Err(error(
+            StatusCode::UpgradeRequired,
+            WindmillErrorCode::UpgradeRequired,
+            "websocket version 13 required",
+        ));
+    }
+    let key = header("sec-websocket-key")
+        .last()
+        .map(|key| key.trim().to_owned())
+        .unwrap_or_default();
+    if !valid_key(&key) {
+        return Err(error(
+            StatusCode::BadRequest,
+            WindmillErrorCode::BadRequest,
+            "invalid sec-websocket-key",
+        ));
+    }
+    let digest = Sha1::digest(format!("{}{}", key, GUID).as_bytes());
+    Ok(base64::encode(digest))
+}
+
+fn error(code: StatusCode, error_code: WindmillErrorCode, msg: &str) -> Error {
+    Error::new(code, msg).with_code(error_code)
+}
+
+/// Whether `key` is the base64 encoding of 16 bytes.
+fn valid_key(key: &str) -> bool {
+    base64::decode(key).map_or(false, |key| key.len() == 16)
+}